@@ -0,0 +1,6 @@
+-- Audio stream properties read from the container headers
+ALTER TABLE files ADD COLUMN bitrate INTEGER;
+ALTER TABLE files ADD COLUMN sample_rate INTEGER;
+ALTER TABLE files ADD COLUMN channels INTEGER;
+ALTER TABLE files ADD COLUMN bits_per_sample INTEGER;
+ALTER TABLE files ADD COLUMN codec TEXT;
@@ -0,0 +1,10 @@
+-- Chromaprint fingerprints, raw holds the unpacked sub-fingerprints as little endian u32
+CREATE TABLE
+    IF NOT EXISTS fingerprints (
+        file_path TEXT PRIMARY KEY,
+        fingerprint TEXT NOT NULL,
+        raw BLOB NOT NULL,
+        duration_ms INTEGER NOT NULL,
+        created_at INTEGER NOT NULL,
+        FOREIGN KEY (file_path) REFERENCES files (path) ON DELETE CASCADE
+    );
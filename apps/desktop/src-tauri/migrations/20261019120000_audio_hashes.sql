@@ -0,0 +1,10 @@
+-- Hash of the audio payload without tags, cached until the file changes
+CREATE TABLE
+    IF NOT EXISTS audio_hashes (
+        file_path TEXT PRIMARY KEY,
+        hash TEXT NOT NULL,
+        last_modified INTEGER NOT NULL,
+        FOREIGN KEY (file_path) REFERENCES files (path) ON DELETE CASCADE
+    );
+
+CREATE INDEX IF NOT EXISTS idx_audio_hashes_hash ON audio_hashes (hash);
@@ -0,0 +1,48 @@
+-- Full-text index over tag values, external content tables kept in sync by triggers
+CREATE VIRTUAL TABLE IF NOT EXISTS tag_text_fts USING fts5 (
+    value,
+    content = 'tag_text',
+    content_rowid = 'id',
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS tag_comment_fts USING fts5 (
+    text,
+    description,
+    content = 'tag_comment',
+    content_rowid = 'id',
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+CREATE TRIGGER IF NOT EXISTS tag_text_fts_insert AFTER INSERT ON tag_text BEGIN
+    INSERT INTO tag_text_fts (rowid, value) VALUES (new.id, new.value);
+END;
+
+CREATE TRIGGER IF NOT EXISTS tag_text_fts_delete AFTER DELETE ON tag_text BEGIN
+    INSERT INTO tag_text_fts (tag_text_fts, rowid, value) VALUES ('delete', old.id, old.value);
+END;
+
+CREATE TRIGGER IF NOT EXISTS tag_text_fts_update AFTER UPDATE ON tag_text BEGIN
+    INSERT INTO tag_text_fts (tag_text_fts, rowid, value) VALUES ('delete', old.id, old.value);
+    INSERT INTO tag_text_fts (rowid, value) VALUES (new.id, new.value);
+END;
+
+CREATE TRIGGER IF NOT EXISTS tag_comment_fts_insert AFTER INSERT ON tag_comment BEGIN
+    INSERT INTO tag_comment_fts (rowid, text, description) VALUES (new.id, new.text, new.description);
+END;
+
+CREATE TRIGGER IF NOT EXISTS tag_comment_fts_delete AFTER DELETE ON tag_comment BEGIN
+    INSERT INTO tag_comment_fts (tag_comment_fts, rowid, text, description) VALUES ('delete', old.id, old.text, old.description);
+END;
+
+CREATE TRIGGER IF NOT EXISTS tag_comment_fts_update AFTER UPDATE ON tag_comment BEGIN
+    INSERT INTO tag_comment_fts (tag_comment_fts, rowid, text, description) VALUES ('delete', old.id, old.text, old.description);
+    INSERT INTO tag_comment_fts (rowid, text, description) VALUES (new.id, new.text, new.description);
+END;
+
+-- index whatever was stored before this migration
+INSERT INTO tag_text_fts (tag_text_fts) VALUES ('rebuild');
+INSERT INTO tag_comment_fts (tag_comment_fts) VALUES ('rebuild');
+
+CREATE INDEX IF NOT EXISTS idx_tag_text_file_key ON tag_text (file_path, key);
+CREATE INDEX IF NOT EXISTS idx_tag_comment_file ON tag_comment (file_path);
@@ -0,0 +1,24 @@
+-- When a file first entered the library, for queries like "added this week"
+ALTER TABLE files ADD COLUMN added_at INTEGER;
+UPDATE files SET added_at = COALESCE(last_validated, last_modified, strftime('%s', 'now'));
+
+CREATE TABLE
+    IF NOT EXISTS saved_queries (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        query TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+
+-- Last evaluated result of each saved query, to tell which ones a file change affects
+CREATE TABLE
+    IF NOT EXISTS saved_query_results (
+        query_id INTEGER NOT NULL,
+        file_path TEXT NOT NULL,
+        PRIMARY KEY (query_id, file_path),
+        FOREIGN KEY (query_id) REFERENCES saved_queries (id) ON DELETE CASCADE,
+        FOREIGN KEY (file_path) REFERENCES files (path) ON DELETE CASCADE
+    );
+
+CREATE INDEX IF NOT EXISTS idx_saved_query_results_path ON saved_query_results (file_path);
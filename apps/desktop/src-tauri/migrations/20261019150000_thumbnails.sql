@@ -0,0 +1,17 @@
+-- Content hash of the picture, so covers shared by an album are only thumbnailed once
+ALTER TABLE tag_pictures ADD COLUMN hash TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_tag_pictures_hash ON tag_pictures (hash);
+
+-- Small renditions of embedded pictures, keyed by the hash of the original
+CREATE TABLE
+    IF NOT EXISTS thumbnails (
+        hash TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        mime_type TEXT NOT NULL,
+        width INTEGER NOT NULL,
+        height INTEGER NOT NULL,
+        data BLOB NOT NULL,
+        created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+        PRIMARY KEY (hash, size)
+    );
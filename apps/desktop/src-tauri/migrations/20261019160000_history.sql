@@ -0,0 +1,31 @@
+-- Undo history, kept across restarts. The payload is the serialized action
+CREATE TABLE
+    IF NOT EXISTS history_actions (
+        id INTEGER PRIMARY KEY,
+        kind TEXT NOT NULL,
+        payload TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        undone INTEGER NOT NULL DEFAULT 0,
+        created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+    );
+
+-- Files each action touched
+CREATE TABLE
+    IF NOT EXISTS history_files (
+        action_id INTEGER NOT NULL REFERENCES history_actions (id) ON DELETE CASCADE,
+        path TEXT NOT NULL,
+        PRIMARY KEY (action_id, path)
+    );
+
+CREATE INDEX IF NOT EXISTS idx_history_files_path ON history_files (path);
+
+-- Each file as the history last left it: size and modification time tell quickly that it's
+-- unchanged, the content hash decides when they differ. Undo refuses when the hash no longer
+-- matches
+CREATE TABLE
+    IF NOT EXISTS history_file_stamps (
+        path TEXT PRIMARY KEY,
+        size INTEGER,
+        mtime INTEGER,
+        hash TEXT
+    );
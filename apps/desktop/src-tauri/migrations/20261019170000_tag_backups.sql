@@ -0,0 +1,16 @@
+-- Original metadata region of each file, saved before its first write. The bytes live in the
+-- content-addressed store under the app data folder, keyed by hash
+CREATE TABLE
+    IF NOT EXISTS tag_backups (
+        path TEXT PRIMARY KEY,
+        hash TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        tail_len INTEGER NOT NULL DEFAULT 0,
+        data_offset INTEGER,
+        created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+        -- Past the retention period the bytes are gone, the row stays so the file's next
+        -- write isn't taken for its original
+        expired INTEGER NOT NULL DEFAULT 0
+    );
+
+CREATE INDEX IF NOT EXISTS idx_tag_backups_hash ON tag_backups (hash);
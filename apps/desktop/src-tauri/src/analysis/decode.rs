@@ -0,0 +1,181 @@
+use std::fs::File;
+use std::path::Path;
+
+use symphonia::core::audio::{Channels, SampleBuffer};
+use symphonia::core::codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL, CODEC_TYPE_OPUS};
+use symphonia::core::errors::Error;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+/// Why a file couldn't be opened for decoding
+#[derive(Debug)]
+pub enum OpenError {
+    /// No pure-Rust decoder exists for the codec (Opus), the file is skipped rather than failed
+    Unsupported(String),
+    Failed(String),
+}
+
+impl From<OpenError> for String {
+    fn from(e: OpenError) -> String {
+        match e {
+            OpenError::Unsupported(m) | OpenError::Failed(m) => m,
+        }
+    }
+}
+
+/// Decodes an audio file to interleaved f32 samples, one packet at a time. MP3, AAC, FLAC and
+/// Vorbis go through symphonia. Opus is out of scope for now: symphonia has no Opus decoder
+/// and the Opus crates available bind the C libopus, so Opus files come back as
+/// `OpenError::Unsupported`
+pub struct PcmStream {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    sample_buf: Option<SampleBuffer<f32>>,
+    pub sample_rate: u32,
+    pub channels: usize,
+    channel_layout: Option<Channels>,
+}
+
+impl PcmStream {
+    pub fn open(path: &Path) -> Result<Self, OpenError> {
+        let file = File::open(path)
+            .map_err(|e| OpenError::Failed(format!("Failed to open file: {}", e)))?;
+        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+        let mut hint = Hint::new();
+        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+            hint.with_extension(ext);
+        }
+
+        let probed = symphonia::default::get_probe()
+            .format(
+                &hint,
+                mss,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )
+            .map_err(|e| {
+                OpenError::Failed(format!("Unsupported or corrupt audio stream: {}", e))
+            })?;
+        let format = probed.format;
+
+        let track = format
+            .tracks()
+            .iter()
+            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or(OpenError::Failed("No audio track found".to_string()))?;
+
+        if track.codec_params.codec == CODEC_TYPE_OPUS {
+            return Err(OpenError::Unsupported(
+                "Opus isn't supported for analysis yet, there is no pure-Rust Opus decoder"
+                    .to_string(),
+            ));
+        }
+
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())
+            .map_err(|e| {
+                OpenError::Unsupported(format!("No decoder available for this codec: {}", e))
+            })?;
+
+        let track_id = track.id;
+        let sample_rate = track.codec_params.sample_rate.unwrap_or(0);
+        let channel_layout = track.codec_params.channels;
+        let channels = channel_layout.map(|c| c.count()).unwrap_or(0);
+
+        Ok(Self {
+            format,
+            decoder,
+            track_id,
+            sample_buf: None,
+            sample_rate,
+            channels,
+            channel_layout,
+        })
+    }
+
+    /// Decode the next packet. Returns false at the end of the stream
+    pub fn next_chunk(&mut self) -> Result<bool, String> {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    return Ok(false);
+                }
+                Err(Error::ResetRequired) => return Ok(false),
+                Err(e) => return Err(format!("Failed to read audio packet: {}", e)),
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            match self.decoder.decode(&packet) {
+                Ok(decoded) => {
+                    let spec = *decoded.spec();
+                    if decoded.frames() == 0 {
+                        continue;
+                    }
+                    self.sample_rate = spec.rate;
+                    self.channels = spec.channels.count();
+                    self.channel_layout = Some(spec.channels);
+
+                    let capacity = decoded.capacity() as u64;
+                    let needed = capacity as usize * spec.channels.count();
+                    let needs_new = match &self.sample_buf {
+                        Some(buf) => buf.capacity() < needed,
+                        None => true,
+                    };
+                    if needs_new {
+                        self.sample_buf = Some(SampleBuffer::<f32>::new(capacity, spec));
+                    }
+                    if let Some(buf) = self.sample_buf.as_mut() {
+                        buf.copy_interleaved_ref(decoded);
+                    }
+                    break;
+                }
+                // corrupt frames are skipped like most players do
+                Err(Error::DecodeError(_)) => continue,
+                Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    return Ok(false);
+                }
+                Err(e) => return Err(format!("Failed to decode audio: {}", e)),
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Interleaved samples of the last decoded packet
+    pub fn samples(&self) -> &[f32] {
+        match &self.sample_buf {
+            Some(buf) => buf.samples(),
+            None => &[],
+        }
+    }
+
+    /// BS.1770 channel weights. LFE is ignored and surround channels get +1.5 dB
+    pub fn channel_weights(&self) -> Vec<f64> {
+        match self.channel_layout {
+            Some(layout) if layout.count() == self.channels => layout
+                .iter()
+                .map(|c| {
+                    if c == Channels::LFE1 || c == Channels::LFE2 {
+                        0.0
+                    } else if c == Channels::REAR_LEFT
+                        || c == Channels::REAR_RIGHT
+                        || c == Channels::SIDE_LEFT
+                        || c == Channels::SIDE_RIGHT
+                    {
+                        1.41
+                    } else {
+                        1.0
+                    }
+                })
+                .collect(),
+            _ => vec![1.0; self.channels],
+        }
+    }
+}
@@ -0,0 +1,587 @@
+// Chromaprint compatible fingerprint (algorithm 2, the default used by fpcalc and AcoustID)
+use crate::analysis::decode::PcmStream;
+use crate::database::Database;
+use crate::jobs::Job;
+use crate::tag_manager::batch::write_batch;
+use crate::tag_manager::properties::read_properties;
+use crate::tag_manager::tag_backend::{BackendError, DefaultBackend, TagError};
+use crate::tag_manager::utils::{Changes, FrameKey, SerializableTagValue};
+use crate::utils::refresh_files;
+use crate::AppState;
+use base64::{engine::general_purpose as b64_gp, Engine as _};
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{async_runtime, AppHandle, Emitter, Manager};
+
+const SAMPLE_RATE: u32 = 11025;
+const FRAME_SIZE: usize = 4096;
+// frames overlap by two thirds
+const HOP: usize = FRAME_SIZE / 3;
+const MIN_FREQ: f64 = 28.0;
+const MAX_FREQ: f64 = 3520.0;
+const MAX_SECONDS: u64 = 120;
+const ALGORITHM: u8 = 1;
+
+const CHROMA_FILTER: [f64; 5] = [0.25, 0.75, 1.0, 0.75, 0.25];
+const GRAY_CODE: [u32; 4] = [0, 1, 3, 2];
+
+struct Classifier {
+    kind: u8,
+    y: usize,
+    height: usize,
+    width: usize,
+    thresholds: [f64; 3],
+}
+
+const fn classifier(kind: u8, y: usize, height: usize, width: usize, t: [f64; 3]) -> Classifier {
+    Classifier {
+        kind,
+        y,
+        height,
+        width,
+        thresholds: t,
+    }
+}
+
+const CLASSIFIERS: [Classifier; 16] = [
+    classifier(0, 4, 3, 15, [1.98215, 2.35817, 2.63523]),
+    classifier(4, 4, 6, 15, [-1.03809, -0.651211, -0.282167]),
+    classifier(1, 0, 4, 16, [-0.298702, 0.119262, 0.558497]),
+    classifier(3, 8, 2, 12, [-0.105439, 0.0153946, 0.135898]),
+    classifier(3, 4, 4, 8, [-0.142891, 0.0258736, 0.200632]),
+    classifier(4, 0, 3, 5, [-0.826319, -0.590612, -0.368214]),
+    classifier(1, 2, 2, 9, [-0.557409, -0.233035, 0.0534525]),
+    classifier(2, 7, 3, 4, [-0.0646826, 0.00620476, 0.0784847]),
+    classifier(2, 6, 2, 16, [-0.192387, -0.029699, 0.215855]),
+    classifier(2, 1, 3, 2, [-0.0397818, -0.00568076, 0.0292026]),
+    classifier(5, 10, 1, 15, [-0.53823, -0.369934, -0.190235]),
+    classifier(3, 6, 2, 10, [-0.124877, 0.0296483, 0.139239]),
+    classifier(2, 1, 1, 14, [-0.101475, 0.0225617, 0.231971]),
+    classifier(3, 5, 6, 4, [-0.0799915, -0.00729616, 0.063262]),
+    classifier(1, 9, 2, 12, [-0.272556, 0.019424, 0.302559]),
+    classifier(3, 4, 2, 14, [-0.164292, -0.0321188, 0.08463]),
+];
+
+pub struct Fingerprint {
+    pub raw: Vec<u32>,
+    pub encoded: String,
+    pub duration_ms: u64,
+}
+
+/// Windowed-sinc resampler to the 11025 Hz mono signal chromaprint works on
+struct Resampler {
+    ratio: f64,
+    cutoff: f64,
+    half_taps: isize,
+    input: Vec<f64>,
+    consumed: usize,
+    position: f64,
+}
+
+impl Resampler {
+    fn new(source_rate: u32) -> Self {
+        let ratio = source_rate as f64 / SAMPLE_RATE as f64;
+        Self {
+            ratio,
+            cutoff: 0.8 / ratio.max(1.0),
+            half_taps: (16.0 * ratio.max(1.0)).ceil() as isize,
+            input: Vec::new(),
+            consumed: 0,
+            position: 0.0,
+        }
+    }
+
+    fn push(&mut self, mono: &[f64], out: &mut Vec<f64>) {
+        self.input.extend_from_slice(mono);
+        let available = (self.consumed + self.input.len()) as f64;
+        while self.position + (self.half_taps as f64) < available {
+            let center = self.position.floor() as isize;
+            let mut acc = 0.0;
+            for k in (center - self.half_taps + 1)..=(center + self.half_taps) {
+                let idx = k - self.consumed as isize;
+                if idx < 0 || idx as usize >= self.input.len() {
+                    continue;
+                }
+                let t = self.position - k as f64;
+                let x = PI * t * self.cutoff;
+                let sinc = if x.abs() < 1e-9 { 1.0 } else { x.sin() / x };
+                let window = 0.5 + 0.5 * (PI * t / (self.half_taps as f64 + 1.0)).cos();
+                acc += self.input[idx as usize] * sinc * window * self.cutoff;
+            }
+            out.push(acc);
+            self.position += self.ratio;
+        }
+        // keep only what the next output samples still need
+        let keep_from = (self.position.floor() as isize - self.half_taps).max(0) as usize;
+        if keep_from > self.consumed {
+            let drop = (keep_from - self.consumed).min(self.input.len());
+            self.input.drain(..drop);
+            self.consumed += drop;
+        }
+    }
+}
+
+/// Chroma features (12 bands per frame) from the resampled signal
+fn chroma_features(samples: &[f64]) -> Vec<[f64; 12]> {
+    let mut planner = FftPlanner::<f64>::new();
+    let fft = planner.plan_fft_forward(FRAME_SIZE);
+    let window: Vec<f64> = (0..FRAME_SIZE)
+        .map(|i| 0.54 - 0.46 * (2.0 * PI * i as f64 / (FRAME_SIZE as f64 - 1.0)).cos())
+        .collect();
+
+    let freq_to_index = |f: f64| (FRAME_SIZE as f64 * f / SAMPLE_RATE as f64).round() as usize;
+    let min_index = freq_to_index(MIN_FREQ).max(1);
+    let max_index = freq_to_index(MAX_FREQ).min(FRAME_SIZE / 2);
+    let notes: Vec<usize> = (0..max_index)
+        .map(|i| {
+            let freq = i as f64 * SAMPLE_RATE as f64 / FRAME_SIZE as f64;
+            let octave = (freq / (440.0 / 16.0)).log2();
+            (12.0 * (octave - octave.floor())) as usize % 12
+        })
+        .collect();
+
+    let mut raw_chroma: Vec<[f64; 12]> = Vec::new();
+    let mut buffer = vec![Complex::new(0.0, 0.0); FRAME_SIZE];
+    let mut start = 0usize;
+    while start + FRAME_SIZE <= samples.len() {
+        for (i, b) in buffer.iter_mut().enumerate() {
+            *b = Complex::new(samples[start + i] * window[i], 0.0);
+        }
+        fft.process(&mut buffer);
+        let mut chroma = [0.0f64; 12];
+        for i in min_index..max_index {
+            chroma[notes[i]] += buffer[i].norm_sqr();
+        }
+        raw_chroma.push(chroma);
+        start += HOP;
+    }
+
+    // smooth over time, then normalize each vector
+    let mut features = Vec::new();
+    for i in 0..raw_chroma.len().saturating_sub(CHROMA_FILTER.len() - 1) {
+        let mut row = [0.0f64; 12];
+        for (j, c) in CHROMA_FILTER.iter().enumerate() {
+            for band in 0..12 {
+                row[band] += c * raw_chroma[i + j][band];
+            }
+        }
+        let norm = row.iter().map(|v| v * v).sum::<f64>().sqrt();
+        if norm < 0.01 {
+            row = [0.0; 12];
+        } else {
+            for v in row.iter_mut() {
+                *v /= norm;
+            }
+        }
+        features.push(row);
+    }
+    features
+}
+
+/// Summed area table over (time, band)
+struct IntegralImage {
+    rows: Vec<[f64; 12]>,
+}
+
+impl IntegralImage {
+    fn new(features: &[[f64; 12]]) -> Self {
+        let mut rows: Vec<[f64; 12]> = Vec::with_capacity(features.len());
+        for (r, feature) in features.iter().enumerate() {
+            let mut row = [0.0f64; 12];
+            let mut running = 0.0;
+            for band in 0..12 {
+                running += feature[band];
+                row[band] = running + if r > 0 { rows[r - 1][band] } else { 0.0 };
+            }
+            rows.push(row);
+        }
+        Self { rows }
+    }
+
+    fn area(&self, r1: usize, c1: usize, r2: usize, c2: usize) -> f64 {
+        if r1 == r2 || c1 == c2 {
+            return 0.0;
+        }
+        let at = |r: usize, c: usize| self.rows[r - 1][c - 1];
+        let mut total = at(r2, c2);
+        if r1 > 0 {
+            total -= at(r1, c2);
+        }
+        if c1 > 0 {
+            total -= at(r2, c1);
+        }
+        if r1 > 0 && c1 > 0 {
+            total += at(r1, c1);
+        }
+        total
+    }
+}
+
+fn subtract_log(a: f64, b: f64) -> f64 {
+    (1.0 + a).ln() - (1.0 + b).ln()
+}
+
+impl Classifier {
+    fn filter(&self, image: &IntegralImage, x: usize) -> f64 {
+        let (y, w, h) = (self.y, self.width, self.height);
+        let area = |x1, y1, x2, y2| image.area(x1, y1, x2, y2);
+        match self.kind {
+            0 => subtract_log(area(x, y, x + w, y + h), 0.0),
+            1 => {
+                let h2 = h / 2;
+                subtract_log(area(x, y + h2, x + w, y + h), area(x, y, x + w, y + h2))
+            }
+            2 => {
+                let w2 = w / 2;
+                subtract_log(area(x + w2, y, x + w, y + h), area(x, y, x + w2, y + h))
+            }
+            3 => {
+                let (w2, h2) = (w / 2, h / 2);
+                let a = area(x, y + h2, x + w2, y + h) + area(x + w2, y, x + w, y + h2);
+                let b = area(x, y, x + w2, y + h2) + area(x + w2, y + h2, x + w, y + h);
+                subtract_log(a, b)
+            }
+            4 => {
+                let h3 = h / 3;
+                let a = area(x, y + h3, x + w, y + 2 * h3);
+                let b = area(x, y, x + w, y + h3) + area(x, y + 2 * h3, x + w, y + h);
+                subtract_log(a, b)
+            }
+            _ => {
+                let w3 = w / 3;
+                let a = area(x + w3, y, x + 2 * w3, y + h);
+                let b = area(x, y, x + w3, y + h) + area(x + 2 * w3, y, x + w, y + h);
+                subtract_log(a, b)
+            }
+        }
+    }
+
+    fn classify(&self, image: &IntegralImage, x: usize) -> usize {
+        let value = self.filter(image, x);
+        let [t0, t1, t2] = self.thresholds;
+        if value < t1 {
+            if value < t0 {
+                0
+            } else {
+                1
+            }
+        } else if value < t2 {
+            2
+        } else {
+            3
+        }
+    }
+}
+
+fn raw_fingerprint(features: &[[f64; 12]]) -> Vec<u32> {
+    let max_width = CLASSIFIERS.iter().map(|c| c.width).max().unwrap_or(1);
+    let image = IntegralImage::new(features);
+    (0..(features.len() + 1).saturating_sub(max_width))
+        .map(|x| {
+            CLASSIFIERS.iter().fold(0u32, |bits, c| {
+                (bits << 2) | GRAY_CODE[c.classify(&image, x)]
+            })
+        })
+        .collect()
+}
+
+/// Pack small integers LSB first, `bits` bits each
+fn pack(values: &[u8], bits: u32, out: &mut Vec<u8>) {
+    let mut acc: u32 = 0;
+    let mut filled = 0u32;
+    for v in values {
+        acc |= (*v as u32) << filled;
+        filled += bits;
+        while filled >= 8 {
+            out.push((acc & 0xff) as u8);
+            acc >>= 8;
+            filled -= 8;
+        }
+    }
+    if filled > 0 {
+        out.push((acc & 0xff) as u8);
+    }
+}
+
+/// Compressed, base64 encoded form as produced by fpcalc
+pub fn encode(raw: &[u32]) -> String {
+    let mut normal: Vec<u8> = Vec::new();
+    let mut exceptional: Vec<u8> = Vec::new();
+    for (i, value) in raw.iter().enumerate() {
+        let mut x = if i > 0 { value ^ raw[i - 1] } else { *value };
+        let mut last_bit = 0u32;
+        let mut bit = 1u32;
+        while x != 0 {
+            if x & 1 != 0 {
+                let delta = bit - last_bit;
+                if delta >= 7 {
+                    normal.push(7);
+                    exceptional.push((delta - 7) as u8);
+                } else {
+                    normal.push(delta as u8);
+                }
+                last_bit = bit;
+            }
+            x >>= 1;
+            bit += 1;
+        }
+        normal.push(0);
+    }
+
+    let len = raw.len() as u32;
+    let mut out = vec![
+        ALGORITHM,
+        ((len >> 16) & 0xff) as u8,
+        ((len >> 8) & 0xff) as u8,
+        (len & 0xff) as u8,
+    ];
+    pack(&normal, 3, &mut out);
+    pack(&exceptional, 5, &mut out);
+    b64_gp::URL_SAFE_NO_PAD.encode(out)
+}
+
+/// Unpack `count` values of `bits` bits, LSB first
+fn unpack(bytes: &[u8], bits: u32, count: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(count);
+    let mut acc: u32 = 0;
+    let mut filled = 0u32;
+    let mut bytes = bytes.iter();
+    while out.len() < count {
+        while filled < bits {
+            acc |= (*bytes.next()? as u32) << filled;
+            filled += 8;
+        }
+        out.push((acc & ((1 << bits) - 1)) as u8);
+        acc >>= bits;
+        filled -= bits;
+    }
+    Some(out)
+}
+
+/// Inverse of `encode`, also accepts fingerprints written by fpcalc or Picard
+pub fn decode(encoded: &str) -> Option<Vec<u32>> {
+    let bytes = b64_gp::URL_SAFE_NO_PAD
+        .decode(encoded.trim().trim_end_matches('='))
+        .ok()?;
+    if bytes.len() < 4 {
+        return None;
+    }
+    let len = u32::from_be_bytes([0, bytes[1], bytes[2], bytes[3]]) as usize;
+    let body = &bytes[4..];
+
+    // normal values run until every item has seen its terminating zero
+    let mut normal: Vec<u8> = Vec::new();
+    let mut terminators = 0usize;
+    let mut acc: u32 = 0;
+    let mut filled = 0u32;
+    let mut consumed = 0usize;
+    while terminators < len {
+        if filled < 3 {
+            acc |= (*body.get(consumed)? as u32) << filled;
+            consumed += 1;
+            filled += 8;
+        }
+        let value = (acc & 0x07) as u8;
+        acc >>= 3;
+        filled -= 3;
+        if value == 0 {
+            terminators += 1;
+        }
+        normal.push(value);
+    }
+    let exceptional_count = normal.iter().filter(|v| **v == 7).count();
+    let mut exceptional = unpack(&body[consumed..], 5, exceptional_count)?.into_iter();
+
+    let mut raw = Vec::with_capacity(len);
+    let mut x = 0u32;
+    let mut last_bit = 0u32;
+    for value in normal {
+        if value == 0 {
+            let previous = raw.last().copied().unwrap_or(0);
+            raw.push(x ^ previous);
+            x = 0;
+            last_bit = 0;
+            continue;
+        }
+        let mut delta = value as u32;
+        if value == 7 {
+            delta += exceptional.next()? as u32;
+        }
+        last_bit += delta;
+        if last_bit > 32 {
+            return None;
+        }
+        x |= 1 << (last_bit - 1);
+    }
+    Some(raw)
+}
+
+/// Fingerprint the first two minutes of a file. Returns None if cancelled
+pub fn fingerprint_file(path: &Path, cancel: &AtomicBool) -> Result<Option<Fingerprint>, String> {
+    let mut stream = PcmStream::open(path)?;
+    let mut resampler: Option<Resampler> = None;
+    let mut resampled: Vec<f64> = Vec::new();
+    let mut mono: Vec<f64> = Vec::new();
+    let limit = (MAX_SECONDS * SAMPLE_RATE as u64) as usize;
+
+    while resampled.len() < limit && stream.next_chunk()? {
+        if cancel.load(Ordering::SeqCst) {
+            return Ok(None);
+        }
+        let channels = stream.channels.max(1);
+        let resampler = resampler.get_or_insert_with(|| Resampler::new(stream.sample_rate));
+        mono.clear();
+        for frame in stream.samples().chunks_exact(channels) {
+            mono.push(frame.iter().map(|s| *s as f64).sum::<f64>() / channels as f64);
+        }
+        resampler.push(&mono, &mut resampled);
+    }
+    resampled.truncate(limit);
+
+    let raw = raw_fingerprint(&chroma_features(&resampled));
+    if raw.is_empty() {
+        return Err("Audio is too short to fingerprint".to_string());
+    }
+    // like fpcalc, report the length of the whole track rather than the analyzed part
+    let duration_ms = read_properties(path)
+        .map(|p| p.duration_ms)
+        .filter(|ms| *ms > 0)
+        .unwrap_or(resampled.len() as u64 * 1000 / SAMPLE_RATE as u64);
+    Ok(Some(Fingerprint {
+        encoded: encode(&raw),
+        duration_ms,
+        raw,
+    }))
+}
+
+/// Share of matching bits at the best alignment of two raw fingerprints, 0.0 to 1.0
+pub fn similarity(a: &[u32], b: &[u32]) -> f64 {
+    const MAX_OFFSET: isize = 80;
+    const MIN_OVERLAP: usize = 30;
+    let mut best = 0.0f64;
+    for offset in -MAX_OFFSET..=MAX_OFFSET {
+        let (a_start, b_start) = if offset >= 0 {
+            (offset as usize, 0)
+        } else {
+            (0, (-offset) as usize)
+        };
+        if a_start >= a.len() || b_start >= b.len() {
+            continue;
+        }
+        let overlap = (a.len() - a_start).min(b.len() - b_start);
+        if overlap < MIN_OVERLAP {
+            continue;
+        }
+        let errors: u32 = (0..overlap)
+            .map(|i| (a[a_start + i] ^ b[b_start + i]).count_ones())
+            .sum();
+        let score = 1.0 - errors as f64 / (overlap as f64 * 32.0);
+        best = best.max(score);
+    }
+    best
+}
+
+pub fn raw_to_bytes(raw: &[u32]) -> Vec<u8> {
+    raw.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+pub fn raw_from_bytes(bytes: &[u8]) -> Vec<u32> {
+    bytes
+        .chunks_exact(4)
+        .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+        .collect()
+}
+
+/// Keep the fingerprint around so duplicates can be found without decoding again. Only indexed
+/// files have their fingerprint stored, files in the workspace keep it in their tag
+pub async fn store_fingerprint(
+    db: &Database,
+    path: &str,
+    fingerprint: &Fingerprint,
+) -> Result<(), sqlx::Error> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    sqlx::query(
+        "INSERT INTO fingerprints (file_path, fingerprint, raw, duration_ms, created_at)
+         SELECT ?1, ?2, ?3, ?4, ?5 WHERE EXISTS (SELECT 1 FROM files WHERE path = ?1)
+         ON CONFLICT(file_path) DO UPDATE SET
+            fingerprint = excluded.fingerprint,
+            raw = excluded.raw,
+            duration_ms = excluded.duration_ms,
+            created_at = excluded.created_at",
+    )
+    .bind(path)
+    .bind(&fingerprint.encoded)
+    .bind(raw_to_bytes(&fingerprint.raw))
+    .bind(fingerprint.duration_ms as i64)
+    .bind(now)
+    .execute(&db.pool)
+    .await?;
+    Ok(())
+}
+
+fn fingerprint_error(path: &str, message: String) -> BackendError {
+    BackendError::ReadFailed(TagError {
+        path: path.to_string(),
+        public_message: format!("Fingerprinting failed: {}", message),
+        internal_message: message,
+    })
+}
+
+/// Fingerprint files, write the AcoustID fingerprint tag and store it in the database once the
+/// tag is written
+pub fn run_fingerprints(job: Job, app_handle: AppHandle, paths: Vec<String>) {
+    let backend = DefaultBackend::new();
+    let db = app_handle.state::<AppState>().db.clone();
+    let mut errors: Vec<BackendError> = Vec::new();
+    let mut written: Vec<String> = Vec::new();
+
+    for path in paths.iter() {
+        let fingerprint = match fingerprint_file(Path::new(path), job.cancel_flag()) {
+            Ok(Some(fingerprint)) => fingerprint,
+            Ok(None) => break,
+            Err(e) => {
+                errors.push(fingerprint_error(path, e));
+                job.advance(path);
+                continue;
+            }
+        };
+
+        let mut tags = HashMap::new();
+        tags.insert(
+            FrameKey::AcoustidFingerprint,
+            vec![SerializableTagValue::Text(fingerprint.encoded.clone())],
+        );
+        let report = write_batch(
+            &backend,
+            &[Changes {
+                paths: vec![path.clone()],
+                tags,
+            }],
+        );
+        // only a fingerprint the file carries is stored
+        if report.committed {
+            if let Err(e) = async_runtime::block_on(store_fingerprint(&db, path, &fingerprint)) {
+                errors.push(fingerprint_error(path, e.to_string()));
+            }
+            written.push(path.clone());
+        }
+        errors.extend(report.errors());
+        job.advance(path);
+    }
+
+    if !written.is_empty() {
+        refresh_files(app_handle.clone(), written);
+    }
+    if !errors.is_empty() {
+        let _ = app_handle.emit("error", errors);
+    }
+    job.finish();
+}
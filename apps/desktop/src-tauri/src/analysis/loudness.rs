@@ -0,0 +1,273 @@
+// ITU-R BS.1770-4 / EBU R128 loudness measurement
+use std::f64::consts::PI;
+
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+const RANGE_RELATIVE_GATE: f64 = -20.0;
+
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self {
+            b,
+            a,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z1;
+        self.z1 = self.b[1] * x - self.a[1] * y + self.z2;
+        self.z2 = self.b[2] * x - self.a[2] * y;
+        y
+    }
+}
+
+/// Pre-filter (high shelf) and RLB high-pass, recalculated for the actual sample rate
+fn k_weighting(sample_rate: u32) -> (Biquad, Biquad) {
+    let rate = sample_rate as f64;
+
+    let f0 = 1681.974450955533;
+    let g = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(g / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    (shelf, high_pass)
+}
+
+/// Polyphase interpolator used to estimate inter-sample peaks
+struct TruePeak {
+    factor: usize,
+    phases: Vec<Vec<f64>>,
+    history: Vec<Vec<f64>>,
+    pos: usize,
+    peak: f64,
+}
+
+impl TruePeak {
+    fn new(sample_rate: u32, channels: usize) -> Self {
+        let factor = if sample_rate < 96000 {
+            4
+        } else if sample_rate < 192000 {
+            2
+        } else {
+            1
+        };
+        let taps_per_phase = 12;
+        let total = taps_per_phase * factor;
+        let center = (total as f64 - 1.0) / 2.0;
+        let mut phases = vec![vec![0.0; taps_per_phase]; factor];
+        for n in 0..total {
+            let x = (n as f64 - center) / factor as f64;
+            let sinc = if x.abs() < 1e-9 {
+                1.0
+            } else {
+                (PI * x).sin() / (PI * x)
+            };
+            let window = 0.5 * (1.0 - (2.0 * PI * (n as f64 + 1.0) / (total as f64 + 1.0)).cos());
+            phases[n % factor][n / factor] = sinc * window;
+        }
+        Self {
+            factor,
+            phases,
+            history: vec![vec![0.0; taps_per_phase]; channels],
+            pos: 0,
+            peak: 0.0,
+        }
+    }
+
+    fn process(&mut self, frame: &[f32]) {
+        let taps = self.phases[0].len();
+        self.pos = (self.pos + taps - 1) % taps;
+        for (ch, sample) in frame.iter().enumerate() {
+            let history = &mut self.history[ch];
+            history[self.pos] = *sample as f64;
+            if self.factor == 1 {
+                self.peak = self.peak.max((*sample as f64).abs());
+                continue;
+            }
+            for phase in self.phases.iter() {
+                let mut acc = 0.0;
+                for (k, c) in phase.iter().enumerate() {
+                    acc += c * history[(self.pos + k) % taps];
+                }
+                self.peak = self.peak.max(acc.abs());
+            }
+        }
+    }
+}
+
+/// Streaming loudness meter. Keeps the gating block energies so several tracks can be
+/// combined into an album measurement
+pub struct LoudnessMeter {
+    channels: usize,
+    weights: Vec<f64>,
+    filters: Vec<(Biquad, Biquad)>,
+    step_len: usize,
+    step_pos: usize,
+    step_sum: f64,
+    steps: Vec<f64>,
+    steps_since_short_term: usize,
+    sample_peak: f64,
+    true_peak: TruePeak,
+    pub blocks: Vec<f64>,
+    pub short_term_blocks: Vec<f64>,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: u32, channels: usize, weights: Vec<f64>) -> Self {
+        let filters = (0..channels).map(|_| k_weighting(sample_rate)).collect();
+        Self {
+            channels,
+            weights,
+            filters,
+            step_len: (sample_rate as usize / 10).max(1),
+            step_pos: 0,
+            step_sum: 0.0,
+            steps: Vec::new(),
+            steps_since_short_term: 0,
+            sample_peak: 0.0,
+            true_peak: TruePeak::new(sample_rate, channels),
+            blocks: Vec::new(),
+            short_term_blocks: Vec::new(),
+        }
+    }
+
+    /// Feed interleaved samples
+    pub fn process(&mut self, samples: &[f32]) {
+        if self.channels == 0 {
+            return;
+        }
+        for frame in samples.chunks_exact(self.channels) {
+            self.true_peak.process(frame);
+            let mut energy = 0.0;
+            for (ch, sample) in frame.iter().enumerate() {
+                let x = *sample as f64;
+                self.sample_peak = self.sample_peak.max(x.abs());
+                let (shelf, high_pass) = &mut self.filters[ch];
+                let y = high_pass.process(shelf.process(x));
+                energy += self.weights.get(ch).copied().unwrap_or(1.0) * y * y;
+            }
+            self.step_sum += energy;
+            self.step_pos += 1;
+            if self.step_pos == self.step_len {
+                self.end_step();
+            }
+        }
+    }
+
+    // 400ms momentary blocks and 3s short-term blocks overlap, so both are built from 100ms steps
+    fn end_step(&mut self) {
+        self.steps.push(self.step_sum / self.step_len as f64);
+        self.step_sum = 0.0;
+        self.step_pos = 0;
+
+        let n = self.steps.len();
+        if n >= 4 {
+            let block = self.steps[n - 4..].iter().sum::<f64>() / 4.0;
+            self.blocks.push(block);
+        }
+        self.steps_since_short_term += 1;
+        if n >= 30 && self.steps_since_short_term >= 10 {
+            let block = self.steps[n - 30..].iter().sum::<f64>() / 30.0;
+            self.short_term_blocks.push(block);
+            self.steps_since_short_term = 0;
+        }
+        if n > 30 {
+            self.steps.drain(..n - 30);
+        }
+    }
+
+    pub fn integrated(&self) -> f64 {
+        gated_loudness(&self.blocks)
+    }
+
+    pub fn range(&self) -> f64 {
+        loudness_range(&self.short_term_blocks)
+    }
+
+    /// Largest of sample peak and the oversampled true peak, as linear amplitude
+    pub fn peak(&self) -> f64 {
+        self.sample_peak.max(self.true_peak.peak)
+    }
+}
+
+pub fn energy_to_loudness(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+fn loudness_to_energy(loudness: f64) -> f64 {
+    10f64.powf((loudness + 0.691) / 10.0)
+}
+
+/// Integrated loudness in LUFS with absolute and relative gating
+pub fn gated_loudness(blocks: &[f64]) -> f64 {
+    let absolute = loudness_to_energy(ABSOLUTE_GATE);
+    let above: Vec<f64> = blocks.iter().copied().filter(|e| *e > absolute).collect();
+    if above.is_empty() {
+        return f64::NEG_INFINITY;
+    }
+    let mean = above.iter().sum::<f64>() / above.len() as f64;
+    let relative = loudness_to_energy(energy_to_loudness(mean) + RELATIVE_GATE);
+    let gated: Vec<f64> = above.into_iter().filter(|e| *e > relative).collect();
+    if gated.is_empty() {
+        return f64::NEG_INFINITY;
+    }
+    energy_to_loudness(gated.iter().sum::<f64>() / gated.len() as f64)
+}
+
+/// Loudness range in LU (EBU Tech 3342)
+pub fn loudness_range(short_term_blocks: &[f64]) -> f64 {
+    let absolute = loudness_to_energy(ABSOLUTE_GATE);
+    let above: Vec<f64> = short_term_blocks
+        .iter()
+        .copied()
+        .filter(|e| *e > absolute)
+        .collect();
+    if above.is_empty() {
+        return 0.0;
+    }
+    let mean = above.iter().sum::<f64>() / above.len() as f64;
+    let relative = loudness_to_energy(energy_to_loudness(mean) + RANGE_RELATIVE_GATE);
+    let mut gated: Vec<f64> = above
+        .into_iter()
+        .filter(|e| *e > relative)
+        .map(energy_to_loudness)
+        .collect();
+    if gated.len() < 2 {
+        return 0.0;
+    }
+    gated.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let last = (gated.len() - 1) as f64;
+    let low = gated[(last * 0.10).round() as usize];
+    let high = gated[(last * 0.95).round() as usize];
+    high - low
+}
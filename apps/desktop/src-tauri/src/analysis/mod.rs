@@ -0,0 +1,4 @@
+pub mod decode;
+pub mod fingerprint;
+pub mod loudness;
+pub mod replaygain;
@@ -0,0 +1,255 @@
+use crate::analysis::decode::{OpenError, PcmStream};
+use crate::analysis::loudness::{gated_loudness, loudness_range, LoudnessMeter};
+use crate::jobs::Job;
+use crate::library::albums::{album_key, AlbumKey};
+use crate::tag_manager::batch::write_batch;
+use crate::tag_manager::tag_backend::{BackendError, DefaultBackend, TagBackend, TagError};
+use crate::tag_manager::utils::{Changes, FrameKey, SerializableTagValue, TagValue};
+use crate::utils::refresh_files;
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri::{AppHandle, Emitter};
+
+/// ReplayGain 2.0 reference level
+pub const REFERENCE_LOUDNESS: f64 = -18.0;
+
+pub struct TrackLoudness {
+    pub integrated: f64,
+    pub peak: f64,
+    pub range: f64,
+    blocks: Vec<f64>,
+    short_term_blocks: Vec<f64>,
+}
+
+/// Decode a whole file and measure it. Returns None if the job was cancelled midway
+pub fn analyze_track(path: &Path, cancel: &AtomicBool) -> Result<Option<TrackLoudness>, OpenError> {
+    let mut stream = PcmStream::open(path)?;
+    let mut meter: Option<LoudnessMeter> = None;
+
+    while stream.next_chunk().map_err(OpenError::Failed)? {
+        if cancel.load(Ordering::SeqCst) {
+            return Ok(None);
+        }
+        // the channel layout is only reliable once the first packet has been decoded
+        let meter = meter.get_or_insert_with(|| {
+            LoudnessMeter::new(
+                stream.sample_rate,
+                stream.channels,
+                stream.channel_weights(),
+            )
+        });
+        meter.process(stream.samples());
+    }
+
+    let Some(meter) = meter else {
+        return Err(OpenError::Failed("No audio could be decoded".to_string()));
+    };
+    let integrated = meter.integrated();
+    if !integrated.is_finite() {
+        return Err(OpenError::Failed("Track is silent".to_string()));
+    }
+
+    Ok(Some(TrackLoudness {
+        integrated,
+        peak: meter.peak(),
+        range: meter.range(),
+        blocks: meter.blocks,
+        short_term_blocks: meter.short_term_blocks,
+    }))
+}
+
+/// Album loudness is measured over the blocks of all tracks, not averaged per track
+pub fn album_loudness(tracks: &[&TrackLoudness]) -> TrackLoudness {
+    let blocks: Vec<f64> = tracks.iter().flat_map(|t| t.blocks.clone()).collect();
+    let short_term_blocks: Vec<f64> = tracks
+        .iter()
+        .flat_map(|t| t.short_term_blocks.clone())
+        .collect();
+    TrackLoudness {
+        integrated: gated_loudness(&blocks),
+        peak: tracks.iter().map(|t| t.peak).fold(0.0, f64::max),
+        range: loudness_range(&short_term_blocks),
+        blocks,
+        short_term_blocks,
+    }
+}
+
+fn text(value: String) -> Vec<SerializableTagValue> {
+    vec![SerializableTagValue::Text(value)]
+}
+
+fn track_values(track: &TrackLoudness) -> HashMap<FrameKey, Vec<SerializableTagValue>> {
+    let mut tags = HashMap::new();
+    tags.insert(
+        FrameKey::ReplayGainTrackGain,
+        text(format!("{:.2} dB", REFERENCE_LOUDNESS - track.integrated)),
+    );
+    tags.insert(
+        FrameKey::ReplayGainTrackPeak,
+        text(format!("{:.6}", track.peak)),
+    );
+    tags.insert(
+        FrameKey::ReplayGainTrackRange,
+        text(format!("{:.2} dB", track.range)),
+    );
+    tags.insert(
+        FrameKey::ReplayGainReferenceLoudness,
+        text(format!("{:.2} LUFS", REFERENCE_LOUDNESS)),
+    );
+    tags
+}
+
+fn album_values(album: &TrackLoudness) -> HashMap<FrameKey, Vec<SerializableTagValue>> {
+    let mut tags = HashMap::new();
+    tags.insert(
+        FrameKey::ReplayGainAlbumGain,
+        text(format!("{:.2} dB", REFERENCE_LOUDNESS - album.integrated)),
+    );
+    tags.insert(
+        FrameKey::ReplayGainAlbumPeak,
+        text(format!("{:.6}", album.peak)),
+    );
+    tags.insert(
+        FrameKey::ReplayGainAlbumRange,
+        text(format!("{:.2} dB", album.range)),
+    );
+    tags
+}
+
+fn first_text(tags: &HashMap<FrameKey, Vec<TagValue>>, key: FrameKey) -> String {
+    match tags.get(&key).and_then(|v| v.first()) {
+        Some(TagValue::Text(s)) => s.trim().to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Groups paths into albums the way the album view does. Files without an album only get
+/// track gain
+fn group_by_album(backend: &DefaultBackend, paths: &[String]) -> Vec<(bool, Vec<String>)> {
+    let mut groups: Vec<(bool, Vec<String>)> = Vec::new();
+    let mut index: HashMap<AlbumKey, usize> = HashMap::new();
+    for path in paths {
+        let key = backend
+            .read(&PathBuf::from(path))
+            .ok()
+            .and_then(|file| album_key(|k| Some(first_text(&file.tags, k))));
+        let Some(key) = key else {
+            groups.push((false, vec![path.clone()]));
+            continue;
+        };
+        match index.get(&key) {
+            Some(i) => groups[*i].1.push(path.clone()),
+            None => {
+                index.insert(key, groups.len());
+                groups.push((true, vec![path.clone()]));
+            }
+        }
+    }
+    groups
+}
+
+fn analysis_error(path: &str, message: String) -> BackendError {
+    BackendError::ReadFailed(TagError {
+        path: path.to_string(),
+        public_message: format!("Loudness analysis failed: {}", message),
+        internal_message: message,
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedFile {
+    pub path: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayGainReport {
+    pub job_id: String,
+    pub written: Vec<String>,
+    /// Files in a codec that can't be decoded, Opus for now, left without tags
+    pub skipped: Vec<SkippedFile>,
+}
+
+/// Analyze files album by album and write ReplayGain 2.0 tags. The report is sent on
+/// "replay-gain-finished"
+pub fn run_replay_gain(job: Job, app_handle: AppHandle, paths: Vec<String>) {
+    let backend = DefaultBackend::new();
+    let mut errors: Vec<BackendError> = Vec::new();
+    let mut written: Vec<String> = Vec::new();
+    let mut skipped: Vec<SkippedFile> = Vec::new();
+
+    'groups: for (is_album, group) in group_by_album(&backend, &paths) {
+        let mut measured: Vec<(String, TrackLoudness)> = Vec::new();
+        let mut failed = false;
+        for path in group.iter() {
+            match analyze_track(Path::new(path), job.cancel_flag()) {
+                Ok(Some(track)) => measured.push((path.clone(), track)),
+                Ok(None) => break 'groups,
+                // album gain left out too, it would not cover the whole album
+                Err(OpenError::Unsupported(reason)) => {
+                    failed = true;
+                    skipped.push(SkippedFile {
+                        path: path.clone(),
+                        reason,
+                    });
+                }
+                Err(OpenError::Failed(e)) => {
+                    failed = true;
+                    errors.push(analysis_error(path, e));
+                }
+            }
+            job.advance(path);
+        }
+
+        let album = if is_album && !failed && !measured.is_empty() {
+            let tracks: Vec<&TrackLoudness> = measured.iter().map(|(_, t)| t).collect();
+            Some(album_loudness(&tracks))
+        } else {
+            None
+        };
+
+        // an album is tagged as a whole, so its album gain never ends up on only some tracks
+        let batch: Vec<Changes> = measured
+            .iter()
+            .map(|(path, track)| {
+                let mut tags = track_values(track);
+                if let Some(album) = &album {
+                    tags.extend(album_values(album));
+                }
+                Changes {
+                    paths: vec![path.clone()],
+                    tags,
+                }
+            })
+            .collect();
+        if batch.is_empty() {
+            continue;
+        }
+        let report = write_batch(&backend, &batch);
+        if report.committed {
+            written.extend(measured.into_iter().map(|(path, _)| path));
+        } else {
+            errors.extend(report.errors());
+        }
+    }
+
+    if !written.is_empty() {
+        refresh_files(app_handle.clone(), written.clone());
+    }
+    if !errors.is_empty() {
+        let _ = app_handle.emit("error", errors);
+    }
+    let _ = app_handle.emit(
+        "replay-gain-finished",
+        ReplayGainReport {
+            job_id: job.id.clone(),
+            written,
+            skipped,
+        },
+    );
+    job.finish();
+}
@@ -0,0 +1,331 @@
+use crate::artwork::process::encode_jpeg;
+use crate::artwork::{front_picture, FRONT_COVER};
+use crate::history::{current_files, Action, CoverChange, HistoryActionType};
+use crate::jobs::Job;
+use crate::tag_manager::batch::write_batch;
+use crate::tag_manager::picture::picture_dimensions;
+use crate::tag_manager::tag_backend::{BackendError, DefaultBackend};
+use crate::tag_manager::utils::{Changes, FrameKey, TagValue, TagValuesWrapper};
+use crate::utils::{is_supported_file, refresh_files};
+use crate::AppState;
+
+use image::ImageFormat;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{async_runtime, AppHandle, Emitter, Manager};
+
+const IMAGE_EXTENSIONS: [&str; 6] = ["jpg", "jpeg", "png", "webp", "bmp", "gif"];
+/// Quality used when a cover has to become a JPEG
+const JPEG_QUALITY: u8 = 90;
+
+/// Names players look for next to the tracks
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum CoverFileName {
+    #[default]
+    #[serde(rename = "folder.jpg")]
+    Folder,
+    #[serde(rename = "cover.jpg")]
+    Cover,
+    #[serde(rename = "AlbumArt.jpg")]
+    AlbumArt,
+}
+
+impl CoverFileName {
+    fn as_str(&self) -> &'static str {
+        match self {
+            CoverFileName::Folder => "folder.jpg",
+            CoverFileName::Cover => "cover.jpg",
+            CoverFileName::AlbumArt => "AlbumArt.jpg",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportCoverReport {
+    pub written: Vec<String>,
+    /// Targets that were already there and not overwritten
+    pub existing: Vec<String>,
+    /// Folders none of the files had a picture for
+    pub no_cover: Vec<String>,
+    pub failed: Vec<FailedFolder>,
+}
+
+/// A folder that was skipped, the others still went ahead
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedFolder {
+    pub folder: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderArt {
+    pub folder: String,
+    /// The sidecar image that was picked
+    pub image: String,
+    pub width: u32,
+    pub height: u32,
+    /// Tracks the image was embedded into
+    pub embedded: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportFolderArtReport {
+    pub job_id: String,
+    pub folders: Vec<FolderArt>,
+    /// Folders without a usable sidecar image
+    pub no_image: Vec<String>,
+    pub failed: Vec<FailedFolder>,
+}
+
+fn mime_for(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Png => "image/png",
+        _ => "image/jpeg",
+    }
+}
+
+/// Write the front cover of the files to a sidecar image in each of their folders
+pub async fn export_covers(
+    state: &AppState,
+    paths: Vec<String>,
+    name: CoverFileName,
+    overwrite: bool,
+) -> Result<ExportCoverReport, String> {
+    let mut folders: BTreeMap<PathBuf, Option<Vec<u8>>> = BTreeMap::new();
+    for file in current_files(state, &paths).await {
+        let Some(folder) = file.path.parent() else {
+            continue;
+        };
+        let cover = folders.entry(folder.to_path_buf()).or_default();
+        if cover.is_some() {
+            continue;
+        }
+        if let Some(TagValue::Picture { data, .. }) = front_picture(&file.tags) {
+            *cover = Some(data.clone());
+        }
+    }
+
+    let mut report = ExportCoverReport::default();
+    for (folder, cover) in folders {
+        let Some(data) = cover else {
+            report.no_cover.push(folder.to_string_lossy().to_string());
+            continue;
+        };
+        let target = folder.join(name.as_str());
+        let target_str = target.to_string_lossy().to_string();
+        if target.exists() && !overwrite {
+            report.existing.push(target_str);
+            continue;
+        }
+        match write_sidecar(&target, data) {
+            Ok(()) => report.written.push(target_str),
+            Err(error) => report.failed.push(FailedFolder {
+                folder: folder.to_string_lossy().to_string(),
+                error,
+            }),
+        }
+    }
+    Ok(report)
+}
+
+fn write_sidecar(target: &Path, data: Vec<u8>) -> Result<(), String> {
+    // the names all say .jpg
+    let data = match image::guess_format(&data) {
+        Ok(ImageFormat::Jpeg) => data,
+        _ => {
+            let image = image::load_from_memory(&data).map_err(|e| e.to_string())?;
+            encode_jpeg(&image, JPEG_QUALITY)?
+        }
+    };
+    fs::write(target, data).map_err(|e| e.to_string())
+}
+
+/// Whether the file name looks like album art: folder.*, cover.*, front.*, AlbumArt*, *cover*
+fn is_cover_name(path: &Path) -> bool {
+    let is_image = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| IMAGE_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+        .unwrap_or(false);
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    is_image
+        && (stem == "folder"
+            || stem.starts_with("front")
+            || stem.starts_with("albumart")
+            || stem.contains("cover"))
+}
+
+/// The largest cover-like image in `folder`
+fn best_sidecar(folder: &Path) -> Option<(PathBuf, Vec<u8>, u32, u32)> {
+    let mut best: Option<(PathBuf, Vec<u8>, u32, u32)> = None;
+    for entry in fs::read_dir(folder).ok()?.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if !path.is_file() || !is_cover_name(&path) {
+            continue;
+        }
+        let Ok(data) = fs::read(&path) else {
+            continue;
+        };
+        let Some((width, height)) = picture_dimensions(&data) else {
+            continue;
+        };
+        let area = width as u64 * height as u64;
+        if best
+            .as_ref()
+            .map(|(_, _, w, h)| area > *w as u64 * *h as u64)
+            .unwrap_or(true)
+        {
+            best = Some((path, data, width, height));
+        }
+    }
+    best
+}
+
+/// The sidecar image as a front cover, WebP, BMP and GIF ones as JPEG since a lot of players
+/// don't show them
+fn sidecar_picture(data: Vec<u8>) -> Result<TagValue, String> {
+    let format = image::guess_format(&data).map_err(|e| e.to_string())?;
+    let data = match format {
+        ImageFormat::Jpeg | ImageFormat::Png => data,
+        _ => {
+            let image = image::load_from_memory(&data).map_err(|e| e.to_string())?;
+            encode_jpeg(&image, JPEG_QUALITY)?
+        }
+    };
+    Ok(TagValue::Picture {
+        mime: mime_for(format).to_string(),
+        data,
+        picture_type: Some(FRONT_COVER),
+        description: None,
+    })
+}
+
+/// Embed the best sidecar image of each folder into the tracks of that folder that have no
+/// picture. Everything written goes in as one history entry, folders that fail are reported
+/// and the others still go ahead
+pub fn import_folder_art(
+    app_handle: &AppHandle,
+    job: &Job,
+    folders: Vec<String>,
+) -> ImportFolderArtReport {
+    let state = app_handle.state::<AppState>();
+    let backend = DefaultBackend::new();
+    let mut report = ImportFolderArtReport {
+        job_id: job.id.clone(),
+        ..Default::default()
+    };
+    let mut errors: Vec<BackendError> = Vec::new();
+    let mut history: HashMap<String, CoverChange> = HashMap::new();
+
+    for folder in folders {
+        if job.is_cancelled() {
+            break;
+        }
+        let Some((image_path, data, width, height)) = best_sidecar(Path::new(&folder)) else {
+            job.advance(&folder);
+            report.no_image.push(folder);
+            continue;
+        };
+        let prepared = sidecar_picture(data).and_then(|picture| {
+            let tracks: Vec<String> = fs::read_dir(&folder)
+                .map_err(|e| e.to_string())?
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.is_file() && is_supported_file(p))
+                .map(|p| p.to_string_lossy().to_string())
+                .collect();
+            Ok((picture, tracks))
+        });
+        let (picture, tracks) = match prepared {
+            Ok(prepared) => prepared,
+            Err(error) => {
+                job.advance(&folder);
+                report.failed.push(FailedFolder { folder, error });
+                continue;
+            }
+        };
+        let mut changes: Vec<(String, CoverChange)> = Vec::new();
+        for file in async_runtime::block_on(current_files(&state, &tracks)) {
+            let has_picture = file
+                .tags
+                .get(&FrameKey::AttachedPicture)
+                .map(|v| !v.is_empty())
+                .unwrap_or(false);
+            if has_picture {
+                continue;
+            }
+            let path = file.path.to_string_lossy().to_string();
+            let change = CoverChange {
+                before: Vec::new(),
+                after: TagValuesWrapper(vec![picture.clone()]).into(),
+            };
+            changes.push((path, change));
+        }
+        // the tracks of a folder get their cover together or not at all
+        let batch: Vec<Changes> = changes
+            .iter()
+            .map(|(path, change)| Changes {
+                paths: vec![path.clone()],
+                tags: HashMap::from([(FrameKey::AttachedPicture, change.after.clone())]),
+            })
+            .collect();
+        let mut embedded = Vec::new();
+        if !batch.is_empty() {
+            let written = write_batch(&backend, &batch);
+            if written.committed {
+                for (path, change) in changes {
+                    history.insert(path.clone(), change);
+                    embedded.push(path);
+                }
+            } else {
+                errors.extend(written.errors());
+            }
+        }
+        job.advance(&folder);
+        report.folders.push(FolderArt {
+            folder,
+            image: image_path.to_string_lossy().to_string(),
+            width,
+            height,
+            embedded,
+        });
+    }
+
+    if !history.is_empty() {
+        let written: Vec<String> = history.keys().cloned().collect();
+        state.history.lock().unwrap().add(Action {
+            action_type: HistoryActionType::Covers(history),
+        });
+        refresh_files(app_handle.clone(), written);
+    }
+    if !errors.is_empty() {
+        let _ = app_handle.emit("error", errors);
+    }
+    report
+}
+
+/// Import folder art in the background. Returns the job id, the report is sent on
+/// "folder-art-imported" when done
+pub fn start_import_folder_art(app_handle: &AppHandle, folders: Vec<String>) -> String {
+    let job =
+        app_handle
+            .state::<AppState>()
+            .jobs
+            .start(app_handle, "importFolderArt", folders.len());
+    let id = job.id.clone();
+    let app_handle = app_handle.clone();
+    async_runtime::spawn_blocking(move || {
+        let report = import_folder_art(&app_handle, &job, folders);
+        job.finish();
+        let _ = app_handle.emit("folder-art-imported", report);
+    });
+    id
+}
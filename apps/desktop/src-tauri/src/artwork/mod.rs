@@ -0,0 +1,63 @@
+pub mod folder_art;
+pub mod process;
+pub mod thumbnails;
+pub mod validate;
+
+use crate::tag_manager::batch;
+use crate::tag_manager::tag_backend::{DefaultBackend, TagBackend};
+use crate::tag_manager::utils::{FrameKey, TagValue};
+use crate::AppState;
+
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// ID3 picture type of the front cover
+pub const FRONT_COVER: u8 = 3;
+
+/// Content hash of an image, identical covers embedded in several files share it
+pub fn picture_hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The picture a file is best represented by: the front cover, else the first one
+pub fn front_picture(tags: &HashMap<FrameKey, Vec<TagValue>>) -> Option<&TagValue> {
+    let pictures = tags.get(&FrameKey::AttachedPicture)?;
+    pictures
+        .iter()
+        .find(|p| {
+            matches!(p, TagValue::Picture {
+                picture_type: Some(t),
+                ..
+            } if *t == FRONT_COVER)
+        })
+        .or_else(|| {
+            pictures
+                .iter()
+                .find(|p| matches!(p, TagValue::Picture { .. }))
+        })
+}
+
+/// Embedded pictures of a file, from the workspace when it is loaded there, else from disk
+pub fn file_pictures(state: &AppState, path: &str) -> Result<Vec<TagValue>, String> {
+    let path = PathBuf::from(path);
+    let loaded = {
+        let ws = state.workspace.lock().unwrap();
+        ws.files
+            .iter()
+            .find(|f| f.path == path)
+            .map(|f| f.tags.get(&FrameKey::AttachedPicture).cloned())
+    };
+    let pictures = match loaded {
+        Some(pictures) => pictures,
+        None => DefaultBackend::new()
+            .read(&path)
+            .map_err(|e| batch::error_message(&e))?
+            .tags
+            .remove(&FrameKey::AttachedPicture),
+    };
+    Ok(pictures.unwrap_or_default())
+}
@@ -0,0 +1,471 @@
+use crate::history::{current_files, Action, CoverChange, HistoryActionType};
+use crate::jobs::Job;
+use crate::tag_manager::batch::write_batch;
+use crate::tag_manager::tag_backend::{BackendError, DefaultBackend};
+use crate::tag_manager::utils::{Changes, FrameKey, TagValue, TagValuesWrapper};
+use crate::utils::refresh_files;
+use crate::AppState;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::imageops::FilterType;
+use image::{ImageEncoder, ImageFormat};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::{async_runtime, AppHandle, Emitter, Manager};
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum CoverFormat {
+    /// Everything becomes a JPEG at the chosen quality
+    #[default]
+    Jpeg,
+    /// PNG pictures stay PNG, anything else that isn't JPEG is converted
+    KeepPng,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverOptions {
+    /// Longest side allowed, larger pictures are scaled down
+    pub max_dimension: Option<u32>,
+    #[serde(default)]
+    pub format: CoverFormat,
+    #[serde(default = "default_quality")]
+    pub quality: u8,
+}
+
+pub fn default_quality() -> u8 {
+    85
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProcessedCover {
+    pub path: String,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedCover {
+    pub path: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverReport {
+    pub job_id: String,
+    pub processed: Vec<ProcessedCover>,
+    /// Files whose pictures were already as small as they get
+    pub unchanged: usize,
+    pub failed: Vec<FailedCover>,
+    pub bytes_saved: i64,
+}
+
+/// Copy of a JPEG without the APP1 (EXIF, XMP) and APP13 (Photoshop) segments. The image
+/// data is left untouched
+pub fn strip_jpeg_metadata(data: &[u8]) -> Vec<u8> {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return data.to_vec();
+    }
+    let mut out = vec![0xff, 0xd8];
+    let mut i = 2;
+    while i + 4 <= data.len() && data[i] == 0xff {
+        let marker = data[i + 1];
+        // fill bytes may pad the space before a marker
+        if marker == 0xff {
+            i += 1;
+            continue;
+        }
+        // the entropy coded data follows start of scan, copy the rest as is
+        if marker == 0xda {
+            break;
+        }
+        let len = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+        let end = (i + 2 + len).min(data.len());
+        if marker != 0xe1 && marker != 0xed {
+            out.extend_from_slice(&data[i..end]);
+        }
+        i = end;
+    }
+    out.extend_from_slice(&data[i.min(data.len())..]);
+    out
+}
+
+/// Copy of a PNG without the eXIf, text and time chunks
+pub fn strip_png_metadata(data: &[u8]) -> Vec<u8> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    const DROPPED: [&[u8]; 5] = [b"eXIf", b"tEXt", b"zTXt", b"iTXt", b"tIME"];
+    if !data.starts_with(SIGNATURE) {
+        return data.to_vec();
+    }
+    let mut out = SIGNATURE.to_vec();
+    let mut i = SIGNATURE.len();
+    while i + 12 <= data.len() {
+        let len = u32::from_be_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]) as usize;
+        let end = (i + 12 + len).min(data.len());
+        if !DROPPED.contains(&&data[i + 4..i + 8]) {
+            out.extend_from_slice(&data[i..end]);
+        }
+        i = end;
+    }
+    out
+}
+
+pub fn encode_jpeg(image: &image::DynamicImage, quality: u8) -> Result<Vec<u8>, String> {
+    let rgb = image.to_rgb8();
+    let mut out = Vec::new();
+    JpegEncoder::new_with_quality(&mut out, quality.clamp(1, 100))
+        .encode_image(&rgb)
+        .map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+fn encode_png(image: &image::DynamicImage) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    PngEncoder::new(&mut out)
+        .write_image(
+            image.as_bytes(),
+            image.width(),
+            image.height(),
+            image.color().into(),
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+/// Apply `options` to one picture. Returns the new MIME type and data, or None when the
+/// picture is best left as it is
+pub fn process_picture(
+    data: &[u8],
+    options: &CoverOptions,
+) -> Result<Option<(String, Vec<u8>)>, String> {
+    let format = image::guess_format(data).map_err(|e| e.to_string())?;
+    let image = image::load_from_memory_with_format(data, format).map_err(|e| e.to_string())?;
+    let too_big = options
+        .max_dimension
+        .map(|max| image.width() > max || image.height() > max)
+        .unwrap_or(false);
+    let keep_png = format == ImageFormat::Png && options.format == CoverFormat::KeepPng;
+    let reencode = too_big
+        || !matches!(format, ImageFormat::Jpeg | ImageFormat::Png)
+        || (format == ImageFormat::Png && !keep_png)
+        || (format == ImageFormat::Jpeg && options.format == CoverFormat::Jpeg);
+
+    let (mime, out) = if !reencode {
+        match format {
+            ImageFormat::Png => ("image/png", strip_png_metadata(data)),
+            _ => ("image/jpeg", strip_jpeg_metadata(data)),
+        }
+    } else {
+        let image = match options.max_dimension {
+            Some(max) if too_big => image.resize(max, max, FilterType::Lanczos3),
+            _ => image,
+        };
+        if keep_png {
+            ("image/png", encode_png(&image)?)
+        } else {
+            ("image/jpeg", encode_jpeg(&image, options.quality)?)
+        }
+    };
+
+    // a JPEG re-encoded at a higher quality can come out bigger, that's no use
+    let converted = !matches!(format, ImageFormat::Jpeg | ImageFormat::Png)
+        || (format == ImageFormat::Png && !keep_png);
+    if !too_big && !converted && out.len() >= data.len() {
+        return Ok(None);
+    }
+    Ok(Some((mime.to_string(), out)))
+}
+
+fn picture_bytes(pictures: &[TagValue]) -> u64 {
+    pictures
+        .iter()
+        .map(|p| match p {
+            TagValue::Picture { data, .. } => data.len() as u64,
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Process each picture of a file, keeping picture type and description. Pictures that fail
+/// stay as they were, with their errors returned alongside. `None` when nothing changed
+pub fn process_pictures(
+    pictures: &[TagValue],
+    options: &CoverOptions,
+) -> (Option<Vec<TagValue>>, Vec<String>) {
+    let mut changed = false;
+    let mut errors = Vec::new();
+    let mut processed = Vec::with_capacity(pictures.len());
+    for picture in pictures {
+        let TagValue::Picture {
+            data,
+            picture_type,
+            description,
+            ..
+        } = picture
+        else {
+            processed.push(picture.clone());
+            continue;
+        };
+        match process_picture(data, options) {
+            Ok(Some((mime, data))) => {
+                changed = true;
+                processed.push(TagValue::Picture {
+                    mime,
+                    data,
+                    picture_type: *picture_type,
+                    description: description.clone(),
+                });
+            }
+            Ok(None) => processed.push(picture.clone()),
+            Err(e) => {
+                errors.push(e);
+                processed.push(picture.clone());
+            }
+        }
+    }
+    (changed.then_some(processed), errors)
+}
+
+/// Resize, recompress and convert the embedded pictures of `paths`, keeping picture type and
+/// description. All files that changed are written as one batch and go in as one history entry
+pub fn process_covers(
+    app_handle: &AppHandle,
+    job: &Job,
+    paths: Vec<String>,
+    options: &CoverOptions,
+) -> CoverReport {
+    let state = app_handle.state::<AppState>();
+    let mut report = CoverReport {
+        job_id: job.id.clone(),
+        ..Default::default()
+    };
+    let mut errors: Vec<BackendError> = Vec::new();
+    let mut history: HashMap<String, CoverChange> = HashMap::new();
+    let mut batch: Vec<Changes> = Vec::new();
+    let mut processed_covers: Vec<ProcessedCover> = Vec::new();
+
+    for file in async_runtime::block_on(current_files(&state, &paths)) {
+        if job.is_cancelled() {
+            break;
+        }
+        let path = file.path.to_string_lossy().to_string();
+        let pictures = file
+            .tags
+            .get(&FrameKey::AttachedPicture)
+            .cloned()
+            .unwrap_or_default();
+
+        let (processed, failed) = process_pictures(&pictures, options);
+        report
+            .failed
+            .extend(failed.into_iter().map(|error| FailedCover {
+                path: path.clone(),
+                error,
+            }));
+        let Some(processed) = processed else {
+            report.unchanged += 1;
+            job.advance(&path);
+            continue;
+        };
+
+        let change = CoverChange {
+            before: TagValuesWrapper(pictures.clone()).into(),
+            after: TagValuesWrapper(processed.clone()).into(),
+        };
+        batch.push(Changes {
+            paths: vec![path.clone()],
+            tags: HashMap::from([(FrameKey::AttachedPicture, change.after.clone())]),
+        });
+        processed_covers.push(ProcessedCover {
+            path: path.clone(),
+            bytes_before: picture_bytes(&pictures),
+            bytes_after: picture_bytes(&processed),
+        });
+        history.insert(path.clone(), change);
+        job.advance(&path);
+    }
+
+    if !batch.is_empty() {
+        let written = write_batch(&DefaultBackend::new(), &batch);
+        if written.committed {
+            report.bytes_saved = processed_covers
+                .iter()
+                .map(|c| c.bytes_before as i64 - c.bytes_after as i64)
+                .sum();
+            report.processed = processed_covers;
+        } else {
+            history.clear();
+            errors.extend(written.errors());
+        }
+    }
+
+    if !history.is_empty() {
+        let written: Vec<String> = history.keys().cloned().collect();
+        state.history.lock().unwrap().add(Action {
+            action_type: HistoryActionType::Covers(history),
+        });
+        refresh_files(app_handle.clone(), written);
+    }
+    if !errors.is_empty() {
+        let _ = app_handle.emit("error", errors);
+    }
+    report
+}
+
+/// Process covers in the background. Returns the job id, the report is sent on
+/// "covers-processed" when done
+pub fn start_process_covers(
+    app_handle: &AppHandle,
+    paths: Vec<String>,
+    options: CoverOptions,
+) -> String {
+    let job = app_handle
+        .state::<AppState>()
+        .jobs
+        .start(app_handle, "processCovers", paths.len());
+    let id = job.id.clone();
+    let app_handle = app_handle.clone();
+    async_runtime::spawn_blocking(move || {
+        let report = process_covers(&app_handle, &job, paths, &options);
+        job.finish();
+        let _ = app_handle.emit("covers-processed", report);
+    });
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(marker: u8, body: &[u8]) -> Vec<u8> {
+        let mut out = vec![0xff, marker];
+        out.extend(((body.len() + 2) as u16).to_be_bytes());
+        out.extend(body);
+        out
+    }
+
+    fn chunk(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = (body.len() as u32).to_be_bytes().to_vec();
+        out.extend(kind);
+        out.extend(body);
+        out.extend([0u8; 4]);
+        out
+    }
+
+    /// Start of scan and entropy coded data, with a stuffed 0xff and the end of image
+    const SCAN: [u8; 12] = [0xff, 0xda, 0, 8, 1, 1, 0, 0, 0xff, 0x00, 0xff, 0xd9];
+
+    #[test]
+    fn jpeg_drops_exif_and_photoshop_segments() {
+        let app0 = segment(0xe0, b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0");
+        let dqt = segment(0xdb, &[0; 65]);
+        let sof = segment(0xc0, &[8, 0, 16, 0, 16, 1, 1, 0x11, 0]);
+        let data = [
+            vec![0xff, 0xd8],
+            app0.clone(),
+            segment(0xe1, b"Exif\0\0II*\0"),
+            dqt.clone(),
+            segment(0xed, b"Photoshop 3.0\0"),
+            sof.clone(),
+            SCAN.to_vec(),
+        ]
+        .concat();
+        let expected = [vec![0xff, 0xd8], app0, dqt, sof, SCAN.to_vec()].concat();
+        assert_eq!(strip_jpeg_metadata(&data), expected);
+    }
+
+    #[test]
+    fn jpeg_fill_bytes_before_marker() {
+        let sof = segment(0xc0, &[8, 0, 16, 0, 16, 1, 1, 0x11, 0]);
+        let data = [
+            vec![0xff, 0xd8, 0xff, 0xff],
+            segment(0xe1, b"Exif\0\0"),
+            vec![0xff],
+            sof.clone(),
+            SCAN.to_vec(),
+        ]
+        .concat();
+        let expected = [vec![0xff, 0xd8], sof, SCAN.to_vec()].concat();
+        assert_eq!(strip_jpeg_metadata(&data), expected);
+    }
+
+    #[test]
+    fn jpeg_truncated_segment() {
+        let mut data = [vec![0xff, 0xd8], segment(0xe0, &[0; 14])].concat();
+        // length runs past the end of the data
+        data.extend([0xff, 0xdb, 0x00, 0x43, 1, 2]);
+        assert_eq!(strip_jpeg_metadata(&data), data);
+        let mut cut = [vec![0xff, 0xd8], segment(0xe1, &[0; 14])].concat();
+        cut.truncate(10);
+        assert_eq!(strip_jpeg_metadata(&cut), vec![0xff, 0xd8]);
+        assert_eq!(
+            strip_jpeg_metadata(&[0xff, 0xd8, 0xff]),
+            vec![0xff, 0xd8, 0xff]
+        );
+    }
+
+    #[test]
+    fn jpeg_other_data_untouched() {
+        assert_eq!(strip_jpeg_metadata(b"\x89PNG"), b"\x89PNG".to_vec());
+        assert_eq!(strip_jpeg_metadata(&[]), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn png_drops_text_time_and_exif() {
+        let signature = b"\x89PNG\r\n\x1a\n".to_vec();
+        let ihdr = chunk(b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 0]);
+        let idat = chunk(b"IDAT", &[1, 2, 3]);
+        let iend = chunk(b"IEND", &[]);
+        let data = [
+            signature.clone(),
+            ihdr.clone(),
+            chunk(b"tEXt", b"Comment\0x"),
+            chunk(b"zTXt", b"Raw\0\0x"),
+            chunk(b"iTXt", b"XML:com.adobe.xmp\0\0\0\0\0x"),
+            chunk(b"tIME", &[7, 234, 1, 1, 0, 0, 0]),
+            chunk(b"eXIf", b"II*\0"),
+            idat.clone(),
+            iend.clone(),
+        ]
+        .concat();
+        let expected = [signature, ihdr, idat, iend].concat();
+        assert_eq!(strip_png_metadata(&data), expected);
+    }
+
+    #[test]
+    fn png_keeps_palette() {
+        let data = [
+            b"\x89PNG\r\n\x1a\n".to_vec(),
+            chunk(b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 3, 0, 0, 0]),
+            chunk(b"PLTE", &[255, 0, 0, 0, 255, 0]),
+            chunk(b"tRNS", &[0, 255]),
+            chunk(b"tEXt", b"Software\0x"),
+            chunk(b"IDAT", &[0]),
+            chunk(b"IEND", &[]),
+        ]
+        .concat();
+        let stripped = strip_png_metadata(&data);
+        assert_eq!(stripped.len(), data.len() - 22);
+        assert!(stripped.windows(4).any(|w| w == b"PLTE"));
+        assert!(stripped.windows(4).any(|w| w == b"tRNS"));
+        assert!(!stripped.windows(4).any(|w| w == b"tEXt"));
+    }
+
+    #[test]
+    fn png_truncated_chunk() {
+        let mut data = [
+            b"\x89PNG\r\n\x1a\n".to_vec(),
+            chunk(b"IHDR", &[0; 13]),
+            chunk(b"IDAT", &[0; 64]),
+        ]
+        .concat();
+        data.truncate(50);
+        assert_eq!(strip_png_metadata(&data), data);
+        assert_eq!(strip_png_metadata(b"\x89PNG\r\n"), b"\x89PNG\r\n".to_vec());
+    }
+}
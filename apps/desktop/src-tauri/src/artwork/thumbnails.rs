@@ -0,0 +1,292 @@
+use crate::artwork::{file_pictures, front_picture, picture_hash, FRONT_COVER};
+use crate::config::user::ViewMode;
+use crate::tag_manager::utils::TagValue;
+use crate::AppState;
+
+use image::codecs::jpeg::JpegEncoder;
+use serde::Serialize;
+use sqlx::{Row, SqlitePool};
+use std::collections::{HashMap, HashSet};
+use tauri::http::{header, Response, StatusCode};
+use tauri::{AppHandle, Manager};
+
+/// Longest side of the thumbnails kept per picture
+pub const THUMBNAIL_SIZES: [u32; 3] = [64, 256, 512];
+const THUMBNAIL_QUALITY: u8 = 85;
+/// Name of the URI scheme thumbnails are served on, `thumbnail://localhost/<hash>/<size>`.
+/// The asset protocol only serves files from disk, thumbnails are kept in the database
+pub const THUMBNAIL_SCHEME: &str = "thumbnail";
+
+/// Where the frontend can load the thumbnail of a file from
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThumbnailRef {
+    pub path: String,
+    /// Files with the same cover share the hash, and so the thumbnail
+    pub hash: String,
+    pub size: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct Thumbnail {
+    pub mime: String,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+/// The smallest kept size that is at least `size`, or the largest one
+pub fn snap_size(size: u32) -> u32 {
+    THUMBNAIL_SIZES
+        .iter()
+        .copied()
+        .find(|s| *s >= size)
+        .unwrap_or(THUMBNAIL_SIZES[THUMBNAIL_SIZES.len() - 1])
+}
+
+/// Decode a JPEG, PNG or WebP picture and scale it to fit in `size` pixels as a JPEG.
+/// Pictures already smaller than that keep their dimensions
+pub fn render_thumbnail(data: &[u8], size: u32) -> Result<Thumbnail, String> {
+    let image = image::load_from_memory(data).map_err(|e| e.to_string())?;
+    let image = if image.width() > size || image.height() > size {
+        image.thumbnail(size, size)
+    } else {
+        image
+    };
+    let rgb = image.to_rgb8();
+    let mut out = Vec::new();
+    JpegEncoder::new_with_quality(&mut out, THUMBNAIL_QUALITY)
+        .encode_image(&rgb)
+        .map_err(|e| e.to_string())?;
+    Ok(Thumbnail {
+        mime: "image/jpeg".to_string(),
+        width: rgb.width(),
+        height: rgb.height(),
+        data: out,
+    })
+}
+
+async fn stored_thumbnail(pool: &SqlitePool, hash: &str, size: u32) -> Option<Thumbnail> {
+    let row = sqlx::query(
+        "SELECT mime_type, width, height, data FROM thumbnails WHERE hash = ?1 AND size = ?2",
+    )
+    .bind(hash)
+    .bind(size as i64)
+    .fetch_optional(pool)
+    .await
+    .ok()??;
+    Some(Thumbnail {
+        mime: row.get("mime_type"),
+        width: row.get::<i64, _>("width") as u32,
+        height: row.get::<i64, _>("height") as u32,
+        data: row.get("data"),
+    })
+}
+
+async fn store_thumbnail(
+    pool: &SqlitePool,
+    hash: &str,
+    size: u32,
+    thumbnail: &Thumbnail,
+) -> Result<(), String> {
+    sqlx::query(
+        "INSERT INTO thumbnails (hash, size, mime_type, width, height, data) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT(hash, size) DO NOTHING",
+    )
+    .bind(hash)
+    .bind(size as i64)
+    .bind(&thumbnail.mime)
+    .bind(thumbnail.width as i64)
+    .bind(thumbnail.height as i64)
+    .bind(&thumbnail.data)
+    .execute(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Cached thumbnail of the picture with `hash`, rendered from `data` when there is none yet
+async fn ensure_thumbnail(
+    pool: &SqlitePool,
+    hash: &str,
+    size: u32,
+    data: impl std::future::Future<Output = Option<Vec<u8>>>,
+) -> Result<Thumbnail, String> {
+    if let Some(thumbnail) = stored_thumbnail(pool, hash, size).await {
+        return Ok(thumbnail);
+    }
+    let data = data.await.ok_or("Picture not found".to_string())?;
+    let thumbnail = render_thumbnail(&data, size)?;
+    store_thumbnail(pool, hash, size, &thumbnail).await?;
+    Ok(thumbnail)
+}
+
+async fn indexed_picture_data(pool: &SqlitePool, hash: &str) -> Option<Vec<u8>> {
+    sqlx::query_scalar("SELECT data FROM tag_pictures WHERE hash = ?1 LIMIT 1")
+        .bind(hash)
+        .fetch_optional(pool)
+        .await
+        .ok()?
+}
+
+/// Hash of the front picture of an indexed file, hashing and saving it for rows stored before
+/// pictures had one
+async fn indexed_front_hash(pool: &SqlitePool, path: &str) -> Result<Option<String>, String> {
+    let row = sqlx::query(
+        "SELECT id, hash FROM tag_pictures WHERE file_path = ?1 ORDER BY picture_type != ?2, id LIMIT 1",
+    )
+    .bind(path)
+    .bind(FRONT_COVER as i64)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    if let Some(hash) = row.get::<Option<String>, _>("hash") {
+        return Ok(Some(hash));
+    }
+    let id: i64 = row.get("id");
+    let data: Vec<u8> = sqlx::query_scalar("SELECT data FROM tag_pictures WHERE id = ?1")
+        .bind(id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    let hash = picture_hash(&data);
+    sqlx::query("UPDATE tag_pictures SET hash = ?2 WHERE id = ?1")
+        .bind(id)
+        .bind(&hash)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(Some(hash))
+}
+
+/// Thumbnails of the front cover of each file that has one, rendered on first request.
+/// A cover shared by a whole album is decoded once
+pub async fn get_thumbnails(
+    state: &AppState,
+    paths: Vec<String>,
+    size: u32,
+) -> Result<Vec<ThumbnailRef>, String> {
+    let size = snap_size(size);
+    let pool = &state.db.pool;
+
+    // the picture itself when it's at hand, indexed ones are only loaded to render
+    let mut pictures: Vec<(String, String, Option<Vec<u8>>)> = Vec::new();
+    if state.view_mode == ViewMode::Simple {
+        let ws = state.workspace.lock().unwrap();
+        for file in ws.files.iter() {
+            let path = file.path.to_string_lossy().to_string();
+            if !paths.contains(&path) {
+                continue;
+            }
+            if let Some(TagValue::Picture { data, .. }) = front_picture(&file.tags) {
+                pictures.push((path, picture_hash(data), Some(data.clone())));
+            }
+        }
+    } else {
+        for path in paths {
+            if let Some(hash) = indexed_front_hash(pool, &path).await? {
+                pictures.push((path, hash, None));
+            }
+        }
+    }
+
+    let mut rendered: HashMap<String, (u32, u32)> = HashMap::new();
+    let mut failed: HashSet<String> = HashSet::new();
+    let mut out = Vec::new();
+    for (path, hash, data) in pictures {
+        if !rendered.contains_key(&hash) && !failed.contains(&hash) {
+            let data = async {
+                match data {
+                    Some(data) => Some(data),
+                    None => indexed_picture_data(pool, &hash).await,
+                }
+            };
+            match ensure_thumbnail(pool, &hash, size, data).await {
+                Ok(t) => {
+                    rendered.insert(hash.clone(), (t.width, t.height));
+                }
+                Err(_) => {
+                    failed.insert(hash.clone());
+                }
+            }
+        }
+        if let Some((width, height)) = rendered.get(&hash) {
+            out.push(ThumbnailRef {
+                path,
+                hash,
+                size,
+                width: *width,
+                height: *height,
+            });
+        }
+    }
+    Ok(out)
+}
+
+fn plain_response(status: StatusCode, message: &str) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(message.as_bytes().to_vec())
+        .unwrap()
+}
+
+/// Picture with `hash` embedded in the file at `path`, for pictures that aren't indexed
+fn file_picture_data(state: &AppState, path: &str, hash: &str) -> Option<Vec<u8>> {
+    file_pictures(state, path)
+        .ok()?
+        .into_iter()
+        .find_map(|picture| match picture {
+            TagValue::Picture { data, .. } if picture_hash(&data) == hash => Some(data),
+            _ => None,
+        })
+}
+
+/// Answer a `thumbnail://localhost/<hash>/<size>` request. An optional `?path=<path>` names a
+/// file the picture is embedded in, for pictures that aren't indexed
+pub async fn thumbnail_response(
+    app_handle: &AppHandle,
+    uri_path: &str,
+    query: Option<&str>,
+) -> Response<Vec<u8>> {
+    let Some(state) = app_handle.try_state::<AppState>() else {
+        return plain_response(StatusCode::SERVICE_UNAVAILABLE, "not ready");
+    };
+    let mut parts = uri_path.trim_matches('/').split('/');
+    let (Some(hash), Some(size), None) = (parts.next(), parts.next(), parts.next()) else {
+        return plain_response(StatusCode::BAD_REQUEST, "expected /<hash>/<size>");
+    };
+    let Ok(size) = size.parse::<u32>() else {
+        return plain_response(StatusCode::BAD_REQUEST, "invalid size");
+    };
+    let size = snap_size(size);
+    let pool = &state.db.pool;
+
+    let path = query.and_then(|q| {
+        url::form_urlencoded::parse(q.as_bytes())
+            .find(|(key, _)| key == "path")
+            .map(|(_, value)| value.into_owned())
+    });
+    let data = async {
+        match indexed_picture_data(pool, hash).await {
+            Some(data) => Some(data),
+            None => file_picture_data(&state, path.as_deref()?, hash),
+        }
+    };
+
+    match ensure_thumbnail(pool, hash, size, data).await {
+        Ok(thumbnail) => Response::builder()
+            .header(header::CONTENT_TYPE, thumbnail.mime)
+            .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            // the url names the content, it never changes
+            .header(header::CACHE_CONTROL, "public, max-age=31536000, immutable")
+            .body(thumbnail.data)
+            .unwrap(),
+        Err(e) => plain_response(StatusCode::NOT_FOUND, &e),
+    }
+}
@@ -0,0 +1,109 @@
+use crate::history::current_files;
+use crate::tag_manager::picture::{is_same_mime, picture_info, sniff_mime};
+use crate::tag_manager::utils::{FrameKey, TagValue};
+use crate::AppState;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum PictureProblem {
+    /// No image data at all
+    Empty,
+    /// The data isn't a JPEG, PNG, GIF, WebP or BMP
+    UnknownFormat,
+    /// The declared MIME type names another format than the data is
+    MimeMismatch,
+    /// Right format, but spelled in a way some players don't recognise (image/jpg)
+    NonStandardMime,
+    /// The header is fine but the image doesn't decode
+    Corrupt,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PictureIssue {
+    pub path: String,
+    /// Position of the picture among the file's pictures
+    pub index: usize,
+    pub picture_type: Option<u8>,
+    pub declared_mime: String,
+    pub detected_mime: Option<String>,
+    pub problem: PictureProblem,
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PictureValidationReport {
+    pub checked_files: usize,
+    pub checked_pictures: usize,
+    pub issues: Vec<PictureIssue>,
+}
+
+/// What is wrong with one picture, if anything
+fn check_picture(mime: &str, data: &[u8]) -> Option<(PictureProblem, Option<String>)> {
+    if data.is_empty() {
+        return Some((PictureProblem::Empty, None));
+    }
+    let Some(sniffed) = sniff_mime(data) else {
+        return Some((PictureProblem::UnknownFormat, None));
+    };
+    if picture_info(data).is_none() {
+        return Some((
+            PictureProblem::Corrupt,
+            Some("Unreadable image header".to_string()),
+        ));
+    }
+    if let Err(e) = image::load_from_memory(data) {
+        return Some((PictureProblem::Corrupt, Some(e.to_string())));
+    }
+    if mime != sniffed {
+        let problem = if is_same_mime(mime, sniffed) {
+            PictureProblem::NonStandardMime
+        } else {
+            PictureProblem::MimeMismatch
+        };
+        return Some((problem, None));
+    }
+    None
+}
+
+/// Check every embedded picture of `paths` against its declared MIME type and try to decode it
+pub async fn validate_pictures(
+    state: &AppState,
+    paths: Vec<String>,
+) -> Result<PictureValidationReport, String> {
+    let mut report = PictureValidationReport::default();
+    for file in current_files(state, &paths).await {
+        report.checked_files += 1;
+        let path = file.path.to_string_lossy().to_string();
+        let Some(pictures) = file.tags.get(&FrameKey::AttachedPicture) else {
+            continue;
+        };
+        for (index, picture) in pictures.iter().enumerate() {
+            let TagValue::Picture {
+                mime,
+                data,
+                picture_type,
+                ..
+            } = picture
+            else {
+                continue;
+            };
+            report.checked_pictures += 1;
+            if let Some((problem, detail)) = check_picture(mime, data) {
+                report.issues.push(PictureIssue {
+                    path: path.clone(),
+                    index,
+                    picture_type: *picture_type,
+                    declared_mime: mime.clone(),
+                    detected_mime: sniff_mime(data).map(|m| m.to_string()),
+                    problem,
+                    detail,
+                });
+            }
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,262 @@
+pub mod store;
+
+use crate::database::Database;
+use crate::history::store::now;
+use crate::tag_manager::regions::{self, Layout, Region};
+use crate::tag_manager::tag_backend::{BackendError, TagError};
+use crate::tag_manager::utils::temp_path_for;
+use store::{BackupEntry, StoreOp};
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use tauri::async_runtime;
+use walkdir::WalkDir;
+
+/// Folder of the object store, under the app data folder
+pub const BACKUP_DIR: &str = "tag_backups";
+
+struct Backups {
+    /// None until `init`, nothing is backed up before that
+    dir: Option<PathBuf>,
+    enabled: bool,
+    /// Backups older than this many days are dropped, 0 keeps them forever. The file stays
+    /// marked as expired, its next write is not the original anymore
+    retention_days: u32,
+    index: HashMap<String, BackupEntry>,
+    store: Option<Sender<StoreOp>>,
+}
+
+static BACKUPS: Lazy<Mutex<Backups>> = Lazy::new(|| {
+    Mutex::new(Backups {
+        dir: None,
+        enabled: false,
+        retention_days: 0,
+        index: HashMap::new(),
+        store: None,
+    })
+});
+
+/// Objects are named by the SHA-256 of their bytes, spread over folders by the first two digits
+fn object_path(dir: &Path, hash: &str) -> PathBuf {
+    dir.join(&hash[..2]).join(hash)
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+impl Backups {
+    fn save(&self, op: StoreOp) {
+        if let Some(store) = &self.store {
+            let _ = store.send(op);
+        }
+    }
+
+    /// Mark backups past the retention period expired, then drop the objects no live backup
+    /// points to anymore
+    fn prune(&mut self) {
+        if self.retention_days > 0 {
+            let cutoff = now() - self.retention_days as i64 * 24 * 60 * 60;
+            let mut expired: Vec<String> = Vec::new();
+            for (path, entry) in self.index.iter_mut() {
+                if !entry.expired && entry.created_at < cutoff {
+                    entry.expired = true;
+                    expired.push(path.clone());
+                }
+            }
+            if !expired.is_empty() {
+                self.save(StoreOp::Expire(expired));
+            }
+        }
+
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        let used: HashSet<&str> = self
+            .index
+            .values()
+            .filter(|e| !e.expired)
+            .map(|e| e.hash.as_str())
+            .collect();
+        for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+            let name = entry.file_name().to_string_lossy();
+            if entry.file_type().is_file() && !used.contains(name.as_ref()) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+}
+
+/// Load the backup index and apply the retention policy. Without an index nothing is backed
+/// up, a file backed up before would otherwise have its edited tags taken for the original
+pub fn init(dir: PathBuf, db: &Database, enabled: bool, retention_days: u32) {
+    let (index, enabled) = match async_runtime::block_on(store::load(&db.pool)) {
+        Ok(index) => (index, enabled),
+        Err(_) => (HashMap::new(), false),
+    };
+    let mut backups = BACKUPS.lock().unwrap();
+    *backups = Backups {
+        dir: Some(dir),
+        enabled,
+        retention_days,
+        index,
+        store: Some(store::spawn_writer(db.pool.clone())),
+    };
+    backups.prune();
+}
+
+pub fn set_policy(enabled: bool, retention_days: u32) {
+    let mut backups = BACKUPS.lock().unwrap();
+    backups.enabled = enabled;
+    backups.retention_days = retention_days;
+    backups.prune();
+}
+
+/// Wait until the backup index changes made so far are saved, for a process about to exit
+pub fn flush() {
+    let (tx, rx) = mpsc::channel();
+    BACKUPS.lock().unwrap().save(StoreOp::Flush(tx));
+    let _ = rx.recv();
+}
+
+/// Save the metadata region of a file before its first write. Files backed up already, also
+/// when that backup expired, and formats without a known region are left alone
+pub fn ensure(path: &Path) -> Result<(), String> {
+    let mut backups = BACKUPS.lock().unwrap();
+    let Some(dir) = backups.dir.clone().filter(|_| backups.enabled) else {
+        return Ok(());
+    };
+    let key = path.to_string_lossy().to_string();
+    if backups.index.contains_key(&key) {
+        return Ok(());
+    }
+    let Some(region) = regions::read_region(path)? else {
+        return Ok(());
+    };
+
+    let hash = sha256(&region.bytes);
+    let object = object_path(&dir, &hash);
+    if !object.exists() {
+        let folder = object.parent().unwrap_or(&dir);
+        fs::create_dir_all(folder).map_err(|e| e.to_string())?;
+        let tmp = temp_path_for(&object);
+        fs::write(&tmp, &region.bytes).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, &object).map_err(|e| e.to_string())?;
+    }
+    let entry = BackupEntry {
+        hash,
+        kind: region.layout.kind,
+        tail_len: region.layout.tail,
+        data_offset: region.layout.data_offset,
+        created_at: now(),
+        expired: false,
+    };
+    backups.index.insert(key.clone(), entry.clone());
+    backups.save(StoreOp::Insert(key, entry));
+    Ok(())
+}
+
+/// Keep backups with their files when they are renamed. Names swapped within the batch are fine
+pub fn follow_renames(renames: &[(String, String)]) {
+    let mut backups = BACKUPS.lock().unwrap();
+    let moved: Vec<(String, String, BackupEntry)> = renames
+        .iter()
+        .filter(|(from, to)| from != to)
+        .filter_map(|(from, to)| Some((from.clone(), to.clone(), backups.index.remove(from)?)))
+        .collect();
+    if moved.is_empty() {
+        return;
+    }
+    backups.save(StoreOp::Remove(
+        moved.iter().map(|(from, _, _)| from.clone()).collect(),
+    ));
+    for (_, to, entry) in moved {
+        backups.index.insert(to.clone(), entry.clone());
+        backups.save(StoreOp::Insert(to, entry));
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreReport {
+    pub restored: Vec<String>,
+    /// Files never written since backups were turned on
+    pub without_backup: Vec<String>,
+    /// Files whose backup was dropped by the retention policy
+    pub expired: Vec<String>,
+    pub errors: Vec<BackendError>,
+}
+
+fn restore_file(dir: &Path, path: &str, entry: &BackupEntry) -> Result<(), String> {
+    let bytes = fs::read(object_path(dir, &entry.hash)).map_err(|e| e.to_string())?;
+    if sha256(&bytes) != entry.hash {
+        return Err("The backup is damaged".to_string());
+    }
+    let region = Region {
+        layout: Layout {
+            kind: entry.kind,
+            range: 0..bytes.len() as u64 - entry.tail_len,
+            tail: entry.tail_len,
+            data_offset: entry.data_offset,
+        },
+        bytes,
+    };
+    regions::restore_region(Path::new(path), &region)
+}
+
+/// Put the original tags back on files, and on every backed up file inside the folders among
+/// `paths`. The index is only locked while picking the backups, not while files are rewritten
+pub fn restore_original_tags(paths: &[String]) -> RestoreReport {
+    let backups = BACKUPS.lock().unwrap();
+    let mut report = RestoreReport::default();
+    let mut targets: Vec<(String, BackupEntry)> = Vec::new();
+    for path in paths {
+        if Path::new(path).is_dir() {
+            let mut inside: Vec<(String, BackupEntry)> = backups
+                .index
+                .iter()
+                .filter(|(p, _)| Path::new(p).starts_with(path))
+                .map(|(p, entry)| (p.clone(), entry.clone()))
+                .collect();
+            inside.sort_by(|a, b| a.0.cmp(&b.0));
+            targets.extend(inside);
+        } else if let Some(entry) = backups.index.get(path) {
+            targets.push((path.clone(), entry.clone()));
+        } else {
+            report.without_backup.push(path.clone());
+        }
+    }
+    let dir = backups.dir.clone();
+    drop(backups);
+    let Some(dir) = dir else {
+        report
+            .without_backup
+            .extend(targets.into_iter().map(|(p, _)| p));
+        return report;
+    };
+
+    for (path, entry) in targets {
+        if entry.expired {
+            report.expired.push(path);
+            continue;
+        }
+        match restore_file(&dir, &path, &entry) {
+            Ok(()) => report.restored.push(path),
+            Err(e) => report.errors.push(BackendError::WriteFailed(TagError {
+                path: path.clone(),
+                public_message: format!("Could not restore original tags: {}", e),
+                internal_message: e,
+            })),
+        }
+    }
+    report
+}
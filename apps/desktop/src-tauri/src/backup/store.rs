@@ -0,0 +1,110 @@
+use crate::tag_manager::regions::RegionKind;
+
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use tauri::async_runtime;
+
+/// A file's backed up region, the bytes themselves are in the object store
+#[derive(Debug, Clone)]
+pub struct BackupEntry {
+    pub hash: String,
+    pub kind: RegionKind,
+    /// Bytes at the end of the object that go at the end of the file
+    pub tail_len: u64,
+    pub data_offset: Option<u64>,
+    pub created_at: i64,
+    /// Past the retention period, the bytes are gone
+    pub expired: bool,
+}
+
+/// Changes to the backup index, applied in order on a thread of their own
+pub enum StoreOp {
+    Insert(String, BackupEntry),
+    Remove(Vec<String>),
+    Expire(Vec<String>),
+    /// Answers once every change sent before it is saved
+    Flush(Sender<()>),
+}
+
+async fn apply_op(pool: &SqlitePool, op: StoreOp) -> Result<(), sqlx::Error> {
+    match op {
+        StoreOp::Insert(path, entry) => {
+            sqlx::query(
+                "INSERT OR REPLACE INTO tag_backups (path, hash, kind, tail_len, data_offset, created_at, expired)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )
+            .bind(path)
+            .bind(entry.hash)
+            .bind(entry.kind.as_str())
+            .bind(entry.tail_len as i64)
+            .bind(entry.data_offset.map(|d| d as i64))
+            .bind(entry.created_at)
+            .bind(entry.expired)
+            .execute(pool)
+            .await?;
+        }
+        StoreOp::Remove(paths) => {
+            let mut tx = pool.begin().await?;
+            for path in paths {
+                sqlx::query("DELETE FROM tag_backups WHERE path = ?1")
+                    .bind(path)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            tx.commit().await?;
+        }
+        StoreOp::Expire(paths) => {
+            let mut tx = pool.begin().await?;
+            for path in paths {
+                sqlx::query("UPDATE tag_backups SET expired = 1 WHERE path = ?1")
+                    .bind(path)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            tx.commit().await?;
+        }
+        StoreOp::Flush(done) => {
+            let _ = done.send(());
+        }
+    }
+    Ok(())
+}
+
+/// Start the thread that writes index changes to the database
+pub fn spawn_writer(pool: SqlitePool) -> Sender<StoreOp> {
+    let (tx, rx) = mpsc::channel::<StoreOp>();
+    thread::spawn(move || {
+        for op in rx {
+            if let Err(e) = async_runtime::block_on(apply_op(&pool, op)) {
+                eprintln!("saving backup index failed: {e}");
+            }
+        }
+    });
+    tx
+}
+
+pub async fn load(pool: &SqlitePool) -> Result<HashMap<String, BackupEntry>, String> {
+    let rows = sqlx::query(
+        "SELECT path, hash, kind, tail_len, data_offset, created_at, expired FROM tag_backups",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| {
+            let kind = RegionKind::parse(row.get::<String, _>("kind").as_str())?;
+            let entry = BackupEntry {
+                hash: row.get("hash"),
+                kind,
+                tail_len: row.get::<i64, _>("tail_len") as u64,
+                data_offset: row.get::<Option<i64>, _>("data_offset").map(|d| d as u64),
+                created_at: row.get("created_at"),
+                expired: row.get("expired"),
+            };
+            Some((row.get("path"), entry))
+        })
+        .collect())
+}
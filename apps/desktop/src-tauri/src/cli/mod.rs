@@ -0,0 +1,599 @@
+//! `audexis-cli`, for reading and writing tags from scripts without starting the app
+mod yaml;
+
+use crate::backup;
+use crate::config::user::{load_config, CONFIG_FILE};
+use crate::database::{Database, DATABASE_FILE};
+use crate::tag_manager::batch::{self, BatchReport, FileOutcome, FileReport};
+use crate::tag_manager::journal::{self, JOURNAL_FILE};
+use crate::tag_manager::picture::sniff_mime;
+use crate::tag_manager::tag_backend::{BackendError, DefaultBackend, TagBackend};
+use crate::tag_manager::traits::Formats;
+use crate::tag_manager::utils::{
+    Changes, CleanupRule, File, FrameKey, SerializableFile, SerializableTagValue, TagValue,
+    TagValuesWrapper,
+};
+use crate::utils::RenameResultItem;
+use crate::workspace::{PlannedRename, Workspace};
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use tauri::async_runtime;
+
+const USAGE: &str = "Usage: audexis-cli <command> [options] <files...>
+
+Commands:
+  read                       Print the tags of each file
+  write                      Set or clear frames
+      --set <key>=<value>    Set a frame, repeat the key for more values
+      --clear <key>          Remove a frame
+  rename                     Rename files from their tags
+      --pattern <pattern>    New name, e.g. \"{trackNumber} - {title}\"
+      --dry-run              Print the renames without doing them
+  cleanup                    Clean up file names
+      --rule <rule>          replace_underscores, normalize_dashes, fix_capitalization,
+                             trim_whitespace, collapse_spaces, remove_suffixes,
+                             normalize_feat or remove_brackets, applied in order
+      --dry-run              Print the renames without doing them
+  strip                      Remove every frame
+      --frame <key>          Remove only these frames
+  convert                    Rewrite the ID3v2 tag of MP3 files in another version. Frames
+                             the app doesn't know are dropped
+      --to <version>         id3v2.3 or id3v2.4, id3v2.4 by default
+  import-cover               Embed an image
+      --image <path>         The image to embed
+      --type <n>             Picture type, 3 (front cover) by default. Pictures of this
+                             type are replaced
+      --description <text>   Picture description
+
+Options:
+  --format json|yaml         Output format, json by default
+  --app-data <dir>           The app's data folder. Writes then go through its write journal
+                             and back up original tags like the app does. Only use it while
+                             the app is closed
+
+Frame keys are the ones the app uses, e.g. title, artist, albumArtist, trackNumber.
+Writes to several files are all-or-nothing. Without --app-data they skip the app's write
+journal and backups: a write cut off halfway is not recovered, and nothing is saved for
+restoring original tags.
+
+Exit codes: 0 on success, 1 when a file failed, 2 on bad arguments. Errors are printed to
+stderr as an \"error\" document in the chosen format.
+";
+
+const EXIT_OK: i32 = 0;
+const EXIT_FAILED: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+
+/// Options every command takes
+const GLOBAL: &[&str] = &["--format", "--app-data"];
+
+/// Options that take no value
+const FLAGS: &[&str] = &["--dry-run"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Json,
+    Yaml,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+enum ErrorKind {
+    /// Bad arguments, nothing was done
+    Usage,
+    /// The command ran but some or all files failed, the output says which
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+struct CliError {
+    kind: ErrorKind,
+    message: String,
+}
+
+impl CliError {
+    fn usage(message: impl Into<String>) -> Self {
+        Self {
+            kind: ErrorKind::Usage,
+            message: message.into(),
+        }
+    }
+
+    fn failed(message: impl Into<String>) -> Self {
+        Self {
+            kind: ErrorKind::Failed,
+            message: message.into(),
+        }
+    }
+}
+
+/// What a command prints, and whether every file went through
+struct Output {
+    value: Value,
+    complete: bool,
+}
+
+fn output<T: Serialize>(value: &T, complete: bool) -> Result<Output, CliError> {
+    let value = serde_json::to_value(value).map_err(|e| CliError::failed(e.to_string()))?;
+    Ok(Output { value, complete })
+}
+
+struct Args {
+    options: Vec<(String, String)>,
+    flags: Vec<String>,
+    paths: Vec<String>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self, CliError> {
+        let mut parsed = Args {
+            options: Vec::new(),
+            flags: Vec::new(),
+            paths: Vec::new(),
+        };
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if arg == "--" {
+                parsed.paths.extend(iter.by_ref().cloned());
+            } else if FLAGS.contains(&arg.as_str()) {
+                parsed.flags.push(arg.clone());
+            } else if let Some((name, value)) =
+                arg.split_once('=').filter(|_| arg.starts_with("--"))
+            {
+                parsed.options.push((name.to_string(), value.to_string()));
+            } else if arg.starts_with("--") {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| CliError::usage(format!("{} needs a value", arg)))?;
+                parsed.options.push((arg.clone(), value.clone()));
+            } else {
+                parsed.paths.push(arg.clone());
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Refuse options the command doesn't know
+    fn allow(&self, known: &[&str]) -> Result<(), CliError> {
+        let given = self
+            .options
+            .iter()
+            .map(|(name, _)| name)
+            .chain(self.flags.iter());
+        for name in given {
+            if !GLOBAL.contains(&name.as_str()) && !known.contains(&name.as_str()) {
+                return Err(CliError::usage(format!("Unknown option {}", name)));
+            }
+        }
+        Ok(())
+    }
+
+    fn values(&self, name: &str) -> Vec<&str> {
+        self.options
+            .iter()
+            .filter(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.values(name).last().copied()
+    }
+
+    fn parsed<T: std::str::FromStr>(&self, name: &str) -> Result<Option<T>, CliError> {
+        self.value(name)
+            .map(|v| {
+                v.parse()
+                    .map_err(|_| CliError::usage(format!("Invalid value for {}: {}", name, v)))
+            })
+            .transpose()
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|f| f == name)
+    }
+
+    fn paths(&self) -> Result<&[String], CliError> {
+        if self.paths.is_empty() {
+            return Err(CliError::usage("No files given"));
+        }
+        Ok(&self.paths)
+    }
+
+    fn format(&self) -> Result<Format, CliError> {
+        match self.value("--format") {
+            None | Some("json") => Ok(Format::Json),
+            Some("yaml") => Ok(Format::Yaml),
+            Some(other) => Err(CliError::usage(format!("Unknown format {}", other))),
+        }
+    }
+}
+
+fn render(value: &Value, format: Format) -> String {
+    match format {
+        Format::Json => format!(
+            "{}\n",
+            serde_json::to_string_pretty(value).unwrap_or_default()
+        ),
+        Format::Yaml => yaml::to_yaml(value),
+    }
+}
+
+fn print_error(error: &CliError, format: Format) {
+    let document = serde_json::json!({ "error": error });
+    eprint!("{}", render(&document, format));
+}
+
+/// Run the command in `args`, the program name left out. Returns the exit code
+pub fn run(args: Vec<String>) -> i32 {
+    let Some(command) = args.first() else {
+        eprint!("{}", USAGE);
+        return EXIT_USAGE;
+    };
+    if matches!(command.as_str(), "help" | "--help" | "-h") {
+        print!("{}", USAGE);
+        return EXIT_OK;
+    }
+    let parsed = Args::parse(&args[1..]);
+    let format = parsed
+        .as_ref()
+        .ok()
+        .and_then(|a| a.format().ok())
+        .unwrap_or(Format::Json);
+    let result = parsed.and_then(|args| {
+        args.format()?;
+        if let Some(dir) = args.value("--app-data") {
+            use_app_data(dir)?;
+        }
+        match command.as_str() {
+            "read" => read(&args),
+            "write" => write(&args),
+            "rename" => rename(&args),
+            "cleanup" => cleanup(&args),
+            "strip" => strip(&args),
+            "convert" => convert(&args),
+            "import-cover" => import_cover(&args),
+            other => Err(CliError::usage(format!("Unknown command {}", other))),
+        }
+    });
+
+    // the backup index is saved on a thread of its own
+    backup::flush();
+    match result {
+        Ok(out) => {
+            print!("{}", render(&out.value, format));
+            if out.complete {
+                EXIT_OK
+            } else {
+                print_error(
+                    &CliError::failed("Some files failed, see the output for which"),
+                    format,
+                );
+                EXIT_FAILED
+            }
+        }
+        Err(e) => {
+            print_error(&e, format);
+            match e.kind {
+                ErrorKind::Usage => EXIT_USAGE,
+                ErrorKind::Failed => EXIT_FAILED,
+            }
+        }
+    }
+}
+
+fn frame_key(name: &str) -> Result<FrameKey, CliError> {
+    FrameKey::from_str(name).ok_or_else(|| CliError::usage(format!("Unknown frame key {}", name)))
+}
+
+/// Read every file, or say which ones couldn't be
+fn read_files(backend: &DefaultBackend, paths: &[String]) -> (Vec<File>, Vec<BackendError>) {
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    for path in paths {
+        match backend.read(&PathBuf::from(path)) {
+            Ok(file) => files.push(file),
+            Err(e) => errors.push(e),
+        }
+    }
+    (files, errors)
+}
+
+/// The report of a batch that stopped before writing because some files couldn't be read
+fn unreadable(files: &[File], errors: &[BackendError]) -> BatchReport {
+    let failed = errors.iter().map(|e| {
+        let path = match e {
+            BackendError::ReadFailed(e) | BackendError::WriteFailed(e) => e.path.clone(),
+        };
+        FileReport {
+            path,
+            outcome: FileOutcome::Failed,
+            error: Some(batch::error_message(e)),
+        }
+    });
+    let skipped = files.iter().map(|f| FileReport {
+        path: f.path.to_string_lossy().to_string(),
+        outcome: FileOutcome::RolledBack,
+        error: None,
+    });
+    BatchReport {
+        committed: false,
+        files: failed.chain(skipped).collect(),
+    }
+}
+
+/// Journal writes and back up original tags in the app's data folder, as the app does
+fn use_app_data(dir: &str) -> Result<(), CliError> {
+    let dir = PathBuf::from(dir);
+    let db_path = dir.join(DATABASE_FILE);
+    if !db_path.is_file() {
+        return Err(CliError::usage(format!(
+            "{} is not the app's data folder",
+            dir.display()
+        )));
+    }
+    let db = async_runtime::block_on(Database::init(&db_path)).map_err(CliError::failed)?;
+    let config = load_config(&dir.join(CONFIG_FILE));
+    journal::init(dir.join(JOURNAL_FILE), config.fsync_policy);
+    backup::init(
+        dir.join(backup::BACKUP_DIR),
+        &db,
+        config.backup_originals,
+        config.backup_retention_days,
+    );
+    Ok(())
+}
+
+/// Write the batch all-or-nothing, journaled and backed up when `--app-data` is given
+fn commit(batch: &[Changes]) -> Result<Output, CliError> {
+    let report = batch::write_batch(&DefaultBackend::new(), batch);
+    output(&report, report.committed)
+}
+
+#[derive(Serialize)]
+struct ReadReport {
+    files: Vec<SerializableFile>,
+    errors: Vec<BackendError>,
+}
+
+fn read(args: &Args) -> Result<Output, CliError> {
+    args.allow(&[])?;
+    let (files, errors) = read_files(&DefaultBackend::new(), args.paths()?);
+    let complete = errors.is_empty();
+    let report = ReadReport {
+        files: files.into_iter().map(SerializableFile::from).collect(),
+        errors,
+    };
+    output(&report, complete)
+}
+
+fn write(args: &Args) -> Result<Output, CliError> {
+    args.allow(&["--set", "--clear"])?;
+    let mut tags: HashMap<FrameKey, Vec<SerializableTagValue>> = HashMap::new();
+    for set in args.values("--set") {
+        let (name, value) = set
+            .split_once('=')
+            .ok_or_else(|| CliError::usage(format!("--set takes key=value, got {}", set)))?;
+        let key = frame_key(name)?;
+        if key == FrameKey::AttachedPicture {
+            return Err(CliError::usage("Use import-cover to embed pictures"));
+        }
+        tags.entry(key)
+            .or_default()
+            .push(SerializableTagValue::Text(value.to_string()));
+    }
+    for name in args.values("--clear") {
+        tags.insert(frame_key(name)?, Vec::new());
+    }
+    if tags.is_empty() {
+        return Err(CliError::usage("Nothing to write, use --set or --clear"));
+    }
+    commit(&[Changes {
+        paths: args.paths()?.to_vec(),
+        tags,
+    }])
+}
+
+#[derive(Serialize)]
+struct RenameReport<T> {
+    renames: Vec<T>,
+    errors: Vec<BackendError>,
+}
+
+/// A workspace holding the files that could be read, for the renames that work from tags
+fn open_workspace(paths: &[String]) -> (Workspace, Vec<String>, Vec<BackendError>) {
+    let backend = DefaultBackend::new();
+    let (files, errors) = read_files(&backend, paths);
+    let read: Vec<String> = files
+        .iter()
+        .map(|f| f.path.to_string_lossy().to_string())
+        .collect();
+    (Workspace { backend, files }, read, errors)
+}
+
+fn rename_output(
+    mut ws: Workspace,
+    dry_run: bool,
+    errors: Vec<BackendError>,
+    plan: impl Fn(&Workspace) -> Vec<PlannedRename>,
+    apply: impl FnOnce(&mut Workspace) -> Vec<(String, String, Result<(), String>)>,
+) -> Result<Output, CliError> {
+    if dry_run {
+        let renames = plan(&ws);
+        let complete = errors.is_empty() && renames.iter().all(|r| r.error.is_none());
+        return output(&RenameReport { renames, errors }, complete);
+    }
+    let renames: Vec<RenameResultItem> = apply(&mut ws)
+        .into_iter()
+        .map(|(old, new, res)| RenameResultItem {
+            old,
+            new,
+            ok: res.is_ok(),
+            error: res.err(),
+        })
+        .collect();
+    let complete = errors.is_empty() && renames.iter().all(|r| r.ok);
+    output(&RenameReport { renames, errors }, complete)
+}
+
+fn rename(args: &Args) -> Result<Output, CliError> {
+    args.allow(&["--pattern", "--dry-run"])?;
+    let pattern = args
+        .value("--pattern")
+        .ok_or_else(|| CliError::usage("rename needs --pattern"))?;
+    let (ws, paths, errors) = open_workspace(args.paths()?);
+    rename_output(
+        ws,
+        args.flag("--dry-run"),
+        errors,
+        |ws| ws.plan_rename_by_pattern(&paths, pattern),
+        |ws| ws.rename_by_pattern(paths.clone(), pattern),
+    )
+}
+
+fn cleanup(args: &Args) -> Result<Output, CliError> {
+    args.allow(&["--rule", "--dry-run"])?;
+    let rules = args
+        .values("--rule")
+        .into_iter()
+        .map(|rule| {
+            serde_json::from_value::<CleanupRule>(Value::String(rule.to_string()))
+                .map_err(|_| CliError::usage(format!("Unknown rule {}", rule)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if rules.is_empty() {
+        return Err(CliError::usage("cleanup needs at least one --rule"));
+    }
+    let (ws, paths, errors) = open_workspace(args.paths()?);
+    rename_output(
+        ws,
+        args.flag("--dry-run"),
+        errors,
+        |ws| ws.plan_clean_up_file_names(&paths, &rules),
+        |ws| ws.clean_up_file_names(paths.clone(), rules.clone()),
+    )
+}
+
+fn strip(args: &Args) -> Result<Output, CliError> {
+    args.allow(&["--frame"])?;
+    let only = args
+        .values("--frame")
+        .into_iter()
+        .map(frame_key)
+        .collect::<Result<Vec<_>, _>>()?;
+    let (files, errors) = read_files(&DefaultBackend::new(), args.paths()?);
+    if !errors.is_empty() {
+        let report = unreadable(&files, &errors);
+        return output(&report, false);
+    }
+    let batch: Vec<Changes> = files
+        .iter()
+        .map(|file| Changes {
+            paths: vec![file.path.to_string_lossy().to_string()],
+            tags: file
+                .tags
+                .keys()
+                .filter(|k| only.is_empty() || only.contains(k))
+                .map(|k| (*k, Vec::new()))
+                .collect(),
+        })
+        .filter(|changes| !changes.tags.is_empty())
+        .collect();
+    commit(&batch)
+}
+
+#[derive(Serialize)]
+struct ConvertReport {
+    batch: BatchReport,
+    /// Files whose tag was in that version already
+    unchanged: Vec<String>,
+}
+
+fn convert(args: &Args) -> Result<Output, CliError> {
+    args.allow(&["--to"])?;
+    let target = match args.value("--to") {
+        None | Some("id3v2.4") => Formats::Id3v24,
+        Some("id3v2.3") => Formats::Id3v23,
+        Some(other) => return Err(CliError::usage(format!("Cannot convert to {}", other))),
+    };
+    let backend = DefaultBackend::new();
+    let (files, errors) = read_files(&backend, args.paths()?);
+    let mut report = ConvertReport {
+        batch: BatchReport::default(),
+        unchanged: Vec::new(),
+    };
+    if !errors.is_empty() {
+        report.batch = unreadable(&files, &errors);
+        return output(&report, false);
+    }
+
+    let mut paths: Vec<String> = Vec::new();
+    for file in files {
+        let path = file.path.to_string_lossy().to_string();
+        if file.tag_format == target {
+            report.unchanged.push(path);
+        } else {
+            paths.push(path);
+        }
+    }
+    report.batch = batch::write_batch_with(&paths, |_, path, tmp| {
+        backend.convert_to_copy(path, tmp, &target)
+    });
+    let complete = report.batch.committed;
+    output(&report, complete)
+}
+
+fn import_cover(args: &Args) -> Result<Output, CliError> {
+    args.allow(&["--image", "--type", "--description"])?;
+    let image = args
+        .value("--image")
+        .ok_or_else(|| CliError::usage("import-cover needs --image"))?;
+    let picture_type = args.parsed::<u8>("--type")?.unwrap_or(3);
+    let data = fs::read(image)
+        .map_err(|e| CliError::failed(format!("Could not read {}: {}", image, e)))?;
+    let mime = sniff_mime(&data)
+        .ok_or_else(|| CliError::failed(format!("{} is not a supported image", image)))?;
+    let cover = TagValue::Picture {
+        mime: mime.to_string(),
+        data,
+        picture_type: Some(picture_type),
+        description: args.value("--description").map(|d| d.to_string()),
+    };
+
+    let (files, errors) = read_files(&DefaultBackend::new(), args.paths()?);
+    if !errors.is_empty() {
+        let report = unreadable(&files, &errors);
+        return output(&report, false);
+    }
+    let batch: Vec<Changes> = files
+        .into_iter()
+        .map(|file| {
+            let mut pictures: Vec<TagValue> = file
+                .tags
+                .get(&FrameKey::AttachedPicture)
+                .cloned()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|p| match p {
+                    TagValue::Picture {
+                        picture_type: t, ..
+                    } => t.unwrap_or(3) != picture_type,
+                    _ => true,
+                })
+                .collect();
+            pictures.push(cover.clone());
+            Changes {
+                paths: vec![file.path.to_string_lossy().to_string()],
+                tags: HashMap::from([(
+                    FrameKey::AttachedPicture,
+                    TagValuesWrapper(pictures).into(),
+                )]),
+            }
+        })
+        .collect();
+    commit(&batch)
+}
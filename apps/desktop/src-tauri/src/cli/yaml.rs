@@ -0,0 +1,82 @@
+use serde_json::Value;
+
+/// Render a JSON value as a YAML document. Strings are quoted whenever a YAML reader could take
+/// them for something else, so the document reads back to the same value
+pub fn to_yaml(value: &Value) -> String {
+    let mut out = String::new();
+    if is_block(value) {
+        block(value, 0, &mut out);
+    } else {
+        out.push_str(&scalar(value));
+        out.push('\n');
+    }
+    out
+}
+
+fn is_block(value: &Value) -> bool {
+    match value {
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+        _ => false,
+    }
+}
+
+fn block(value: &Value, indent: usize, out: &mut String) {
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Object(map) => {
+            for (key, item) in map {
+                out.push_str(&format!("{}{}:", pad, string(key)));
+                if is_block(item) {
+                    out.push('\n');
+                    block(item, indent + 2, out);
+                } else {
+                    out.push_str(&format!(" {}\n", scalar(item)));
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                if is_block(item) {
+                    // the dash takes the place of the first line's indent
+                    let mut inner = String::new();
+                    block(item, indent + 2, &mut inner);
+                    out.push_str(&format!("{}- {}", pad, &inner[indent + 2..]));
+                } else {
+                    out.push_str(&format!("{}- {}\n", pad, scalar(item)));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => string(s),
+        Value::Array(_) => "[]".to_string(),
+        Value::Object(_) => "{}".to_string(),
+    }
+}
+
+/// Plain when it is safe, otherwise double quoted. JSON string escapes are valid in YAML
+fn string(s: &str) -> String {
+    let reserved = matches!(
+        s.to_lowercase().as_str(),
+        "true" | "false" | "null" | "yes" | "no" | "on" | "off" | "y" | "n" | "~"
+    );
+    let plain = !s.is_empty()
+        && !reserved
+        && s.chars()
+            .all(|c| c.is_alphanumeric() || " _-./()".contains(c))
+        && !s.starts_with(|c: char| c.is_ascii_digit() || " -.".contains(c))
+        && !s.ends_with(' ');
+    if plain {
+        s.to_string()
+    } else {
+        serde_json::to_string(s).unwrap_or_default()
+    }
+}
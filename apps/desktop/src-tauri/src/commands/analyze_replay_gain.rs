@@ -0,0 +1,20 @@
+use crate::analysis::replaygain::run_replay_gain;
+use crate::AppState;
+use tauri::{async_runtime, command, AppHandle, State};
+
+/// Start a background ReplayGain 2.0 analysis. Returns the job id
+#[command]
+pub fn analyze_replay_gain(
+    app_handle: AppHandle,
+    paths: Vec<String>,
+    state: State<'_, AppState>,
+) -> String {
+    let job = state.jobs.start(&app_handle, "replayGain", paths.len());
+    let id = job.id.clone();
+
+    async_runtime::spawn_blocking(move || {
+        run_replay_gain(job, app_handle, paths);
+    });
+
+    id
+}
@@ -0,0 +1,7 @@
+use crate::AppState;
+use tauri::{command, State};
+
+#[command]
+pub fn cancel_job(id: String, state: State<'_, AppState>) -> bool {
+    state.jobs.cancel(&id)
+}
@@ -0,0 +1,9 @@
+use crate::database::integrity::{self, IntegrityReport};
+use crate::AppState;
+use tauri::{command, State};
+
+/// Report stale rows, orphaned tags and folder count drift without changing anything
+#[command]
+pub async fn check_database(state: State<'_, AppState>) -> Result<IntegrityReport, String> {
+    integrity::check_database(&state.db).await
+}
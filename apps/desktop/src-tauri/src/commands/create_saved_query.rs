@@ -0,0 +1,13 @@
+use crate::library::saved_queries::{self, SavedQuery};
+use crate::AppState;
+use tauri::{command, State};
+
+/// Save a library search as a smart playlist, e.g. `format:flac -has:replaygain`
+#[command]
+pub async fn create_saved_query(
+    name: String,
+    query: String,
+    state: State<'_, AppState>,
+) -> Result<SavedQuery, String> {
+    saved_queries::create_saved_query(&state.db.pool, &name, &query).await
+}
@@ -0,0 +1,8 @@
+use crate::library::saved_queries;
+use crate::AppState;
+use tauri::{command, State};
+
+#[command]
+pub async fn delete_saved_query(id: i64, state: State<'_, AppState>) -> Result<(), String> {
+    saved_queries::delete_saved_query(&state.db.pool, id).await
+}
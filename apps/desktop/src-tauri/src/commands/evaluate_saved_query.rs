@@ -0,0 +1,15 @@
+use crate::library::saved_queries;
+use crate::library::search::{SearchOptions, SearchResult};
+use crate::AppState;
+use tauri::{command, State};
+
+/// Run a saved query against the current library
+#[command]
+pub async fn evaluate_saved_query(
+    id: i64,
+    options: Option<SearchOptions>,
+    state: State<'_, AppState>,
+) -> Result<SearchResult, String> {
+    let options = options.unwrap_or_default();
+    saved_queries::evaluate_saved_query(&state.db.pool, id, &options).await
+}
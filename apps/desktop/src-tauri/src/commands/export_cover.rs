@@ -0,0 +1,28 @@
+use crate::artwork::folder_art::{self, CoverFileName, ExportCoverReport};
+use crate::library::albums::{album_paths, AlbumSelector};
+use crate::AppState;
+use tauri::{command, State};
+
+/// Save the front cover of the selected files, or of an album, next to the tracks as
+/// folder.jpg, cover.jpg or AlbumArt.jpg
+#[command]
+pub async fn export_cover(
+    paths: Option<Vec<String>>,
+    album: Option<AlbumSelector>,
+    file_name: Option<CoverFileName>,
+    overwrite: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<ExportCoverReport, String> {
+    let paths = match (paths, album) {
+        (Some(paths), _) => paths,
+        (None, Some(album)) => album_paths(&state, &album).await?,
+        (None, None) => return Err("Nothing to export".to_string()),
+    };
+    folder_art::export_covers(
+        &state,
+        paths,
+        file_name.unwrap_or_default(),
+        overwrite.unwrap_or(false),
+    )
+    .await
+}
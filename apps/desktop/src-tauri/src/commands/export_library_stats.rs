@@ -0,0 +1,34 @@
+use crate::library::stats::{
+    library_stats, stats_to_csv, StatsExportFormat, DEFAULT_MIN_COVER_SIZE,
+};
+use crate::AppState;
+use std::path::PathBuf;
+use tauri::{command, State};
+
+/// Write the statistics report to `target` as JSON or CSV, picked from the extension if not given
+#[command]
+pub async fn export_library_stats(
+    target: String,
+    format: Option<StatsExportFormat>,
+    min_cover_size: Option<u32>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let target = PathBuf::from(target);
+    let format = format.unwrap_or_else(|| {
+        match target
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+        {
+            Some(ext) if ext == "csv" => StatsExportFormat::Csv,
+            _ => StatsExportFormat::Json,
+        }
+    });
+    let stats = library_stats(&state, min_cover_size.unwrap_or(DEFAULT_MIN_COVER_SIZE)).await?;
+    let content = match format {
+        StatsExportFormat::Json => {
+            serde_json::to_string_pretty(&stats).map_err(|e| e.to_string())?
+        }
+        StatsExportFormat::Csv => stats_to_csv(&stats),
+    };
+    std::fs::write(&target, content).map_err(|e| e.to_string())
+}
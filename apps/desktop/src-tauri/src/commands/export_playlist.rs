@@ -0,0 +1,31 @@
+use crate::library::saved_queries::saved_query_paths;
+use crate::playlist::{self, PathStyle, PlaylistFormat};
+use crate::AppState;
+use std::path::PathBuf;
+use tauri::{command, State};
+
+/// Write the selected files, or the results of a saved query, to a playlist.
+/// The format follows the target extension unless given. Returns the number of entries
+#[command]
+pub async fn export_playlist(
+    target: String,
+    paths: Option<Vec<String>>,
+    saved_query_id: Option<i64>,
+    format: Option<PlaylistFormat>,
+    path_style: Option<PathStyle>,
+    state: State<'_, AppState>,
+) -> Result<usize, String> {
+    let target = PathBuf::from(target);
+    let format = format
+        .or_else(|| PlaylistFormat::from_path(&target))
+        .ok_or("Unknown playlist format, use .m3u8, .pls or .xspf".to_string())?;
+
+    let paths = match (paths, saved_query_id) {
+        (Some(paths), _) => paths,
+        (None, Some(id)) => saved_query_paths(&state.db.pool, id).await?,
+        (None, None) => return Err("Nothing to export".to_string()),
+    };
+    let entries = playlist::track_entries(&state, &paths).await;
+    playlist::write_playlist(&target, format, path_style.unwrap_or_default(), &entries)?;
+    Ok(entries.len())
+}
@@ -0,0 +1,17 @@
+use crate::library::duplicates::{start_find_duplicates, DuplicateCriterion, DEFAULT_SIMILARITY};
+use tauri::{command, AppHandle};
+
+/// Look for duplicates by the given criteria. Returns the job id, the groups are sent on
+/// "duplicates-found"
+#[command]
+pub fn find_duplicates(
+    app_handle: AppHandle,
+    criteria: Vec<DuplicateCriterion>,
+    threshold: Option<f64>,
+) -> Result<String, String> {
+    if criteria.is_empty() {
+        return Err("No duplicate criteria given".to_string());
+    }
+    let threshold = threshold.unwrap_or(DEFAULT_SIMILARITY).clamp(0.0, 1.0);
+    Ok(start_find_duplicates(&app_handle, criteria, threshold))
+}
@@ -0,0 +1,20 @@
+use crate::analysis::fingerprint::run_fingerprints;
+use crate::AppState;
+use tauri::{async_runtime, command, AppHandle, State};
+
+/// Start a background Chromaprint fingerprinting job. Returns the job id
+#[command]
+pub fn generate_fingerprints(
+    app_handle: AppHandle,
+    paths: Vec<String>,
+    state: State<'_, AppState>,
+) -> String {
+    let job = state.jobs.start(&app_handle, "fingerprint", paths.len());
+    let id = job.id.clone();
+
+    async_runtime::spawn_blocking(move || {
+        run_fingerprints(job, app_handle, paths);
+    });
+
+    id
+}
@@ -0,0 +1,9 @@
+use crate::library::albums::{album_groups, AlbumGroup};
+use crate::AppState;
+use tauri::{command, State};
+
+/// Albums built from the tags, with missing and duplicated track numbers
+#[command]
+pub async fn get_albums(state: State<'_, AppState>) -> Result<Vec<AlbumGroup>, String> {
+    album_groups(&state).await
+}
@@ -42,5 +42,22 @@ pub fn get_all_columns(remove: Option<bool>) -> Vec<Column> {
             kind: frame_key.get_kind(),
         });
     }
+    if remove != Some(true) {
+        for (label, value) in [
+            ("Duration", "duration"),
+            ("Bitrate", "bitrate"),
+            ("Sample Rate", "sampleRate"),
+            ("Channels", "channels"),
+            ("Bit Depth", "bitsPerSample"),
+            ("Codec", "codec"),
+        ] {
+            columns.push(Column {
+                label: label.to_string(),
+                value: value.to_string(),
+                size: 100,
+                kind: ColumnKind::Text,
+            });
+        }
+    }
     columns
 }
@@ -0,0 +1,9 @@
+use crate::history::list::HistoryItem;
+use crate::AppState;
+use tauri::{command, State};
+
+/// Every action in the undo history, oldest first
+#[command]
+pub fn get_history(state: State<'_, AppState>) -> Vec<HistoryItem> {
+    state.history.lock().unwrap().list()
+}
@@ -0,0 +1,12 @@
+use crate::library::stats::{library_stats, LibraryStats, DEFAULT_MIN_COVER_SIZE};
+use crate::AppState;
+use tauri::{command, State};
+
+/// Library health: formats, tag fill rates, cover problems and inconsistent albums
+#[command]
+pub async fn get_library_stats(
+    min_cover_size: Option<u32>,
+    state: State<'_, AppState>,
+) -> Result<LibraryStats, String> {
+    library_stats(&state, min_cover_size.unwrap_or(DEFAULT_MIN_COVER_SIZE)).await
+}
@@ -0,0 +1,14 @@
+use crate::artwork::file_pictures;
+use crate::tag_manager::utils::{SerializableTagValue, TagValuesWrapper};
+use crate::AppState;
+use tauri::{command, State};
+
+/// Embedded pictures of `path` with their data. List views only carry references to them
+#[command]
+pub async fn get_pictures(
+    path: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<SerializableTagValue>, String> {
+    let pictures = file_pictures(&state, &path)?;
+    Ok(TagValuesWrapper(pictures).into())
+}
@@ -0,0 +1,14 @@
+use crate::artwork::thumbnails::{self, ThumbnailRef};
+use crate::AppState;
+use tauri::{command, State};
+
+/// Thumbnails of the front covers of `paths`, at the kept size closest to `size`.
+/// Load them from `thumbnail://localhost/<hash>/<size>` instead of sending the pictures over
+#[command]
+pub async fn get_thumbnails(
+    paths: Vec<String>,
+    size: u32,
+    state: State<'_, AppState>,
+) -> Result<Vec<ThumbnailRef>, String> {
+    thumbnails::get_thumbnails(&state, paths, size).await
+}
@@ -0,0 +1,9 @@
+use crate::artwork::folder_art::start_import_folder_art;
+use tauri::{command, AppHandle};
+
+/// Embed sidecar cover images (folder.jpg, cover.*, front.*...) into the tracks of each folder
+/// that have no picture yet. Returns the job id
+#[command]
+pub fn import_folder_art(app_handle: AppHandle, folders: Vec<String>) -> String {
+    start_import_folder_art(&app_handle, folders)
+}
@@ -0,0 +1,34 @@
+use crate::config::user::ViewMode;
+use crate::playlist::{self, ImportReport};
+use crate::utils::handle_import_files;
+use crate::AppState;
+use std::path::{Path, PathBuf};
+use tauri::{command, AppHandle, State};
+
+/// Read a playlist and resolve its entries against the library. In simple view the
+/// files that were found are added to the workspace
+#[command]
+pub async fn import_playlist(
+    app_handle: AppHandle,
+    path: String,
+    state: State<'_, AppState>,
+) -> Result<ImportReport, String> {
+    let library = playlist::library_paths(&state).await;
+    let report = playlist::import_playlist(Path::new(&path), &library)?;
+
+    if state.view_mode == ViewMode::Simple && !report.entries.is_empty() {
+        let paths: Vec<PathBuf> = {
+            let ws = state.workspace.lock().unwrap();
+            report
+                .entries
+                .iter()
+                .map(|e| PathBuf::from(&e.path))
+                .filter(|p| !ws.files.iter().any(|f| &f.path == p))
+                .collect()
+        };
+        if !paths.is_empty() {
+            handle_import_files(app_handle, paths, state);
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,8 @@
+use crate::library::saved_queries::{self, SavedQuery};
+use crate::AppState;
+use tauri::{command, State};
+
+#[command]
+pub async fn list_saved_queries(state: State<'_, AppState>) -> Result<Vec<SavedQuery>, String> {
+    saved_queries::list_saved_queries(&state.db.pool).await
+}
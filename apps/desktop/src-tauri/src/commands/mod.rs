@@ -1,23 +1,55 @@
+pub mod analyze_replay_gain;
+pub mod cancel_job;
+pub mod check_database;
 pub mod check_update;
 pub mod clean_up_file_names;
+pub mod create_saved_query;
+pub mod delete_saved_query;
+pub mod evaluate_saved_query;
+pub mod export_cover;
+pub mod export_library_stats;
+pub mod export_playlist;
+pub mod find_duplicates;
+pub mod generate_fingerprints;
+pub mod get_albums;
 pub mod get_all_columns;
 pub mod get_all_sidebar_items;
 pub mod get_folder_children;
 pub mod get_folder_config;
+pub mod get_history;
+pub mod get_library_stats;
 pub mod get_multi_frame_keys;
+pub mod get_pictures;
+pub mod get_thumbnails;
 pub mod get_workspace_files;
 pub mod get_workspace_root;
 pub mod import_files;
+pub mod import_folder_art;
 pub mod import_image;
 pub mod import_paths;
+pub mod import_playlist;
+pub mod list_saved_queries;
 pub mod open;
 pub mod open_default;
+pub mod optimize_database;
+pub mod preview_auto_tagging;
+pub mod preview_clean_up_file_names;
+pub mod preview_rename_files;
+pub mod process_covers;
 pub mod redo;
 pub mod remove_files;
 pub mod rename_files;
+pub mod rename_saved_query;
+pub mod repair_database;
 pub mod request_file;
+pub mod rescan_library;
+pub mod restore_original_tags;
+pub mod revert_history_action;
 pub mod save_frame_changes;
+pub mod search_library;
+pub mod set_album_fields;
 pub mod set_folder_config;
 pub mod undo;
 pub mod update_app;
 pub mod update_user_config;
+pub mod validate_pictures;
@@ -0,0 +1,8 @@
+use crate::database::integrity::{self, OptimizeReport};
+use crate::AppState;
+use tauri::{command, State};
+
+#[command]
+pub async fn optimize_database(state: State<'_, AppState>) -> Result<OptimizeReport, String> {
+    integrity::optimize_database(&state.db).await
+}
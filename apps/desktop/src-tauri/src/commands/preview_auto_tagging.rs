@@ -0,0 +1,24 @@
+use crate::config::folder::find_folder_config;
+use crate::tag_manager::tag_backend::{DefaultBackend, TagPreview};
+use crate::tag_manager::utils::Changes;
+use std::path::Path;
+use tauri::command;
+
+/// What the default values of each file's folder would change in it
+#[command]
+pub fn preview_auto_tagging(paths: Vec<String>) -> Vec<TagPreview> {
+    let backend = DefaultBackend::new();
+    paths
+        .into_iter()
+        .flat_map(|path| {
+            let config = Path::new(&path)
+                .parent()
+                .and_then(|folder| find_folder_config(&folder.to_string_lossy()))
+                .unwrap_or_default();
+            backend.preview_changes(&Changes {
+                paths: vec![path],
+                tags: config.default_values,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,14 @@
+use crate::tag_manager::utils::CleanupRule;
+use crate::workspace::PlannedRename;
+use crate::AppState;
+use tauri::{command, State};
+
+#[command]
+pub fn preview_clean_up_file_names(
+    options: Vec<CleanupRule>,
+    paths: Vec<String>,
+    state: State<'_, AppState>,
+) -> Vec<PlannedRename> {
+    let ws = state.workspace.lock().unwrap();
+    ws.plan_clean_up_file_names(&paths, &options)
+}
@@ -0,0 +1,13 @@
+use crate::workspace::PlannedRename;
+use crate::AppState;
+use tauri::{command, State};
+
+#[command]
+pub fn preview_rename_files(
+    pattern: &str,
+    paths: Vec<String>,
+    state: State<'_, AppState>,
+) -> Vec<PlannedRename> {
+    let ws = state.workspace.lock().unwrap();
+    ws.plan_rename_by_pattern(&paths, pattern)
+}
@@ -0,0 +1,8 @@
+use crate::artwork::process::{start_process_covers, CoverOptions};
+use tauri::{command, AppHandle};
+
+/// Resize, recompress or convert the embedded pictures of `paths`. Returns the job id
+#[command]
+pub fn process_covers(app_handle: AppHandle, paths: Vec<String>, options: CoverOptions) -> String {
+    start_process_covers(&app_handle, paths, options)
+}
@@ -1,14 +1,24 @@
+use crate::history::{Action, HistoryActionType};
 use crate::tag_manager::utils::SerializableFile;
 use crate::AppState;
 use std::path::PathBuf;
 use tauri::{command, AppHandle, Emitter, State};
 #[command]
 pub fn remove_files(app_handle: AppHandle, paths: Vec<String>, state: State<'_, AppState>) {
-    {
+    let removed: Vec<String> = {
         let mut ws = state.workspace.lock().unwrap();
-        for p in &paths {
-            ws.remove_file(&PathBuf::from(p));
-        }
+        paths
+            .into_iter()
+            .filter(|p| ws.remove_file(&PathBuf::from(p)))
+            .collect()
+    };
+    if !removed.is_empty() {
+        state.history.lock().unwrap().add(Action {
+            action_type: HistoryActionType::Workspace {
+                added: Vec::new(),
+                removed,
+            },
+        });
     }
     let serializable_files: Vec<SerializableFile> = {
         let ws = state.workspace.lock().unwrap();
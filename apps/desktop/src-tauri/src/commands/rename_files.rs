@@ -1,3 +1,6 @@
+use crate::backup;
+use crate::history::{Action, HistoryActionType, Rename};
+use crate::playlist;
 use crate::tag_manager::utils::SerializableFile;
 use crate::utils::RenameResultItem;
 use crate::AppState;
@@ -14,6 +17,27 @@ pub fn rename_files(
         ws.rename_by_pattern(paths, pattern)
     };
 
+    let renamed: Vec<(String, String)> = results
+        .iter()
+        .filter(|(_, _, res)| res.is_ok())
+        .map(|(old, new, _)| (old.clone(), new.clone()))
+        .collect();
+    playlist::follow_renames(&app_handle, &renamed);
+    backup::follow_renames(&renamed);
+    let renames: Vec<Rename> = renamed
+        .iter()
+        .filter(|(old, new)| old != new)
+        .map(|(old, new)| Rename {
+            from: old.clone(),
+            to: new.clone(),
+        })
+        .collect();
+    if !renames.is_empty() {
+        state.history.lock().unwrap().add(Action {
+            action_type: HistoryActionType::Renames(renames),
+        });
+    }
+
     let serializable_files: Vec<SerializableFile> = {
         let ws = state.workspace.lock().unwrap();
         ws.files
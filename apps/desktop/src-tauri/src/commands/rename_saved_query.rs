@@ -0,0 +1,12 @@
+use crate::library::saved_queries::{self, SavedQuery};
+use crate::AppState;
+use tauri::{command, State};
+
+#[command]
+pub async fn rename_saved_query(
+    id: i64,
+    name: String,
+    state: State<'_, AppState>,
+) -> Result<SavedQuery, String> {
+    saved_queries::rename_saved_query(&state.db.pool, id, &name).await
+}
@@ -0,0 +1,9 @@
+use crate::database::integrity::{self, IntegrityReport};
+use crate::AppState;
+use tauri::{command, State};
+
+/// Fix what `check_database` reports, returns the problems that were fixed
+#[command]
+pub async fn repair_database(state: State<'_, AppState>) -> Result<IntegrityReport, String> {
+    integrity::repair_database(&state.db).await
+}
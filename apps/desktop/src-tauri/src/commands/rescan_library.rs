@@ -0,0 +1,8 @@
+use crate::library::rescan::start_rescan;
+use tauri::{command, AppHandle};
+
+/// Rescan every import root now. Returns the job id
+#[command]
+pub fn rescan_library(app_handle: AppHandle) -> Result<String, String> {
+    start_rescan(&app_handle).ok_or("A rescan is already running".to_string())
+}
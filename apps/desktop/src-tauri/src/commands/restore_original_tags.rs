@@ -0,0 +1,24 @@
+use crate::backup::{self, RestoreReport};
+use crate::utils::refresh_files;
+use tauri::{async_runtime, command, AppHandle, Emitter};
+
+/// Put back the tags files had before their first write. A folder restores every backed up
+/// file inside it. The files are rewritten on a blocking thread
+#[command]
+pub async fn restore_original_tags(
+    app_handle: AppHandle,
+    paths: Vec<String>,
+) -> Result<RestoreReport, String> {
+    async_runtime::spawn_blocking(move || {
+        let report = backup::restore_original_tags(&paths);
+        if !report.errors.is_empty() {
+            let _ = app_handle.emit("error", report.errors.clone());
+        }
+        if !report.restored.is_empty() {
+            refresh_files(app_handle, report.restored.clone());
+        }
+        report
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
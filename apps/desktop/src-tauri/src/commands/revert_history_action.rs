@@ -0,0 +1,9 @@
+use crate::AppState;
+use tauri::{command, State};
+
+/// Take back one past action without undoing the ones after it
+#[command]
+pub fn revert_history_action(id: i64, state: State<'_, AppState>) -> Result<(), String> {
+    let mut history = state.history.lock().unwrap();
+    history.revert(id)
+}
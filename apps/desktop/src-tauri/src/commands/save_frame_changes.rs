@@ -1,26 +1,25 @@
 use crate::config::user::ViewMode;
-use crate::history::{Action, Frames, HistoryActionType};
+use crate::history::{Action, CoverChange, Frames, HistoryActionType};
 use crate::tag_manager::utils::{
     Changes, FrameChanges, FrameKey, SerializableFile, SerializableTagValue,
     SerializableTagValuesWrapper, TagValue, TagValuesWrapper,
 };
 use crate::utils::get_tags;
 
-use crate::tag_manager::tag_backend::{DefaultBackend, TagBackend};
+use crate::tag_manager::batch::write_batch;
+use crate::tag_manager::tag_backend::DefaultBackend;
 use crate::AppState;
 use std::collections::HashMap;
 use std::path::PathBuf;
 use tauri::{command, AppHandle, Emitter, State};
 
-use uuid::Uuid;
-
 #[command]
 pub async fn save_frame_changes(
     app_handle: AppHandle,
     frame_changes: FrameChanges,
     state: State<'_, AppState>,
 ) -> Result<(), ()> {
-    let mut before_changes: HashMap<Uuid, HashMap<FrameKey, Vec<SerializableTagValue>>> =
+    let mut before_changes: HashMap<String, HashMap<FrameKey, Vec<SerializableTagValue>>> =
         HashMap::new();
 
     if state.view_mode == ViewMode::Simple {
@@ -38,7 +37,7 @@ pub async fn save_frame_changes(
                     let existing_vals = wrapped_values.into();
                     frame_map.insert(frame.key, existing_vals);
                 }
-                before_changes.insert(f.id, frame_map);
+                before_changes.insert(p.clone(), frame_map);
             }
         });
         let mut tag_map: HashMap<FrameKey, Vec<TagValue>> = HashMap::new();
@@ -62,39 +61,61 @@ pub async fn save_frame_changes(
 
             write_changes.tags.insert(k, ser_vals);
         }
-        let res = backend.write_changes(&write_changes);
+        let report = write_batch(&backend, std::slice::from_ref(&write_changes));
         {
             let mut history = state.history.lock().unwrap();
 
             let mut ws = state.workspace.lock().unwrap();
-            history.add(Action {
-                action_type: HistoryActionType::Tags({
-                    let mut fc_map: HashMap<Uuid, Frames> = HashMap::new();
-                    for p in &frame_changes.paths {
-                        if let Some(file) = ws.files.iter().find(|f| f.path.to_string_lossy() == *p)
-                        {
-                            let before_map: HashMap<FrameKey, Vec<SerializableTagValue>> =
-                                before_changes
-                                    .get(&file.id)
-                                    .cloned()
-                                    .unwrap_or(HashMap::new());
-                            let mut after_map: HashMap<FrameKey, Vec<SerializableTagValue>> =
-                                HashMap::new();
-                            for frame in &frame_changes.frames {
-                                after_map.insert(frame.key, frame.values.clone());
-                            }
-                            fc_map.insert(
-                                file.id,
-                                Frames {
-                                    before: before_map,
-                                    after: after_map,
-                                },
-                            );
-                        }
+            let mut fc_map: HashMap<String, Frames> = HashMap::new();
+            for p in &frame_changes.paths {
+                if ws.files.iter().any(|f| f.path.to_string_lossy() == *p) {
+                    let before_map: HashMap<FrameKey, Vec<SerializableTagValue>> =
+                        before_changes.get(p).cloned().unwrap_or(HashMap::new());
+                    let mut after_map: HashMap<FrameKey, Vec<SerializableTagValue>> =
+                        HashMap::new();
+                    for frame in &frame_changes.frames {
+                        after_map.insert(frame.key, frame.values.clone());
                     }
+                    fc_map.insert(
+                        p.clone(),
+                        Frames {
+                            before: before_map,
+                            after: after_map,
+                        },
+                    );
+                }
+            }
+            // a picture edit on its own is kept as a cover change
+            let only_pictures = !frame_changes.frames.is_empty()
+                && frame_changes
+                    .frames
+                    .iter()
+                    .all(|f| f.key == FrameKey::AttachedPicture);
+            let action_type = if only_pictures {
+                HistoryActionType::Covers(
                     fc_map
-                }),
-            });
+                        .into_iter()
+                        .map(|(p, mut f)| {
+                            let change = CoverChange {
+                                before: f
+                                    .before
+                                    .remove(&FrameKey::AttachedPicture)
+                                    .unwrap_or_default(),
+                                after: f
+                                    .after
+                                    .remove(&FrameKey::AttachedPicture)
+                                    .unwrap_or_default(),
+                            };
+                            (p, change)
+                        })
+                        .collect(),
+                )
+            } else {
+                HistoryActionType::Tags(fc_map)
+            };
+            if report.committed {
+                history.add(Action { action_type });
+            }
             for pth in &frame_changes.paths {
                 ws.refresh_tags(&PathBuf::from(pth));
             }
@@ -106,8 +127,8 @@ pub async fn save_frame_changes(
                 .collect();
             let _ = app_handle.emit("workspace-updated", serializable_files);
         }
-        if res.len() > 0 {
-            app_handle.emit("error", res).unwrap();
+        if !report.committed {
+            app_handle.emit("error", report.errors()).unwrap();
         }
     } else {
         let current_files = get_tags(&state.db, frame_changes.paths.clone(), false).await;
@@ -138,7 +159,10 @@ pub async fn save_frame_changes(
             write_changes.tags.insert(k, ser_vals);
         }
 
-        backend.write_changes(&write_changes);
+        let report = write_batch(&backend, &[write_changes]);
+        if !report.committed {
+            let _ = app_handle.emit("error", report.errors());
+        }
     }
     return Ok(());
 }
@@ -0,0 +1,16 @@
+use crate::library::query::Query;
+use crate::library::search::{search, SearchOptions, SearchResult};
+use crate::AppState;
+use tauri::{command, State};
+
+/// Search the tag index, e.g. `artist:"Daft Punk" year:>=2000 -has:cover format:flac`
+#[command]
+pub async fn search_library(
+    query: String,
+    options: Option<SearchOptions>,
+    state: State<'_, AppState>,
+) -> Result<SearchResult, String> {
+    let query = Query::parse(&query)?;
+    let options = options.unwrap_or_default();
+    search(&state.db.pool, &query, &options).await
+}
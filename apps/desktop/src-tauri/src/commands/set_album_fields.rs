@@ -0,0 +1,17 @@
+use crate::library::albums::{self, AlbumSelector};
+use crate::tag_manager::batch::BatchReport;
+use crate::tag_manager::utils::SerializableTagFrame;
+use tauri::{async_runtime, command, AppHandle};
+
+/// Set album-level fields (cover, year, label, album artist...) on every track of an album.
+/// The tracks are written on a blocking thread
+#[command]
+pub async fn set_album_fields(
+    app_handle: AppHandle,
+    album: AlbumSelector,
+    frames: Vec<SerializableTagFrame>,
+) -> Result<BatchReport, String> {
+    async_runtime::spawn_blocking(move || albums::set_album_fields(&app_handle, &album, frames))
+        .await
+        .map_err(|e| e.to_string())?
+}
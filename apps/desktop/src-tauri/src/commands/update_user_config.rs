@@ -1,4 +1,6 @@
+use crate::backup;
 use crate::config::user::{get_config_path, load_config, save_config, PartialUserConfig};
+use crate::tag_manager::journal;
 
 use tauri::{command, AppHandle, Emitter};
 
@@ -33,6 +35,17 @@ pub fn update_user_config(patch: PartialUserConfig, app_handle: AppHandle) -> Re
     if let Some(show_diff_modal) = patch.show_diff_modal {
         config.show_diff_modal = show_diff_modal;
     }
+    if let Some(fsync_policy) = patch.fsync_policy {
+        config.fsync_policy = fsync_policy;
+        journal::set_policy(fsync_policy);
+    }
+    if patch.backup_originals.is_some() || patch.backup_retention_days.is_some() {
+        config.backup_originals = patch.backup_originals.unwrap_or(config.backup_originals);
+        config.backup_retention_days = patch
+            .backup_retention_days
+            .unwrap_or(config.backup_retention_days);
+        backup::set_policy(config.backup_originals, config.backup_retention_days);
+    }
 
     save_config(&path, &config).map_err(|e| format!("Save failed: {}", e))?;
     app_handle.emit("user-config-updated", config).unwrap();
@@ -0,0 +1,12 @@
+use crate::artwork::validate::{self, PictureValidationReport};
+use crate::AppState;
+use tauri::{command, State};
+
+/// List embedded pictures that are corrupted or whose MIME type doesn't match the data
+#[command]
+pub async fn validate_pictures(
+    paths: Vec<String>,
+    state: State<'_, AppState>,
+) -> Result<PictureValidationReport, String> {
+    validate::validate_pictures(&state, paths).await
+}
@@ -44,6 +44,13 @@ pub fn get_folder_config(path: &str) -> Result<FolderConfig, ()> {
     println!("Loaded config for {}: {:?}", path, config);
     Ok(config)
 }
+/// The config of a folder, None when it has none. Unlike `get_folder_config` this never creates
+/// the file
+pub fn find_folder_config(path: &str) -> Option<FolderConfig> {
+    let config_path = std::path::PathBuf::from(path).join(CONFIG_FILE);
+    let content = fs::read_to_string(config_path).ok()?;
+    serde_json::from_str(&content).ok()
+}
 pub fn save_folder_config(path: &str, config: &FolderConfig) -> std::io::Result<()> {
     let config_path = std::path::PathBuf::from(path).join(CONFIG_FILE);
     if !&config_path.exists() {
@@ -84,6 +84,23 @@ impl Default for Density {
     }
 }
 
+/// How hard file writes push data to disk before moving on
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum FsyncPolicy {
+    /// Sync the write journal, every staged file and the folders they land in
+    Always,
+    /// Sync only the write journal, leaving file contents to the OS
+    JournalOnly,
+    /// Never sync, fastest but a power cut can lose recent writes
+    Never,
+}
+
+impl Default for FsyncPolicy {
+    fn default() -> Self {
+        FsyncPolicy::Always
+    }
+}
+
 impl Default for ViewMode {
     fn default() -> Self {
         ViewMode::Folder
@@ -103,6 +120,11 @@ pub struct UserConfig {
     pub just_updated: bool,
 
     pub show_diff_modal: bool,
+    pub fsync_policy: FsyncPolicy,
+    /// Save the original tags of each file before its first write
+    pub backup_originals: bool,
+    /// Days backups are kept, 0 keeps them forever
+    pub backup_retention_days: u32,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -121,6 +143,9 @@ impl Default for UserConfig {
             just_updated: true,
             albums: vec![],
             show_diff_modal: false,
+            fsync_policy: FsyncPolicy::Always,
+            backup_originals: false,
+            backup_retention_days: 90,
             sidebar_items: vec![
                 SidebarItem {
                     label: "Title".into(),
@@ -267,6 +292,9 @@ pub struct PartialUserConfig {
     pub density: Option<Density>,
     pub show_diff_modal: Option<bool>,
     pub sidebar_items: Option<Vec<SidebarItem>>,
+    pub fsync_policy: Option<FsyncPolicy>,
+    pub backup_originals: Option<bool>,
+    pub backup_retention_days: Option<u32>,
 }
 pub const CONFIG_FILE: &str = "user_config.json";
 
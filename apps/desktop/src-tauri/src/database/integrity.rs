@@ -0,0 +1,361 @@
+use crate::database::Database;
+use crate::utils::{get_imported_folders, is_in_folder};
+
+use serde::Serialize;
+use sqlx::Row;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Tables whose rows belong to a row in `files`
+const FILE_TABLES: [&str; 9] = [
+    "tag_text",
+    "tag_pictures",
+    "tag_user_text",
+    "tag_user_url",
+    "tag_comment",
+    "freeform_tags",
+    "saved_query_results",
+    "fingerprints",
+    "audio_hashes",
+];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanedRows {
+    pub table: String,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderDrift {
+    pub path: String,
+    pub file_count: i64,
+    pub actual_file_count: i64,
+    pub total_file_count: i64,
+    pub actual_total_file_count: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityReport {
+    pub schema_version: i64,
+    /// Messages from `PRAGMA integrity_check`, empty when the file is sound
+    pub corruption: Vec<String>,
+    pub foreign_key_violations: u64,
+    /// Indexed files that are gone from disk while their import root is still reachable
+    pub missing_files: Vec<String>,
+    /// Indexed files no active import root contains anymore
+    pub unrooted_files: Vec<String>,
+    pub orphaned_rows: Vec<OrphanedRows>,
+    pub folder_drift: Vec<FolderDrift>,
+    /// Folder rows whose folder no longer exists
+    pub stale_folders: Vec<String>,
+    /// Thumbnails of pictures no indexed file carries anymore
+    pub unused_thumbnails: u64,
+}
+
+impl IntegrityReport {
+    pub fn is_clean(&self) -> bool {
+        self.corruption.is_empty()
+            && self.foreign_key_violations == 0
+            && self.missing_files.is_empty()
+            && self.unrooted_files.is_empty()
+            && self.orphaned_rows.is_empty()
+            && self.folder_drift.is_empty()
+            && self.stale_folders.is_empty()
+            && self.unused_thumbnails == 0
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OptimizeReport {
+    pub size_before: u64,
+    pub size_after: u64,
+}
+
+async fn corruption(db: &Database) -> Result<Vec<String>, String> {
+    let messages: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_all(&db.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(messages.into_iter().filter(|m| m != "ok").collect())
+}
+
+async fn foreign_key_violations(db: &Database) -> Result<u64, String> {
+    let rows = sqlx::query("PRAGMA foreign_key_check")
+        .fetch_all(&db.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(rows.len() as u64)
+}
+
+/// Split indexed paths that are gone into missing (root reachable) and unrooted (no root).
+/// Files under a root that can't be reached, an unplugged drive for example, are left alone
+async fn missing_files(db: &Database) -> Result<(Vec<String>, Vec<String>), String> {
+    let roots: Vec<PathBuf> = get_imported_folders(db)
+        .await
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+    let paths: Vec<String> = sqlx::query_scalar("SELECT path FROM files ORDER BY path")
+        .fetch_all(&db.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut missing = Vec::new();
+    let mut unrooted = Vec::new();
+    for path in paths {
+        let file = PathBuf::from(&path);
+        match roots.iter().find(|r| is_in_folder(r, &file)) {
+            None => unrooted.push(path),
+            Some(root) if root.is_dir() && !file.exists() => missing.push(path),
+            _ => {}
+        }
+    }
+    Ok((missing, unrooted))
+}
+
+async fn orphaned_rows(db: &Database) -> Result<Vec<OrphanedRows>, String> {
+    let mut out = Vec::new();
+    for table in FILE_TABLES {
+        let (column, parent) = if table == "saved_query_results" {
+            ("query_id", "SELECT id FROM saved_queries")
+        } else {
+            ("file_path", "SELECT path FROM files")
+        };
+        let sql = format!(
+            "SELECT COUNT(*) FROM {} WHERE {} NOT IN ({})",
+            table, column, parent
+        );
+        let mut count: i64 = sqlx::query_scalar(&sql)
+            .fetch_one(&db.pool)
+            .await
+            .map_err(|e| e.to_string())?;
+        if table == "saved_query_results" {
+            count += sqlx::query_scalar::<_, i64>(
+                "SELECT COUNT(*) FROM saved_query_results WHERE file_path NOT IN (SELECT path FROM files)",
+            )
+            .fetch_one(&db.pool)
+            .await
+            .map_err(|e| e.to_string())?;
+        }
+        if count > 0 {
+            out.push(OrphanedRows {
+                table: table.to_string(),
+                count: count as u64,
+            });
+        }
+    }
+    Ok(out)
+}
+
+const UNUSED_THUMBNAILS: &str =
+    "thumbnails WHERE hash NOT IN (SELECT hash FROM tag_pictures WHERE hash IS NOT NULL)";
+
+async fn unused_thumbnails(db: &Database) -> Result<u64, String> {
+    let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", UNUSED_THUMBNAILS))
+        .fetch_one(&db.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(count as u64)
+}
+
+/// Direct and recursive file counts of every folder row, from the files table
+async fn actual_folder_counts(db: &Database) -> Result<HashMap<String, (i64, i64)>, String> {
+    let folders: Vec<String> = sqlx::query_scalar("SELECT path FROM folders")
+        .fetch_all(&db.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    let files: Vec<String> = sqlx::query_scalar("SELECT path FROM files")
+        .fetch_all(&db.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut counts: HashMap<String, (i64, i64)> =
+        folders.into_iter().map(|f| (f, (0, 0))).collect();
+    for file in files {
+        let file = PathBuf::from(file);
+        let mut parent = file.parent();
+        let mut direct = true;
+        while let Some(dir) = parent {
+            if let Some(c) = counts.get_mut(dir.to_string_lossy().as_ref()) {
+                if direct {
+                    c.0 += 1;
+                }
+                c.1 += 1;
+            }
+            direct = false;
+            parent = dir.parent();
+        }
+    }
+    Ok(counts)
+}
+
+async fn folder_problems(db: &Database) -> Result<(Vec<FolderDrift>, Vec<String>), String> {
+    let actual = actual_folder_counts(db).await?;
+    let rows = sqlx::query(
+        "SELECT path, COALESCE(file_count, 0) AS file_count, COALESCE(total_file_count, 0) AS total_file_count FROM folders ORDER BY path",
+    )
+    .fetch_all(&db.pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut drift = Vec::new();
+    let mut stale = Vec::new();
+    for row in rows {
+        let path: String = row.get("path");
+        if !Path::new(&path).is_dir() {
+            stale.push(path);
+            continue;
+        }
+        let (actual_file_count, actual_total_file_count) =
+            actual.get(&path).copied().unwrap_or((0, 0));
+        let file_count: i64 = row.get("file_count");
+        let total_file_count: i64 = row.get("total_file_count");
+        if file_count != actual_file_count || total_file_count != actual_total_file_count {
+            drift.push(FolderDrift {
+                path,
+                file_count,
+                actual_file_count,
+                total_file_count,
+                actual_total_file_count,
+            });
+        }
+    }
+    Ok((drift, stale))
+}
+
+/// Look for problems without changing anything
+pub async fn check_database(db: &Database) -> Result<IntegrityReport, String> {
+    let (missing_files, unrooted_files) = missing_files(db).await?;
+    let (folder_drift, stale_folders) = folder_problems(db).await?;
+    Ok(IntegrityReport {
+        schema_version: db.schema_version().await?,
+        corruption: corruption(db).await?,
+        foreign_key_violations: foreign_key_violations(db).await?,
+        missing_files,
+        unrooted_files,
+        orphaned_rows: orphaned_rows(db).await?,
+        folder_drift,
+        stale_folders,
+        unused_thumbnails: unused_thumbnails(db).await?,
+    })
+}
+
+/// Fix what `check_database` finds. Returns the report of the problems that were there
+pub async fn repair_database(db: &Database) -> Result<IntegrityReport, String> {
+    let report = check_database(db).await?;
+    if report.is_clean() {
+        return Ok(report);
+    }
+
+    if !report.corruption.is_empty() {
+        sqlx::query("REINDEX")
+            .execute(&db.pool)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    let mut tx = db.pool.begin().await.map_err(|e| e.to_string())?;
+    for path in report
+        .missing_files
+        .iter()
+        .chain(report.unrooted_files.iter())
+    {
+        sqlx::query("DELETE FROM files WHERE path = ?1")
+            .bind(path)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    // cascades only cover rows written since foreign keys were turned on
+    for table in FILE_TABLES {
+        let sql = if table == "saved_query_results" {
+            "DELETE FROM saved_query_results WHERE query_id NOT IN (SELECT id FROM saved_queries) OR file_path NOT IN (SELECT path FROM files)".to_string()
+        } else {
+            format!(
+                "DELETE FROM {} WHERE file_path NOT IN (SELECT path FROM files)",
+                table
+            )
+        };
+        sqlx::query(&sql)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    for path in report.stale_folders.iter() {
+        sqlx::query("DELETE FROM folders WHERE path = ?1")
+            .bind(path)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    // also those of pictures that went with the files removed above. A thumbnail still
+    // needed is rendered again on request
+    sqlx::query(&format!("DELETE FROM {}", UNUSED_THUMBNAILS))
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    // children of removed folders point nowhere now
+    sqlx::query(
+        "DELETE FROM folders WHERE parent_path IS NOT NULL AND parent_path NOT IN (SELECT path FROM folders)",
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    // counts change with the rows removed above
+    let actual = actual_folder_counts(db).await?;
+    for (path, (direct, total)) in actual {
+        sqlx::query("UPDATE folders SET file_count = ?2, total_file_count = ?3 WHERE path = ?1")
+            .bind(path)
+            .bind(direct)
+            .bind(total)
+            .execute(&db.pool)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    for fts in ["tag_text_fts", "tag_comment_fts"] {
+        sqlx::query(&format!("INSERT INTO {}({}) VALUES('rebuild')", fts, fts))
+            .execute(&db.pool)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(report)
+}
+
+async fn database_size(db: &Database) -> Result<u64, String> {
+    let pages: i64 = sqlx::query_scalar("PRAGMA page_count")
+        .fetch_one(&db.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    let page_size: i64 = sqlx::query_scalar("PRAGMA page_size")
+        .fetch_one(&db.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok((pages * page_size).max(0) as u64)
+}
+
+/// Refresh planner statistics, compact the file and fold the WAL back in
+pub async fn optimize_database(db: &Database) -> Result<OptimizeReport, String> {
+    let size_before = database_size(db).await?;
+    for statement in [
+        "PRAGMA optimize",
+        "VACUUM",
+        "PRAGMA wal_checkpoint(TRUNCATE)",
+    ] {
+        sqlx::query(statement)
+            .execute(&db.pool)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(OptimizeReport {
+        size_before,
+        size_after: database_size(db).await?,
+    })
+}
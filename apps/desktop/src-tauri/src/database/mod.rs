@@ -1,7 +1,13 @@
+pub mod integrity;
+
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
 use sqlx::Executor;
 use std::fs;
 use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Name of the library database in the app data folder
+pub const DATABASE_FILE: &str = "audexis.db";
 
 #[derive(Clone)]
 pub struct Database {
@@ -9,19 +15,17 @@ pub struct Database {
 }
 
 impl Database {
-    pub async fn init(db_path: &PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
-        let config_folder = db_path.parent();
-        if config_folder.is_none() {
-            panic!("No Config folder bruh")
-        }
-        let config_folder = config_folder.unwrap();
-        println!("{}", &config_folder.as_os_str().display());
-        fs::create_dir_all(config_folder).expect("Could not create config folder");
-
-        let exists = fs::exists(db_path)?.then(|| ());
-        exists.is_none().then(|| {
-            fs::File::create(db_path).expect("Failed to create database file");
-        });
+    pub async fn init(db_path: &PathBuf) -> Result<Self, String> {
+        let config_folder = db_path
+            .parent()
+            .ok_or(format!("{} has no parent folder", db_path.display()))?;
+        fs::create_dir_all(config_folder).map_err(|e| {
+            format!(
+                "Could not create config folder {}: {}",
+                config_folder.display(),
+                e
+            )
+        })?;
 
         let connection_options = SqliteConnectOptions::new()
             .create_if_missing(true)
@@ -32,14 +36,57 @@ impl Database {
             .after_connect(|conn, _| {
                 Box::pin(async move {
                     conn.execute("PRAGMA journal_mode=WAL;").await?;
+                    conn.execute("PRAGMA foreign_keys=ON;").await?;
                     Ok(())
                 })
             })
             .connect_with(connection_options)
-            .await?;
+            .await
+            .map_err(|e| format!("Could not open {}: {}", db_path.display(), e))?;
 
-        sqlx::migrate!("./migrations").run(&pool).await?;
+        Self::migrate(pool).await
+    }
 
+    /// Throwaway database used when the real one can't be opened, so the app still starts
+    pub async fn in_memory() -> Result<Self, String> {
+        let connection_options =
+            SqliteConnectOptions::from_str("sqlite::memory:").map_err(|e| e.to_string())?;
+        // every connection to :memory: opens a database of its own, so the pool keeps exactly
+        // one and never closes it
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .after_connect(|conn, _| {
+                Box::pin(async move {
+                    conn.execute("PRAGMA foreign_keys=ON;").await?;
+                    Ok(())
+                })
+            })
+            .connect_with(connection_options)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Self::migrate(pool).await
+    }
+
+    async fn migrate(pool: SqlitePool) -> Result<Self, String> {
+        sqlx::migrate!("./migrations")
+            .run(&pool)
+            .await
+            .map_err(|e| format!("Database migration failed: {}", e))?;
         Ok(Self { pool })
     }
+
+    /// Version of the last migration applied
+    pub async fn schema_version(&self) -> Result<i64, String> {
+        sqlx::query_scalar::<_, Option<i64>>(
+            "SELECT MAX(version) FROM _sqlx_migrations WHERE success = 1",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map(|v| v.unwrap_or(0))
+        .map_err(|e| e.to_string())
+    }
 }
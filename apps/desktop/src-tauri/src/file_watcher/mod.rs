@@ -19,8 +19,9 @@ use crate::config::folder::get_folder_config;
 use crate::config::user::load_config;
 use crate::config::user::ViewMode;
 use crate::config::user::CONFIG_FILE;
+use crate::library::saved_queries::refresh_saved_queries;
+use crate::tag_manager::batch::write_batch;
 use crate::tag_manager::tag_backend::DefaultBackend;
-use crate::tag_manager::tag_backend::TagBackend;
 use crate::tag_manager::utils::Changes;
 use crate::tag_manager::utils::SerializableFile;
 use crate::utils::delete_file_path;
@@ -538,6 +539,23 @@ impl FileWatcher {
             }
         }
         if is_folder_view == true {
+            let mut changed: Vec<String> = Vec::new();
+            for op in op_events.iter() {
+                match op {
+                    OpEvent::Create(f) if !f.is_directory => {
+                        changed.push(f.path.to_string_lossy().to_string())
+                    }
+                    OpEvent::Modify(f) if !f.is_directory => {
+                        changed.push(f.path.to_string_lossy().to_string());
+                        if f.old_path != f.path {
+                            changed.push(f.old_path.to_string_lossy().to_string());
+                        }
+                    }
+                    OpEvent::Remove(f) => changed.push(f.path.to_string_lossy().to_string()),
+                    _ => {}
+                }
+            }
+            refresh_saved_queries(app_handle, changed);
             app_handle.emit("folder-view-events", op_events).ok();
             return;
         }
@@ -676,5 +694,5 @@ fn handle_auto_tagging(file_path: PathBuf) {
         tags: config.default_values,
     };
     let backend = DefaultBackend::new();
-    backend.write_changes(&changes);
+    write_batch(&backend, &[changes]);
 }
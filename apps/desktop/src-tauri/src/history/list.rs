@@ -0,0 +1,190 @@
+use super::{CoverChange, Frames, History, HistoryActionType, Rename};
+use crate::tag_manager::utils::FrameKey;
+
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+/// One action as the history browser shows it
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryItem {
+    pub id: i64,
+    pub created_at: i64,
+    pub kind: &'static str,
+    pub summary: String,
+    pub file_count: usize,
+    /// Undone and waiting to be redone
+    pub undone: bool,
+    /// The action an undo would take back next
+    pub current: bool,
+}
+
+/// "TrackNumber" as "Track Number"
+fn frame_label(key: &FrameKey) -> String {
+    let name = format!("{:?}", key);
+    let mut out = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if i > 0 && c.is_uppercase() {
+            out.push(' ');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn files(count: usize) -> String {
+    if count == 1 {
+        "1 file".to_string()
+    } else {
+        format!("{} files", count)
+    }
+}
+
+/// A file and the frame of it an action changes, None when the action is about the whole file
+pub type Touched = (String, Option<FrameKey>);
+
+/// Whether two actions change any of the same frames or files
+pub fn overlaps(a: &[Touched], b: &[Touched]) -> bool {
+    a.iter().any(|(path, key)| {
+        b.iter().any(|(other, other_key)| {
+            path == other && (key.is_none() || other_key.is_none() || key == other_key)
+        })
+    })
+}
+
+impl HistoryActionType {
+    /// What the action did, in a few words
+    pub fn summary(&self) -> String {
+        match self {
+            HistoryActionType::Tags(fc) => {
+                let keys: BTreeSet<String> = fc
+                    .values()
+                    .flat_map(|f| f.after.keys().map(frame_label))
+                    .collect();
+                let cleared = fc.values().all(|f| f.after.values().all(|v| v.is_empty()));
+                let names = match keys.len() {
+                    0 => "tags".to_string(),
+                    1..=3 => keys.into_iter().collect::<Vec<_>>().join(", "),
+                    n => format!("{} fields", n),
+                };
+                let verb = if cleared { "Cleared" } else { "Set" };
+                format!("{} {} on {}", verb, names, files(fc.len()))
+            }
+            HistoryActionType::Renames(renames) => format!("Renamed {}", files(renames.len())),
+            HistoryActionType::Workspace { added, removed } => match (added.len(), removed.len()) {
+                (a, 0) => format!("Added {} to the workspace", files(a)),
+                (0, r) => format!("Removed {} from the workspace", files(r)),
+                (a, r) => format!(
+                    "Added {} to and removed {} from the workspace",
+                    files(a),
+                    files(r)
+                ),
+            },
+            HistoryActionType::Covers(covers) => {
+                let removed = covers.values().all(|c| c.after.is_empty());
+                let verb = if removed { "Removed" } else { "Changed" };
+                format!("{} the cover of {}", verb, files(covers.len()))
+            }
+            HistoryActionType::Revert { summary, .. } => format!("Revert \"{}\"", summary),
+        }
+    }
+
+    /// Number of files the action changed
+    pub fn file_count(&self) -> usize {
+        match self {
+            HistoryActionType::Tags(fc) => fc.len(),
+            HistoryActionType::Renames(renames) => renames.len(),
+            HistoryActionType::Workspace { added, removed } => added.len() + removed.len(),
+            HistoryActionType::Covers(covers) => covers.len(),
+            HistoryActionType::Revert { action, .. } => action.file_count(),
+        }
+    }
+
+    /// Frames and files the action changes
+    pub fn touched(&self) -> Vec<Touched> {
+        match self {
+            HistoryActionType::Tags(fc) => fc
+                .iter()
+                .flat_map(|(path, f)| f.after.keys().map(|k| (path.clone(), Some(*k))))
+                .collect(),
+            HistoryActionType::Covers(covers) => covers
+                .keys()
+                .map(|path| (path.clone(), Some(FrameKey::AttachedPicture)))
+                .collect(),
+            HistoryActionType::Renames(_) => self
+                .written_paths()
+                .into_iter()
+                .map(|path| (path, None))
+                .collect(),
+            HistoryActionType::Workspace { added, removed } => added
+                .iter()
+                .chain(removed.iter())
+                .map(|path| (path.clone(), None))
+                .collect(),
+            HistoryActionType::Revert { action, .. } => action.touched(),
+        }
+    }
+
+    /// The action that takes this one back
+    pub fn inverse(&self) -> HistoryActionType {
+        match self {
+            HistoryActionType::Tags(fc) => HistoryActionType::Tags(
+                fc.iter()
+                    .map(|(path, f)| {
+                        let frames = Frames {
+                            before: f.after.clone(),
+                            after: f.before.clone(),
+                        };
+                        (path.clone(), frames)
+                    })
+                    .collect(),
+            ),
+            HistoryActionType::Renames(renames) => HistoryActionType::Renames(
+                renames
+                    .iter()
+                    .rev()
+                    .map(|r| Rename {
+                        from: r.to.clone(),
+                        to: r.from.clone(),
+                    })
+                    .collect(),
+            ),
+            HistoryActionType::Workspace { added, removed } => HistoryActionType::Workspace {
+                added: removed.clone(),
+                removed: added.clone(),
+            },
+            HistoryActionType::Covers(covers) => HistoryActionType::Covers(
+                covers
+                    .iter()
+                    .map(|(path, c)| {
+                        let change = CoverChange {
+                            before: c.after.clone(),
+                            after: c.before.clone(),
+                        };
+                        (path.clone(), change)
+                    })
+                    .collect(),
+            ),
+            HistoryActionType::Revert { action, .. } => action.inverse(),
+        }
+    }
+}
+
+impl History {
+    /// Every action in the history, oldest first
+    pub fn list(&self) -> Vec<HistoryItem> {
+        self.changes
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| HistoryItem {
+                id: entry.id,
+                created_at: entry.created_at,
+                kind: entry.action.action_type.kind(),
+                summary: entry.action.action_type.summary(),
+                file_count: entry.action.action_type.file_count(),
+                undone: i as isize > self.cursor,
+                current: i as isize == self.cursor,
+            })
+            .collect()
+    }
+}
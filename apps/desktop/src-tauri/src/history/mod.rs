@@ -1,57 +1,352 @@
-use crate::tag_manager::tag_backend::{DefaultBackend, TagBackend};
+pub mod list;
+pub mod moves;
+pub mod store;
+
+use crate::backup;
+use crate::config::user::ViewMode;
+use crate::database::Database;
+use crate::playlist;
+use crate::tag_manager::batch::write_batch;
+use crate::tag_manager::tag_backend::{BackendError, DefaultBackend, TagError};
 use crate::tag_manager::utils::Changes;
-use crate::tag_manager::utils::{FrameKey, SerializableTagValue};
+use crate::tag_manager::utils::{File, FrameKey, SerializableTagValue, TagValuesWrapper};
+use crate::utils::{get_tags, move_indexed_file, refresh_files};
 use crate::AppState;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
 use std::collections::HashMap;
-use tauri::State;
-use tauri::{AppHandle, Emitter};
-use uuid::Uuid;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use store::{file_hash, file_stamp, now, FileStamp, HistoryEntry, StoreOp, HISTORY_SIZE_CAP};
+use tauri::async_runtime;
+use tauri::{AppHandle, Emitter, Manager};
 #[derive(Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct HistoryUpdate {
     pub can_undo: bool,
     pub can_redo: bool,
+    /// Files an undo or redo failed to write, the action was left as it was
+    pub errors: Vec<BackendError>,
 }
 pub struct History {
-    pub changes: Vec<Action>,
+    pub changes: Vec<HistoryEntry>,
     pub app_handle: AppHandle,
     pub cursor: isize,
+    /// Size and modification time of every file in the history as the last recorded write
+    /// left it. Their content hashes are only in the database
+    stamps: HashMap<String, Option<FileStamp>>,
+    next_id: i64,
+    store: Sender<StoreOp>,
+    pool: SqlitePool,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Frames {
     pub before: HashMap<FrameKey, Vec<SerializableTagValue>>,
     pub after: HashMap<FrameKey, Vec<SerializableTagValue>>,
 }
+/// Files as they are now, from the workspace or the index
+pub async fn current_files(state: &AppState, paths: &[String]) -> Vec<File> {
+    if state.view_mode == ViewMode::Simple {
+        let ws = state.workspace.lock().unwrap();
+        ws.files
+            .iter()
+            .filter(|f| paths.contains(&f.path.to_string_lossy().to_string()))
+            .cloned()
+            .collect()
+    } else {
+        get_tags(&state.db, paths.to_vec(), false)
+            .await
+            .unwrap_or_default()
+    }
+}
+
+/// Current values of `keys` for each path, to be able to undo
+pub async fn current_frames(
+    state: &AppState,
+    paths: &[String],
+    keys: &[FrameKey],
+) -> HashMap<String, HashMap<FrameKey, Vec<SerializableTagValue>>> {
+    current_files(state, paths)
+        .await
+        .into_iter()
+        .map(|file| {
+            let frames = keys
+                .iter()
+                .map(|key| {
+                    let values = file.tags.get(key).cloned().unwrap_or_default();
+                    (*key, TagValuesWrapper(values).into())
+                })
+                .collect();
+            (file.path.to_string_lossy().to_string(), frames)
+        })
+        .collect()
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Rename {
+    pub from: String,
+    pub to: String,
+}
+
+/// Pictures of one file before and after
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CoverChange {
+    pub before: Vec<SerializableTagValue>,
+    pub after: Vec<SerializableTagValue>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value")]
 pub enum HistoryActionType {
-    Tags(HashMap<Uuid, Frames>),
+    /// Tag changes keyed by path
+    Tags(HashMap<String, Frames>),
+    /// Files moved or renamed in one go
+    Renames(Vec<Rename>),
+    /// Files added to or removed from the workspace, the files themselves are left alone
+    Workspace {
+        added: Vec<String>,
+        removed: Vec<String>,
+    },
+    /// Embedded pictures replaced, keyed by path
+    Covers(HashMap<String, CoverChange>),
+    /// An earlier action taken back on its own, without undoing what came after it
+    Revert {
+        id: i64,
+        summary: String,
+        action: Box<HistoryActionType>,
+    },
+}
+
+/// What applying an action did
+#[derive(Default)]
+pub struct Applied {
+    /// Files changed on disk or in the workspace, under the name they have now
+    pub written: Vec<String>,
+    pub errors: Vec<BackendError>,
+}
+
+/// Write the frames of each file, all of them or none
+fn write_frames(frames: HashMap<String, HashMap<FrameKey, Vec<SerializableTagValue>>>) -> Applied {
+    let batch: Vec<Changes> = frames
+        .into_iter()
+        .map(|(path, tags)| Changes {
+            tags,
+            paths: vec![path],
+        })
+        .collect();
+    let report = write_batch(&DefaultBackend::new(), &batch);
+    if !report.committed {
+        return Applied {
+            written: Vec::new(),
+            errors: report.errors(),
+        };
+    }
+    Applied {
+        written: report.files.into_iter().map(|f| f.path).collect(),
+        errors: Vec::new(),
+    }
+}
+
+/// Move the entry of `from` to `to`, true when there was one
+fn rekey<T>(map: &mut HashMap<String, T>, from: &str, to: &str) -> bool {
+    match map.remove(from) {
+        Some(value) => {
+            map.insert(to.to_string(), value);
+            true
+        }
+        None => false,
+    }
+}
+
+fn move_error(from: &str, to: &str, e: String) -> BackendError {
+    BackendError::WriteFailed(TagError {
+        path: from.to_string(),
+        public_message: format!("Could not move {} to {}: {}", from, to, e),
+        internal_message: e,
+    })
 }
 
 impl HistoryActionType {
-    pub fn apply(&self, app: &State<'_, AppState>, is_undo: bool) {
+    /// Name the action is stored under
+    pub fn kind(&self) -> &'static str {
         match self {
-            HistoryActionType::Tags(fc) => {
-                let ws = app.workspace.lock().unwrap();
+            HistoryActionType::Tags(_) => "tags",
+            HistoryActionType::Renames(_) => "renames",
+            HistoryActionType::Workspace { .. } => "workspace",
+            HistoryActionType::Covers(_) => "covers",
+            HistoryActionType::Revert { .. } => "revert",
+        }
+    }
 
-                fc.iter().for_each(|(id, fc)| {
-                    let file = ws.get_file(*id);
-                    if file.is_none() {
-                        return;
-                    }
-                    let file = file.unwrap();
-                    let frames = if is_undo {
-                        fc.before.clone()
+    /// Files on disk the action writes to, under every name they have in it
+    pub fn written_paths(&self) -> Vec<String> {
+        match self {
+            HistoryActionType::Tags(fc) => fc.keys().cloned().collect(),
+            HistoryActionType::Renames(renames) => renames
+                .iter()
+                .flat_map(|r| [r.from.clone(), r.to.clone()])
+                .collect(),
+            HistoryActionType::Workspace { .. } => Vec::new(),
+            HistoryActionType::Covers(covers) => covers.keys().cloned().collect(),
+            HistoryActionType::Revert { action, .. } => action.written_paths(),
+        }
+    }
+
+    /// Whether the action moves `from` to `to` when applied
+    fn records_move(&self, from: &str, to: &str) -> bool {
+        match self {
+            HistoryActionType::Renames(renames) => {
+                renames.iter().any(|r| r.from == from && r.to == to)
+            }
+            HistoryActionType::Revert { action, .. } => action.records_move(from, to),
+            _ => false,
+        }
+    }
+
+    /// Point the action at a file's new name after it was renamed outside the app. A rename is
+    /// followed on the side the file is at, `to` while the action is applied. True when
+    /// anything changed
+    fn follow_rename(&mut self, from: &str, to: &str, applied: bool) -> bool {
+        match self {
+            HistoryActionType::Tags(fc) => rekey(fc, from, to),
+            HistoryActionType::Covers(covers) => rekey(covers, from, to),
+            HistoryActionType::Renames(renames) => {
+                let mut changed = false;
+                for rename in renames.iter_mut() {
+                    let side = if applied {
+                        &mut rename.to
                     } else {
-                        fc.after.clone()
+                        &mut rename.from
                     };
-                    println!("{:?}", frames);
-                    let backend = DefaultBackend::new();
-                    backend.write_changes(&Changes {
-                        tags: frames,
-                        paths: vec![file.path.to_string_lossy().to_string()],
-                    });
-                });
+                    if side == from {
+                        *side = to.to_string();
+                        changed = true;
+                    }
+                }
+                changed
+            }
+            HistoryActionType::Workspace { added, removed } => {
+                let mut changed = false;
+                for path in added.iter_mut().chain(removed.iter_mut()) {
+                    if path == from {
+                        *path = to.to_string();
+                        changed = true;
+                    }
+                }
+                changed
+            }
+            HistoryActionType::Revert { action, .. } => action.follow_rename(from, to, applied),
+        }
+    }
+
+    pub fn apply(&self, app_handle: &AppHandle, is_undo: bool) -> Applied {
+        let state = app_handle.state::<AppState>();
+        match self {
+            HistoryActionType::Tags(fc) => {
+                let frames = fc
+                    .iter()
+                    .map(|(path, fc)| {
+                        let frames = if is_undo { &fc.before } else { &fc.after };
+                        (path.clone(), frames.clone())
+                    })
+                    .collect();
+                write_frames(frames)
+            }
+            HistoryActionType::Covers(covers) => {
+                let frames = covers
+                    .iter()
+                    .map(|(path, c)| {
+                        let values = if is_undo { &c.before } else { &c.after };
+                        (
+                            path.clone(),
+                            HashMap::from([(FrameKey::AttachedPicture, values.clone())]),
+                        )
+                    })
+                    .collect();
+                write_frames(frames)
+            }
+            HistoryActionType::Renames(renames) => {
+                let moves: Vec<(String, String)> = if is_undo {
+                    renames
+                        .iter()
+                        .rev()
+                        .map(|r| (r.to.clone(), r.from.clone()))
+                        .collect()
+                } else {
+                    renames
+                        .iter()
+                        .map(|r| (r.from.clone(), r.to.clone()))
+                        .collect()
+                };
+                let mut applied = Applied::default();
+                let mut moved: Vec<(String, String)> = Vec::new();
+                for (from, to, res) in moves::move_files(&moves) {
+                    match res {
+                        Ok(()) => moved.push((from, to)),
+                        Err(e) => applied.errors.push(move_error(&from, &to, e)),
+                    }
+                }
+                // moves go all or none too, the ones that went through are moved back
+                if !applied.errors.is_empty() {
+                    let back: Vec<(String, String)> = moved
+                        .iter()
+                        .rev()
+                        .map(|(from, to)| (to.clone(), from.clone()))
+                        .collect();
+                    let mut stuck: Vec<(String, String)> = Vec::new();
+                    for (from, to, res) in moves::move_files(&back) {
+                        if let Err(e) = res {
+                            applied.errors.push(move_error(&from, &to, e));
+                            stuck.push((to, from));
+                        }
+                    }
+                    moved = stuck;
+                }
+                if state.view_mode == ViewMode::Simple {
+                    let mut ws = state.workspace.lock().unwrap();
+                    for (from, to) in moved.iter() {
+                        if let Some(file) = ws.get_file_by_path_mut(Path::new(from)) {
+                            file.path = PathBuf::from(to);
+                        }
+                    }
+                } else {
+                    for (from, to) in moved.iter() {
+                        move_indexed_file(app_handle, Path::new(from), Path::new(to));
+                    }
+                }
+                playlist::follow_renames(app_handle, &moved);
+                backup::follow_renames(&moved);
+                applied.written = moved.into_iter().map(|(_, to)| to).collect();
+                applied
             }
+            HistoryActionType::Workspace { added, removed } => {
+                let (remove, import) = if is_undo {
+                    (added, removed)
+                } else {
+                    (removed, added)
+                };
+                let mut applied = Applied::default();
+                {
+                    let mut ws = state.workspace.lock().unwrap();
+                    for path in remove {
+                        if ws.remove_file(&PathBuf::from(path)) {
+                            applied.written.push(path.clone());
+                        }
+                    }
+                    for path in import {
+                        match ws.import(PathBuf::from(path)) {
+                            Ok(()) => applied.written.push(path.clone()),
+                            Err(e) => applied.errors.push(e),
+                        }
+                    }
+                }
+                if let Ok(mut watcher) = state.file_watcher.lock() {
+                    let _ = watcher.watch_workspace();
+                }
+                applied
+            }
+            HistoryActionType::Revert { action, .. } => action.apply(app_handle, is_undo),
         }
     }
 }
@@ -60,67 +355,283 @@ pub struct Action {
 }
 
 impl Action {
-    pub fn undo(&self, app: &State<'_, AppState>) {
-        self.action_type.apply(app, true);
+    pub fn undo(&self, app_handle: &AppHandle) -> Applied {
+        self.run(app_handle, true)
+    }
+
+    pub fn redo(&self, app_handle: &AppHandle) -> Applied {
+        self.run(app_handle, false)
     }
 
-    pub fn redo(&self, app: &State<'_, AppState>) {
-        self.action_type.apply(app, false);
+    fn run(&self, app_handle: &AppHandle, is_undo: bool) -> Applied {
+        self.action_type.apply(app_handle, is_undo)
     }
 }
 impl History {
-    pub fn new(app: &AppHandle) -> Self {
+    /// History of the previous sessions, read back from the database
+    pub fn new(app: &AppHandle, db: &Database) -> Self {
+        let stored = async_runtime::block_on(store::load(&db.pool)).unwrap_or_else(|e| {
+            eprintln!("history failed to load: {e}");
+            store::StoredHistory {
+                entries: Vec::new(),
+                cursor: -1,
+                stamps: HashMap::new(),
+            }
+        });
         Self {
             app_handle: app.clone(),
-            changes: Vec::new(),
-            cursor: -1,
+            next_id: stored.entries.last().map(|e| e.id + 1).unwrap_or(1),
+            changes: stored.entries,
+            cursor: stored.cursor,
+            stamps: stored.stamps,
+            store: store::spawn_writer(db.pool.clone()),
+            pool: db.pool.clone(),
         }
     }
-    pub fn clear(&mut self) {
-        self.changes.clear();
-        self.cursor = -1;
+    fn emit_update(&self, errors: Vec<BackendError>) {
         let payload = HistoryUpdate {
-            can_redo: (self.cursor + 1) <= self.changes.len() as isize - 1,
+            can_redo: self.cursor + 1 < self.changes.len() as isize,
             can_undo: self.cursor >= 0,
+            errors,
         };
         let _ = self.app_handle.emit("history_update", payload);
     }
+    fn save(&self, op: StoreOp) {
+        let _ = self.store.send(op);
+    }
+    /// Stamp the files again after the history wrote to them
+    fn record_stamps(&mut self, paths: Vec<String>) {
+        let stamps: Vec<(String, Option<FileStamp>)> = paths
+            .into_iter()
+            .map(|p| {
+                let stamp = file_stamp(&p);
+                (p, stamp)
+            })
+            .collect();
+        for (path, stamp) in stamps.iter() {
+            self.stamps.insert(path.clone(), *stamp);
+        }
+        self.save(StoreOp::Stamps(stamps));
+    }
+    /// Files of the action that were changed by something else since the history last wrote
+    /// them. Files whose stamp moved are hashed and compared with the recorded content hash
+    fn changed_on_disk(&self, action: &Action) -> Vec<String> {
+        action
+            .action_type
+            .written_paths()
+            .into_iter()
+            .filter(|p| match self.stamps.get(p) {
+                Some(Some(stamp)) if file_stamp(p) != Some(*stamp) => {
+                    let recorded = async_runtime::block_on(store::recorded_hash(&self.pool, p));
+                    recorded.is_none() || recorded != file_hash(p)
+                }
+                _ => false,
+            })
+            .collect()
+    }
+    /// Follow a file renamed outside the app, so its actions and stamp go with it. Renames the
+    /// history made itself are already where they belong
+    pub fn follow_rename(&mut self, from: &str, to: &str) {
+        let cursor = self.cursor;
+        let made_here = self.changes.iter().enumerate().any(|(idx, e)| {
+            if idx as isize <= cursor {
+                e.action.action_type.records_move(from, to)
+            } else {
+                e.action.action_type.records_move(to, from)
+            }
+        });
+        if made_here {
+            return;
+        }
+        let mut payloads = Vec::new();
+        for (idx, entry) in self.changes.iter_mut().enumerate() {
+            if entry
+                .action
+                .action_type
+                .follow_rename(from, to, idx as isize <= cursor)
+            {
+                let payload = serde_json::to_string(&entry.action.action_type).unwrap_or_default();
+                entry.size = payload.len();
+                payloads.push((entry.id, payload));
+            }
+        }
+        let stamped = rekey(&mut self.stamps, from, to);
+        if stamped || !payloads.is_empty() {
+            self.save(StoreOp::Follow {
+                from: from.to_string(),
+                to: to.to_string(),
+                payloads,
+            });
+        }
+    }
+    /// Drop the oldest entries until the history fits in the size cap
+    fn trim(&mut self) {
+        let mut total: usize = self.changes.iter().map(|e| e.size).sum();
+        let mut removed = Vec::new();
+        while total > HISTORY_SIZE_CAP && self.changes.len() > 1 {
+            let entry = self.changes.remove(0);
+            total -= entry.size;
+            removed.push(entry.id);
+            self.cursor -= 1;
+        }
+        if !removed.is_empty() {
+            let kept: std::collections::HashSet<String> = self
+                .changes
+                .iter()
+                .flat_map(|e| e.action.action_type.written_paths())
+                .collect();
+            self.stamps.retain(|p, _| kept.contains(p));
+            self.save(StoreOp::Remove(removed));
+        }
+    }
+    /// Take back one applied action without undoing the ones after it, recorded as a new
+    /// action. Refused when a later action touched the same frames or files, or when one of its
+    /// files changed on disk since
+    pub fn revert(&mut self, id: i64) -> Result<(), String> {
+        let idx = self
+            .changes
+            .iter()
+            .position(|e| e.id == id)
+            .ok_or("No such history entry".to_string())?;
+        if idx as isize > self.cursor {
+            return Err("That action is undone already".to_string());
+        }
+        let entry = &self.changes[idx];
+        let touched = entry.action.action_type.touched();
+        let later = self.changes[idx + 1..=self.cursor as usize]
+            .iter()
+            .find(|e| list::overlaps(&touched, &e.action.action_type.touched()));
+        if let Some(later) = later {
+            return Err(format!(
+                "Can't revert, changed again by \"{}\"",
+                later.action.action_type.summary()
+            ));
+        }
+        let changed = self.changed_on_disk(&entry.action);
+        if !changed.is_empty() {
+            return Err(format!(
+                "Can't revert, changed on disk since: {}",
+                changed.join(", ")
+            ));
+        }
+        let applied = entry.action.undo(&self.app_handle);
+        let revert = HistoryActionType::Revert {
+            id,
+            summary: entry.action.action_type.summary(),
+            action: Box::new(entry.action.action_type.inverse()),
+        };
+        if applied.errors.is_empty() {
+            self.add(Action {
+                action_type: revert,
+            });
+        } else {
+            let paths = entry.action.action_type.written_paths();
+            self.record_stamps(paths);
+        }
+        self.finish(applied, "revert")
+    }
+    /// Refresh what an undo, redo or revert changed and tell the frontend how it went
+    fn finish(&mut self, applied: Applied, what: &str) -> Result<(), String> {
+        refresh_files(self.app_handle.clone(), applied.written);
+        let failed = applied.errors.len();
+        if failed == 0 {
+            return Ok(());
+        }
+        self.emit_update(applied.errors);
+        Err(format!(
+            "Could not {}, {} failed to write and nothing was changed",
+            what,
+            if failed == 1 {
+                "1 file".to_string()
+            } else {
+                format!("{} files", failed)
+            }
+        ))
+    }
+    pub fn clear(&mut self) {
+        self.changes.clear();
+        self.stamps.clear();
+        self.cursor = -1;
+        self.save(StoreOp::Clear);
+        self.emit_update(Vec::new());
+    }
     pub fn add(&mut self, change: Action) {
         if (self.cursor) < self.changes.len() as isize - 1 {
-            self.changes.truncate((self.cursor + 1) as usize);
+            let dropped: Vec<i64> = self
+                .changes
+                .drain((self.cursor + 1) as usize..)
+                .map(|e| e.id)
+                .collect();
+            self.save(StoreOp::Remove(dropped));
         }
 
-        self.changes.push(change);
+        let payload = serde_json::to_string(&change.action_type).unwrap_or_default();
+        let paths = change.action_type.written_paths();
+        let entry = HistoryEntry {
+            id: self.next_id,
+            created_at: now(),
+            size: payload.len(),
+            action: change,
+        };
+        self.next_id += 1;
+        self.save(StoreOp::Insert {
+            id: entry.id,
+            created_at: entry.created_at,
+            kind: entry.action.action_type.kind(),
+            payload,
+            paths: paths.clone(),
+        });
+        self.changes.push(entry);
+        self.record_stamps(paths);
 
         self.cursor = self.changes.len() as isize - 1;
-        let payload = HistoryUpdate {
-            can_redo: (self.cursor + 1) <= self.changes.len() as isize - 1,
-            can_undo: self.cursor >= 0,
-        };
-        let _ = self.app_handle.emit("history_update", payload);
+        self.trim();
+        self.emit_update(Vec::new());
     }
-    pub fn undo(&mut self, app: &State<'_, AppState>) {
+    /// Undo the last action. Refused when one of its files changed on disk since
+    pub fn undo(&mut self) -> Result<(), String> {
         if self.cursor >= 0 {
-            let action = &self.changes[self.cursor as usize];
-            action.undo(app);
-            self.cursor -= 1;
-            let payload = HistoryUpdate {
-                can_redo: (self.cursor + 1) <= self.changes.len() as isize - 1,
-                can_undo: self.cursor >= 0,
-            };
-            let _ = self.app_handle.emit("history_update", payload);
-        }
-    }
-    pub fn redo(&mut self, app: &State<'_, AppState>) {
-        if (self.cursor + 1) <= self.changes.len() as isize - 1 {
-            self.cursor += 1;
-            let action = &self.changes[self.cursor as usize];
-            action.redo(app);
-            let payload = HistoryUpdate {
-                can_redo: (self.cursor + 1) <= self.changes.len() as isize - 1,
-                can_undo: self.cursor >= 0,
-            };
-            let _ = self.app_handle.emit("history_update", payload);
+            let entry = &self.changes[self.cursor as usize];
+            let changed = self.changed_on_disk(&entry.action);
+            if !changed.is_empty() {
+                return Err(format!(
+                    "Can't undo, changed on disk since: {}",
+                    changed.join(", ")
+                ));
+            }
+            let applied = entry.action.undo(&self.app_handle);
+            let (id, paths) = (entry.id, entry.action.action_type.written_paths());
+            self.record_stamps(paths);
+            if applied.errors.is_empty() {
+                self.save(StoreOp::SetUndone(id, true));
+                self.cursor -= 1;
+                self.emit_update(Vec::new());
+            }
+            return self.finish(applied, "undo");
+        }
+        Ok(())
+    }
+    /// Redo the next action. Refused when one of its files changed on disk since
+    pub fn redo(&mut self) -> Result<(), String> {
+        if self.cursor + 1 < self.changes.len() as isize {
+            let entry = &self.changes[(self.cursor + 1) as usize];
+            let changed = self.changed_on_disk(&entry.action);
+            if !changed.is_empty() {
+                return Err(format!(
+                    "Can't redo, changed on disk since: {}",
+                    changed.join(", ")
+                ));
+            }
+            let applied = entry.action.redo(&self.app_handle);
+            let (id, paths) = (entry.id, entry.action.action_type.written_paths());
+            self.record_stamps(paths);
+            if applied.errors.is_empty() {
+                self.save(StoreOp::SetUndone(id, false));
+                self.cursor += 1;
+                self.emit_update(Vec::new());
+            }
+            return self.finish(applied, "redo");
         }
+        Ok(())
     }
 }
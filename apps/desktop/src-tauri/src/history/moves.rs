@@ -0,0 +1,82 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where a file waits between the two halves of a batch move
+fn staging_path(from: &Path) -> PathBuf {
+    let name = from
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let mut staged = from.with_file_name(format!(".{}.audexis-move", name));
+    let mut idx = 1;
+    while staged.exists() {
+        staged = from.with_file_name(format!(".{}.audexis-move{}", name, idx));
+        idx += 1;
+    }
+    staged
+}
+
+/// Whether both paths name the same file, as a case-only rename does on macOS and Windows
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Move each file from the first path to the second. Every file is first moved out of the way,
+/// so names swapped or chained within the batch don't collide. A target taken by a file outside
+/// the batch is never overwritten
+pub fn move_files(moves: &[(String, String)]) -> Vec<(String, String, Result<(), String>)> {
+    let sources: HashSet<&str> = moves.iter().map(|(from, _)| from.as_str()).collect();
+    let mut results = Vec::new();
+    let mut staged = Vec::new();
+
+    for (from, to) in moves {
+        if from == to {
+            continue;
+        }
+        let (from_path, to_path) = (Path::new(from), Path::new(to));
+        if to_path.exists() && !sources.contains(to.as_str()) && !same_file(from_path, to_path) {
+            results.push((
+                from.clone(),
+                to.clone(),
+                Err(format!("{} already exists", to)),
+            ));
+            continue;
+        }
+        let tmp = staging_path(from_path);
+        match fs::rename(from_path, &tmp) {
+            Ok(_) => staged.push((from, to, tmp)),
+            Err(e) => results.push((from.clone(), to.clone(), Err(e.to_string()))),
+        }
+    }
+
+    // a target can still be taken here, by a source that failed to stage or by an earlier entry
+    // with the same target, and a rename would replace it
+    for (from, to, tmp) in staged {
+        let res = if Path::new(to).exists() {
+            Err(format!("{} already exists", to))
+        } else {
+            fs::rename(&tmp, to).map_err(|e| e.to_string())
+        };
+        match res {
+            Ok(()) => results.push((from.clone(), to.clone(), Ok(()))),
+            Err(e) => results.push((from.clone(), to.clone(), Err(put_back(&tmp, from, e)))),
+        }
+    }
+    results
+}
+
+/// Move a staged file back to where it came from, unless that name was taken meanwhile
+fn put_back(tmp: &Path, from: &str, error: String) -> String {
+    if Path::new(from).exists() {
+        return format!("{}, the file was left at {}", error, tmp.display());
+    }
+    match fs::rename(tmp, from) {
+        Ok(()) => error,
+        Err(e) => format!("{}, the file was left at {}: {}", error, tmp.display(), e),
+    }
+}
@@ -0,0 +1,285 @@
+use super::{Action, HistoryActionType};
+
+use sha2::{Digest, Sha256};
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::UNIX_EPOCH;
+use tauri::async_runtime;
+
+/// Total size of the serialized actions kept, older ones are dropped past it
+pub const HISTORY_SIZE_CAP: usize = 64 * 1024 * 1024;
+
+/// An action as it sits in the history
+pub struct HistoryEntry {
+    pub id: i64,
+    pub created_at: i64,
+    pub action: Action,
+    /// Size of the serialized action, counted against the cap
+    pub size: usize,
+}
+
+/// Everything loaded back at startup
+pub struct StoredHistory {
+    pub entries: Vec<HistoryEntry>,
+    pub cursor: isize,
+    pub stamps: HashMap<String, Option<FileStamp>>,
+}
+
+/// Changes to the stored history, applied in order on a thread of their own so callers
+/// inside async commands never have to wait on the database
+pub enum StoreOp {
+    Insert {
+        id: i64,
+        created_at: i64,
+        kind: &'static str,
+        payload: String,
+        paths: Vec<String>,
+    },
+    SetUndone(i64, bool),
+    Remove(Vec<i64>),
+    /// Stamps as the history left the files, their content hashes are taken on the writer
+    /// thread
+    Stamps(Vec<(String, Option<FileStamp>)>),
+    /// A file renamed outside the app, with the actions that mention it serialized again
+    Follow {
+        from: String,
+        to: String,
+        payloads: Vec<(i64, String)>,
+    },
+    Clear,
+}
+
+/// Size and modification time of a file. Equal stamps mean the file is unchanged, different
+/// ones are settled by the content hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    pub size: u64,
+    /// Nanoseconds since the epoch
+    pub mtime: i64,
+}
+
+/// None when the file can't be read
+pub fn file_stamp(path: &str) -> Option<FileStamp> {
+    let meta = fs::metadata(path).ok()?;
+    let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(FileStamp {
+        size: meta.len(),
+        mtime: mtime.as_nanos() as i64,
+    })
+}
+
+/// SHA-256 of the whole file, None when it can't be read
+pub fn file_hash(path: &str) -> Option<String> {
+    let mut file = File::open(path).ok()?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 65536];
+    loop {
+        let read = file.read(&mut buf).ok()?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    Some(
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect(),
+    )
+}
+
+/// Content hash recorded for a file, None when there is none yet
+pub async fn recorded_hash(pool: &SqlitePool, path: &str) -> Option<String> {
+    sqlx::query_scalar::<_, Option<String>>("SELECT hash FROM history_file_stamps WHERE path = ?1")
+        .bind(path)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .flatten()
+}
+
+pub fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+async fn apply_op(pool: &SqlitePool, op: StoreOp) -> Result<(), sqlx::Error> {
+    match op {
+        StoreOp::Insert {
+            id,
+            created_at,
+            kind,
+            payload,
+            paths,
+        } => {
+            let mut tx = pool.begin().await?;
+            sqlx::query(
+                "INSERT INTO history_actions (id, kind, payload, size, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            )
+            .bind(id)
+            .bind(kind)
+            .bind(&payload)
+            .bind(payload.len() as i64)
+            .bind(created_at)
+            .execute(&mut *tx)
+            .await?;
+            for path in paths {
+                sqlx::query(
+                    "INSERT OR IGNORE INTO history_files (action_id, path) VALUES (?1, ?2)",
+                )
+                .bind(id)
+                .bind(path)
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+        }
+        StoreOp::SetUndone(id, undone) => {
+            sqlx::query("UPDATE history_actions SET undone = ?2 WHERE id = ?1")
+                .bind(id)
+                .bind(undone)
+                .execute(pool)
+                .await?;
+        }
+        StoreOp::Remove(ids) => {
+            let mut tx = pool.begin().await?;
+            for id in ids {
+                sqlx::query("DELETE FROM history_actions WHERE id = ?1")
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            sqlx::query(
+                "DELETE FROM history_file_stamps WHERE path NOT IN (SELECT path FROM history_files)",
+            )
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+        }
+        StoreOp::Stamps(stamps) => {
+            let mut tx = pool.begin().await?;
+            for (path, stamp) in stamps {
+                // a file changed again since it was stamped gets no hash, so it reads as changed
+                let hash = stamp
+                    .filter(|s| file_stamp(&path) == Some(*s))
+                    .and_then(|_| file_hash(&path));
+                sqlx::query(
+                    "INSERT INTO history_file_stamps (path, size, mtime, hash) VALUES (?1, ?2, ?3, ?4)
+                     ON CONFLICT(path) DO UPDATE SET size = excluded.size, mtime = excluded.mtime, hash = excluded.hash",
+                )
+                .bind(path)
+                .bind(stamp.map(|s| s.size as i64))
+                .bind(stamp.map(|s| s.mtime))
+                .bind(hash)
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+        }
+        StoreOp::Follow { from, to, payloads } => {
+            let mut tx = pool.begin().await?;
+            for (id, payload) in payloads {
+                sqlx::query("UPDATE history_actions SET payload = ?2, size = ?3 WHERE id = ?1")
+                    .bind(id)
+                    .bind(&payload)
+                    .bind(payload.len() as i64)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            for table in ["history_files", "history_file_stamps"] {
+                sqlx::query(&format!(
+                    "UPDATE OR REPLACE {} SET path = ?2 WHERE path = ?1",
+                    table
+                ))
+                .bind(&from)
+                .bind(&to)
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+        }
+        StoreOp::Clear => {
+            sqlx::query("DELETE FROM history_actions")
+                .execute(pool)
+                .await?;
+            sqlx::query("DELETE FROM history_file_stamps")
+                .execute(pool)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Start the thread that writes history changes to the database
+pub fn spawn_writer(pool: SqlitePool) -> Sender<StoreOp> {
+    let (tx, rx) = mpsc::channel::<StoreOp>();
+    thread::spawn(move || {
+        for op in rx {
+            if let Err(e) = async_runtime::block_on(apply_op(&pool, op)) {
+                eprintln!("saving history failed: {e}");
+            }
+        }
+    });
+    tx
+}
+
+/// History saved by previous sessions. Actions that no longer deserialize are skipped
+pub async fn load(pool: &SqlitePool) -> Result<StoredHistory, String> {
+    let rows = sqlx::query(
+        "SELECT id, payload, size, undone, created_at FROM history_actions ORDER BY id",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    let mut entries = Vec::new();
+    let mut cursor: isize = -1;
+    for row in rows {
+        let payload: String = row.get("payload");
+        let Ok(action_type) = serde_json::from_str::<HistoryActionType>(&payload) else {
+            eprintln!(
+                "skipping unreadable history entry {}",
+                row.get::<i64, _>("id")
+            );
+            continue;
+        };
+        if !row.get::<bool, _>("undone") {
+            cursor = entries.len() as isize;
+        }
+        entries.push(HistoryEntry {
+            id: row.get("id"),
+            created_at: row.get("created_at"),
+            action: Action { action_type },
+            size: row.get::<i64, _>("size") as usize,
+        });
+    }
+
+    let stamps = sqlx::query("SELECT path, size, mtime FROM history_file_stamps")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|row| {
+            let size: Option<i64> = row.get("size");
+            let mtime: Option<i64> = row.get("mtime");
+            let stamp = size.zip(mtime).map(|(size, mtime)| FileStamp {
+                size: size as u64,
+                mtime,
+            });
+            (row.get("path"), stamp)
+        })
+        .collect();
+
+    Ok(StoredHistory {
+        entries,
+        cursor,
+        stamps,
+    })
+}
@@ -0,0 +1,137 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum JobStatus {
+    Running,
+    Finished,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobProgress {
+    pub id: String,
+    pub kind: String,
+    pub done: usize,
+    pub total: usize,
+    pub current: Option<String>,
+    pub status: JobStatus,
+}
+
+/// Registry of running background jobs so they can be cancelled from the frontend
+#[derive(Clone)]
+pub struct Jobs {
+    running: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>,
+}
+
+impl Jobs {
+    pub fn new() -> Self {
+        Self {
+            running: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Register a new job and return its handle
+    pub fn start(&self, app_handle: &AppHandle, kind: &str, total: usize) -> Job {
+        let id = Uuid::new_v4().to_string();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.running
+            .lock()
+            .unwrap()
+            .insert(id.clone(), cancelled.clone());
+        let job = Job {
+            id,
+            kind: kind.to_string(),
+            total: Arc::new(AtomicUsize::new(total)),
+            done: Arc::new(AtomicUsize::new(0)),
+            cancelled,
+            running: self.running.clone(),
+            app_handle: app_handle.clone(),
+        };
+        job.emit(None, JobStatus::Running);
+        job
+    }
+
+    /// Ask a job to stop. Returns false if no job with that id is running
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.running.lock().unwrap().get(id) {
+            Some(flag) => {
+                flag.store(true, Ordering::SeqCst);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Job {
+    pub id: String,
+    pub kind: String,
+    total: Arc<AtomicUsize>,
+    done: Arc<AtomicUsize>,
+    cancelled: Arc<AtomicBool>,
+    running: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>,
+    app_handle: AppHandle,
+}
+
+impl Job {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn cancel_flag(&self) -> &AtomicBool {
+        &self.cancelled
+    }
+
+    /// Mark one unit of work as done and notify the frontend
+    pub fn advance(&self, current: &str) {
+        self.advance_by(1, current);
+    }
+
+    /// Mark several units of work as done with a single event, for jobs with many small steps
+    pub fn advance_by(&self, n: usize, current: &str) {
+        self.done.fetch_add(n, Ordering::SeqCst);
+        self.emit(Some(current.to_string()), JobStatus::Running);
+    }
+
+    /// For jobs that only learn how much work there is once they are underway
+    pub fn set_total(&self, total: usize) {
+        self.total.store(total, Ordering::SeqCst);
+    }
+
+    pub fn done(&self) -> usize {
+        self.done.load(Ordering::SeqCst)
+    }
+
+    /// Remove the job from the registry and send the final progress event
+    pub fn finish(&self) {
+        self.running.lock().unwrap().remove(&self.id);
+        let status = if self.is_cancelled() {
+            JobStatus::Cancelled
+        } else {
+            JobStatus::Finished
+        };
+        self.emit(None, status);
+    }
+
+    fn emit(&self, current: Option<String>, status: JobStatus) {
+        let _ = self.app_handle.emit(
+            "job-progress",
+            JobProgress {
+                id: self.id.clone(),
+                kind: self.kind.clone(),
+                done: self.done.load(Ordering::SeqCst),
+                total: self.total.load(Ordering::SeqCst),
+                current,
+                status,
+            },
+        );
+    }
+}
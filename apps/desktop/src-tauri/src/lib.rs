@@ -1,22 +1,33 @@
+mod analysis;
+mod artwork;
+mod backup;
+pub mod cli;
 mod commands;
 mod config;
 mod constants;
 mod database;
 mod file_watcher;
 mod history;
+mod jobs;
+mod library;
+mod playlist;
 mod tag_manager;
 mod utils;
 mod workspace;
 
 use crate::config::user::{load_config, Theme, ViewMode, CONFIG_FILE};
-use crate::database::Database;
+use crate::database::{Database, DATABASE_FILE};
 use crate::file_watcher::FileWatcher;
+use crate::jobs::Jobs;
+use crate::tag_manager::journal::{self, JOURNAL_FILE};
 use crate::utils::handle_file_associations;
 use crate::workspace::Workspace;
 
 use serde::Serialize;
 
+use rfd::{MessageDialog, MessageLevel};
 use std::env;
+use std::path::PathBuf;
 use std::sync::Mutex;
 
 use tauri::{
@@ -32,6 +43,27 @@ pub struct AppState {
     pub history: Mutex<history::History>,
     pub db: Database,
     pub view_mode: ViewMode,
+    pub jobs: Jobs,
+}
+/// Open the library database. When that fails the user is told why and the app runs on an
+/// in-memory database instead of crashing
+fn open_database(db_path: &PathBuf) -> Database {
+    let result = async_runtime::block_on(Database::init(db_path));
+    match result {
+        Ok(db) => db,
+        Err(e) => {
+            MessageDialog::new()
+                .set_level(MessageLevel::Error)
+                .set_title("Audexis")
+                .set_description(format!(
+                    "The library database could not be opened, changes to the library will not be saved.\n\n{}",
+                    e
+                ))
+                .show();
+            async_runtime::block_on(Database::in_memory())
+                .expect("In-memory database failed to initialize")
+        }
+    }
 }
 #[derive(Debug, Clone, Serialize)]
 pub struct FileNode {
@@ -87,11 +119,7 @@ pub fn run() {
                             }
                             let app_path = app_path.unwrap();
                             let db_path = app_path.join("audexis.db");
-                            let db = async_runtime::block_on(async {
-                                Database::init(&db_path)
-                                    .await
-                                    .expect("Database failed to initialize")
-                            });
+                            let db = open_database(&db_path);
                             let config_path = path.join(CONFIG_FILE);
 
                             let user_config = load_config(&config_path);
@@ -108,9 +136,10 @@ pub fn run() {
                             app.manage(AppState {
                                 workspace: Mutex::new(Workspace::new(&app)),
                                 file_watcher: Mutex::new(FileWatcher::new(&app)),
-                                history: Mutex::new(history::History::new(&app)),
+                                history: Mutex::new(history::History::new(&app, &db)),
                                 db: db,
                                 view_mode: user_config.view,
+                                jobs: Jobs::new(),
                             });
                             if let Ok(mut watcher) = app.state::<AppState>().file_watcher.lock() {
                                 let _ = watcher.watch_workspace();
@@ -151,16 +180,26 @@ pub fn run() {
                 .path()
                 .app_data_dir()
                 .expect("Failed to get app data directory");
-            let db_path = app.path().app_data_dir()?.join("audexis.db");
+            let db_path = app.path().app_data_dir()?.join(DATABASE_FILE);
 
-            let db = async_runtime::block_on(async {
-                Database::init(&db_path)
-                    .await
-                    .expect("Database failed to initialize")
-            });
+            let db = open_database(&db_path);
             let config_path = path.join(CONFIG_FILE);
 
             let user_config = load_config(&config_path);
+            // settle writes an earlier session was cut off in the middle of, before any new one
+            let recovery = journal::init(path.join(JOURNAL_FILE), user_config.fsync_policy);
+            if recovery.finished + recovery.rolled_back + recovery.unsettled > 0 {
+                eprintln!(
+                    "write journal: finished {}, rolled back {} and could not settle {} interrupted writes",
+                    recovery.finished, recovery.rolled_back, recovery.unsettled
+                );
+            }
+            backup::init(
+                path.join(backup::BACKUP_DIR),
+                &db,
+                user_config.backup_originals,
+                user_config.backup_retention_days,
+            );
             let theme = match user_config.theme {
                 Theme::Light => "light",
                 Theme::Dark => "dark",
@@ -193,13 +232,17 @@ pub fn run() {
             app.manage(AppState {
                 workspace: Mutex::new(Workspace::new(&app.handle())),
                 file_watcher: Mutex::new(FileWatcher::new(&app.handle())),
-                history: Mutex::new(history::History::new(&app.handle())),
+                history: Mutex::new(history::History::new(&app.handle(), &db)),
                 db: db,
                 view_mode: user_config.view,
+                jobs: Jobs::new(),
             });
             if let Ok(mut watcher) = app.state::<AppState>().file_watcher.lock() {
                 let _ = watcher.watch_workspace();
             }
+            if app.state::<AppState>().view_mode == ViewMode::Folder {
+                library::rescan::start_rescan_service(app.handle());
+            }
             if is_autostart {
                 return Ok(());
             }
@@ -253,6 +296,21 @@ pub fn run() {
         .plugin(tauri_plugin_os::init())
         .plugin(tauri_plugin_process::init())
         .plugin(tauri_plugin_updater::Builder::new().build())
+        .register_asynchronous_uri_scheme_protocol(
+            artwork::thumbnails::THUMBNAIL_SCHEME,
+            |ctx, request, responder| {
+                let app_handle = ctx.app_handle().clone();
+                async_runtime::spawn(async move {
+                    let response = artwork::thumbnails::thumbnail_response(
+                        &app_handle,
+                        request.uri().path(),
+                        request.uri().query(),
+                    )
+                    .await;
+                    responder.respond(response);
+                });
+            },
+        )
         .invoke_handler(tauri::generate_handler![
             commands::import_files::import_files,
             commands::get_workspace_files::get_workspace_files,
@@ -276,7 +334,39 @@ pub fn run() {
             commands::redo::redo,
             commands::get_workspace_root::get_workspace_root,
             commands::get_folder_children::get_folder_children,
-            commands::request_file::request_file
+            commands::request_file::request_file,
+            commands::analyze_replay_gain::analyze_replay_gain,
+            commands::cancel_job::cancel_job,
+            commands::generate_fingerprints::generate_fingerprints,
+            commands::find_duplicates::find_duplicates,
+            commands::search_library::search_library,
+            commands::create_saved_query::create_saved_query,
+            commands::list_saved_queries::list_saved_queries,
+            commands::rename_saved_query::rename_saved_query,
+            commands::delete_saved_query::delete_saved_query,
+            commands::evaluate_saved_query::evaluate_saved_query,
+            commands::export_playlist::export_playlist,
+            commands::import_playlist::import_playlist,
+            commands::get_library_stats::get_library_stats,
+            commands::export_library_stats::export_library_stats,
+            commands::get_albums::get_albums,
+            commands::set_album_fields::set_album_fields,
+            commands::check_database::check_database,
+            commands::repair_database::repair_database,
+            commands::optimize_database::optimize_database,
+            commands::rescan_library::rescan_library,
+            commands::get_thumbnails::get_thumbnails,
+            commands::get_pictures::get_pictures,
+            commands::process_covers::process_covers,
+            commands::export_cover::export_cover,
+            commands::import_folder_art::import_folder_art,
+            commands::validate_pictures::validate_pictures,
+            commands::get_history::get_history,
+            commands::revert_history_action::revert_history_action,
+            commands::restore_original_tags::restore_original_tags,
+            commands::preview_rename_files::preview_rename_files,
+            commands::preview_clean_up_file_names::preview_clean_up_file_names,
+            commands::preview_auto_tagging::preview_auto_tagging
         ])
         .build(tauri::generate_context!())
         .expect("Error while running Audexis")
@@ -0,0 +1,359 @@
+use crate::config::user::ViewMode;
+use crate::history::{current_frames, Action, Frames, HistoryActionType};
+use crate::tag_manager::batch::{write_batch, BatchReport};
+use crate::tag_manager::tag_backend::DefaultBackend;
+use crate::tag_manager::utils::{
+    Changes, FrameKey, SerializableTagFrame, SerializableTagValue, TagValue,
+};
+use crate::utils::refresh_files;
+use crate::AppState;
+
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tauri::{async_runtime, AppHandle, Emitter, Manager};
+
+/// Fields that describe the release rather than a single track
+pub const ALBUM_LEVEL_KEYS: [FrameKey; 21] = [
+    FrameKey::Album,
+    FrameKey::AlbumArtist,
+    FrameKey::AlbumArtistSort,
+    FrameKey::AlbumSort,
+    FrameKey::AttachedPicture,
+    FrameKey::Year,
+    FrameKey::OriginalYear,
+    FrameKey::OriginalDate,
+    FrameKey::Label,
+    FrameKey::Genre,
+    FrameKey::Compilation,
+    FrameKey::TotalDiscs,
+    FrameKey::CatalogNumber,
+    FrameKey::Barcode,
+    FrameKey::Asin,
+    FrameKey::Media,
+    FrameKey::ReleaseCountry,
+    FrameKey::ReleaseStatus,
+    FrameKey::ReleaseType,
+    FrameKey::MusicBrainzAlbumId,
+    FrameKey::MusicBrainzReleaseGroupId,
+];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumTrack {
+    pub path: String,
+    pub title: Option<String>,
+    pub track_number: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumGroup {
+    /// Album artist, or the track artist when the files have none
+    pub album_artist: String,
+    pub album: String,
+    pub disc: Option<u32>,
+    pub total_tracks: Option<u32>,
+    pub tracks: Vec<AlbumTrack>,
+    /// Track numbers between 1 and the total (or the highest number) nobody has
+    pub missing_tracks: Vec<u32>,
+    /// Track numbers used by more than one file
+    pub duplicate_tracks: Vec<u32>,
+    pub has_cover: bool,
+}
+
+/// Identifies an album across all of its discs, or one disc when `disc` is set
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumSelector {
+    pub album_artist: String,
+    pub album: String,
+    pub disc: Option<u32>,
+}
+
+/// Album artist and album, lowercased
+pub type AlbumKey = (String, String);
+
+/// The album a track belongs to, the same wherever tracks are grouped into albums: album
+/// artist, or the track artist when there is none, and album, ignoring case. None without an
+/// album
+pub fn album_key(text: impl Fn(FrameKey) -> Option<String>) -> Option<AlbumKey> {
+    let field = |key: FrameKey| {
+        text(key)
+            .map(|s| s.trim().to_lowercase())
+            .filter(|s| !s.is_empty())
+    };
+    let album = field(FrameKey::Album)?;
+    let album_artist = field(FrameKey::AlbumArtist)
+        .or_else(|| field(FrameKey::Artist))
+        .unwrap_or_default();
+    Some((album_artist, album))
+}
+
+struct TrackRow {
+    path: String,
+    key: AlbumKey,
+    album_artist: String,
+    album: String,
+    disc: Option<u32>,
+    track: Option<u32>,
+    total_tracks: Option<u32>,
+    title: Option<String>,
+    has_cover: bool,
+}
+
+/// "3", "03" or "3/12", the second number being the total
+fn parse_position(value: Option<&str>) -> (Option<u32>, Option<u32>) {
+    let Some(value) = value else {
+        return (None, None);
+    };
+    let (number, total) = match value.split_once('/') {
+        Some((n, t)) => (n, Some(t)),
+        None => (value, None),
+    };
+    (
+        number.trim().parse().ok().filter(|n| *n > 0),
+        total.and_then(|t| t.trim().parse().ok()).filter(|t| *t > 0),
+    )
+}
+
+fn track_row(
+    path: String,
+    text: impl Fn(FrameKey) -> Option<String>,
+    has_cover: bool,
+) -> Option<TrackRow> {
+    let key = album_key(&text)?;
+    let (track, total_from_track) = parse_position(text(FrameKey::TrackNumber).as_deref());
+    let (disc, _) = parse_position(text(FrameKey::DiscNumber).as_deref());
+    Some(TrackRow {
+        path,
+        key,
+        album_artist: text(FrameKey::AlbumArtist)
+            .or_else(|| text(FrameKey::Artist))
+            .unwrap_or_default(),
+        album: text(FrameKey::Album).unwrap_or_default(),
+        disc,
+        track,
+        total_tracks: text(FrameKey::TotalTracks)
+            .and_then(|t| t.trim().parse().ok())
+            .filter(|t| *t > 0)
+            .or(total_from_track),
+        title: text(FrameKey::Title),
+        has_cover,
+    })
+}
+
+fn workspace_rows(state: &AppState) -> Vec<TrackRow> {
+    let ws = state.workspace.lock().unwrap();
+    ws.files
+        .iter()
+        .filter_map(|file| {
+            let text = |key: FrameKey| match file.tags.get(&key).and_then(|v| v.first()) {
+                Some(TagValue::Text(s)) if !s.trim().is_empty() => Some(s.trim().to_string()),
+                _ => None,
+            };
+            let has_cover = file
+                .tags
+                .get(&FrameKey::AttachedPicture)
+                .map(|v| !v.is_empty())
+                .unwrap_or(false);
+            track_row(file.path.to_string_lossy().to_string(), text, has_cover)
+        })
+        .collect()
+}
+
+/// First value of each key per file, straight from the index. One pass over the tags,
+/// pivoted per file here
+async fn database_rows(state: &AppState) -> Result<Vec<TrackRow>, String> {
+    const KEYS: [FrameKey; 7] = [
+        FrameKey::AlbumArtist,
+        FrameKey::Artist,
+        FrameKey::Album,
+        FrameKey::DiscNumber,
+        FrameKey::TrackNumber,
+        FrameKey::TotalTracks,
+        FrameKey::Title,
+    ];
+    let pool = &state.db.pool;
+    let sql = format!(
+        "SELECT file_path, key, TRIM(value) AS value FROM tag_text WHERE key IN ({}) AND TRIM(value) != '' ORDER BY id",
+        vec!["?"; KEYS.len()].join(", ")
+    );
+    let mut query = sqlx::query(&sql);
+    for key in KEYS.iter() {
+        query = query.bind(key.to_string());
+    }
+    let by_name: HashMap<String, FrameKey> = KEYS.iter().map(|k| (k.to_string(), *k)).collect();
+    let mut files: BTreeMap<String, HashMap<FrameKey, String>> = BTreeMap::new();
+    for row in query.fetch_all(pool).await.map_err(|e| e.to_string())? {
+        let Some(key) = by_name.get(&row.get::<String, _>("key")) else {
+            continue;
+        };
+        files
+            .entry(row.get("file_path"))
+            .or_default()
+            .entry(*key)
+            .or_insert(row.get("value"));
+    }
+    let covers: HashSet<String> = sqlx::query_scalar("SELECT DISTINCT file_path FROM tag_pictures")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .collect();
+    Ok(files
+        .into_iter()
+        .filter_map(|(path, values)| {
+            let has_cover = covers.contains(&path);
+            track_row(path, |key| values.get(&key).cloned(), has_cover)
+        })
+        .collect())
+}
+
+async fn track_rows(state: &AppState) -> Result<Vec<TrackRow>, String> {
+    if state.view_mode == ViewMode::Simple {
+        Ok(workspace_rows(state))
+    } else {
+        database_rows(state).await
+    }
+}
+
+/// Files grouped into albums by album artist, album and disc
+pub async fn album_groups(state: &AppState) -> Result<Vec<AlbumGroup>, String> {
+    let mut groups: BTreeMap<(AlbumKey, Option<u32>), Vec<TrackRow>> = BTreeMap::new();
+    for row in track_rows(state).await? {
+        groups
+            .entry((row.key.clone(), row.disc))
+            .or_default()
+            .push(row);
+    }
+
+    Ok(groups
+        .into_values()
+        .map(|mut rows| {
+            rows.sort_by(|a, b| {
+                (a.track.unwrap_or(u32::MAX), &a.path).cmp(&(b.track.unwrap_or(u32::MAX), &b.path))
+            });
+            let total_tracks = rows.iter().filter_map(|r| r.total_tracks).max();
+            let mut counts: BTreeMap<u32, u32> = BTreeMap::new();
+            for n in rows.iter().filter_map(|r| r.track) {
+                *counts.entry(n).or_default() += 1;
+            }
+            let last = total_tracks
+                .into_iter()
+                .chain(counts.keys().next_back().copied())
+                .max()
+                .unwrap_or(0);
+            AlbumGroup {
+                album_artist: rows[0].album_artist.clone(),
+                album: rows[0].album.clone(),
+                disc: rows[0].disc,
+                total_tracks,
+                missing_tracks: (1..=last).filter(|n| !counts.contains_key(n)).collect(),
+                duplicate_tracks: counts
+                    .iter()
+                    .filter(|(_, c)| **c > 1)
+                    .map(|(n, _)| *n)
+                    .collect(),
+                has_cover: rows.iter().any(|r| r.has_cover),
+                tracks: rows
+                    .into_iter()
+                    .map(|r| AlbumTrack {
+                        path: r.path,
+                        title: r.title,
+                        track_number: r.track,
+                    })
+                    .collect(),
+            }
+        })
+        .collect())
+}
+
+/// Paths of every track of the selected album
+pub async fn album_paths(
+    state: &AppState,
+    selector: &AlbumSelector,
+) -> Result<Vec<String>, String> {
+    let key = |album_artist: &str, album: &str| {
+        album_key(|k| match k {
+            FrameKey::AlbumArtist => Some(album_artist.to_string()),
+            FrameKey::Album => Some(album.to_string()),
+            _ => None,
+        })
+    };
+    let selected = key(&selector.album_artist, &selector.album);
+    let paths: Vec<String> = album_groups(state)
+        .await?
+        .into_iter()
+        .filter(|g| {
+            key(&g.album_artist, &g.album) == selected
+                && (selector.disc.is_none() || g.disc == selector.disc)
+        })
+        .flat_map(|g| g.tracks.into_iter().map(|t| t.path))
+        .collect();
+    if paths.is_empty() {
+        return Err(format!("No tracks found for {}", selector.album));
+    }
+    Ok(paths)
+}
+
+/// Write album-level frames to every track of the selected album as a single history entry.
+/// Either every track is written or none is
+pub fn set_album_fields(
+    app_handle: &AppHandle,
+    selector: &AlbumSelector,
+    frames: Vec<SerializableTagFrame>,
+) -> Result<BatchReport, String> {
+    if let Some(frame) = frames.iter().find(|f| !ALBUM_LEVEL_KEYS.contains(&f.key)) {
+        return Err(format!("{} is not an album-level field", frame.key));
+    }
+    if frames.is_empty() {
+        return Ok(BatchReport {
+            committed: true,
+            files: Vec::new(),
+        });
+    }
+
+    let state = app_handle.state::<AppState>();
+    let paths = async_runtime::block_on(album_paths(&state, selector))?;
+
+    let keys: Vec<FrameKey> = frames.iter().map(|f| f.key).collect();
+    let before = async_runtime::block_on(current_frames(&state, &paths, &keys));
+    let after: HashMap<FrameKey, Vec<SerializableTagValue>> =
+        frames.into_iter().map(|f| (f.key, f.values)).collect();
+
+    let report = write_batch(
+        &DefaultBackend::new(),
+        &[Changes {
+            paths: paths.clone(),
+            tags: after.clone(),
+        }],
+    );
+    if !report.committed {
+        let _ = app_handle.emit("error", report.errors());
+        return Ok(report);
+    }
+
+    {
+        let mut history = state.history.lock().unwrap();
+        history.add(Action {
+            action_type: HistoryActionType::Tags(
+                paths
+                    .iter()
+                    .map(|p| {
+                        (
+                            p.clone(),
+                            Frames {
+                                before: before.get(p).cloned().unwrap_or_default(),
+                                after: after.clone(),
+                            },
+                        )
+                    })
+                    .collect(),
+            ),
+        });
+    }
+
+    refresh_files(app_handle.clone(), paths);
+    Ok(report)
+}
@@ -0,0 +1,244 @@
+use crate::tag_manager::properties::id3v2_size;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+const CHUNK: usize = 65536;
+
+/// Feed `from..to` of the file into the hasher
+fn hash_range(file: &mut File, hasher: &mut Sha256, from: u64, to: u64) -> Result<(), String> {
+    file.seek(SeekFrom::Start(from))
+        .map_err(|e| e.to_string())?;
+    let mut remaining = to.saturating_sub(from);
+    let mut buf = vec![0u8; CHUNK];
+    while remaining > 0 {
+        let want = remaining.min(CHUNK as u64) as usize;
+        file.read_exact(&mut buf[..want])
+            .map_err(|e| e.to_string())?;
+        hasher.update(&buf[..want]);
+        remaining -= want as u64;
+    }
+    Ok(())
+}
+
+fn read_at(file: &mut File, pos: u64, buf: &mut [u8]) -> bool {
+    file.seek(SeekFrom::Start(pos)).is_ok() && file.read_exact(buf).is_ok()
+}
+
+/// Skip any number of stacked ID3v2 tags at the start
+fn leading_tags(file: &mut File, file_size: u64) -> u64 {
+    let mut start = id3v2_size(file);
+    loop {
+        let mut header = [0u8; 10];
+        if start + 10 > file_size || !read_at(file, start, &mut header) || &header[0..3] != b"ID3" {
+            return start.min(file_size);
+        }
+        let size = ((header[6] as u64 & 0x7f) << 21)
+            | ((header[7] as u64 & 0x7f) << 14)
+            | ((header[8] as u64 & 0x7f) << 7)
+            | (header[9] as u64 & 0x7f);
+        start += 10 + size + if header[5] & 0x10 != 0 { 10 } else { 0 };
+    }
+}
+
+/// End of the audio once ID3v1, APEv2 and Lyrics3v2 tags at the end are removed
+fn trailing_tags(file: &mut File, start: u64, file_size: u64) -> u64 {
+    let mut end = file_size;
+    loop {
+        let before = end;
+
+        let mut id3v1 = [0u8; 3];
+        if end >= start + 128 && read_at(file, end - 128, &mut id3v1) && &id3v1 == b"TAG" {
+            end -= 128;
+        }
+
+        let mut footer = [0u8; 32];
+        if end >= start + 32 && read_at(file, end - 32, &mut footer) && &footer[0..8] == b"APETAGEX"
+        {
+            let size = u32::from_le_bytes([footer[12], footer[13], footer[14], footer[15]]) as u64;
+            let flags = u32::from_le_bytes([footer[20], footer[21], footer[22], footer[23]]);
+            let header = if flags & 0x8000_0000 != 0 { 32 } else { 0 };
+            end = end.saturating_sub(size + header).max(start);
+        }
+
+        let mut lyrics = [0u8; 15];
+        if end >= start + 15
+            && read_at(file, end - 15, &mut lyrics)
+            && &lyrics[6..15] == b"LYRICS200"
+        {
+            let size = std::str::from_utf8(&lyrics[0..6])
+                .ok()
+                .and_then(|s| s.parse::<u64>().ok());
+            if let Some(size) = size {
+                end = end.saturating_sub(size + 15).max(start);
+            }
+        }
+
+        if end == before {
+            return end;
+        }
+    }
+}
+
+/// FLAC audio frames start after the last metadata block
+fn flac_audio_start(file: &mut File, start: u64, end: u64) -> Result<u64, String> {
+    let mut pos = start + 4;
+    loop {
+        let mut header = [0u8; 4];
+        if !read_at(file, pos, &mut header) {
+            return Err("Truncated FLAC metadata".to_string());
+        }
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as u64;
+        pos += 4 + len;
+        if header[0] & 0x80 != 0 || pos >= end {
+            return Ok(pos.min(end));
+        }
+    }
+}
+
+struct OggStream {
+    packets: u64,
+    codec: &'static str,
+    partial: Vec<u8>,
+    /// Audio packets of this stream alone, pages of several streams may interleave differently
+    hasher: Sha256,
+}
+
+/// Comment and padding packets are what taggers rewrite, everything else is audio
+fn is_tag_packet(stream: &OggStream, packet: &[u8]) -> bool {
+    if packet.starts_with(b"\x03vorbis") || packet.starts_with(b"OpusTags") {
+        return true;
+    }
+    match stream.codec {
+        // metadata blocks follow the mapping header, audio frames start with a sync code
+        "FLAC" => stream.packets > 0 && packet.first().map(|b| *b != 0xff).unwrap_or(false),
+        "Speex" => stream.packets == 1,
+        _ => false,
+    }
+}
+
+/// Hash the packet payloads of every logical stream so re-paginated files still match. Serial
+/// numbers are left out, muxers pick new ones when rewriting a file
+fn hash_ogg(file: &mut File, hasher: &mut Sha256, start: u64, end: u64) -> Result<(), String> {
+    let mut streams: HashMap<u32, OggStream> = HashMap::new();
+    // in the order the streams start
+    let mut serials: Vec<u32> = Vec::new();
+    let mut pos = start;
+    while pos + 27 <= end {
+        let mut header = [0u8; 27];
+        if !read_at(file, pos, &mut header) || &header[0..4] != b"OggS" {
+            return Err("Broken Ogg page".to_string());
+        }
+        let serial = u32::from_le_bytes([header[14], header[15], header[16], header[17]]);
+        let mut segments = vec![0u8; header[26] as usize];
+        file.read_exact(&mut segments).map_err(|e| e.to_string())?;
+        let body_len: u64 = segments.iter().map(|s| *s as u64).sum();
+        let mut body = vec![0u8; body_len as usize];
+        file.read_exact(&mut body).map_err(|e| e.to_string())?;
+
+        let stream = streams.entry(serial).or_insert_with(|| {
+            serials.push(serial);
+            OggStream {
+                packets: 0,
+                codec: "",
+                partial: Vec::new(),
+                hasher: Sha256::new(),
+            }
+        });
+        let mut offset = 0usize;
+        for segment in segments {
+            let len = segment as usize;
+            stream
+                .partial
+                .extend_from_slice(&body[offset..offset + len]);
+            offset += len;
+            if segment == 255 {
+                continue;
+            }
+            let packet = std::mem::take(&mut stream.partial);
+            if stream.packets == 0 {
+                stream.codec = if packet.starts_with(b"\x7fFLAC") {
+                    "FLAC"
+                } else if packet.starts_with(b"Speex   ") {
+                    "Speex"
+                } else {
+                    "other"
+                };
+            }
+            if !is_tag_packet(stream, &packet) {
+                stream.hasher.update(&packet);
+            }
+            stream.packets += 1;
+        }
+        pos += 27 + header[26] as u64 + body_len;
+    }
+    for serial in serials {
+        if let Some(stream) = streams.remove(&serial) {
+            hasher.update(stream.hasher.finalize());
+        }
+    }
+    Ok(())
+}
+
+/// Only the mdat atoms hold audio, ilst lives in moov and may move around
+fn hash_mp4(file: &mut File, hasher: &mut Sha256, end: u64) -> Result<(), String> {
+    let mut pos = 0u64;
+    while pos + 8 <= end {
+        let mut header = [0u8; 16];
+        if !read_at(file, pos, &mut header[0..8]) {
+            break;
+        }
+        let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let mut header_len = 8u64;
+        if size == 1 {
+            if !read_at(file, pos + 8, &mut header[8..16]) {
+                break;
+            }
+            size = u64::from_be_bytes(header[8..16].try_into().unwrap());
+            header_len = 16;
+        } else if size == 0 {
+            size = end - pos;
+        }
+        if size < header_len {
+            break;
+        }
+        if &header[4..8] == b"mdat" {
+            hash_range(file, hasher, pos + header_len, (pos + size).min(end))?;
+        }
+        pos += size;
+    }
+    Ok(())
+}
+
+/// SHA-256 of the audio payload with all tag blocks left out, so retagged copies hash the same
+pub fn audio_payload_hash(path: &Path) -> Result<String, String> {
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    let file_size = file.metadata().map_err(|e| e.to_string())?.len();
+    let mut hasher = Sha256::new();
+
+    let start = leading_tags(&mut file, file_size);
+    let mut magic = [0u8; 8];
+    let has_magic = read_at(&mut file, start, &mut magic);
+
+    if has_magic && &magic[4..8] == b"ftyp" {
+        hash_mp4(&mut file, &mut hasher, file_size)?;
+    } else {
+        let end = trailing_tags(&mut file, start, file_size);
+        if has_magic && &magic[0..4] == b"fLaC" {
+            let audio_start = flac_audio_start(&mut file, start, end)?;
+            hash_range(&mut file, &mut hasher, audio_start, end)?;
+        } else if has_magic && &magic[0..4] == b"OggS" {
+            hash_ogg(&mut file, &mut hasher, start, end)?;
+        } else {
+            hash_range(&mut file, &mut hasher, start, end)?;
+        }
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
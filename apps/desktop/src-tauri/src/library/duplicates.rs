@@ -0,0 +1,698 @@
+use crate::analysis::fingerprint::{decode, raw_from_bytes, similarity};
+use crate::config::user::ViewMode;
+use crate::database::Database;
+use crate::jobs::Job;
+use crate::library::audio_hash::audio_payload_hash;
+use crate::tag_manager::properties::AudioProperties;
+use crate::tag_manager::utils::{FrameKey, TagValue};
+use crate::AppState;
+
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use std::collections::{HashMap, HashSet};
+use std::fs::metadata;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use tauri::{async_runtime, AppHandle, Emitter, Manager};
+
+/// Recordings further apart than this are never compared
+const MAX_DURATION_DIFF_MS: u64 = 7000;
+/// Same artist and title still counts as a different version past this
+const METADATA_DURATION_DIFF_MS: u64 = 2000;
+pub const DEFAULT_SIMILARITY: f64 = 0.85;
+/// Sub-fingerprints are indexed by their top 24 bits. Copies of a recording share plenty of
+/// them at the matching offset, so only files that do are compared
+const INDEX_SHIFT: u32 = 8;
+/// Keys more files than this share say nothing, silence and the like
+const MAX_BUCKET: usize = 64;
+/// Shared keys before two files are compared
+const MIN_SHARED_KEYS: usize = 2;
+
+/// Fields a well tagged track is expected to have
+const COMPLETENESS_KEYS: [FrameKey; 7] = [
+    FrameKey::Title,
+    FrameKey::Artist,
+    FrameKey::Album,
+    FrameKey::AlbumArtist,
+    FrameKey::Year,
+    FrameKey::TrackNumber,
+    FrameKey::Genre,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DuplicateCriterion {
+    /// Acoustically similar, from Chromaprint fingerprints
+    Fingerprint,
+    /// Byte identical audio once all tags are left out
+    AudioHash,
+    /// Same normalised artist and title with about the same duration
+    Metadata,
+    /// Same MusicBrainz recording ID
+    RecordingId,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QualityHints {
+    pub format: String,
+    pub lossless: bool,
+    pub bitrate: Option<u32>,
+    pub sample_rate: Option<u32>,
+    pub bits_per_sample: Option<u16>,
+    pub duration_ms: Option<u64>,
+    pub file_size: u64,
+    /// Share of the core fields (title, artist, album...) that are filled in, 0.0 to 1.0
+    pub tag_completeness: f64,
+    pub has_cover: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateEntry {
+    pub path: String,
+    /// How close the file is to the first one of its group, 1.0 for exact matches
+    pub score: f64,
+    pub quality: QualityHints,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateGroup {
+    pub criterion: DuplicateCriterion,
+    pub files: Vec<DuplicateEntry>,
+    /// The copy worth keeping: lossless first, then bitrate, then tags
+    pub best: String,
+}
+
+/// What duplicate detection needs to know about a track
+struct TrackRecord {
+    path: String,
+    artist: String,
+    title: String,
+    recording_id: String,
+    properties: Option<AudioProperties>,
+    quality: QualityHints,
+}
+
+struct FingerprintEntry {
+    path: String,
+    raw: Vec<u32>,
+    duration_ms: u64,
+}
+
+/// Simple union find to merge pairwise matches into groups
+struct Groups {
+    parent: Vec<usize>,
+}
+
+impl Groups {
+    fn new(len: usize) -> Self {
+        Self {
+            parent: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, i: usize) -> usize {
+        let mut root = i;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        let mut i = i;
+        while self.parent[i] != root {
+            let next = self.parent[i];
+            self.parent[i] = root;
+            i = next;
+        }
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parent[b.max(a)] = a.min(b);
+        }
+    }
+
+    fn sets(&mut self) -> Vec<Vec<usize>> {
+        let mut by_root: HashMap<usize, Vec<usize>> = HashMap::new();
+        for i in 0..self.parent.len() {
+            let root = self.find(i);
+            by_root.entry(root).or_default().push(i);
+        }
+        let mut sets: Vec<Vec<usize>> = by_root.into_values().filter(|s| s.len() > 1).collect();
+        sets.sort_by_key(|s| s[0]);
+        sets
+    }
+}
+
+/// Lowercase, drop punctuation and a leading "the" so "The Beatles" matches "beatles"
+fn normalize(s: &str) -> String {
+    let cleaned: String = s
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+    let words: Vec<&str> = cleaned.split_whitespace().collect();
+    match words.split_first() {
+        Some((&"the", rest)) if !rest.is_empty() => rest.join(" "),
+        _ => words.join(" "),
+    }
+}
+
+fn quality_hints(
+    path: &str,
+    properties: Option<&AudioProperties>,
+    filled: usize,
+    has_cover: bool,
+) -> QualityHints {
+    let format = properties
+        .map(|p| p.codec.clone())
+        .filter(|c| !c.is_empty())
+        .unwrap_or_else(|| {
+            Path::new(path)
+                .extension()
+                .map(|e| e.to_string_lossy().to_uppercase())
+                .unwrap_or_default()
+        });
+    QualityHints {
+        lossless: matches!(format.as_str(), "FLAC" | "ALAC"),
+        format,
+        bitrate: properties.map(|p| p.bitrate).filter(|b| *b > 0),
+        sample_rate: properties.map(|p| p.sample_rate).filter(|r| *r > 0),
+        bits_per_sample: properties.and_then(|p| p.bits_per_sample),
+        duration_ms: properties.map(|p| p.duration_ms).filter(|d| *d > 0),
+        file_size: metadata(path).map(|m| m.len()).unwrap_or(0),
+        tag_completeness: filled as f64 / COMPLETENESS_KEYS.len() as f64,
+        has_cover,
+    }
+}
+
+fn first_text(tags: &HashMap<FrameKey, Vec<TagValue>>, key: FrameKey) -> String {
+    match tags.get(&key).and_then(|v| v.first()) {
+        Some(TagValue::Text(s)) => s.trim().to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Tracks in the workspace (simple view) or the indexed library (folder view)
+async fn track_records(state: &AppState) -> Vec<TrackRecord> {
+    if state.view_mode == ViewMode::Simple {
+        let files = state.workspace.lock().unwrap().files.clone();
+        return files
+            .into_iter()
+            .map(|file| {
+                let path = file.path.to_string_lossy().to_string();
+                let filled = COMPLETENESS_KEYS
+                    .iter()
+                    .filter(|k| !first_text(&file.tags, **k).is_empty())
+                    .count();
+                let has_cover = file
+                    .tags
+                    .get(&FrameKey::AttachedPicture)
+                    .map(|v| !v.is_empty())
+                    .unwrap_or(false);
+                TrackRecord {
+                    artist: first_text(&file.tags, FrameKey::Artist),
+                    title: first_text(&file.tags, FrameKey::Title),
+                    recording_id: first_text(&file.tags, FrameKey::MusicBrainzRecordingId),
+                    quality: quality_hints(&path, file.properties.as_ref(), filled, has_cover),
+                    properties: file.properties,
+                    path,
+                }
+            })
+            .collect();
+    }
+
+    let pool = &state.db.pool;
+    let rows = sqlx::query(
+        "SELECT path, duration_ms, bitrate, sample_rate, channels, bits_per_sample, codec FROM files",
+    )
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+
+    let mut keys: Vec<FrameKey> = COMPLETENESS_KEYS.to_vec();
+    keys.push(FrameKey::MusicBrainzRecordingId);
+    let placeholders = vec!["?"; keys.len()].join(", ");
+    let sql = format!(
+        "SELECT file_path, key, value FROM tag_text WHERE key IN ({})",
+        placeholders
+    );
+    let mut query = sqlx::query(&sql);
+    for key in keys.iter() {
+        query = query.bind(key.to_string());
+    }
+    let mut texts: HashMap<String, HashMap<String, String>> = HashMap::new();
+    for row in query.fetch_all(pool).await.unwrap_or_default() {
+        let value: String = row.get("value");
+        if value.trim().is_empty() {
+            continue;
+        }
+        texts
+            .entry(row.get("file_path"))
+            .or_default()
+            .entry(row.get("key"))
+            .or_insert(value.trim().to_string());
+    }
+    let covers: HashSet<String> = sqlx::query_scalar("SELECT DISTINCT file_path FROM tag_pictures")
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+
+    rows.into_iter()
+        .map(|row| {
+            let path: String = row.get("path");
+            let codec: Option<String> = row.get("codec");
+            let properties = codec.map(|codec| AudioProperties {
+                duration_ms: row.get::<Option<i64>, _>("duration_ms").unwrap_or(0).max(0) as u64,
+                bitrate: row.get::<Option<i64>, _>("bitrate").unwrap_or(0).max(0) as u32,
+                sample_rate: row.get::<Option<i64>, _>("sample_rate").unwrap_or(0).max(0) as u32,
+                channels: row.get::<Option<i64>, _>("channels").unwrap_or(0).max(0) as u16,
+                bits_per_sample: row
+                    .get::<Option<i64>, _>("bits_per_sample")
+                    .map(|b| b.max(0) as u16),
+                codec,
+            });
+            let empty = HashMap::new();
+            let tags = texts.get(&path).unwrap_or(&empty);
+            let text = |key: FrameKey| tags.get(&key.to_string()).cloned().unwrap_or_default();
+            let filled = COMPLETENESS_KEYS
+                .iter()
+                .filter(|k| tags.contains_key(&k.to_string()))
+                .count();
+            TrackRecord {
+                artist: text(FrameKey::Artist),
+                title: text(FrameKey::Title),
+                recording_id: text(FrameKey::MusicBrainzRecordingId),
+                quality: quality_hints(&path, properties.as_ref(), filled, covers.contains(&path)),
+                properties,
+                path,
+            }
+        })
+        .collect()
+}
+
+/// Fingerprints of the files currently in view. Files fingerprinted by other tools
+/// only have the tag, those are decoded on the fly
+async fn fingerprint_entries(state: &AppState) -> Vec<FingerprintEntry> {
+    let db: &Database = &state.db;
+    let mut entries: Vec<FingerprintEntry> = Vec::new();
+
+    if state.view_mode == ViewMode::Simple {
+        let files = state.workspace.lock().unwrap().files.clone();
+        for file in files {
+            let path = file.path.to_string_lossy().to_string();
+            let stored =
+                sqlx::query("SELECT raw, duration_ms FROM fingerprints WHERE file_path = ?1")
+                    .bind(&path)
+                    .fetch_optional(&db.pool)
+                    .await
+                    .ok()
+                    .flatten();
+            if let Some(row) = stored {
+                let raw: Vec<u8> = row.get("raw");
+                let duration_ms: i64 = row.get("duration_ms");
+                entries.push(FingerprintEntry {
+                    path,
+                    raw: raw_from_bytes(&raw),
+                    duration_ms: duration_ms.max(0) as u64,
+                });
+                continue;
+            }
+            let tagged = match file
+                .tags
+                .get(&FrameKey::AcoustidFingerprint)
+                .and_then(|v| v.first())
+            {
+                Some(TagValue::Text(s)) => decode(s),
+                _ => None,
+            };
+            if let Some(raw) = tagged {
+                entries.push(FingerprintEntry {
+                    path,
+                    raw,
+                    duration_ms: file.properties.map(|p| p.duration_ms).unwrap_or(0),
+                });
+            }
+        }
+        return entries;
+    }
+
+    let rows = sqlx::query(
+        "SELECT fp.file_path, fp.raw, fp.duration_ms FROM fingerprints fp
+         JOIN files f ON f.path = fp.file_path",
+    )
+    .fetch_all(&db.pool)
+    .await
+    .unwrap_or_default();
+    let mut seen: HashSet<String> = HashSet::new();
+    for row in rows {
+        let path: String = row.get("file_path");
+        let raw: Vec<u8> = row.get("raw");
+        let duration_ms: i64 = row.get("duration_ms");
+        seen.insert(path.clone());
+        entries.push(FingerprintEntry {
+            path,
+            raw: raw_from_bytes(&raw),
+            duration_ms: duration_ms.max(0) as u64,
+        });
+    }
+
+    let tagged = sqlx::query(
+        "SELECT t.file_path, t.value, f.duration_ms FROM tag_text t
+         JOIN files f ON f.path = t.file_path
+         WHERE t.key = ?1",
+    )
+    .bind(FrameKey::AcoustidFingerprint.to_string())
+    .fetch_all(&db.pool)
+    .await
+    .unwrap_or_default();
+    for row in tagged {
+        let path: String = row.get("file_path");
+        if seen.contains(&path) {
+            continue;
+        }
+        let value: String = row.get("value");
+        let duration_ms: Option<i64> = row.get("duration_ms");
+        if let Some(raw) = decode(&value) {
+            seen.insert(path.clone());
+            entries.push(FingerprintEntry {
+                path,
+                raw,
+                duration_ms: duration_ms.unwrap_or(0).max(0) as u64,
+            });
+        }
+    }
+    entries
+}
+
+/// Pairs of entries sharing enough indexed sub-fingerprints to be worth comparing
+fn candidate_pairs(entries: &[FingerprintEntry]) -> Vec<(usize, usize)> {
+    let mut index: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let keys: HashSet<u32> = entry.raw.iter().map(|v| v >> INDEX_SHIFT).collect();
+        for key in keys {
+            index.entry(key).or_default().push(i);
+        }
+    }
+    let mut shared: HashMap<(usize, usize), usize> = HashMap::new();
+    for ids in index.values().filter(|ids| ids.len() <= MAX_BUCKET) {
+        for (n, a) in ids.iter().enumerate() {
+            for b in &ids[n + 1..] {
+                *shared.entry((*a, *b)).or_default() += 1;
+            }
+        }
+    }
+    let mut pairs: Vec<(usize, usize)> = shared
+        .into_iter()
+        .filter(|(_, count)| *count >= MIN_SHARED_KEYS)
+        .map(|(pair, _)| pair)
+        .collect();
+    pairs.sort();
+    pairs
+}
+
+/// Groups of (path, score) for acoustically similar files
+fn fingerprint_sets(
+    entries: Vec<FingerprintEntry>,
+    threshold: f64,
+    job: &Job,
+) -> Vec<Vec<(String, f64)>> {
+    let mut groups = Groups::new(entries.len());
+    for (i, j) in candidate_pairs(&entries) {
+        if job.is_cancelled() {
+            return Vec::new();
+        }
+        let (a, b) = (&entries[i], &entries[j]);
+        // unknown durations are compared with everything
+        let known = a.duration_ms > 0 && b.duration_ms > 0;
+        if known && a.duration_ms.abs_diff(b.duration_ms) > MAX_DURATION_DIFF_MS {
+            continue;
+        }
+        if groups.find(i) != groups.find(j) && similarity(&a.raw, &b.raw) >= threshold {
+            groups.union(i, j);
+        }
+    }
+
+    groups
+        .sets()
+        .into_iter()
+        .map(|set| {
+            let first = &entries[set[0]].raw;
+            set.iter()
+                .map(|i| {
+                    let score = if *i == set[0] {
+                        1.0
+                    } else {
+                        similarity(first, &entries[*i].raw)
+                    };
+                    (entries[*i].path.clone(), score)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn file_mtime(path: &str) -> i64 {
+    metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Payload hash from the cache, recomputed when the file changed since. Only indexed files are
+/// cached
+async fn cached_audio_hash(db: &Database, path: &str) -> Option<String> {
+    let last_modified = file_mtime(path);
+    let cached: Option<String> = sqlx::query_scalar(
+        "SELECT hash FROM audio_hashes WHERE file_path = ?1 AND last_modified = ?2",
+    )
+    .bind(path)
+    .bind(last_modified)
+    .fetch_optional(&db.pool)
+    .await
+    .ok()
+    .flatten();
+    if cached.is_some() {
+        return cached;
+    }
+
+    let hash = audio_payload_hash(Path::new(path)).ok()?;
+    let _ = sqlx::query(
+        "INSERT INTO audio_hashes (file_path, hash, last_modified)
+         SELECT ?1, ?2, ?3 WHERE EXISTS (SELECT 1 FROM files WHERE path = ?1)
+         ON CONFLICT(file_path) DO UPDATE SET hash = excluded.hash, last_modified = excluded.last_modified",
+    )
+    .bind(path)
+    .bind(&hash)
+    .bind(last_modified)
+    .execute(&db.pool)
+    .await;
+    Some(hash)
+}
+
+/// Identical payloads also have identical stream properties, so only those are hashed
+async fn audio_hash_sets(db: &Database, records: &[TrackRecord]) -> Vec<Vec<(String, f64)>> {
+    let mut by_stream: HashMap<(String, u32, u16), Vec<&TrackRecord>> = HashMap::new();
+    let mut unknown: Vec<&TrackRecord> = Vec::new();
+    for record in records {
+        match &record.properties {
+            Some(p) => by_stream
+                .entry((p.codec.clone(), p.sample_rate, p.channels))
+                .or_default()
+                .push(record),
+            None => unknown.push(record),
+        }
+    }
+
+    let mut candidates: Vec<&TrackRecord> = Vec::new();
+    for (_, mut group) in by_stream {
+        group.sort_by_key(|r| r.properties.as_ref().map(|p| p.duration_ms).unwrap_or(0));
+        for i in 0..group.len() {
+            let duration =
+                |r: &TrackRecord| r.properties.as_ref().map(|p| p.duration_ms).unwrap_or(0);
+            let close = |j: usize| duration(group[j]).abs_diff(duration(group[i])) <= 1000;
+            if (i > 0 && close(i - 1)) || (i + 1 < group.len() && close(i + 1)) {
+                candidates.push(group[i]);
+            }
+        }
+    }
+    if unknown.len() > 1 {
+        candidates.extend(unknown);
+    }
+
+    let mut by_hash: HashMap<String, Vec<String>> = HashMap::new();
+    for record in candidates {
+        if let Some(hash) = cached_audio_hash(db, &record.path).await {
+            by_hash.entry(hash).or_default().push(record.path.clone());
+        }
+    }
+    let mut sets: Vec<Vec<(String, f64)>> = by_hash
+        .into_values()
+        .filter(|paths| paths.len() > 1)
+        .map(|mut paths| {
+            paths.sort();
+            paths.into_iter().map(|p| (p, 1.0)).collect()
+        })
+        .collect();
+    sets.sort_by(|a, b| a[0].0.cmp(&b[0].0));
+    sets
+}
+
+fn metadata_sets(records: &[TrackRecord]) -> Vec<Vec<(String, f64)>> {
+    let mut by_name: HashMap<(String, String), Vec<&TrackRecord>> = HashMap::new();
+    for record in records {
+        let (artist, title) = (normalize(&record.artist), normalize(&record.title));
+        if artist.is_empty() || title.is_empty() {
+            continue;
+        }
+        by_name.entry((artist, title)).or_default().push(record);
+    }
+
+    let mut sets: Vec<Vec<(String, f64)>> = Vec::new();
+    for (_, mut group) in by_name {
+        let duration = |r: &TrackRecord| r.properties.as_ref().map(|p| p.duration_ms).unwrap_or(0);
+        group.sort_by_key(|r| duration(r));
+        // split where the gap between neighbouring durations gets too big
+        let mut current: Vec<&TrackRecord> = Vec::new();
+        for record in group {
+            if let Some(last) = current.last() {
+                if duration(record) - duration(last) > METADATA_DURATION_DIFF_MS {
+                    if current.len() > 1 {
+                        sets.push(current.iter().map(|r| (r.path.clone(), 1.0)).collect());
+                    }
+                    current.clear();
+                }
+            }
+            current.push(record);
+        }
+        if current.len() > 1 {
+            sets.push(current.iter().map(|r| (r.path.clone(), 1.0)).collect());
+        }
+    }
+    sets.sort_by(|a, b| a[0].0.cmp(&b[0].0));
+    sets
+}
+
+fn recording_id_sets(records: &[TrackRecord]) -> Vec<Vec<(String, f64)>> {
+    let mut by_id: HashMap<String, Vec<String>> = HashMap::new();
+    for record in records {
+        let id = record.recording_id.trim().to_lowercase();
+        if !id.is_empty() {
+            by_id.entry(id).or_default().push(record.path.clone());
+        }
+    }
+    let mut sets: Vec<Vec<(String, f64)>> = by_id
+        .into_values()
+        .filter(|paths| paths.len() > 1)
+        .map(|paths| paths.into_iter().map(|p| (p, 1.0)).collect())
+        .collect();
+    sets.sort_by(|a, b| a[0].0.cmp(&b[0].0));
+    sets
+}
+
+/// Higher is better
+fn rank(q: &QualityHints) -> (bool, u32, u32, u64, bool) {
+    (
+        q.lossless,
+        q.bitrate.unwrap_or(0),
+        (q.tag_completeness * 100.0) as u32,
+        q.file_size,
+        q.has_cover,
+    )
+}
+
+/// Group files that look like the same recording. Works entirely offline
+pub async fn find_duplicates(
+    state: &AppState,
+    job: &Job,
+    criteria: &[DuplicateCriterion],
+    threshold: f64,
+) -> Vec<DuplicateGroup> {
+    let records = track_records(state).await;
+    let quality: HashMap<&str, &QualityHints> = records
+        .iter()
+        .map(|r| (r.path.as_str(), &r.quality))
+        .collect();
+
+    let mut groups: Vec<DuplicateGroup> = Vec::new();
+    for criterion in criteria {
+        if job.is_cancelled() {
+            break;
+        }
+        let sets = match criterion {
+            DuplicateCriterion::Fingerprint => {
+                fingerprint_sets(fingerprint_entries(state).await, threshold, job)
+            }
+            DuplicateCriterion::AudioHash => audio_hash_sets(&state.db, &records).await,
+            DuplicateCriterion::Metadata => metadata_sets(&records),
+            DuplicateCriterion::RecordingId => recording_id_sets(&records),
+        };
+        for set in sets {
+            let files: Vec<DuplicateEntry> = set
+                .into_iter()
+                .map(|(path, score)| DuplicateEntry {
+                    quality: quality
+                        .get(path.as_str())
+                        .map(|q| (*q).clone())
+                        .unwrap_or_else(|| quality_hints(&path, None, 0, false)),
+                    path,
+                    score,
+                })
+                .collect();
+            let best = files
+                .iter()
+                .max_by_key(|f| rank(&f.quality))
+                .map(|f| f.path.clone())
+                .unwrap_or_default();
+            groups.push(DuplicateGroup {
+                criterion: *criterion,
+                files,
+                best,
+            });
+        }
+        job.advance(&format!("{:?}", criterion));
+    }
+    groups
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicatesReport {
+    pub job_id: String,
+    pub groups: Vec<DuplicateGroup>,
+}
+
+/// Look for duplicates in the background, one step per criterion. Returns the job id, the
+/// groups are sent on "duplicates-found" when done
+pub fn start_find_duplicates(
+    app_handle: &AppHandle,
+    criteria: Vec<DuplicateCriterion>,
+    threshold: f64,
+) -> String {
+    let job =
+        app_handle
+            .state::<AppState>()
+            .jobs
+            .start(app_handle, "findDuplicates", criteria.len());
+    let id = job.id.clone();
+    let app_handle = app_handle.clone();
+    async_runtime::spawn_blocking(move || {
+        let state = app_handle.state::<AppState>();
+        let groups = async_runtime::block_on(find_duplicates(&state, &job, &criteria, threshold));
+        job.finish();
+        let _ = app_handle.emit(
+            "duplicates-found",
+            DuplicatesReport {
+                job_id: job.id.clone(),
+                groups,
+            },
+        );
+    });
+    id
+}
@@ -0,0 +1,261 @@
+use crate::jobs::Job;
+use crate::tag_manager::tag_backend::{DefaultBackend, TagBackend};
+use crate::tag_manager::utils::File;
+use crate::utils::store_metadata;
+use crate::AppState;
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Instant;
+use tauri::{async_runtime, AppHandle, Emitter, Manager};
+
+/// Files written to the index per transaction
+pub const BATCH_SIZE: usize = 200;
+/// Upper bound on reader threads, past this the disk is the bottleneck
+const MAX_WORKERS: usize = 8;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedImport {
+    pub path: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportProgress {
+    pub job_id: String,
+    pub done: usize,
+    pub total: usize,
+    pub failed: usize,
+    /// Files that failed since the previous event
+    pub failures: Vec<FailedImport>,
+    pub files_per_second: f64,
+    /// None until there is a rate to go by
+    pub eta_seconds: Option<u64>,
+}
+
+fn worker_count(files: usize) -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
+        .clamp(1, MAX_WORKERS)
+        .min(files.max(1))
+}
+
+/// Read tags of `paths` on a pool of threads. Results are handed to `on_parsed` on the calling
+/// thread as they arrive, in no particular order. The channel is bounded so the readers never
+/// get far ahead of whatever `on_parsed` does with them. Stops early once `cancel` is set
+pub fn parse_files(
+    paths: &[String],
+    cancel: &AtomicBool,
+    mut on_parsed: impl FnMut(String, Result<File, String>),
+) {
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::sync_channel(BATCH_SIZE);
+
+    thread::scope(|scope| {
+        for _ in 0..worker_count(paths.len()) {
+            let sender = sender.clone();
+            let next = &next;
+            scope.spawn(move || {
+                let backend = DefaultBackend::new();
+                while !cancel.load(Ordering::SeqCst) {
+                    let Some(path) = paths.get(next.fetch_add(1, Ordering::SeqCst)) else {
+                        break;
+                    };
+                    let result = backend
+                        .read(&PathBuf::from(path))
+                        .map_err(|e| format!("{e:?}"));
+                    if sender.send((path.clone(), result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        // the receiver ends once every worker dropped its sender
+        drop(sender);
+        for (path, result) in receiver {
+            on_parsed(path, result);
+        }
+    });
+}
+
+/// Store a batch of parsed files in one transaction. A file that fails is rolled back on its
+/// own and returned with the error, the rest of the batch still goes in
+pub async fn store_files(
+    pool: &SqlitePool,
+    files: &[File],
+) -> Result<Vec<(String, String)>, String> {
+    let mut failed = Vec::new();
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    for file in files {
+        sqlx::query("SAVEPOINT store_file")
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+        let statement = match store_metadata(&mut tx, file).await {
+            Ok(()) => "RELEASE store_file",
+            Err(e) => {
+                failed.push((file.path.to_string_lossy().to_string(), e));
+                "ROLLBACK TO store_file"
+            }
+        };
+        sqlx::query(statement)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    tx.commit().await.map_err(|e| e.to_string())?;
+    Ok(failed)
+}
+
+/// Read files on blocking threads and store them in batches, from async code
+pub async fn read_and_store(
+    pool: &SqlitePool,
+    paths: Vec<String>,
+) -> Result<Vec<(String, Result<File, String>)>, String> {
+    let parsed = async_runtime::spawn_blocking(move || {
+        let mut parsed = Vec::with_capacity(paths.len());
+        parse_files(&paths, &AtomicBool::new(false), |path, result| {
+            parsed.push((path, result))
+        });
+        parsed
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let files: Vec<File> = parsed
+        .iter()
+        .filter_map(|(_, result)| result.as_ref().ok().cloned())
+        .collect();
+    let mut failed = Vec::new();
+    for batch in files.chunks(BATCH_SIZE) {
+        failed.extend(store_files(pool, batch).await?);
+    }
+    Ok(parsed
+        .into_iter()
+        .map(|(path, result)| {
+            let result = match failed.iter().find(|(p, _)| *p == path) {
+                Some((_, e)) => Err(e.clone()),
+                None => result,
+            };
+            (path, result)
+        })
+        .collect())
+}
+
+struct Progress<'a> {
+    app_handle: &'a AppHandle,
+    job: &'a Job,
+    total: usize,
+    done: usize,
+    failed: usize,
+    failures: Vec<FailedImport>,
+    started: Instant,
+}
+
+impl Progress<'_> {
+    fn fail(&mut self, path: String, error: String) {
+        self.failed += 1;
+        self.failures.push(FailedImport { path, error });
+    }
+
+    fn emit(&mut self) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let files_per_second = if elapsed > 0.0 {
+            self.done as f64 / elapsed
+        } else {
+            0.0
+        };
+        let eta_seconds = (files_per_second > 0.0).then(|| {
+            (self.total.saturating_sub(self.done) as f64 / files_per_second).ceil() as u64
+        });
+        let _ = self.app_handle.emit(
+            "import-progress",
+            ImportProgress {
+                job_id: self.job.id.clone(),
+                done: self.done,
+                total: self.total,
+                failed: self.failed,
+                failures: std::mem::take(&mut self.failures),
+                files_per_second,
+                eta_seconds,
+            },
+        );
+    }
+}
+
+/// Read and index `paths`, writing every `BATCH_SIZE` files. Cancelling leaves the files not
+/// read yet pending, they get indexed when they are first shown
+pub fn run_import(app_handle: &AppHandle, job: &Job, paths: Vec<String>) {
+    let db = app_handle.state::<AppState>().db.clone();
+    let mut progress = Progress {
+        app_handle,
+        job,
+        total: paths.len(),
+        done: 0,
+        failed: 0,
+        failures: Vec::new(),
+        started: Instant::now(),
+    };
+    let mut batch: Vec<File> = Vec::with_capacity(BATCH_SIZE);
+
+    let flush = |batch: &mut Vec<File>, progress: &mut Progress| {
+        let count = batch.len();
+        match async_runtime::block_on(store_files(&db.pool, batch)) {
+            Ok(failed) => {
+                for (path, e) in failed {
+                    progress.fail(path, e);
+                }
+            }
+            Err(e) => {
+                for file in batch.iter() {
+                    progress.fail(file.path.to_string_lossy().to_string(), e.clone());
+                }
+            }
+        }
+        progress.done += count;
+        if let Some(last) = batch.last() {
+            job.advance_by(count, &last.path.to_string_lossy());
+        }
+        progress.emit();
+        batch.clear();
+    };
+
+    parse_files(&paths, job.cancel_flag(), |path, result| match result {
+        Ok(file) => {
+            batch.push(file);
+            if batch.len() == BATCH_SIZE {
+                flush(&mut batch, &mut progress);
+            }
+        }
+        Err(e) => {
+            progress.fail(path.clone(), e);
+            progress.done += 1;
+            job.advance(&path);
+        }
+    });
+    if !batch.is_empty() {
+        flush(&mut batch, &mut progress);
+    }
+    progress.emit();
+}
+
+/// Index `paths` in the background. Returns the job id
+pub fn start_import(app_handle: &AppHandle, paths: Vec<String>) -> String {
+    let job = app_handle
+        .state::<AppState>()
+        .jobs
+        .start(app_handle, "import", paths.len());
+    let id = job.id.clone();
+    let app_handle = app_handle.clone();
+    async_runtime::spawn_blocking(move || {
+        run_import(&app_handle, &job, paths);
+        job.finish();
+    });
+    id
+}
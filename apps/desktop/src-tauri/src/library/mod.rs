@@ -0,0 +1,9 @@
+pub mod albums;
+pub mod audio_hash;
+pub mod duplicates;
+pub mod indexer;
+pub mod query;
+pub mod rescan;
+pub mod saved_queries;
+pub mod search;
+pub mod stats;
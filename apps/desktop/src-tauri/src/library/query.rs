@@ -0,0 +1,734 @@
+use crate::constants::FRAME_KEYS;
+use crate::tag_manager::utils::FrameKey;
+
+/// A value bound to one of the `?` placeholders of a generated clause
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bind {
+    Text(String),
+    Number(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn sql(&self) -> &'static str {
+        match self {
+            Comparison::Eq => "=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+}
+
+/// Columns of `files` that can be compared numerically
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    Bitrate,
+    SampleRate,
+    Channels,
+    BitsPerSample,
+    Duration,
+    Size,
+    Modified,
+    Added,
+}
+
+impl Column {
+    pub(crate) fn sql(&self) -> &'static str {
+        match self {
+            Column::Bitrate => "f.bitrate",
+            Column::SampleRate => "f.sample_rate",
+            Column::Channels => "f.channels",
+            Column::BitsPerSample => "f.bits_per_sample",
+            Column::Duration => "f.duration_ms",
+            Column::Size => "f.file_size",
+            Column::Modified => "f.last_modified",
+            Column::Added => "f.added_at",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// Words or a phrase anywhere in the tags or the file name
+    Text {
+        text: String,
+        phrase: bool,
+    },
+    /// Words or a phrase in one frame
+    Field {
+        key: FrameKey,
+        text: String,
+        phrase: bool,
+    },
+    /// Whole value of a frame, case insensitive
+    FieldEquals {
+        key: FrameKey,
+        text: String,
+    },
+    FieldNumber {
+        key: FrameKey,
+        comparison: Comparison,
+        value: f64,
+    },
+    Column {
+        column: Column,
+        comparison: Comparison,
+        value: f64,
+    },
+    Format(String),
+    TagFormat(String),
+    Has(FrameKey),
+    HasFingerprint,
+    Path(String),
+    FileName(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    pub negated: bool,
+    pub condition: Condition,
+}
+
+/// Parsed search query, every term has to match
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Query {
+    pub terms: Vec<Term>,
+}
+
+struct Token {
+    text: String,
+    quoted: bool,
+    /// The quote opened the token, so a colon inside is not a field separator
+    phrase: bool,
+}
+
+/// Split on whitespace, keeping double quoted parts together
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut phrase = false;
+    let mut in_quotes = false;
+    for c in input.chars() {
+        match c {
+            '"' => {
+                if !quoted && (current.is_empty() || current == "-") {
+                    phrase = true;
+                }
+                in_quotes = !in_quotes;
+                quoted = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() || quoted {
+                    tokens.push(Token {
+                        text: std::mem::take(&mut current),
+                        quoted,
+                        phrase,
+                    });
+                }
+                quoted = false;
+                phrase = false;
+            }
+            c => current.push(c),
+        }
+    }
+    if in_quotes {
+        return Err("Unclosed quote in query".to_string());
+    }
+    if !current.is_empty() || quoted {
+        tokens.push(Token {
+            text: current,
+            quoted,
+            phrase,
+        });
+    }
+    Ok(tokens)
+}
+
+/// Frame key from a user typed name like `artist`, `albumartist` or `track`
+pub fn frame_key_for(name: &str) -> Option<FrameKey> {
+    let name = name.to_lowercase().replace(['_', '-'], "");
+    let alias = match name.as_str() {
+        "track" => Some(FrameKey::TrackNumber),
+        "disc" => Some(FrameKey::DiscNumber),
+        "comment" => Some(FrameKey::Comments),
+        "bpm" => Some(FrameKey::BeatsPerMinute),
+        "cover" | "picture" | "artwork" => Some(FrameKey::AttachedPicture),
+        "mbid" | "recordingid" => Some(FrameKey::MusicBrainzRecordingId),
+        _ => None,
+    };
+    alias.or_else(|| {
+        FRAME_KEYS
+            .iter()
+            .find(|k| k.to_string().to_lowercase().replace('_', "") == name)
+            .copied()
+    })
+}
+
+pub(crate) fn column_for(name: &str) -> Option<Column> {
+    match name.to_lowercase().replace(['_', '-'], "").as_str() {
+        "bitrate" => Some(Column::Bitrate),
+        "samplerate" => Some(Column::SampleRate),
+        "channels" => Some(Column::Channels),
+        "bitdepth" | "bits" | "bitspersample" => Some(Column::BitsPerSample),
+        "duration" => Some(Column::Duration),
+        "size" => Some(Column::Size),
+        "modified" => Some(Column::Modified),
+        "added" => Some(Column::Added),
+        _ => None,
+    }
+}
+
+/// `>=2000` style prefix, plain values compare for equality
+fn split_comparison(value: &str) -> (Comparison, &str) {
+    for (prefix, comparison) in [
+        (">=", Comparison::Ge),
+        ("<=", Comparison::Le),
+        (">", Comparison::Gt),
+        ("<", Comparison::Lt),
+        ("=", Comparison::Eq),
+    ] {
+        if let Some(rest) = value.strip_prefix(prefix) {
+            return (comparison, rest.trim());
+        }
+    }
+    (Comparison::Eq, value)
+}
+
+/// Numbers in the unit stored in the database: seconds or m:ss for durations,
+/// k/m/g suffixes for sizes and days ago for modification and import times
+fn column_value(column: Column, value: &str) -> Option<f64> {
+    let lower = value.to_lowercase();
+    match column {
+        Column::Duration => {
+            let seconds = match lower.split_once(':') {
+                Some((m, s)) => m.parse::<f64>().ok()? * 60.0 + s.parse::<f64>().ok()?,
+                None => lower.trim_end_matches('s').parse::<f64>().ok()?,
+            };
+            Some(seconds * 1000.0)
+        }
+        Column::Size => {
+            let (number, factor) = match lower.trim_end_matches('b').chars().last()? {
+                'k' => (&lower[..lower.find('k')?], 1024.0),
+                'm' => (&lower[..lower.find('m')?], 1024.0 * 1024.0),
+                'g' => (&lower[..lower.find('g')?], 1024.0 * 1024.0 * 1024.0),
+                _ => (lower.trim_end_matches('b'), 1.0),
+            };
+            Some(number.trim().parse::<f64>().ok()? * factor)
+        }
+        Column::Modified | Column::Added => {
+            let days = lower.trim_end_matches('d').parse::<f64>().ok()?;
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs() as f64)
+                .unwrap_or(0.0);
+            Some(now - days * 86400.0)
+        }
+        Column::Bitrate => lower.trim_end_matches("kbps").parse::<f64>().ok(),
+        Column::SampleRate => match lower.strip_suffix("khz") {
+            Some(khz) => khz.parse::<f64>().ok().map(|v| v * 1000.0),
+            None => lower.trim_end_matches("hz").parse::<f64>().ok(),
+        },
+        _ => lower.parse::<f64>().ok(),
+    }
+}
+
+/// `modified:<7` reads as "modified less than 7 days ago", which is a later timestamp.
+/// A bare `modified:7` means the same
+fn flip(comparison: Comparison) -> Comparison {
+    match comparison {
+        Comparison::Lt | Comparison::Eq => Comparison::Gt,
+        Comparison::Le => Comparison::Ge,
+        Comparison::Gt => Comparison::Lt,
+        Comparison::Ge => Comparison::Le,
+    }
+}
+
+/// One or two conditions, `year:1990..1999` becomes a pair of comparisons
+fn field_conditions(field: &str, value: &str, quoted: bool) -> Result<Vec<Condition>, String> {
+    let lower = field.to_lowercase();
+    match lower.as_str() {
+        "format" | "codec" => return Ok(vec![Condition::Format(value.to_lowercase())]),
+        "tagformat" => return Ok(vec![Condition::TagFormat(value.to_lowercase())]),
+        "path" => return Ok(vec![Condition::Path(value.to_string())]),
+        "name" | "filename" => return Ok(vec![Condition::FileName(value.to_string())]),
+        "has" | "missing" => {
+            if value.eq_ignore_ascii_case("fingerprint") {
+                return Ok(vec![Condition::HasFingerprint]);
+            }
+            let key = match value.to_lowercase().as_str() {
+                "replaygain" => FrameKey::ReplayGainTrackGain,
+                "lyrics" => FrameKey::UnsyncedLyrics,
+                _ => frame_key_for(value).ok_or(format!("Unknown field \"{}\"", value))?,
+            };
+            return Ok(vec![Condition::Has(key)]);
+        }
+        _ => {}
+    }
+
+    let range = if quoted { None } else { value.split_once("..") };
+
+    if let Some(column) = column_for(field) {
+        let parse = |v: &str| {
+            column_value(column, v).ok_or(format!("\"{}\" is not a valid {} value", v, lower))
+        };
+        let pairs = match range {
+            Some((from, to)) => vec![(Comparison::Ge, parse(from)?), (Comparison::Le, parse(to)?)],
+            None => {
+                let (comparison, rest) = split_comparison(value);
+                vec![(comparison, parse(rest)?)]
+            }
+        };
+        return Ok(pairs
+            .into_iter()
+            .map(|(comparison, value)| Condition::Column {
+                column,
+                comparison: if matches!(column, Column::Modified | Column::Added) {
+                    flip(comparison)
+                } else {
+                    comparison
+                },
+                value,
+            })
+            .collect());
+    }
+
+    let key = frame_key_for(field).ok_or(format!("Unknown field \"{}\"", field))?;
+    if let Some((from, to)) = range {
+        if let (Ok(from), Ok(to)) = (from.parse::<f64>(), to.parse::<f64>()) {
+            return Ok(vec![
+                Condition::FieldNumber {
+                    key,
+                    comparison: Comparison::Ge,
+                    value: from,
+                },
+                Condition::FieldNumber {
+                    key,
+                    comparison: Comparison::Le,
+                    value: to,
+                },
+            ]);
+        }
+    }
+    if !quoted {
+        let (comparison, rest) = split_comparison(value);
+        if let Ok(number) = rest.parse::<f64>() {
+            if comparison != Comparison::Eq || value.starts_with('=') {
+                return Ok(vec![Condition::FieldNumber {
+                    key,
+                    comparison,
+                    value: number,
+                }]);
+            }
+        }
+        if let Some(exact) = value.strip_prefix('=') {
+            return Ok(vec![Condition::FieldEquals {
+                key,
+                text: exact.to_string(),
+            }]);
+        }
+    }
+    Ok(vec![Condition::Field {
+        key,
+        text: value.to_string(),
+        phrase: quoted,
+    }])
+}
+
+impl Query {
+    /// Parse `artist:"Daft Punk" year:>=2000 -has:cover format:flac` style queries
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut terms: Vec<Term> = Vec::new();
+        for token in tokenize(input)? {
+            let (negated, text) = match token.text.strip_prefix('-') {
+                Some(rest) if !rest.is_empty() || token.quoted => (true, rest.to_string()),
+                _ => (false, token.text),
+            };
+            let quoted = token.quoted;
+            let field = if token.phrase {
+                None
+            } else {
+                text.split_once(':').filter(|(field, _)| !field.is_empty())
+            };
+            let conditions = match field {
+                Some((field, value)) => {
+                    let negated_missing = field.eq_ignore_ascii_case("missing");
+                    let conditions = field_conditions(field, value, quoted)?;
+                    if negated_missing {
+                        terms.extend(conditions.into_iter().map(|condition| Term {
+                            negated: !negated,
+                            condition,
+                        }));
+                        continue;
+                    }
+                    conditions
+                }
+                None => vec![Condition::Text {
+                    text: text.clone(),
+                    phrase: quoted,
+                }],
+            };
+            terms.extend(
+                conditions
+                    .into_iter()
+                    .map(|condition| Term { negated, condition }),
+            );
+        }
+        Ok(Self { terms })
+    }
+
+    /// WHERE clause over `files f`, with its bind values in order
+    pub fn to_sql(&self) -> (String, Vec<Bind>) {
+        if self.terms.is_empty() {
+            return ("1".to_string(), Vec::new());
+        }
+        let mut binds: Vec<Bind> = Vec::new();
+        let clauses: Vec<String> = self
+            .terms
+            .iter()
+            .map(|term| {
+                let clause = condition_sql(&term.condition, &mut binds);
+                if term.negated {
+                    // NULL columns should count as "not matching", not as unknown
+                    format!("NOT COALESCE(({}), 0)", clause)
+                } else {
+                    format!("({})", clause)
+                }
+            })
+            .collect();
+        (clauses.join(" AND "), binds)
+    }
+}
+
+/// FTS5 match expression: phrases stay together, single words match as prefixes
+fn fts_expression(text: &str, phrase: bool) -> String {
+    let quote = |s: &str| format!("\"{}\"", s.replace('"', "\"\""));
+    if phrase {
+        return quote(text);
+    }
+    text.split_whitespace()
+        .map(|w| format!("{}*", quote(w)))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// `text` with the LIKE wildcards taken literally, for clauses that escape with a backslash
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn like_pattern(text: &str) -> String {
+    format!("%{}%", escape_like(text))
+}
+
+fn condition_sql(condition: &Condition, binds: &mut Vec<Bind>) -> String {
+    match condition {
+        Condition::Text { text, phrase } => {
+            let expression = fts_expression(text, *phrase);
+            binds.push(Bind::Text(expression.clone()));
+            binds.push(Bind::Text(expression));
+            binds.push(Bind::Text(like_pattern(text)));
+            "f.path IN (SELECT t.file_path FROM tag_text_fts JOIN tag_text t ON t.id = tag_text_fts.rowid WHERE tag_text_fts MATCH ?) \
+             OR f.path IN (SELECT c.file_path FROM tag_comment_fts JOIN tag_comment c ON c.id = tag_comment_fts.rowid WHERE tag_comment_fts MATCH ?) \
+             OR f.file_name LIKE ? ESCAPE '\\'"
+                .to_string()
+        }
+        Condition::Field { key, text, phrase } => {
+            binds.push(Bind::Text(fts_expression(text, *phrase)));
+            binds.push(Bind::Text(key.to_string()));
+            if *key == FrameKey::Comments {
+                "f.path IN (SELECT c.file_path FROM tag_comment_fts JOIN tag_comment c ON c.id = tag_comment_fts.rowid WHERE tag_comment_fts MATCH ? AND c.key = ?)".to_string()
+            } else {
+                "f.path IN (SELECT t.file_path FROM tag_text_fts JOIN tag_text t ON t.id = tag_text_fts.rowid WHERE tag_text_fts MATCH ? AND t.key = ?)".to_string()
+            }
+        }
+        Condition::FieldEquals { key, text } => {
+            binds.push(Bind::Text(key.to_string()));
+            binds.push(Bind::Text(text.clone()));
+            "f.path IN (SELECT file_path FROM tag_text WHERE key = ? AND value = ? COLLATE NOCASE)"
+                .to_string()
+        }
+        Condition::FieldNumber {
+            key,
+            comparison,
+            value,
+        } => {
+            binds.push(Bind::Text(key.to_string()));
+            binds.push(Bind::Number(*value));
+            // CAST reads the leading number, so "3/12" compares as 3 and "2001-05-01" as 2001
+            format!(
+                "f.path IN (SELECT file_path FROM tag_text WHERE key = ? AND CAST(value AS REAL) {} ?)",
+                comparison.sql()
+            )
+        }
+        Condition::Column {
+            column,
+            comparison,
+            value,
+        } => {
+            binds.push(Bind::Number(*value));
+            format!("{} {} ?", column.sql(), comparison.sql())
+        }
+        Condition::Format(format) => {
+            binds.push(Bind::Text(format.clone()));
+            binds.push(Bind::Text(format!("%.{}", escape_like(format))));
+            "lower(f.codec) = ? OR lower(f.path) LIKE ? ESCAPE '\\'".to_string()
+        }
+        Condition::TagFormat(format) => {
+            binds.push(Bind::Text(like_pattern(format)));
+            "lower(f.tag_formats) LIKE ? ESCAPE '\\'".to_string()
+        }
+        Condition::Has(key) => {
+            for _ in 0..5 {
+                binds.push(Bind::Text(key.to_string()));
+            }
+            "f.path IN (SELECT file_path FROM tag_text WHERE key = ? \
+             UNION SELECT file_path FROM tag_comment WHERE key = ? \
+             UNION SELECT file_path FROM tag_pictures WHERE key = ? \
+             UNION SELECT file_path FROM tag_user_text WHERE key = ? \
+             UNION SELECT file_path FROM tag_user_url WHERE key = ?)"
+                .to_string()
+        }
+        Condition::HasFingerprint => {
+            binds.push(Bind::Text(FrameKey::AcoustidFingerprint.to_string()));
+            "f.path IN (SELECT file_path FROM fingerprints UNION SELECT file_path FROM tag_text WHERE key = ?)"
+                .to_string()
+        }
+        Condition::Path(text) => {
+            binds.push(Bind::Text(like_pattern(text)));
+            "f.path LIKE ? ESCAPE '\\'".to_string()
+        }
+        Condition::FileName(text) => {
+            binds.push(Bind::Text(like_pattern(text)));
+            "f.file_name LIKE ? ESCAPE '\\'".to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conditions(input: &str) -> Vec<(bool, Condition)> {
+        Query::parse(input)
+            .unwrap()
+            .terms
+            .into_iter()
+            .map(|t| (t.negated, t.condition))
+            .collect()
+    }
+
+    #[test]
+    fn bare_words_and_phrases() {
+        assert_eq!(
+            conditions(r#"daft "one more time""#),
+            vec![
+                (
+                    false,
+                    Condition::Text {
+                        text: "daft".to_string(),
+                        phrase: false
+                    }
+                ),
+                (
+                    false,
+                    Condition::Text {
+                        text: "one more time".to_string(),
+                        phrase: true
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn quoted_colon_is_not_a_field() {
+        assert_eq!(
+            conditions(r#""re:mix""#),
+            vec![(
+                false,
+                Condition::Text {
+                    text: "re:mix".to_string(),
+                    phrase: true
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn field_with_quoted_value() {
+        assert_eq!(
+            conditions(r#"artist:"Daft Punk""#),
+            vec![(
+                false,
+                Condition::Field {
+                    key: FrameKey::Artist,
+                    text: "Daft Punk".to_string(),
+                    phrase: true
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn exact_value_and_numbers() {
+        assert_eq!(
+            conditions("genre:=rock year:>=2000"),
+            vec![
+                (
+                    false,
+                    Condition::FieldEquals {
+                        key: FrameKey::Genre,
+                        text: "rock".to_string()
+                    }
+                ),
+                (
+                    false,
+                    Condition::FieldNumber {
+                        key: FrameKey::Year,
+                        comparison: Comparison::Ge,
+                        value: 2000.0
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn ranges_become_two_comparisons() {
+        assert_eq!(
+            conditions("year:1990..1999"),
+            vec![
+                (
+                    false,
+                    Condition::FieldNumber {
+                        key: FrameKey::Year,
+                        comparison: Comparison::Ge,
+                        value: 1990.0
+                    }
+                ),
+                (
+                    false,
+                    Condition::FieldNumber {
+                        key: FrameKey::Year,
+                        comparison: Comparison::Le,
+                        value: 1999.0
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn column_units() {
+        assert_eq!(
+            conditions("duration:>3:30 size:<5mb samplerate:44.1khz"),
+            vec![
+                (
+                    false,
+                    Condition::Column {
+                        column: Column::Duration,
+                        comparison: Comparison::Gt,
+                        value: 210_000.0
+                    }
+                ),
+                (
+                    false,
+                    Condition::Column {
+                        column: Column::Size,
+                        comparison: Comparison::Lt,
+                        value: 5.0 * 1024.0 * 1024.0
+                    }
+                ),
+                (
+                    false,
+                    Condition::Column {
+                        column: Column::SampleRate,
+                        comparison: Comparison::Eq,
+                        value: 44_100.0
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn days_ago_flip_the_comparison() {
+        let terms = conditions("modified:<7");
+        assert!(matches!(
+            terms[0].1,
+            Condition::Column {
+                column: Column::Modified,
+                comparison: Comparison::Gt,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn negation_and_missing() {
+        assert_eq!(
+            conditions("-has:cover missing:lyrics -missing:replaygain"),
+            vec![
+                (true, Condition::Has(FrameKey::AttachedPicture)),
+                (true, Condition::Has(FrameKey::UnsyncedLyrics)),
+                (false, Condition::Has(FrameKey::ReplayGainTrackGain)),
+            ]
+        );
+    }
+
+    #[test]
+    fn lone_dash_is_a_word() {
+        assert_eq!(
+            conditions("-"),
+            vec![(
+                false,
+                Condition::Text {
+                    text: "-".to_string(),
+                    phrase: false
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(Query::parse(r#"artist:"daft"#).is_err());
+        assert!(Query::parse("nosuchfield:x").is_err());
+        assert!(Query::parse("bitrate:fast").is_err());
+        assert!(Query::parse("has:nothing").is_err());
+    }
+
+    #[test]
+    fn empty_query_matches_everything() {
+        let query = Query::parse("   ").unwrap();
+        assert!(query.terms.is_empty());
+        assert_eq!(query.to_sql(), ("1".to_string(), Vec::new()));
+    }
+
+    #[test]
+    fn like_wildcards_are_escaped() {
+        let (sql, binds) = Query::parse("format:f_a% path:100%_done").unwrap().to_sql();
+        assert!(sql.contains("LIKE ? ESCAPE"));
+        assert_eq!(
+            binds,
+            vec![
+                Bind::Text("f_a%".to_string()),
+                Bind::Text("%.f\\_a\\%".to_string()),
+                Bind::Text("%100\\%\\_done%".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,325 @@
+use crate::file_watcher::{CreatedFile, DeletedFile, OpEvent};
+use crate::jobs::Job;
+use crate::library::saved_queries::refresh_saved_queries;
+use crate::tag_manager::journal;
+use crate::utils::{
+    get_imported_folders, get_tags, insert_pending_files, is_in_folder, is_supported_file,
+    systemtime_to_unix, update_root_scan_time,
+};
+use crate::AppState;
+
+use serde::Serialize;
+use sqlx::Row;
+use std::collections::{HashMap, HashSet};
+use std::fs::metadata;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use tauri::{async_runtime, AppHandle, Emitter, Manager};
+use walkdir::WalkDir;
+
+/// Time between two scheduled rescans
+const RESCAN_INTERVAL: Duration = Duration::from_secs(30 * 60);
+/// Files looked at before the walk pauses, so a rescan never saturates the disk
+const THROTTLE_EVERY: usize = 200;
+const THROTTLE_PAUSE: Duration = Duration::from_millis(20);
+/// Files re-read from disk between pauses, reading tags costs a lot more than a stat
+const READ_THROTTLE_EVERY: usize = 25;
+
+/// Only one rescan at a time, whether it came from the schedule or the frontend
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Lets the next rescan start once this one ends, even when it panicked
+struct RunningGuard;
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedRead {
+    pub path: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RescanReport {
+    pub job_id: String,
+    pub scanned: usize,
+    /// Files on disk the index didn't know about
+    pub added: Vec<String>,
+    /// Indexed files whose size or modification time changed
+    pub changed: Vec<String>,
+    /// Indexed files that are gone while their root is reachable
+    pub missing: Vec<String>,
+    /// Files marked missing before that are back
+    pub restored: Vec<String>,
+    /// Roots that couldn't be reached, their files are left alone
+    pub unreachable_roots: Vec<String>,
+    /// New or changed files whose tags couldn't be read
+    pub failed: Vec<FailedRead>,
+    /// Staged copies left behind by writes a crash cut short, removed before walking
+    pub removed_temp_files: usize,
+    pub cancelled: bool,
+    /// Why the rescan stopped early, when it did
+    pub error: Option<String>,
+}
+
+struct IndexedFile {
+    last_modified: i64,
+    file_size: i64,
+    missing: bool,
+}
+
+async fn indexed_files(pool: &sqlx::SqlitePool) -> Result<HashMap<String, IndexedFile>, String> {
+    let rows = sqlx::query(
+        "SELECT path, COALESCE(last_modified, 0) AS last_modified, COALESCE(file_size, 0) AS file_size, status FROM files",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(rows
+        .iter()
+        .map(|row| {
+            (
+                row.get("path"),
+                IndexedFile {
+                    last_modified: row.get("last_modified"),
+                    file_size: row.get("file_size"),
+                    missing: row.get::<Option<String>, _>("status").as_deref() == Some("missing"),
+                },
+            )
+        })
+        .collect())
+}
+
+async fn set_status(pool: &sqlx::SqlitePool, paths: &[String], status: &str) -> Result<(), String> {
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    for path in paths {
+        sqlx::query("UPDATE files SET status = ?2 WHERE path = ?1")
+            .bind(path)
+            .bind(status)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    tx.commit().await.map_err(|e| e.to_string())
+}
+
+/// Walk one root and sort what's on disk against the index
+fn walk_root(
+    root: &PathBuf,
+    indexed: &HashMap<String, IndexedFile>,
+    report: &mut RescanReport,
+    seen: &mut HashSet<String>,
+    job: &Job,
+) {
+    let mut batch = 0;
+    for entry in WalkDir::new(root).into_iter().filter_map(|e| e.ok()) {
+        if job.is_cancelled() {
+            return;
+        }
+        let path = entry.path().to_path_buf();
+        if !entry.file_type().is_file() || !is_supported_file(&path) {
+            continue;
+        }
+        let Ok(meta) = metadata(&path) else {
+            continue;
+        };
+        let path_str = path.to_string_lossy().to_string();
+        let disk_modified = meta.modified().map(systemtime_to_unix).unwrap_or(0);
+
+        match indexed.get(&path_str) {
+            None => report.added.push(path_str.clone()),
+            Some(file) => {
+                if file.missing {
+                    report.restored.push(path_str.clone());
+                }
+                if file.file_size != meta.len() as i64 || disk_modified > file.last_modified {
+                    report.changed.push(path_str.clone());
+                }
+            }
+        }
+        seen.insert(path_str);
+        report.scanned += 1;
+
+        batch += 1;
+        if batch == THROTTLE_EVERY {
+            job.advance_by(batch, &path.to_string_lossy());
+            batch = 0;
+            thread::sleep(THROTTLE_PAUSE);
+        }
+    }
+    if batch > 0 {
+        job.advance_by(batch, &root.to_string_lossy());
+    }
+}
+
+/// Frontend events for the folder tree, the same shape the file watcher sends
+fn op_events(report: &RescanReport) -> Vec<OpEvent> {
+    let parent = |p: &PathBuf| p.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+    let created = report.added.iter().chain(report.restored.iter()).map(|p| {
+        let path = PathBuf::from(p);
+        OpEvent::Create(CreatedFile {
+            name: path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            parent_path: parent(&path),
+            is_directory: false,
+            path,
+        })
+    });
+    let removed = report.missing.iter().map(|p| {
+        let path = PathBuf::from(p);
+        OpEvent::Remove(DeletedFile {
+            parent_path: parent(&path),
+            path,
+        })
+    });
+    created.chain(removed).collect()
+}
+
+/// Walk every active import root, re-read files whose size or modification time changed,
+/// index new ones and mark the ones that disappeared as missing. With `clean_orphans`, staged
+/// copies left in the roots are removed first
+fn rescan_library(
+    app_handle: &AppHandle,
+    job: &Job,
+    report: &mut RescanReport,
+    clean_orphans: bool,
+) -> Result<(), String> {
+    let db = app_handle.state::<AppState>().db.clone();
+
+    let roots = async_runtime::block_on(get_imported_folders(&db));
+    if clean_orphans {
+        report.removed_temp_files = journal::clean_orphans(&roots);
+    }
+    let indexed = async_runtime::block_on(indexed_files(&db.pool))?;
+    // the index is the best guess of how much there is to walk
+    job.set_total(indexed.len());
+
+    let mut seen: HashSet<String> = HashSet::new();
+    for root in roots.iter().map(PathBuf::from) {
+        if !root.is_dir() {
+            report
+                .unreachable_roots
+                .push(root.to_string_lossy().to_string());
+            continue;
+        }
+        walk_root(&root, &indexed, report, &mut seen, job);
+        if job.is_cancelled() {
+            report.cancelled = true;
+            return Ok(());
+        }
+        let _ = async_runtime::block_on(update_root_scan_time(&db.pool, &root.to_string_lossy()));
+        report.missing.extend(
+            indexed
+                .iter()
+                .filter(|(path, file)| {
+                    !file.missing
+                        && !seen.contains(*path)
+                        && is_in_folder(&root, &PathBuf::from(path))
+                })
+                .map(|(path, _)| path.clone()),
+        );
+    }
+    report.missing.sort();
+
+    async_runtime::block_on(set_status(&db.pool, &report.missing, "missing"))?;
+    async_runtime::block_on(set_status(&db.pool, &report.restored, "pending"))?;
+
+    let to_read: Vec<String> = report
+        .added
+        .iter()
+        .chain(report.changed.iter())
+        .cloned()
+        .collect();
+    job.set_total(job.done() + to_read.len());
+    // stores the new size, and the row for files that weren't indexed yet
+    async_runtime::block_on(insert_pending_files(
+        &db.pool,
+        to_read.iter().map(PathBuf::from).collect(),
+    ))
+    .map_err(|e| e.to_string())?;
+    for chunk in to_read.chunks(READ_THROTTLE_EVERY) {
+        if job.is_cancelled() {
+            report.cancelled = true;
+            break;
+        }
+        if let Err(e) = async_runtime::block_on(get_tags(&db, chunk.to_vec(), true)) {
+            report.failed.extend(chunk.iter().map(|path| FailedRead {
+                path: path.clone(),
+                error: e.to_string(),
+            }));
+        }
+        job.advance_by(chunk.len(), &chunk[chunk.len() - 1]);
+        thread::sleep(THROTTLE_PAUSE);
+    }
+
+    let touched: Vec<String> = to_read
+        .iter()
+        .chain(report.missing.iter())
+        .chain(report.restored.iter())
+        .cloned()
+        .collect();
+    refresh_saved_queries(app_handle, touched);
+    let events = op_events(report);
+    if !events.is_empty() {
+        let _ = app_handle.emit("folder-view-events", events);
+    }
+    Ok(())
+}
+
+/// Sends the report on "library-rescanned" when done, also when the rescan failed
+fn run_rescan(app_handle: &AppHandle, job: Job, clean_orphans: bool) {
+    let mut report = RescanReport {
+        job_id: job.id.clone(),
+        ..Default::default()
+    };
+    if let Err(e) = rescan_library(app_handle, &job, &mut report, clean_orphans) {
+        report.error = Some(e);
+    }
+    job.finish();
+    let _ = app_handle.emit("library-rescanned", report);
+}
+
+/// Start a rescan in the background. Returns the job id, or None when one is already running
+pub fn start_rescan(app_handle: &AppHandle) -> Option<String> {
+    start(app_handle, false)
+}
+
+fn start(app_handle: &AppHandle, clean_orphans: bool) -> Option<String> {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return None;
+    }
+    let guard = RunningGuard;
+    let job = app_handle
+        .state::<AppState>()
+        .jobs
+        .start(app_handle, "rescan", 0);
+    let id = job.id.clone();
+    let app_handle = app_handle.clone();
+    async_runtime::spawn_blocking(move || {
+        let _guard = guard;
+        run_rescan(&app_handle, job, clean_orphans);
+    });
+    Some(id)
+}
+
+/// Rescan on startup, to catch what changed while the app was closed, then on a schedule. The
+/// first one also removes the staged copies writes cut short by a crash left in the roots
+pub fn start_rescan_service(app_handle: &AppHandle) {
+    let app_handle = app_handle.clone();
+    thread::spawn(move || {
+        start(&app_handle, true);
+        loop {
+            thread::sleep(RESCAN_INTERVAL);
+            start_rescan(&app_handle);
+        }
+    });
+}
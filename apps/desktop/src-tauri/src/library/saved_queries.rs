@@ -0,0 +1,299 @@
+use crate::library::query::Query;
+use crate::library::search::{bind_all, search, SearchOptions, SearchResult};
+use crate::utils::get_tags;
+use crate::AppState;
+
+use serde::Serialize;
+use sqlx::{Row, SqlitePool};
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{async_runtime, AppHandle, Emitter, Manager};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedQuery {
+    pub id: i64,
+    pub name: String,
+    pub query: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// Sent as "saved-query-updated" when a file change touches a saved query's results
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedQueryUpdate {
+    pub id: i64,
+    pub paths: Vec<String>,
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+fn from_row(row: &sqlx::sqlite::SqliteRow) -> SavedQuery {
+    SavedQuery {
+        id: row.get("id"),
+        name: row.get("name"),
+        query: row.get("query"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+pub async fn get_saved_query(pool: &SqlitePool, id: i64) -> Result<SavedQuery, String> {
+    sqlx::query("SELECT id, name, query, created_at, updated_at FROM saved_queries WHERE id = ?1")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .map(|row| from_row(&row))
+        .ok_or(format!("Saved query {} does not exist", id))
+}
+
+pub async fn list_saved_queries(pool: &SqlitePool) -> Result<Vec<SavedQuery>, String> {
+    let rows = sqlx::query(
+        "SELECT id, name, query, created_at, updated_at FROM saved_queries ORDER BY name COLLATE NOCASE",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(rows.iter().map(from_row).collect())
+}
+
+fn validate_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Saved query name cannot be empty".to_string());
+    }
+    Ok(name.to_string())
+}
+
+pub async fn create_saved_query(
+    pool: &SqlitePool,
+    name: &str,
+    query: &str,
+) -> Result<SavedQuery, String> {
+    let name = validate_name(name)?;
+    // refuse queries that would only fail later
+    let parsed = Query::parse(query)?;
+    let now = now();
+    let id = sqlx::query(
+        "INSERT INTO saved_queries (name, query, created_at, updated_at) VALUES (?1, ?2, ?3, ?3)",
+    )
+    .bind(&name)
+    .bind(query.trim())
+    .bind(now)
+    .execute(pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .last_insert_rowid();
+    store_results(pool, id, &parsed).await?;
+    get_saved_query(pool, id).await
+}
+
+pub async fn rename_saved_query(
+    pool: &SqlitePool,
+    id: i64,
+    name: &str,
+) -> Result<SavedQuery, String> {
+    let name = validate_name(name)?;
+    sqlx::query("UPDATE saved_queries SET name = ?2, updated_at = ?3 WHERE id = ?1")
+        .bind(id)
+        .bind(&name)
+        .bind(now())
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    get_saved_query(pool, id).await
+}
+
+pub async fn delete_saved_query(pool: &SqlitePool, id: i64) -> Result<(), String> {
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    sqlx::query("DELETE FROM saved_query_results WHERE query_id = ?1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    sqlx::query("DELETE FROM saved_queries WHERE id = ?1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    tx.commit().await.map_err(|e| e.to_string())
+}
+
+/// Replace the cached result set of a saved query, returns the new paths
+async fn store_results(pool: &SqlitePool, id: i64, query: &Query) -> Result<Vec<String>, String> {
+    let (clause, binds) = query.to_sql();
+    let sql = format!(
+        "SELECT f.path FROM files f WHERE {} ORDER BY f.path",
+        clause
+    );
+    let paths: Vec<String> = bind_all(sqlx::query(&sql), &binds)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map(|row| row.get("path"))
+        .collect();
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    sqlx::query("DELETE FROM saved_query_results WHERE query_id = ?1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    for path in paths.iter() {
+        sqlx::query("INSERT INTO saved_query_results (query_id, file_path) VALUES (?1, ?2)")
+            .bind(id)
+            .bind(path)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    tx.commit().await.map_err(|e| e.to_string())?;
+    Ok(paths)
+}
+
+/// Run a saved query against the database, one page at a time
+pub async fn evaluate_saved_query(
+    pool: &SqlitePool,
+    id: i64,
+    options: &SearchOptions,
+) -> Result<SearchResult, String> {
+    let saved = get_saved_query(pool, id).await?;
+    let query = Query::parse(&saved.query)?;
+    store_results(pool, id, &query).await?;
+    search(pool, &query, options).await
+}
+
+/// Every file currently matching a saved query, in path order
+pub async fn saved_query_paths(pool: &SqlitePool, id: i64) -> Result<Vec<String>, String> {
+    let saved = get_saved_query(pool, id).await?;
+    let query = Query::parse(&saved.query)?;
+    store_results(pool, id, &query).await
+}
+
+/// Paths changed since the last refresh are checked this many at a time
+const REFRESH_CHUNK: usize = 500;
+
+/// Re-check the `changed` paths against the cached results of a saved query.
+/// Returns whether one of them is in the results, or was
+async fn refresh_results(
+    pool: &SqlitePool,
+    id: i64,
+    query: &Query,
+    changed: &[String],
+) -> Result<bool, String> {
+    let (clause, binds) = query.to_sql();
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    let mut touched = false;
+    for chunk in changed.chunks(REFRESH_CHUNK) {
+        let marks = vec!["?"; chunk.len()].join(", ");
+        let sql = format!(
+            "SELECT f.path FROM files f WHERE ({}) AND f.path IN ({})",
+            clause, marks
+        );
+        let mut select = bind_all(sqlx::query(&sql), &binds);
+        for path in chunk {
+            select = select.bind(path);
+        }
+        let matching: HashSet<String> = select
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?
+            .iter()
+            .map(|row| row.get("path"))
+            .collect();
+
+        let sql = format!(
+            "SELECT file_path FROM saved_query_results WHERE query_id = ? AND file_path IN ({})",
+            marks
+        );
+        let mut select = sqlx::query_scalar::<_, String>(&sql).bind(id);
+        for path in chunk {
+            select = select.bind(path);
+        }
+        let cached: HashSet<String> = select
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .collect();
+
+        touched |= !matching.is_empty() || !cached.is_empty();
+        for path in cached.difference(&matching) {
+            sqlx::query("DELETE FROM saved_query_results WHERE query_id = ?1 AND file_path = ?2")
+                .bind(id)
+                .bind(path)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        for path in matching.difference(&cached) {
+            sqlx::query("INSERT INTO saved_query_results (query_id, file_path) VALUES (?1, ?2)")
+                .bind(id)
+                .bind(path)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    tx.commit().await.map_err(|e| e.to_string())?;
+    Ok(touched)
+}
+
+/// Update saved queries after the file watcher saw `changed` paths. Only those paths are
+/// checked against each query, in the background. Queries whose old or new results contain
+/// one of them are sent to the frontend
+pub fn refresh_saved_queries(app_handle: &AppHandle, changed: Vec<String>) {
+    if changed.is_empty() {
+        return;
+    }
+    let app_handle = app_handle.clone();
+    async_runtime::spawn(async move {
+        let db = app_handle.state::<AppState>().db.clone();
+        // the index has to reflect the change before queries can see it
+        let existing: Vec<String> = changed
+            .iter()
+            .filter(|p| std::path::Path::new(p).is_file())
+            .cloned()
+            .collect();
+        if !existing.is_empty() {
+            let _ = get_tags(&db, existing, false).await;
+        }
+
+        let mut changed = changed;
+        changed.sort();
+        changed.dedup();
+        for saved in list_saved_queries(&db.pool).await.unwrap_or_default() {
+            let Ok(query) = Query::parse(&saved.query) else {
+                continue;
+            };
+            if !matches!(
+                refresh_results(&db.pool, saved.id, &query, &changed).await,
+                Ok(true)
+            ) {
+                continue;
+            }
+            let paths: Vec<String> = sqlx::query_scalar(
+                "SELECT file_path FROM saved_query_results WHERE query_id = ?1 ORDER BY file_path",
+            )
+            .bind(saved.id)
+            .fetch_all(&db.pool)
+            .await
+            .unwrap_or_default();
+            let _ = app_handle.emit(
+                "saved-query-updated",
+                SavedQueryUpdate {
+                    id: saved.id,
+                    paths,
+                },
+            );
+        }
+    });
+}
@@ -0,0 +1,126 @@
+use crate::library::query::{column_for, frame_key_for, Bind, Query};
+use crate::tag_manager::utils::FrameKey;
+
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqliteArguments, SqlitePool};
+use sqlx::{Row, Sqlite};
+
+pub const DEFAULT_PAGE_SIZE: u32 = 100;
+const MAX_PAGE_SIZE: u32 = 5000;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchOptions {
+    /// Zero based
+    pub page: Option<u32>,
+    pub page_size: Option<u32>,
+    /// `path`, `fileName`, a column like `bitrate` or any frame key like `artist`
+    pub sort_by: Option<String>,
+    pub descending: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResult {
+    pub paths: Vec<String>,
+    /// Matches over all pages
+    pub total: i64,
+    pub page: u32,
+    pub page_size: u32,
+}
+
+pub fn bind_all<'q>(
+    mut query: sqlx::query::Query<'q, Sqlite, SqliteArguments<'q>>,
+    binds: &'q [Bind],
+) -> sqlx::query::Query<'q, Sqlite, SqliteArguments<'q>> {
+    for bind in binds {
+        query = match bind {
+            Bind::Text(s) => query.bind(s.as_str()),
+            Bind::Number(n) => query.bind(*n),
+        };
+    }
+    query
+}
+
+/// ORDER BY expression for a sort name, frame values sort by their first value
+fn sort_expression(sort_by: &str, binds: &mut Vec<Bind>) -> Result<String, String> {
+    match sort_by {
+        "path" => return Ok("f.path".to_string()),
+        "fileName" | "name" => return Ok("f.file_name COLLATE NOCASE".to_string()),
+        "codec" | "format" => return Ok("f.codec".to_string()),
+        _ => {}
+    }
+    if let Some(column) = column_for(sort_by) {
+        return Ok(column.sql().to_string());
+    }
+    let key = frame_key_for(sort_by).ok_or(format!("Cannot sort by \"{}\"", sort_by))?;
+    binds.push(Bind::Text(key.to_string()));
+    let value =
+        "(SELECT value FROM tag_text WHERE file_path = f.path AND key = ? ORDER BY id LIMIT 1)";
+    Ok(match key {
+        FrameKey::TrackNumber
+        | FrameKey::DiscNumber
+        | FrameKey::Year
+        | FrameKey::BeatsPerMinute
+        | FrameKey::TotalTracks
+        | FrameKey::TotalDiscs => format!("CAST({} AS REAL)", value),
+        _ => format!("{} COLLATE NOCASE", value),
+    })
+}
+
+/// Run a query against the tag index, one page at a time
+pub async fn search(
+    pool: &SqlitePool,
+    query: &Query,
+    options: &SearchOptions,
+) -> Result<SearchResult, String> {
+    let (clause, mut binds) = query.to_sql();
+
+    let count_sql = format!("SELECT COUNT(*) AS total FROM files f WHERE {}", clause);
+    let total: i64 = bind_all(sqlx::query(&count_sql), &binds)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .get("total");
+
+    let page = options.page.unwrap_or(0);
+    let page_size = options
+        .page_size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let mut sort_binds: Vec<Bind> = Vec::new();
+    let sort = sort_expression(
+        options.sort_by.as_deref().unwrap_or("path"),
+        &mut sort_binds,
+    )?;
+    let direction = if options.descending.unwrap_or(false) {
+        "DESC"
+    } else {
+        "ASC"
+    };
+    // the sort expression appears twice, so its binds do too
+    binds.extend(sort_binds.clone());
+    binds.extend(sort_binds);
+
+    // missing values go last whichever way we sort
+    let sql = format!(
+        "SELECT f.path FROM files f WHERE {} ORDER BY ({}) IS NULL, {} {}, f.path LIMIT {} OFFSET {}",
+        clause,
+        sort,
+        sort,
+        direction,
+        page_size,
+        page as u64 * page_size as u64
+    );
+    let rows = bind_all(sqlx::query(&sql), &binds)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(SearchResult {
+        paths: rows.iter().map(|r| r.get("path")).collect(),
+        total,
+        page,
+        page_size,
+    })
+}
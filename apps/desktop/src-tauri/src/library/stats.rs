@@ -0,0 +1,469 @@
+use crate::config::user::ViewMode;
+use crate::constants::FRAME_KEYS;
+use crate::library::albums::{album_key, AlbumKey};
+use crate::tag_manager::picture::picture_dimensions;
+use crate::tag_manager::utils::{FrameKey, TagValue};
+use crate::AppState;
+
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::metadata;
+use std::path::Path;
+
+/// Covers smaller than this on either side are reported
+pub const DEFAULT_MIN_COVER_SIZE: u32 = 500;
+
+/// Fields that should be the same on every track of an album
+const ALBUM_FIELDS: [FrameKey; 9] = [
+    FrameKey::AlbumArtist,
+    FrameKey::Year,
+    FrameKey::Label,
+    FrameKey::Compilation,
+    FrameKey::TotalDiscs,
+    FrameKey::CatalogNumber,
+    FrameKey::Barcode,
+    FrameKey::ReleaseCountry,
+    FrameKey::MusicBrainzAlbumId,
+];
+
+/// Fields `album_key` needs on top of the album fields
+const KEY_FIELDS: [FrameKey; 2] = [FrameKey::Album, FrameKey::Artist];
+
+/// Enough of a picture header to find its size
+const PICTURE_HEAD: i64 = 262144;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FormatCount {
+    pub name: String,
+    pub count: u64,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FillRate {
+    pub key: FrameKey,
+    pub filled: u64,
+    pub rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UndersizedCover {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldValue {
+    /// Empty when the track does not have the field
+    pub value: String,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumInconsistency {
+    pub album: String,
+    pub folder: String,
+    pub field: FrameKey,
+    pub values: Vec<FieldValue>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryStats {
+    pub total_files: u64,
+    pub total_size: u64,
+    pub total_duration_ms: u64,
+    pub by_format: Vec<FormatCount>,
+    pub by_tag_format: Vec<FormatCount>,
+    pub fill_rates: Vec<FillRate>,
+    pub without_cover: Vec<String>,
+    pub undersized_covers: Vec<UndersizedCover>,
+    pub inconsistent_albums: Vec<AlbumInconsistency>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum StatsExportFormat {
+    Json,
+    Csv,
+}
+
+/// What the report needs to know about one file
+#[derive(Default)]
+struct FileFacts {
+    path: String,
+    format: String,
+    tag_format: String,
+    size: u64,
+    duration_ms: u64,
+    keys: HashSet<String>,
+    album_fields: HashMap<FrameKey, String>,
+    /// Largest picture, None when the file has none
+    cover: Option<(u32, u32)>,
+}
+
+fn format_name(path: &str, codec: Option<String>) -> String {
+    codec.filter(|c| !c.is_empty()).unwrap_or_else(|| {
+        Path::new(path)
+            .extension()
+            .map(|e| e.to_string_lossy().to_uppercase())
+            .unwrap_or_else(|| "Unknown".to_string())
+    })
+}
+
+/// Keep the larger of two pictures, pictures of unknown size count as 0x0
+fn larger(a: Option<(u32, u32)>, b: (u32, u32)) -> Option<(u32, u32)> {
+    match a {
+        Some(a) if a.0 as u64 * a.1 as u64 >= b.0 as u64 * b.1 as u64 => Some(a),
+        _ => Some(b),
+    }
+}
+
+fn workspace_facts(state: &AppState) -> Vec<FileFacts> {
+    let ws = state.workspace.lock().unwrap();
+    ws.files
+        .iter()
+        .map(|file| {
+            let path = file.path.to_string_lossy().to_string();
+            let mut facts = FileFacts {
+                format: format_name(&path, file.properties.as_ref().map(|p| p.codec.clone())),
+                tag_format: file.tag_format.to_string(),
+                size: metadata(&file.path).map(|m| m.len()).unwrap_or(0),
+                duration_ms: file.properties.as_ref().map(|p| p.duration_ms).unwrap_or(0),
+                path,
+                ..Default::default()
+            };
+            for (key, values) in file.tags.iter() {
+                for value in values {
+                    match value {
+                        TagValue::Text(s) if s.trim().is_empty() => continue,
+                        TagValue::Text(s)
+                            if KEY_FIELDS.contains(key) || ALBUM_FIELDS.contains(key) =>
+                        {
+                            facts
+                                .album_fields
+                                .entry(*key)
+                                .or_insert(s.trim().to_string());
+                        }
+                        TagValue::Picture { data, .. } => {
+                            let dims = picture_dimensions(data).unwrap_or((0, 0));
+                            facts.cover = larger(facts.cover, dims);
+                        }
+                        _ => {}
+                    }
+                    facts.keys.insert(key.to_string());
+                }
+            }
+            facts
+        })
+        .collect()
+}
+
+async fn database_facts(state: &AppState) -> Result<Vec<FileFacts>, String> {
+    let pool = &state.db.pool;
+    let mut facts: BTreeMap<String, FileFacts> = BTreeMap::new();
+    for row in sqlx::query("SELECT path, file_size, duration_ms, codec, tag_format FROM files")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?
+    {
+        let path: String = row.get("path");
+        facts.insert(
+            path.clone(),
+            FileFacts {
+                format: format_name(&path, row.get("codec")),
+                tag_format: row
+                    .get::<Option<String>, _>("tag_format")
+                    .unwrap_or_else(|| "Unknown".to_string()),
+                size: row.get::<Option<i64>, _>("file_size").unwrap_or(0).max(0) as u64,
+                duration_ms: row.get::<Option<i64>, _>("duration_ms").unwrap_or(0).max(0) as u64,
+                path,
+                ..Default::default()
+            },
+        );
+    }
+
+    let keys = sqlx::query(
+        "SELECT DISTINCT file_path, key FROM tag_text WHERE TRIM(value) != ''
+         UNION SELECT DISTINCT file_path, key FROM tag_pictures
+         UNION SELECT DISTINCT file_path, key FROM tag_comment
+         UNION SELECT DISTINCT file_path, key FROM tag_user_text
+         UNION SELECT DISTINCT file_path, key FROM tag_user_url",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    for row in keys {
+        if let Some(f) = facts.get_mut(&row.get::<String, _>("file_path")) {
+            f.keys.insert(row.get("key"));
+        }
+    }
+
+    let mut album_keys: Vec<FrameKey> = ALBUM_FIELDS.to_vec();
+    album_keys.extend(KEY_FIELDS);
+    let placeholders = vec!["?"; album_keys.len()].join(", ");
+    let sql = format!(
+        "SELECT file_path, key, value FROM tag_text WHERE key IN ({}) ORDER BY id",
+        placeholders
+    );
+    let mut query = sqlx::query(&sql);
+    for key in album_keys.iter() {
+        query = query.bind(key.to_string());
+    }
+    let by_name: HashMap<String, FrameKey> =
+        album_keys.iter().map(|k| (k.to_string(), *k)).collect();
+    for row in query.fetch_all(pool).await.map_err(|e| e.to_string())? {
+        let value: String = row.get("value");
+        let key = by_name.get(&row.get::<String, _>("key"));
+        if let (Some(f), Some(key)) = (facts.get_mut(&row.get::<String, _>("file_path")), key) {
+            if !value.trim().is_empty() {
+                f.album_fields
+                    .entry(*key)
+                    .or_insert(value.trim().to_string());
+            }
+        }
+    }
+
+    // pictures can be large, read them a page at a time and only their headers
+    let mut last_id = 0i64;
+    loop {
+        let rows = sqlx::query(
+            "SELECT id, file_path, SUBSTR(data, 1, ?2) AS head FROM tag_pictures WHERE id > ?1 ORDER BY id LIMIT 200",
+        )
+        .bind(last_id)
+        .bind(PICTURE_HEAD)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+        if rows.is_empty() {
+            break;
+        }
+        for row in rows.iter() {
+            last_id = row.get("id");
+            let head: Vec<u8> = row.get("head");
+            if let Some(f) = facts.get_mut(&row.get::<String, _>("file_path")) {
+                f.cover = larger(f.cover, picture_dimensions(&head).unwrap_or((0, 0)));
+            }
+        }
+    }
+
+    Ok(facts.into_values().collect())
+}
+
+fn count_by(facts: &[FileFacts], name: impl Fn(&FileFacts) -> &str) -> Vec<FormatCount> {
+    let mut counts: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+    for f in facts {
+        let entry = counts.entry(name(f).to_string()).or_default();
+        entry.0 += 1;
+        entry.1 += f.size;
+    }
+    let mut counts: Vec<FormatCount> = counts
+        .into_iter()
+        .map(|(name, (count, size))| FormatCount { name, count, size })
+        .collect();
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then(a.name.cmp(&b.name)));
+    counts
+}
+
+/// Tracks are grouped into albums the way the album view groups them
+fn album_inconsistencies(facts: &[FileFacts]) -> Vec<AlbumInconsistency> {
+    let mut albums: BTreeMap<AlbumKey, Vec<&FileFacts>> = BTreeMap::new();
+    for f in facts {
+        let Some(key) = album_key(|k| f.album_fields.get(&k).cloned()) else {
+            continue;
+        };
+        albums.entry(key).or_default().push(f);
+    }
+
+    let mut out = Vec::new();
+    for tracks in albums.into_values() {
+        if tracks.len() < 2 {
+            continue;
+        }
+        let album = tracks[0].album_fields[&FrameKey::Album].clone();
+        let folder = Path::new(&tracks[0].path)
+            .parent()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default();
+        for field in ALBUM_FIELDS {
+            let mut values: BTreeMap<String, u64> = BTreeMap::new();
+            for t in tracks.iter() {
+                let value = t.album_fields.get(&field).cloned().unwrap_or_default();
+                *values.entry(value).or_default() += 1;
+            }
+            // nobody having the field is a fill rate problem, not an inconsistency
+            if values.len() < 2 {
+                continue;
+            }
+            let mut values: Vec<FieldValue> = values
+                .into_iter()
+                .map(|(value, count)| FieldValue { value, count })
+                .collect();
+            values.sort_by_key(|v| std::cmp::Reverse(v.count));
+            out.push(AlbumInconsistency {
+                album: album.clone(),
+                folder: folder.clone(),
+                field,
+                values,
+            });
+        }
+    }
+    out
+}
+
+/// Health report of the files in view: the workspace in simple view, the index otherwise
+pub async fn library_stats(state: &AppState, min_cover_size: u32) -> Result<LibraryStats, String> {
+    let facts = if state.view_mode == ViewMode::Simple {
+        workspace_facts(state)
+    } else {
+        database_facts(state).await?
+    };
+
+    let total_files = facts.len() as u64;
+    let fill_rates = FRAME_KEYS
+        .iter()
+        .map(|key| {
+            let name = key.to_string();
+            let filled = facts.iter().filter(|f| f.keys.contains(&name)).count() as u64;
+            FillRate {
+                key: *key,
+                filled,
+                rate: if total_files == 0 {
+                    0.0
+                } else {
+                    filled as f64 / total_files as f64
+                },
+            }
+        })
+        .collect();
+
+    let mut undersized_covers = Vec::new();
+    let mut without_cover = Vec::new();
+    for f in facts.iter() {
+        match f.cover {
+            None => without_cover.push(f.path.clone()),
+            Some((width, height)) if width.min(height) < min_cover_size => {
+                undersized_covers.push(UndersizedCover {
+                    path: f.path.clone(),
+                    width,
+                    height,
+                })
+            }
+            _ => {}
+        }
+    }
+
+    Ok(LibraryStats {
+        total_files,
+        total_size: facts.iter().map(|f| f.size).sum(),
+        total_duration_ms: facts.iter().map(|f| f.duration_ms).sum(),
+        by_format: count_by(&facts, |f| &f.format),
+        by_tag_format: count_by(&facts, |f| &f.tag_format),
+        fill_rates,
+        without_cover,
+        undersized_covers,
+        inconsistent_albums: album_inconsistencies(&facts),
+    })
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// One row per fact: `section,name,value,detail`
+pub fn stats_to_csv(stats: &LibraryStats) -> String {
+    let mut rows: Vec<[String; 4]> = vec![
+        [
+            "summary".into(),
+            "totalFiles".into(),
+            stats.total_files.to_string(),
+            String::new(),
+        ],
+        [
+            "summary".into(),
+            "totalSize".into(),
+            stats.total_size.to_string(),
+            String::new(),
+        ],
+        [
+            "summary".into(),
+            "totalDurationMs".into(),
+            stats.total_duration_ms.to_string(),
+            String::new(),
+        ],
+    ];
+    for (section, counts) in [
+        ("format", &stats.by_format),
+        ("tagFormat", &stats.by_tag_format),
+    ] {
+        for c in counts {
+            rows.push([
+                section.into(),
+                c.name.clone(),
+                c.count.to_string(),
+                c.size.to_string(),
+            ]);
+        }
+    }
+    for r in stats.fill_rates.iter() {
+        rows.push([
+            "fillRate".into(),
+            r.key.to_string(),
+            r.filled.to_string(),
+            format!("{:.4}", r.rate),
+        ]);
+    }
+    for path in stats.without_cover.iter() {
+        rows.push([
+            "withoutCover".into(),
+            path.clone(),
+            String::new(),
+            String::new(),
+        ]);
+    }
+    for c in stats.undersized_covers.iter() {
+        rows.push([
+            "undersizedCover".into(),
+            c.path.clone(),
+            format!("{}x{}", c.width, c.height),
+            String::new(),
+        ]);
+    }
+    for a in stats.inconsistent_albums.iter() {
+        let values = a
+            .values
+            .iter()
+            .map(|v| format!("{} ({})", v.value, v.count))
+            .collect::<Vec<_>>()
+            .join("; ");
+        rows.push([
+            "inconsistentAlbum".into(),
+            format!("{} ({})", a.album, a.folder),
+            a.field.to_string(),
+            values,
+        ]);
+    }
+
+    let mut out = String::from("section,name,value,detail\n");
+    for row in rows {
+        out.push_str(
+            &row.iter()
+                .map(|f| csv_field(f))
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+        out.push('\n');
+    }
+    out
+}
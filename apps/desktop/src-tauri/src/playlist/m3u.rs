@@ -0,0 +1,108 @@
+use super::PlaylistEntry;
+
+/// `#EXTINF:<seconds>,<artist> - <title>`, -1 seconds when the length is unknown
+fn extinf(entry: &PlaylistEntry) -> String {
+    let seconds = entry
+        .duration_ms
+        .map(|d| ((d + 500) / 1000) as i64)
+        .unwrap_or(-1);
+    let title = entry.title.clone().unwrap_or_else(|| {
+        std::path::Path::new(&entry.path)
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default()
+    });
+    match entry.artist.as_deref() {
+        Some(artist) if !artist.is_empty() => format!("#EXTINF:{},{} - {}", seconds, artist, title),
+        _ => format!("#EXTINF:{},{}", seconds, title),
+    }
+}
+
+pub fn write(entries: &[PlaylistEntry], locations: &[String]) -> String {
+    let mut out = String::from("#EXTM3U\n");
+    for (entry, location) in entries.iter().zip(locations) {
+        out.push_str(&extinf(entry));
+        out.push('\n');
+        out.push_str(location);
+        out.push('\n');
+    }
+    out
+}
+
+/// Entries with `path` as written in the playlist
+pub fn parse(content: &str) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut pending: Option<(Option<u64>, Option<String>, Option<String>)> = None;
+    for line in content.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            let (seconds, name) = info.split_once(',').unwrap_or((info, ""));
+            // attributes like tvg-id="..." may follow the length
+            let seconds = seconds
+                .split_whitespace()
+                .next()
+                .and_then(|s| s.parse::<f64>().ok())
+                .filter(|s| *s >= 0.0)
+                .map(|s| (s * 1000.0) as u64);
+            let (artist, title) = match name.split_once(" - ") {
+                Some((artist, title)) => (Some(artist.trim()), title.trim()),
+                None => (None, name.trim()),
+            };
+            pending = Some((
+                seconds,
+                artist.filter(|a| !a.is_empty()).map(str::to_string),
+                Some(title.to_string()).filter(|t| !t.is_empty()),
+            ));
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        let (duration_ms, artist, title) = pending.take().unwrap_or((None, None, None));
+        entries.push(PlaylistEntry {
+            path: line.to_string(),
+            title,
+            artist,
+            duration_ms,
+        });
+    }
+    entries
+}
+
+/// Swap locations in place, everything else in the file stays as it was, the BOM included
+pub fn rewrite(content: &str, relocate: &dyn Fn(&str) -> Option<String>) -> Option<String> {
+    let (bom, content) = match content.strip_prefix('\u{feff}') {
+        Some(rest) => ("\u{feff}", rest),
+        None => ("", content),
+    };
+    let newline = if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut changed = false;
+    let lines: Vec<String> = content
+        .split(newline)
+        .map(|line| {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                return line.to_string();
+            }
+            match relocate(trimmed) {
+                Some(location) => {
+                    changed = true;
+                    location
+                }
+                None => line.to_string(),
+            }
+        })
+        .collect();
+    if changed {
+        Some(format!("{}{}", bom, lines.join(newline)))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,527 @@
+pub mod m3u;
+pub mod pls;
+pub mod xspf;
+
+use crate::config::user::ViewMode;
+use crate::tag_manager::tag_backend::{BackendError, TagError};
+use crate::tag_manager::utils::{FrameKey, TagValue};
+use crate::utils::get_imported_folders;
+use crate::AppState;
+
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use tauri::{async_runtime, AppHandle, Emitter, Manager};
+use url::Url;
+use walkdir::WalkDir;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum PlaylistFormat {
+    M3u8,
+    Pls,
+    Xspf,
+}
+
+impl PlaylistFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_string_lossy().to_lowercase();
+        match ext.as_str() {
+            "m3u" | "m3u8" => Some(Self::M3u8),
+            "pls" => Some(Self::Pls),
+            "xspf" => Some(Self::Xspf),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum PathStyle {
+    #[default]
+    Relative,
+    Absolute,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistEntry {
+    pub path: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub duration_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub playlist: String,
+    pub format: PlaylistFormat,
+    /// Entries that point at a file on disk, with `path` resolved
+    pub entries: Vec<PlaylistEntry>,
+    /// Locations as written in the playlist that could not be found
+    pub missing: Vec<String>,
+    /// Stream URLs, kept out of both lists
+    pub remote: Vec<String>,
+}
+
+/// Resolve `..` and `.` without touching the disk, the files may be gone already
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !out.pop() {
+                    out.push("..");
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// `to` as seen from `from_dir`, absolute when they share nothing (other drive)
+fn relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from_dir.components().collect();
+    let to_components: Vec<Component> = to.components().collect();
+    let common = from
+        .iter()
+        .zip(to_components.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        return to.to_path_buf();
+    }
+    let mut out = PathBuf::new();
+    for _ in common..from.len() {
+        out.push("..");
+    }
+    for c in &to_components[common..] {
+        out.push(c);
+    }
+    out
+}
+
+fn encode_uri_path(path: &Path) -> String {
+    let raw = path.to_string_lossy().replace('\\', "/");
+    let mut out = String::with_capacity(raw.len());
+    for b in raw.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~/!$&'()*+,;=:@".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+fn decode_uri_path(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(b) = u8::from_str_radix(hex, 16) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+fn is_remote(location: &str) -> bool {
+    match location.split_once("://") {
+        Some((scheme, _)) => !scheme.eq_ignore_ascii_case("file") && scheme.len() > 1,
+        None => false,
+    }
+}
+
+/// Where a location written in a playlist points, relative ones start at the playlist folder.
+/// XSPF locations are URIs, so relative ones are percent-encoded too
+fn resolve_location(location: &str, base_dir: &Path, uri: bool) -> Option<PathBuf> {
+    if is_remote(location) {
+        return None;
+    }
+    let path = if location.starts_with("file:") {
+        Url::parse(location).ok()?.to_file_path().ok()?
+    } else if location.contains("://") {
+        return None;
+    } else {
+        let mut path = PathBuf::from(location);
+        // playlists written on Windows, read elsewhere
+        if !path.exists() && std::path::MAIN_SEPARATOR == '/' && location.contains('\\') {
+            path = PathBuf::from(location.replace('\\', "/"));
+        }
+        if uri {
+            path = PathBuf::from(decode_uri_path(&path.to_string_lossy()));
+        }
+        path
+    };
+    Some(normalize(&base_dir.join(path)))
+}
+
+/// How playlists refer to tracks, decided once per file
+struct Locator<'a> {
+    base_dir: &'a Path,
+    style: PathStyle,
+    uri: bool,
+}
+
+impl Locator<'_> {
+    fn location(&self, path: &Path) -> String {
+        let path = match self.style {
+            PathStyle::Relative => relative_path(self.base_dir, path),
+            PathStyle::Absolute => path.to_path_buf(),
+        };
+        if !self.uri {
+            return path.to_string_lossy().to_string();
+        }
+        if path.is_absolute() {
+            if let Ok(url) = Url::from_file_path(&path) {
+                return url.to_string();
+            }
+        }
+        encode_uri_path(&path)
+    }
+}
+
+fn base_dir(playlist: &Path) -> PathBuf {
+    playlist
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+pub fn write_playlist(
+    target: &Path,
+    format: PlaylistFormat,
+    style: PathStyle,
+    entries: &[PlaylistEntry],
+) -> Result<(), String> {
+    let base_dir = base_dir(target);
+    let locator = Locator {
+        base_dir: &base_dir,
+        style,
+        uri: format == PlaylistFormat::Xspf,
+    };
+    let locations: Vec<String> = entries
+        .iter()
+        .map(|e| locator.location(Path::new(&e.path)))
+        .collect();
+    let content = match format {
+        PlaylistFormat::M3u8 => m3u::write(entries, &locations),
+        PlaylistFormat::Pls => pls::write(entries, &locations),
+        PlaylistFormat::Xspf => xspf::write(entries, &locations),
+    };
+    fs::write(target, content).map_err(|e| e.to_string())
+}
+
+/// Text encodings playlists are read in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Utf8,
+    /// Old .m3u files are usually Latin-1
+    Latin1,
+}
+
+fn read_playlist(path: &Path) -> Result<(PlaylistFormat, Encoding, String), String> {
+    let format =
+        PlaylistFormat::from_path(path).ok_or(format!("{} is not a playlist", path.display()))?;
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    Ok(match String::from_utf8(bytes) {
+        Ok(s) => (format, Encoding::Utf8, s),
+        Err(e) => (
+            format,
+            Encoding::Latin1,
+            e.into_bytes().iter().map(|b| *b as char).collect(),
+        ),
+    })
+}
+
+/// `content` in `encoding`. Fails when a character has no Latin-1 byte
+fn encode(content: &str, encoding: Encoding) -> Result<Vec<u8>, String> {
+    match encoding {
+        Encoding::Utf8 => Ok(content.as_bytes().to_vec()),
+        Encoding::Latin1 => content
+            .chars()
+            .map(|c| {
+                u8::try_from(c as u32)
+                    .map_err(|_| format!("'{}' can't be written to a Latin-1 playlist", c))
+            })
+            .collect(),
+    }
+}
+
+/// Find the library file a stale location most likely meant, by the longest matching tail
+/// of path components. Ties are left unresolved
+fn match_in_library(location: &Path, library: &HashMap<String, Vec<PathBuf>>) -> Option<PathBuf> {
+    let name = location.file_name()?.to_string_lossy().to_lowercase();
+    let candidates = library.get(&name)?;
+    let wanted: Vec<String> = location
+        .components()
+        .rev()
+        .map(|c| c.as_os_str().to_string_lossy().to_lowercase())
+        .collect();
+    let mut best: Option<(usize, &PathBuf)> = None;
+    let mut tie = false;
+    for candidate in candidates {
+        let score = candidate
+            .components()
+            .rev()
+            .map(|c| c.as_os_str().to_string_lossy().to_lowercase())
+            .zip(wanted.iter())
+            .take_while(|(a, b)| a == *b)
+            .count();
+        match best {
+            Some((s, _)) if s == score => tie = true,
+            Some((s, _)) if s > score => {}
+            _ => {
+                best = Some((score, candidate));
+                tie = false;
+            }
+        }
+    }
+    if tie {
+        return None;
+    }
+    best.map(|(_, p)| p.clone())
+}
+
+pub fn import_playlist(path: &Path, library: &[String]) -> Result<ImportReport, String> {
+    let (format, _, content) = read_playlist(path)?;
+    let parsed = match format {
+        PlaylistFormat::M3u8 => m3u::parse(&content),
+        PlaylistFormat::Pls => pls::parse(&content),
+        PlaylistFormat::Xspf => xspf::parse(&content),
+    };
+
+    let mut by_name: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for file in library {
+        let file = PathBuf::from(file);
+        if let Some(name) = file.file_name() {
+            by_name
+                .entry(name.to_string_lossy().to_lowercase())
+                .or_default()
+                .push(file);
+        }
+    }
+
+    let base_dir = base_dir(path);
+    let mut report = ImportReport {
+        playlist: path.to_string_lossy().to_string(),
+        format,
+        entries: Vec::new(),
+        missing: Vec::new(),
+        remote: Vec::new(),
+    };
+    for entry in parsed {
+        let Some(location) =
+            resolve_location(&entry.path, &base_dir, format == PlaylistFormat::Xspf)
+        else {
+            report.remote.push(entry.path);
+            continue;
+        };
+        let found = if location.is_file() {
+            Some(location)
+        } else {
+            match_in_library(&location, &by_name)
+        };
+        match found {
+            Some(found) => report.entries.push(PlaylistEntry {
+                path: found.to_string_lossy().to_string(),
+                ..entry
+            }),
+            None => report.missing.push(entry.path),
+        }
+    }
+    Ok(report)
+}
+
+/// Point entries of a playlist at renamed files, keeping each entry's path style and the
+/// playlist's encoding. Returns whether the playlist was rewritten
+pub fn rewrite_playlist(path: &Path, renames: &HashMap<PathBuf, PathBuf>) -> Result<bool, String> {
+    let (format, encoding, content) = read_playlist(path)?;
+    let base_dir = base_dir(path);
+    let relocate = |location: &str| -> Option<String> {
+        let old = resolve_location(location, &base_dir, format == PlaylistFormat::Xspf)?;
+        let new = renames.get(&old)?;
+        let is_uri = location.starts_with("file:");
+        let style = if is_uri || Path::new(location).is_absolute() {
+            PathStyle::Absolute
+        } else {
+            PathStyle::Relative
+        };
+        let locator = Locator {
+            base_dir: &base_dir,
+            style,
+            uri: is_uri || format == PlaylistFormat::Xspf,
+        };
+        Some(locator.location(new))
+    };
+    let rewritten = match format {
+        PlaylistFormat::M3u8 => m3u::rewrite(&content, &relocate),
+        PlaylistFormat::Pls => pls::rewrite(&content, &relocate),
+        PlaylistFormat::Xspf => xspf::rewrite(&content, &relocate),
+    };
+    match rewritten {
+        Some(content) => {
+            fs::write(path, encode(&content, encoding)?).map_err(|e| e.to_string())?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Playlists anywhere in the imported folders, plus the ones sitting next to `near`
+fn library_playlists(app_handle: &AppHandle, near: &[PathBuf]) -> Vec<PathBuf> {
+    let state = app_handle.state::<AppState>();
+    let db = state.db.clone();
+    let roots = async_runtime::block_on(async { get_imported_folders(&db).await });
+
+    let mut found: HashSet<PathBuf> = HashSet::new();
+    let walks = roots
+        .iter()
+        .map(WalkDir::new)
+        .chain(near.iter().map(|d| WalkDir::new(d).max_depth(1)));
+    for walk in walks {
+        for entry in walk.into_iter().filter_map(|e| e.ok()) {
+            if entry.file_type().is_file() && PlaylistFormat::from_path(entry.path()).is_some() {
+                found.insert(entry.into_path());
+            }
+        }
+    }
+    let mut found: Vec<PathBuf> = found.into_iter().collect();
+    found.sort();
+    found
+}
+
+/// Called after files were renamed so playlists in the library keep pointing at them. Looking
+/// for the playlists walks the imported folders, so it runs in the background. Playlists that
+/// can't be rewritten are sent on "error"
+pub fn follow_renames(app_handle: &AppHandle, renames: &[(String, String)]) {
+    let renames: HashMap<PathBuf, PathBuf> = renames
+        .iter()
+        .filter(|(old, new)| old != new)
+        .map(|(old, new)| (normalize(Path::new(old)), PathBuf::from(new)))
+        .collect();
+    if renames.is_empty() {
+        return;
+    }
+    let mut dirs: Vec<PathBuf> = renames
+        .keys()
+        .filter_map(|p| p.parent().map(Path::to_path_buf))
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+
+    let app_handle = app_handle.clone();
+    async_runtime::spawn_blocking(move || {
+        let errors: Vec<BackendError> = library_playlists(&app_handle, &dirs)
+            .into_iter()
+            .filter_map(|playlist| {
+                let e = rewrite_playlist(&playlist, &renames).err()?;
+                Some(BackendError::WriteFailed(TagError {
+                    path: playlist.to_string_lossy().to_string(),
+                    public_message: "Could not update the playlist".to_string(),
+                    internal_message: e,
+                }))
+            })
+            .collect();
+        if !errors.is_empty() {
+            let _ = app_handle.emit("error", errors);
+        }
+    });
+}
+
+/// Every audio file Audexis knows about, used to find moved playlist entries
+pub async fn library_paths(state: &AppState) -> Vec<String> {
+    let mut paths: Vec<String> = sqlx::query_scalar("SELECT path FROM files")
+        .fetch_all(&state.db.pool)
+        .await
+        .unwrap_or_default();
+    let ws = state.workspace.lock().unwrap();
+    paths.extend(
+        ws.files
+            .iter()
+            .map(|f| f.path.to_string_lossy().to_string()),
+    );
+    paths
+}
+
+fn first_text(tags: &HashMap<FrameKey, Vec<TagValue>>, key: FrameKey) -> Option<String> {
+    match tags.get(&key).and_then(|v| v.first()) {
+        Some(TagValue::Text(s)) if !s.trim().is_empty() => Some(s.trim().to_string()),
+        _ => None,
+    }
+}
+
+/// Title, artist and length for the given files, in the given order
+pub async fn track_entries(state: &AppState, paths: &[String]) -> Vec<PlaylistEntry> {
+    if state.view_mode == ViewMode::Simple {
+        let ws = state.workspace.lock().unwrap();
+        return paths
+            .iter()
+            .map(
+                |path| match ws.files.iter().find(|f| f.path == Path::new(path)) {
+                    Some(file) => PlaylistEntry {
+                        path: path.clone(),
+                        title: first_text(&file.tags, FrameKey::Title),
+                        artist: first_text(&file.tags, FrameKey::Artist),
+                        duration_ms: file
+                            .properties
+                            .as_ref()
+                            .map(|p| p.duration_ms)
+                            .filter(|d| *d > 0),
+                    },
+                    None => PlaylistEntry {
+                        path: path.clone(),
+                        ..Default::default()
+                    },
+                },
+            )
+            .collect();
+    }
+
+    let mut entries = Vec::with_capacity(paths.len());
+    for path in paths {
+        let duration_ms: Option<i64> =
+            sqlx::query_scalar("SELECT duration_ms FROM files WHERE path = ?1")
+                .bind(path)
+                .fetch_optional(&state.db.pool)
+                .await
+                .ok()
+                .flatten()
+                .flatten();
+        let rows = sqlx::query(
+            "SELECT key, value FROM tag_text WHERE file_path = ?1 AND key IN (?2, ?3) ORDER BY id",
+        )
+        .bind(path)
+        .bind(FrameKey::Title.to_string())
+        .bind(FrameKey::Artist.to_string())
+        .fetch_all(&state.db.pool)
+        .await
+        .unwrap_or_default();
+        let text = |key: FrameKey| {
+            rows.iter()
+                .find(|r| r.get::<String, _>("key") == key.to_string())
+                .map(|r| r.get::<String, _>("value").trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        entries.push(PlaylistEntry {
+            path: path.clone(),
+            title: text(FrameKey::Title),
+            artist: text(FrameKey::Artist),
+            duration_ms: duration_ms.filter(|d| *d > 0).map(|d| d as u64),
+        });
+    }
+    entries
+}
@@ -0,0 +1,98 @@
+use super::PlaylistEntry;
+use std::collections::BTreeMap;
+
+pub fn write(entries: &[PlaylistEntry], locations: &[String]) -> String {
+    let mut out = String::from("[playlist]\n");
+    for (i, (entry, location)) in entries.iter().zip(locations).enumerate() {
+        let n = i + 1;
+        out.push_str(&format!("File{}={}\n", n, location));
+        let title = match (entry.artist.as_deref(), entry.title.as_deref()) {
+            (Some(artist), Some(title)) if !artist.is_empty() => {
+                Some(format!("{} - {}", artist, title))
+            }
+            (_, Some(title)) => Some(title.to_string()),
+            _ => None,
+        };
+        if let Some(title) = title {
+            out.push_str(&format!("Title{}={}\n", n, title));
+        }
+        let seconds = entry
+            .duration_ms
+            .map(|d| ((d + 500) / 1000) as i64)
+            .unwrap_or(-1);
+        out.push_str(&format!("Length{}={}\n", n, seconds));
+    }
+    out.push_str(&format!("NumberOfEntries={}\nVersion=2\n", entries.len()));
+    out
+}
+
+/// `FileN=` index of a line, matched case-insensitively like most players do
+fn file_index(key: &str) -> Option<u32> {
+    let lower = key.trim().to_lowercase();
+    lower.strip_prefix("file")?.parse().ok()
+}
+
+pub fn parse(content: &str) -> Vec<PlaylistEntry> {
+    let mut entries: BTreeMap<u32, PlaylistEntry> = BTreeMap::new();
+    for line in content.trim_start_matches('\u{feff}').lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        let lower = key.trim().to_lowercase();
+        if let Some(n) = file_index(key) {
+            entries.entry(n).or_default().path = value.to_string();
+        } else if let Some(n) = lower.strip_prefix("title").and_then(|n| n.parse().ok()) {
+            let entry = entries.entry(n).or_default();
+            match value.split_once(" - ") {
+                Some((artist, title)) => {
+                    entry.artist = Some(artist.trim().to_string());
+                    entry.title = Some(title.trim().to_string());
+                }
+                None => entry.title = Some(value.to_string()).filter(|t| !t.is_empty()),
+            }
+        } else if let Some(n) = lower.strip_prefix("length").and_then(|n| n.parse().ok()) {
+            entries.entry(n).or_default().duration_ms = value
+                .parse::<i64>()
+                .ok()
+                .filter(|s| *s >= 0)
+                .map(|s| s as u64 * 1000);
+        }
+    }
+    entries
+        .into_values()
+        .filter(|e| !e.path.is_empty())
+        .collect()
+}
+
+pub fn rewrite(content: &str, relocate: &dyn Fn(&str) -> Option<String>) -> Option<String> {
+    let newline = if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut changed = false;
+    let lines: Vec<String> = content
+        .split(newline)
+        .map(|line| {
+            let Some((key, value)) = line.split_once('=') else {
+                return line.to_string();
+            };
+            if file_index(key).is_none() {
+                return line.to_string();
+            }
+            match relocate(value.trim()) {
+                Some(location) => {
+                    changed = true;
+                    format!("{}={}", key, location)
+                }
+                None => line.to_string(),
+            }
+        })
+        .collect();
+    if changed {
+        Some(lines.join(newline))
+    } else {
+        None
+    }
+}
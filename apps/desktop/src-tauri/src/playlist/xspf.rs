@@ -0,0 +1,206 @@
+use super::PlaylistEntry;
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .and_then(|h| u32::from_str_radix(h, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|d| d.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Where the first `<name>` start tag in `xml` begins and where its content does. Attributes
+/// and whitespace inside the tag are allowed, empty `<name/>` elements are passed over
+fn open_tag(xml: &str, name: &str) -> Option<(usize, usize)> {
+    let open = format!("<{}", name);
+    let mut from = 0;
+    while let Some(found) = xml[from..].find(&open) {
+        let start = from + found;
+        let after = start + open.len();
+        match xml[after..].chars().next() {
+            Some('>') => return Some((start, after + 1)),
+            Some(c) if c.is_whitespace() => {
+                let end = xml[after..].find('>')? + after;
+                if !xml[..end].ends_with('/') {
+                    return Some((start, end + 1));
+                }
+                from = end + 1;
+            }
+            _ => from = after,
+        }
+    }
+    None
+}
+
+/// Text of the first `<name>` element in `xml`
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let close = format!("</{}>", name);
+    let (_, start) = open_tag(xml, name)?;
+    let end = xml[start..].find(&close)? + start;
+    Some(xml[start..end].trim())
+}
+
+pub fn write(entries: &[PlaylistEntry], locations: &[String]) -> String {
+    let mut out = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  <trackList>\n",
+    );
+    for (entry, location) in entries.iter().zip(locations) {
+        out.push_str("    <track>\n");
+        out.push_str(&format!(
+            "      <location>{}</location>\n",
+            escape(location)
+        ));
+        if let Some(title) = entry.title.as_deref() {
+            out.push_str(&format!("      <title>{}</title>\n", escape(title)));
+        }
+        if let Some(artist) = entry.artist.as_deref() {
+            out.push_str(&format!("      <creator>{}</creator>\n", escape(artist)));
+        }
+        if let Some(duration) = entry.duration_ms {
+            out.push_str(&format!("      <duration>{}</duration>\n", duration));
+        }
+        out.push_str("    </track>\n");
+    }
+    out.push_str("  </trackList>\n</playlist>\n");
+    out
+}
+
+/// Entries with `path` as the unescaped location URI
+pub fn parse(content: &str) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut rest = content;
+    while let Some((_, start)) = open_tag(rest, "track") {
+        let Some(end) = rest[start..].find("</track>") else {
+            break;
+        };
+        let track = &rest[start..start + end];
+        rest = &rest[start + end..];
+        let Some(location) = element(track, "location") else {
+            continue;
+        };
+        entries.push(PlaylistEntry {
+            path: unescape(location),
+            title: element(track, "title").map(unescape),
+            artist: element(track, "creator").map(unescape),
+            duration_ms: element(track, "duration").and_then(|d| d.parse().ok()),
+        });
+    }
+    entries
+}
+
+pub fn rewrite(content: &str, relocate: &dyn Fn(&str) -> Option<String>) -> Option<String> {
+    let mut out = String::with_capacity(content.len());
+    let mut rest = content;
+    let mut changed = false;
+    while let Some((_, start)) = open_tag(rest, "location") {
+        let Some(end) = rest[start..].find("</location>") else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let location = &rest[start..start + end];
+        match relocate(&unescape(location.trim())) {
+            Some(new_location) => {
+                changed = true;
+                out.push_str(&escape(&new_location));
+            }
+            None => out.push_str(location),
+        }
+        rest = &rest[start + end..];
+    }
+    out.push_str(rest);
+    if changed {
+        Some(out)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(content: &str) -> Vec<String> {
+        parse(content).into_iter().map(|e| e.path).collect()
+    }
+
+    #[test]
+    fn reads_tracks_with_attributes_and_whitespace() {
+        let content = r#"<playlist><trackList>
+            <track xml:base="file:///music/"><location>a.flac</location></track>
+            <track
+              ><location>b.flac</location></track>
+            <track ><location xml:base="x">c&amp;d.flac</location><title>C</title></track>
+        </trackList></playlist>"#;
+        assert_eq!(paths(content), ["a.flac", "b.flac", "c&d.flac"]);
+        assert_eq!(parse(content)[2].title.as_deref(), Some("C"));
+    }
+
+    #[test]
+    fn passes_over_empty_and_longer_named_elements() {
+        let content = "<trackList><track/><track /><tracker>x</tracker>\
+                       <track><location>a.mp3</location></track></trackList>";
+        assert_eq!(paths(content), ["a.mp3"]);
+    }
+
+    #[test]
+    fn rewrites_locations_with_attributes() {
+        let content = "<track><location xml:base=\"file:///\">old.mp3</location></track>";
+        let rewritten = rewrite(content, &|l| {
+            (l == "old.mp3").then(|| "new&.mp3".to_string())
+        });
+        assert_eq!(
+            rewritten.as_deref(),
+            Some("<track><location xml:base=\"file:///\">new&amp;.mp3</location></track>")
+        );
+    }
+
+    #[test]
+    fn leaves_unchanged_playlists_alone() {
+        let written = write(
+            &[PlaylistEntry {
+                path: "a.mp3".to_string(),
+                title: Some("A & B".to_string()),
+                ..Default::default()
+            }],
+            &["file:///a.mp3".to_string()],
+        );
+        assert_eq!(rewrite(&written, &|_| None), None);
+        assert_eq!(parse(&written)[0].title.as_deref(), Some("A & B"));
+    }
+}
@@ -0,0 +1,196 @@
+use super::journal::{self, JournalPair};
+use super::tag_backend::{BackendError, DefaultBackend, TagError};
+use super::utils::{temp_path_for, Changes, FrameKey, SerializableTagValue};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+type Tags = HashMap<FrameKey, Vec<SerializableTagValue>>;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum FileOutcome {
+    Written,
+    /// This file could not be staged, so nothing in the batch was written
+    Failed,
+    /// Staged fine, but not written because another file of the batch failed
+    RolledBack,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileReport {
+    pub path: String,
+    pub outcome: FileOutcome,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchReport {
+    /// Whether every file was written. When false, none was
+    pub committed: bool,
+    pub files: Vec<FileReport>,
+}
+
+impl BatchReport {
+    /// The failures in the shape the "error" event takes
+    pub fn errors(&self) -> Vec<BackendError> {
+        self.files
+            .iter()
+            .filter(|f| f.outcome != FileOutcome::Written)
+            .map(|f| {
+                let message = f
+                    .error
+                    .clone()
+                    .unwrap_or("Not written, another file of the batch failed".to_string());
+                BackendError::WriteFailed(TagError {
+                    path: f.path.clone(),
+                    public_message: message.clone(),
+                    internal_message: message,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Where the original waits while the staged copy takes its place
+pub fn original_path_for(target: &Path) -> PathBuf {
+    let mut p = target.to_path_buf();
+    let file_name = target
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    p.set_file_name(format!(".{}.orig", file_name));
+    p
+}
+
+pub fn error_message(e: &BackendError) -> String {
+    match e {
+        BackendError::ReadFailed(e) | BackendError::WriteFailed(e) => e.public_message.clone(),
+    }
+}
+
+/// Write every file of the batch or none. Each file is first written to a copy next to it;
+/// only once all copies are ready are they swapped in, and a failed swap puts every original
+/// back
+pub fn write_batch(backend: &DefaultBackend, batch: &[Changes]) -> BatchReport {
+    let writes: Vec<(&String, &Tags)> = batch
+        .iter()
+        .flat_map(|changes| changes.paths.iter().map(|path| (path, &changes.tags)))
+        .collect();
+    let paths: Vec<String> = writes.iter().map(|(path, _)| (*path).clone()).collect();
+    write_batch_with(&paths, |i, path, tmp| {
+        backend.write_to_copy(path, tmp, writes[i].1)
+    })
+}
+
+/// Like `write_batch`, with `stage` writing the copy of the `i`th file of `paths` to `tmp`
+pub fn write_batch_with(
+    paths: &[String],
+    stage: impl Fn(usize, &PathBuf, &PathBuf) -> Result<(), BackendError>,
+) -> BatchReport {
+    let mut report = BatchReport::default();
+    let pending = journal::begin(
+        paths
+            .iter()
+            .map(|path| {
+                let path = PathBuf::from(path);
+                JournalPair {
+                    tmp: temp_path_for(&path),
+                    original: Some(original_path_for(&path)),
+                    target: path,
+                }
+            })
+            .collect(),
+    );
+    let mut staged: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for (i, path_str) in paths.iter().enumerate() {
+        let path = PathBuf::from(path_str);
+        let tmp = temp_path_for(&path);
+        match stage(i, &path, &tmp) {
+            Ok(()) => staged.push((path, tmp)),
+            Err(e) => {
+                let _ = fs::remove_file(&tmp);
+                report.files.push(FileReport {
+                    path: path_str.clone(),
+                    outcome: FileOutcome::Failed,
+                    error: Some(error_message(&e)),
+                });
+            }
+        }
+    }
+
+    if !report.files.is_empty() {
+        for (path, tmp) in staged {
+            let _ = fs::remove_file(&tmp);
+            report.files.push(FileReport {
+                path: path.to_string_lossy().to_string(),
+                outcome: FileOutcome::RolledBack,
+                error: None,
+            });
+        }
+        pending.done();
+        return report;
+    }
+    pending.staged();
+
+    // swap the copies in, keeping each original aside until all of them went through
+    let mut swapped: Vec<(PathBuf, PathBuf)> = Vec::new();
+    let mut failure: Option<(PathBuf, String)> = None;
+    for (path, tmp) in staged.iter() {
+        let original = original_path_for(path);
+        if let Err(e) = fs::rename(path, &original) {
+            failure = Some((path.clone(), e.to_string()));
+            break;
+        }
+        if let Err(e) = fs::rename(tmp, path) {
+            let _ = fs::rename(&original, path);
+            failure = Some((path.clone(), e.to_string()));
+            break;
+        }
+        swapped.push((path.clone(), original));
+    }
+
+    if let Some((failed_path, error)) = failure {
+        pending.aborted();
+        for (path, original) in swapped.iter() {
+            if let Err(e) = fs::rename(original, path) {
+                eprintln!(
+                    "could not put back {} from {}: {e}",
+                    path.display(),
+                    original.display()
+                );
+            }
+        }
+        for (path, tmp) in staged {
+            let _ = fs::remove_file(&tmp);
+            let failed = path == failed_path;
+            report.files.push(FileReport {
+                path: path.to_string_lossy().to_string(),
+                outcome: if failed {
+                    FileOutcome::Failed
+                } else {
+                    FileOutcome::RolledBack
+                },
+                error: failed.then(|| error.clone()),
+            });
+        }
+        pending.done();
+        return report;
+    }
+
+    for (path, original) in swapped {
+        let _ = fs::remove_file(&original);
+        report.files.push(FileReport {
+            path: path.to_string_lossy().to_string(),
+            outcome: FileOutcome::Written,
+            error: None,
+        });
+    }
+    pending.done();
+    report.committed = true;
+    report
+}
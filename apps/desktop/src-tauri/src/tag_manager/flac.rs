@@ -62,7 +62,6 @@ impl TagFormat for FlacFormat {
         let mut data: HashMap<FrameKey, Vec<TagValue>> = HashMap::new();
         let b = fs::read(file_path);
         if b.is_err() {
-            print!("I guess");
             return Err(BackendError::ReadFailed(TagError {
                 path: file_path.to_str().unwrap_or("").to_string(),
                 public_message: "Could not read file".to_string(),
@@ -84,7 +83,6 @@ impl TagFormat for FlacFormat {
             let is_last = (b[offset] & 0x80) != 0;
 
             let block_type = FlacBlockType::from(b[offset] & 0x7F);
-            println!("Found block type: {:?}", block_type);
             let block_length = ((b[offset + 1] as u32) << 16)
                 | ((b[offset + 2] as u32) << 8)
                 | (b[offset + 3] as u32);
@@ -283,7 +281,6 @@ impl TagFormat for FlacFormat {
         let write_result = fs::write(file_path, out);
 
         if write_result.is_err() {
-            println!("err");
             return Err(BackendError::WriteFailed(TagError {
                 path: file_path.to_str().unwrap_or("").to_string(),
                 public_message: "Could not write tags".to_string(),
@@ -401,6 +401,7 @@ pub static ID3V24_REVERSE_MAP: Lazy<HashMap<&'static str, FrameKey>> = Lazy::new
 pub fn raw_to_tags(raw: &HashMap<String, Vec<TagValue>>) -> HashMap<FrameKey, Vec<TagValue>> {
     let mut result: HashMap<FrameKey, Vec<TagValue>> = HashMap::new();
     for (id, values) in raw.iter() {
+        let values = lift_described_user_text(id, values, &mut result);
         if let Some(key) = ID3V23_REVERSE_MAP.get(id.as_str()) {
             let mut expanded: Vec<TagValue> = Vec::new();
             for v in values.iter() {
@@ -423,6 +424,20 @@ pub fn raw_to_tags(raw: &HashMap<String, Vec<TagValue>>) -> HashMap<FrameKey, Ve
     }
     result
 }
+fn lift_described_user_text(
+    id: &str,
+    values: &[TagValue],
+    result: &mut HashMap<FrameKey, Vec<TagValue>>,
+) -> Vec<TagValue> {
+    if id != "TXXX" && id != "TXX" {
+        return values.to_vec();
+    }
+    let (described, rest) = split_described_user_text(values);
+    for (key, value) in described {
+        result.entry(key).or_default().push(value);
+    }
+    rest
+}
 pub fn tags_to_raw(tags: &HashMap<FrameKey, TagValue>) -> HashMap<&'static str, TagValue> {
     let mut out: HashMap<&'static str, TagValue> = HashMap::new();
     for (key, value) in tags.iter() {
@@ -439,8 +454,9 @@ pub fn id3v22_raw_to_tags(
 ) -> HashMap<FrameKey, Vec<TagValue>> {
     let mut result: HashMap<FrameKey, Vec<TagValue>> = HashMap::new();
     for (id, values) in raw.iter() {
+        let values = lift_described_user_text(id, values, &mut result);
         if let Some(k) = ID3V22_REVERSE_MAP.get(id.as_str()) {
-            result.entry(*k).or_default().extend(values.clone());
+            result.entry(*k).or_default().extend(values);
         }
     }
     result
@@ -455,10 +471,9 @@ pub fn id3v22_tags_to_raw(tags: &HashMap<FrameKey, TagValue>) -> HashMap<&'stati
 pub fn id3v24_raw_to_tags(
     raw: &HashMap<String, Vec<TagValue>>,
 ) -> HashMap<FrameKey, Vec<TagValue>> {
-    let mut valll: u8 = 0;
     let mut result: HashMap<FrameKey, Vec<TagValue>> = HashMap::new();
     for (id, values) in raw.iter() {
-        println!("{:?}", id);
+        let values = lift_described_user_text(id, values, &mut result);
         let key_opt = ID3V24_REVERSE_MAP.get(id.as_str()).cloned().or_else(|| {
             if id == "TYER" {
                 Some(FrameKey::Year)
@@ -481,11 +496,6 @@ pub fn id3v24_raw_to_tags(
                     _ => expanded.push(v.clone()),
                 }
             }
-            println!("{:?}", k);
-            if k == FrameKey::Year {
-                valll += 1;
-                println!("year, {}", { valll });
-            }
             result.entry(k).or_default().extend(expanded);
         }
     }
@@ -497,3 +507,133 @@ pub fn id3v24_tags_to_raw(tags: &HashMap<FrameKey, TagValue>) -> HashMap<&'stati
         .map(|(k, v)| (id3v24_code(*k), v.clone()))
         .collect()
 }
+
+/// Keys without a frame of their own in ID3v2. They are stored in TXXX frames and told apart by the frame description.
+pub fn txxx_description(key: FrameKey) -> Option<&'static str> {
+    match key {
+        FrameKey::ReplayGainTrackGain => Some("REPLAYGAIN_TRACK_GAIN"),
+        FrameKey::ReplayGainTrackPeak => Some("REPLAYGAIN_TRACK_PEAK"),
+        FrameKey::ReplayGainTrackRange => Some("REPLAYGAIN_TRACK_RANGE"),
+        FrameKey::ReplayGainAlbumGain => Some("REPLAYGAIN_ALBUM_GAIN"),
+        FrameKey::ReplayGainAlbumPeak => Some("REPLAYGAIN_ALBUM_PEAK"),
+        FrameKey::ReplayGainAlbumRange => Some("REPLAYGAIN_ALBUM_RANGE"),
+        FrameKey::ReplayGainReferenceLoudness => Some("REPLAYGAIN_REFERENCE_LOUDNESS"),
+        FrameKey::AcoustidId => Some("Acoustid Id"),
+        FrameKey::AcoustidFingerprint => Some("Acoustid Fingerprint"),
+        _ => None,
+    }
+}
+
+pub fn txxx_key(description: &str) -> Option<FrameKey> {
+    let upper = description.trim().to_ascii_uppercase();
+    [
+        FrameKey::ReplayGainTrackGain,
+        FrameKey::ReplayGainTrackPeak,
+        FrameKey::ReplayGainTrackRange,
+        FrameKey::ReplayGainAlbumGain,
+        FrameKey::ReplayGainAlbumPeak,
+        FrameKey::ReplayGainAlbumRange,
+        FrameKey::ReplayGainReferenceLoudness,
+        FrameKey::AcoustidId,
+        FrameKey::AcoustidFingerprint,
+    ]
+    .into_iter()
+    .find(|key| {
+        txxx_description(*key)
+            .map(|d| d.to_ascii_uppercase() == upper)
+            .unwrap_or(false)
+    })
+}
+
+/// Moves user text entries whose description belongs to a known key out of the TXXX values.
+/// Returns the entries as plain text under their own key, plus the remaining values.
+pub fn split_described_user_text(
+    values: &[TagValue],
+) -> (Vec<(FrameKey, TagValue)>, Vec<TagValue>) {
+    let mut described: Vec<(FrameKey, TagValue)> = Vec::new();
+    let mut rest: Vec<TagValue> = Vec::new();
+    for v in values {
+        match v {
+            TagValue::UserText(ut) => match txxx_key(&ut.description) {
+                Some(key) => described.push((key, TagValue::Text(ut.value.clone()))),
+                None => rest.push(v.clone()),
+            },
+            _ => rest.push(v.clone()),
+        }
+    }
+    (described, rest)
+}
+
+/// Removes keys that are written as described TXXX frames from `flattened` and returns them with their text value.
+pub fn take_described_frames(
+    flattened: &mut HashMap<FrameKey, TagValue>,
+) -> Vec<(FrameKey, String)> {
+    let keys: Vec<FrameKey> = flattened
+        .keys()
+        .filter(|k| txxx_description(**k).is_some())
+        .cloned()
+        .collect();
+    let mut out = Vec::new();
+    for key in keys {
+        if let Some(value) = flattened.remove(&key) {
+            let text = match value {
+                TagValue::Text(s) => s,
+                TagValue::UserText(ut) => ut.value,
+                _ => continue,
+            };
+            out.push((key, text));
+        }
+    }
+    out
+}
+
+/// Reads the description of a raw TXXX payload and resolves it to a known key.
+pub fn described_txxx_key(content: &[u8]) -> Option<FrameKey> {
+    if content.is_empty() {
+        return None;
+    }
+    let rest = &content[1..];
+    let description = match content[0] {
+        0x01 | 0x02 => {
+            let mut units: Vec<u16> = Vec::new();
+            let mut i = 0usize;
+            let mut little_endian = content[0] == 0x01;
+            if rest.len() >= 2 && content[0] == 0x01 {
+                if rest[0] == 0xFE && rest[1] == 0xFF {
+                    little_endian = false;
+                    i = 2;
+                } else if rest[0] == 0xFF && rest[1] == 0xFE {
+                    i = 2;
+                }
+            }
+            while i + 1 < rest.len() {
+                let unit = if little_endian {
+                    u16::from_le_bytes([rest[i], rest[i + 1]])
+                } else {
+                    u16::from_be_bytes([rest[i], rest[i + 1]])
+                };
+                if unit == 0 {
+                    break;
+                }
+                units.push(unit);
+                i += 2;
+            }
+            String::from_utf16_lossy(&units)
+        }
+        _ => {
+            let end = rest.iter().position(|&b| b == 0x00).unwrap_or(rest.len());
+            String::from_utf8_lossy(&rest[..end]).to_string()
+        }
+    };
+    txxx_key(&description)
+}
+
+/// Builds a TXXX payload with a latin1/utf8 description and value.
+pub fn encode_user_text_payload(description: &str, value: &str) -> Vec<u8> {
+    let mut payload = Vec::new();
+    payload.push(0x00);
+    payload.extend_from_slice(description.as_bytes());
+    payload.push(0x00);
+    payload.extend_from_slice(value.as_bytes());
+    payload
+}
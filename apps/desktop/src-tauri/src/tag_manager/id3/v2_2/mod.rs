@@ -1,4 +1,7 @@
-use crate::tag_manager::id3::utils::{id3v22_key, id3v22_raw_to_tags, id3v22_tags_to_raw};
+use crate::tag_manager::id3::utils::{
+    described_txxx_key, encode_user_text_payload, id3v22_code, id3v22_key, id3v22_raw_to_tags,
+    id3v22_tags_to_raw, take_described_frames, txxx_description,
+};
 use crate::tag_manager::id3::v2_3::utils::{create_header_with_version, encode_text_payload};
 use crate::tag_manager::tag_backend::{BackendError, TagError};
 use crate::tag_manager::traits::TagFormat;
@@ -238,6 +241,8 @@ impl TagFormat for V2_2 {
         })?;
         let mut pos = 0usize;
         let mut raw: HashMap<String, Vec<u8>> = HashMap::new();
+        // TXX frames are told apart by their description, so a file can have several
+        let mut user_text: Vec<Vec<u8>> = Vec::new();
         while pos + 6 <= tag_data.len() {
             let id_bytes = &tag_data[pos..pos + 3];
             if id_bytes.iter().all(|b| *b == 0) {
@@ -253,12 +258,18 @@ impl TagFormat for V2_2 {
                 break;
             }
             let content = &tag_data[pos + 6..pos + 6 + size];
-            raw.insert(id, content.to_vec());
+            if id == "TXX" {
+                user_text.push(content.to_vec());
+            } else {
+                raw.insert(id, content.to_vec());
+            }
             pos += 6 + size;
         }
         let mut single_map: HashMap<FrameKey, TagValue> = HashMap::new();
         for (k, vals) in updated.clone().into_iter() {
+            // an empty list clears the frame
             if vals.is_empty() {
+                single_map.insert(k, TagValue::Text(String::new()));
                 continue;
             }
             if matches!(vals[0], TagValue::Text(_)) && vals.len() > 1 {
@@ -275,6 +286,23 @@ impl TagFormat for V2_2 {
                 single_map.insert(k, vals[0].clone());
             }
         }
+        let described = take_described_frames(&mut single_map);
+        let described_keys: Vec<FrameKey> = updated
+            .keys()
+            .filter(|k| txxx_description(**k).is_some())
+            .cloned()
+            .collect();
+        let replaces_user_text = single_map.keys().any(|k| id3v22_code(*k) == "TXX");
+        user_text.retain(|content| match described_txxx_key(content) {
+            Some(key) => !described_keys.contains(&key),
+            None => !replaces_user_text,
+        });
+        for (key, value) in described {
+            if !value.is_empty() {
+                let desc = txxx_description(key).unwrap_or("");
+                user_text.push(encode_user_text_payload(desc, &value));
+            }
+        }
         let raw_updates = id3v22_tags_to_raw(&single_map);
         for (k, v) in raw_updates {
             match v {
@@ -282,6 +310,8 @@ impl TagFormat for V2_2 {
                     if !t.is_empty() {
                         let encoded = encode_text_payload(&t, false);
                         raw.insert(k.to_string(), encoded);
+                    } else {
+                        raw.remove(k);
                     }
                 }
                 TagValue::Picture {
@@ -341,7 +371,8 @@ impl TagFormat for V2_2 {
             }
         }
         let mut frames: Vec<u8> = Vec::new();
-        for (id, content) in raw {
+        let user_text = user_text.into_iter().map(|c| ("TXX".to_string(), c));
+        for (id, content) in raw.into_iter().chain(user_text) {
             if id.len() != 3 {
                 continue;
             }
@@ -1,4 +1,7 @@
-use crate::tag_manager::id3::utils::{id3v23_key, raw_to_tags, tags_to_raw};
+use crate::tag_manager::id3::utils::{
+    described_txxx_key, encode_user_text_payload, id3v23_key, raw_to_tags, tags_to_raw,
+    take_described_frames, txxx_description,
+};
 use crate::tag_manager::tag_backend::{BackendError, TagError};
 use crate::tag_manager::traits::TagFormat;
 use crate::tag_manager::utils::{FrameKey, TagMap, TagValue, UserTextEntry, UserUrlEntry};
@@ -195,7 +198,6 @@ impl TagFormat for V2_3 {
                         continue;
                     }
                     let picture_type = content[picture_type_index];
-                    println!("pic type: {:?}", &picture_type.to_string());
                     let description_start = picture_type_index + 1;
                     let description_end = content[description_start..]
                         .iter()
@@ -313,8 +315,15 @@ impl TagFormat for V2_3 {
 
         let mut pictures: Vec<TagValue> = Vec::new();
         let mut flattened: HashMap<FrameKey, TagValue> = HashMap::new();
+        let mut clear_pictures = false;
         for (k, vec_vals) in updated_tags.clone().into_iter() {
+            // an empty list clears the frame, a blank text is never written
             if vec_vals.is_empty() {
+                if k == FrameKey::AttachedPicture {
+                    clear_pictures = true;
+                } else {
+                    flattened.insert(k, TagValue::Text(String::new()));
+                }
                 continue;
             }
             if k == FrameKey::AttachedPicture {
@@ -340,13 +349,32 @@ impl TagFormat for V2_3 {
                 flattened.insert(k, vec_vals[0].clone());
             }
         }
+        let described = take_described_frames(&mut flattened);
         let raw_updated_tags = tags_to_raw(&flattened);
         let mut updated_keys: Vec<String> =
             raw_updated_tags.keys().map(|k| k.to_string()).collect();
-        if !pictures.is_empty() {
+        if !pictures.is_empty() || clear_pictures {
             updated_keys.push("APIC".to_string());
         }
-        raw_frames.retain(|(id, _)| !updated_keys.iter().any(|k| k == id));
+        let described_keys: Vec<FrameKey> = updated_tags
+            .keys()
+            .filter(|k| txxx_description(**k).is_some())
+            .cloned()
+            .collect();
+        raw_frames.retain(|(id, content)| {
+            if id == "TXXX" {
+                if let Some(key) = described_txxx_key(content) {
+                    return !described_keys.contains(&key);
+                }
+            }
+            !updated_keys.iter().any(|k| k == id)
+        });
+        for (key, value) in described {
+            if !value.is_empty() {
+                let desc = txxx_description(key).unwrap_or("");
+                raw_frames.push(("TXXX".to_string(), encode_user_text_payload(desc, &value)));
+            }
+        }
 
         for (k, v) in raw_updated_tags {
             match v {
@@ -441,10 +469,6 @@ impl TagFormat for V2_3 {
                 raw_frames.push(("APIC".to_string(), encoded_data));
             }
         }
-        println!(
-            "Updated raw tags: {:?}",
-            raw_frames.iter().map(|(k, _)| k).collect::<Vec<_>>()
-        );
 
         let frames = raw_frames
             .iter()
@@ -1,5 +1,8 @@
 use crate::tag_manager;
-use crate::tag_manager::id3::utils::{id3v24_key, id3v24_raw_to_tags, id3v24_tags_to_raw};
+use crate::tag_manager::id3::utils::{
+    described_txxx_key, encode_user_text_payload, id3v24_key, id3v24_raw_to_tags,
+    id3v24_tags_to_raw, take_described_frames, txxx_description,
+};
 use crate::tag_manager::id3::v2_3::utils::{
     create_header_with_version, encode_text_payload, to_synchsafe,
 };
@@ -289,7 +292,9 @@ impl TagFormat for V2_4 {
 
         let mut flattened: HashMap<FrameKey, TagValue> = HashMap::new();
         for (k, vals) in non_picture.into_iter() {
+            // an empty list clears the frame, a blank text is never written
             if vals.is_empty() {
+                flattened.insert(k, TagValue::Text(String::new()));
                 continue;
             }
             if matches!(vals[0], TagValue::Text(_)) && vals.len() > 1 {
@@ -306,12 +311,31 @@ impl TagFormat for V2_4 {
                 flattened.insert(k, vals[0].clone());
             }
         }
+        let described = take_described_frames(&mut flattened);
         let raw_updates = id3v24_tags_to_raw(&flattened);
         let mut updated_keys: Vec<String> = raw_updates.keys().map(|k| k.to_string()).collect();
         if !pictures.is_empty() {
             updated_keys.push("APIC".to_string());
         }
-        raw_frames.retain(|(id, _)| !updated_keys.iter().any(|k| k == id));
+        let described_keys: Vec<FrameKey> = updated
+            .keys()
+            .filter(|k| txxx_description(**k).is_some())
+            .cloned()
+            .collect();
+        raw_frames.retain(|(id, content)| {
+            if id == "TXXX" {
+                if let Some(key) = described_txxx_key(content) {
+                    return !described_keys.contains(&key);
+                }
+            }
+            !updated_keys.iter().any(|k| k == id)
+        });
+        for (key, value) in described {
+            if !value.is_empty() {
+                let desc = txxx_description(key).unwrap_or("");
+                raw_frames.push(("TXXX".to_string(), encode_user_text_payload(desc, &value)));
+            }
+        }
 
         for (k, v) in raw_updates {
             match v {
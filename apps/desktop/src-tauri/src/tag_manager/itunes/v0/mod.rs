@@ -93,7 +93,6 @@ impl V0 {
                 .iter()
                 .find(|atom| atom.atom_type == "ilst")
                 .cloned();
-            println!("onl");
         }
 
         if ilst_atom.is_some() {
@@ -0,0 +1,414 @@
+use super::utils::replace_tmp;
+use crate::config::user::FsyncPolicy;
+use crate::utils::is_supported_file;
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use walkdir::WalkDir;
+
+pub const JOURNAL_FILE: &str = "write_journal.jsonl";
+
+/// A staged file and the file it is going to replace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalPair {
+    pub tmp: PathBuf,
+    pub target: PathBuf,
+    /// Where the target waits while the staged file takes its place, for writes that keep it
+    pub original: Option<PathBuf>,
+}
+
+impl JournalPair {
+    pub fn new(tmp: &Path, target: &Path) -> Self {
+        Self {
+            tmp: tmp.to_path_buf(),
+            target: target.to_path_buf(),
+            original: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+enum Record {
+    /// About to stage the pairs, nothing has replaced a target yet
+    Begin {
+        id: u64,
+        pairs: Vec<JournalPair>,
+    },
+    /// Every staged file is complete. From here on an interrupted write is finished, not undone
+    Staged {
+        id: u64,
+    },
+    /// Given up after staging, the originals go back
+    Aborted {
+        id: u64,
+    },
+    Done {
+        id: u64,
+    },
+}
+
+struct Journal {
+    /// None until `init`, writes go unjournaled before that
+    path: Option<PathBuf>,
+    policy: FsyncPolicy,
+    next_id: u64,
+    /// Staged files and set aside originals of the writes in flight
+    open: HashMap<u64, Vec<PathBuf>>,
+    /// Writes recovery could not settle. They stay in the journal to be tried again next time,
+    /// and their staged files and originals are left alone, either may be the only copy
+    unsettled: Vec<Unfinished>,
+}
+
+static JOURNAL: Lazy<Mutex<Journal>> = Lazy::new(|| {
+    Mutex::new(Journal {
+        path: None,
+        policy: FsyncPolicy::default(),
+        next_id: 1,
+        open: HashMap::new(),
+        unsettled: Vec::new(),
+    })
+});
+
+impl Journal {
+    fn append(&self, record: &Record) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let Ok(line) = serde_json::to_string(record) else {
+            return;
+        };
+        let res = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut f| {
+                f.write_all(format!("{}\n", line).as_bytes())?;
+                if self.policy != FsyncPolicy::Never {
+                    f.sync_data()?;
+                }
+                Ok(())
+            });
+        if let Err(e) = res {
+            eprintln!("writing to the journal failed: {e}");
+        }
+    }
+
+    /// Start the journal over once no write is in flight, so it never grows past a batch
+    fn compact(&self) {
+        if let (Some(path), true) = (&self.path, self.open.is_empty()) {
+            let _ = File::create(path);
+            for write in &self.unsettled {
+                write.records().iter().for_each(|r| self.append(r));
+            }
+        }
+    }
+
+    /// Whether `path` belongs to a write in flight or one recovery could not settle
+    fn holds(&self, path: &Path) -> bool {
+        self.open.values().flatten().any(|held| held == path)
+            || self
+                .unsettled
+                .iter()
+                .flat_map(|w| &w.pairs)
+                .any(|pair| pair.tmp == path || pair.original.as_deref() == Some(path))
+    }
+}
+
+/// Push a file's contents to disk
+fn sync_file(path: &Path) {
+    let res = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .and_then(|f| f.sync_all());
+    if let Err(e) = res {
+        eprintln!("could not sync {}: {e}", path.display());
+    }
+}
+
+/// Push a folder's entries to disk, so renames inside it survive a power cut
+fn sync_dir(dir: &Path) {
+    #[cfg(not(windows))]
+    {
+        if let Err(e) = File::open(dir).and_then(|f| f.sync_all()) {
+            eprintln!("could not sync {}: {e}", dir.display());
+        }
+    }
+    #[cfg(windows)]
+    {
+        let _ = dir;
+    }
+}
+
+/// A journaled write. Mark it staged once every staged file is complete, then done once the
+/// targets were replaced. Dropped before that, the write is rolled back
+pub struct PendingWrite {
+    id: u64,
+    pairs: Vec<JournalPair>,
+    settled: bool,
+}
+
+/// Record a write before the first staged file is created
+pub fn begin(pairs: Vec<JournalPair>) -> PendingWrite {
+    let mut journal = JOURNAL.lock().unwrap();
+    let id = journal.next_id;
+    journal.next_id += 1;
+    let held = pairs
+        .iter()
+        .flat_map(|p| std::iter::once(p.tmp.clone()).chain(p.original.clone()))
+        .collect();
+    journal.open.insert(id, held);
+    journal.append(&Record::Begin {
+        id,
+        pairs: pairs.clone(),
+    });
+    PendingWrite {
+        id,
+        pairs,
+        settled: false,
+    }
+}
+
+impl PendingWrite {
+    pub fn staged(&self) {
+        let journal = JOURNAL.lock().unwrap();
+        if journal.policy == FsyncPolicy::Always {
+            for pair in &self.pairs {
+                sync_file(&pair.tmp);
+            }
+        }
+        journal.append(&Record::Staged { id: self.id });
+    }
+
+    pub fn aborted(&self) {
+        JOURNAL
+            .lock()
+            .unwrap()
+            .append(&Record::Aborted { id: self.id });
+    }
+
+    pub fn done(mut self) {
+        if JOURNAL.lock().unwrap().policy == FsyncPolicy::Always {
+            let dirs: HashSet<&Path> = self
+                .pairs
+                .iter()
+                .filter_map(|p| p.target.parent())
+                .collect();
+            for dir in dirs {
+                sync_dir(dir);
+            }
+        }
+        self.settle();
+    }
+
+    fn settle(&mut self) {
+        let mut journal = JOURNAL.lock().unwrap();
+        journal.append(&Record::Done { id: self.id });
+        journal.open.remove(&self.id);
+        journal.compact();
+        self.settled = true;
+    }
+}
+
+impl Drop for PendingWrite {
+    /// Left on an early return, so undone the way recovery would
+    fn drop(&mut self) {
+        if !self.settled {
+            self.pairs.iter().for_each(|pair| {
+                roll_back(pair);
+            });
+            self.settle();
+        }
+    }
+}
+
+pub fn set_policy(policy: FsyncPolicy) {
+    JOURNAL.lock().unwrap().policy = policy;
+}
+
+/// What startup recovery did with writes a previous session left unfinished
+#[derive(Debug, Default)]
+pub struct Recovery {
+    pub finished: usize,
+    pub rolled_back: usize,
+    /// Writes that could be neither finished nor rolled back, kept for the next start
+    pub unsettled: usize,
+}
+
+#[derive(Default, Clone)]
+struct Unfinished {
+    id: u64,
+    pairs: Vec<JournalPair>,
+    staged: bool,
+    aborted: bool,
+    done: bool,
+}
+
+impl Unfinished {
+    /// The records that bring the write back as it was left
+    fn records(&self) -> Vec<Record> {
+        let mut records = vec![Record::Begin {
+            id: self.id,
+            pairs: self.pairs.clone(),
+        }];
+        if self.staged {
+            records.push(Record::Staged { id: self.id });
+        }
+        if self.aborted {
+            records.push(Record::Aborted { id: self.id });
+        }
+        records
+    }
+}
+
+/// Put the staged file in place of its target. False when it is still pending
+fn finish(pair: &JournalPair) -> bool {
+    if pair.tmp.exists() && replace_tmp(&pair.tmp, &pair.target).is_err() {
+        eprintln!(
+            "could not finish writing {} from {}",
+            pair.target.display(),
+            pair.tmp.display()
+        );
+        return false;
+    }
+    if let Some(original) = &pair.original {
+        if pair.target.exists() {
+            let _ = fs::remove_file(original);
+        }
+    }
+    true
+}
+
+/// Put the original back and drop the staged file. False when the original could not be put
+/// back, the staged file is kept then
+fn roll_back(pair: &JournalPair) -> bool {
+    if let Some(original) = pair.original.as_ref().filter(|o| o.exists()) {
+        if replace_tmp(original, &pair.target).is_err() {
+            eprintln!(
+                "could not put back {} from {}",
+                pair.target.display(),
+                original.display()
+            );
+            return false;
+        }
+    }
+    let _ = fs::remove_file(&pair.tmp);
+    true
+}
+
+/// Finish or roll back what the journal at `path` says was interrupted. Also returns the
+/// writes that could not be settled
+fn recover(path: &Path) -> (Recovery, Vec<Unfinished>) {
+    let mut recovery = Recovery::default();
+    let mut unsettled = Vec::new();
+    let Ok(file) = File::open(path) else {
+        return (recovery, unsettled);
+    };
+    let mut writes: BTreeMap<u64, Unfinished> = BTreeMap::new();
+    for line in BufReader::new(file).lines().map_while(Result::ok) {
+        // the last line may have been cut short by the crash
+        let Ok(record) = serde_json::from_str::<Record>(&line) else {
+            continue;
+        };
+        match record {
+            Record::Begin { id, pairs } => writes.entry(id).or_default().pairs = pairs,
+            Record::Staged { id } => writes.entry(id).or_default().staged = true,
+            Record::Aborted { id } => writes.entry(id).or_default().aborted = true,
+            Record::Done { id } => writes.entry(id).or_default().done = true,
+        }
+    }
+    // newest first, so a write nested in another (Ogg staging inside a batch copy) is settled
+    // before the one around it
+    for write in writes.values().rev().filter(|w| !w.done) {
+        let forward = write.staged && !write.aborted;
+        let settle: fn(&JournalPair) -> bool = if forward { finish } else { roll_back };
+        // every pair is tried, a stuck one doesn't keep the others from settling
+        let stuck = write.pairs.iter().filter(|pair| !settle(pair)).count();
+        if stuck > 0 {
+            recovery.unsettled += 1;
+            unsettled.push(write.clone());
+        } else if forward {
+            recovery.finished += 1;
+        } else {
+            recovery.rolled_back += 1;
+        }
+    }
+    (recovery, unsettled)
+}
+
+/// Recover interrupted writes from the journal at `path` and journal every write from now on
+pub fn init(path: PathBuf, policy: FsyncPolicy) -> Recovery {
+    let (recovery, mut unsettled) = recover(&path);
+    let mut journal = JOURNAL.lock().unwrap();
+    // renumbered in their order, ahead of the writes of this session
+    unsettled.reverse();
+    for (n, write) in unsettled.iter_mut().enumerate() {
+        write.id = n as u64 + 1;
+    }
+    journal.next_id = unsettled.len() as u64 + 1;
+    journal.unsettled = unsettled;
+    journal.path = Some(path);
+    journal.policy = policy;
+    journal.compact();
+    recovery
+}
+
+/// The audio file a staged copy like `.song.mp3.tmp` belongs to, None for other files
+fn staged_for(path: &Path) -> Option<PathBuf> {
+    let mut name = path.file_name()?.to_str()?;
+    let mut stripped = false;
+    while let Some(inner) = name.strip_prefix('.').and_then(|n| n.strip_suffix(".tmp")) {
+        name = inner;
+        stripped = true;
+    }
+    let target = path.with_file_name(name);
+    (stripped && is_supported_file(&target)).then_some(target)
+}
+
+/// The audio file an original set aside like `.song.mp3.orig` belongs to, None for other files
+fn original_for(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let inner = name.strip_prefix('.')?.strip_suffix(".orig")?;
+    let target = path.with_file_name(inner);
+    is_supported_file(&target).then_some(target)
+}
+
+/// Remove staged copies left in `folders` by writes that died before the journal knew of them,
+/// and originals a batch set aside but could not remove once its file was in place. Returns how
+/// many were removed
+pub fn clean_orphans(folders: &[String]) -> usize {
+    let mut removed = 0;
+    for folder in folders {
+        for entry in WalkDir::new(folder).into_iter().filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let orphan = match (staged_for(path), original_for(path)) {
+                (Some(_), _) => true,
+                // without its file the original is the only copy left
+                (None, Some(target)) => target.exists(),
+                (None, None) => false,
+            };
+            if !orphan {
+                continue;
+            }
+            // held while removing, so a write can't begin with this file in between
+            let journal = JOURNAL.lock().unwrap();
+            if journal.holds(path) {
+                continue;
+            }
+            match fs::remove_file(path) {
+                Ok(()) => removed += 1,
+                Err(e) => eprintln!("could not remove {}: {e}", path.display()),
+            }
+        }
+    }
+    removed
+}
@@ -2,10 +2,15 @@ use crate::tag_manager::traits::{Formats, TagFamily};
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
 use std::path::PathBuf;
+pub mod batch;
 mod flac;
 mod id3;
 mod itunes;
+pub mod journal;
 mod ogg;
+pub mod picture;
+pub mod properties;
+pub mod regions;
 pub mod tag_backend;
 pub mod traits;
 pub mod utils;
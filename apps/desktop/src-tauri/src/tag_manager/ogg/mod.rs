@@ -1,6 +1,7 @@
 use crate::tag_manager::tag_backend::{BackendError, TagError};
 use crate::tag_manager::vorbis_comments;
 
+use super::journal::{self, JournalPair};
 use super::traits::{Formats, TagFamily, TagFormat};
 use super::utils::{replace_tmp, temp_path_for, FrameKey, TagValue};
 use std::collections::HashMap;
@@ -10,7 +11,7 @@ use utils::{
     build_page_for_serial, read_page, write_page, PacketAssembler, PacketChunker, SerialMuxState,
     StreamClassifier, StreamKind,
 };
-mod utils;
+pub(super) mod utils;
 #[derive(Debug, Clone)]
 pub struct Ogg;
 impl TagFamily for Ogg {
@@ -69,7 +70,6 @@ impl TagFormat for OggFormat {
 
         let comment = vorbis_comments::utils::parse_comments(&payload);
         if comment.is_err() {
-            println!("Error parsing comments");
             return Err(BackendError::ReadFailed(TagError {
                 path: file_path.to_str().unwrap_or("").to_string(),
                 public_message: "Failed to parse Vorbis comments".to_string(),
@@ -109,6 +109,7 @@ impl TagFormat for OggFormat {
         let mut r = BufReader::new(input);
 
         let tmp_path = temp_path_for(file_path);
+        let pending = journal::begin(vec![JournalPair::new(&tmp_path, file_path)]);
         let out = File::create(&tmp_path).map_err(|_| {
             BackendError::WriteFailed(TagError {
                 path: tmp_path.to_str().unwrap_or("").to_string(),
@@ -343,6 +344,8 @@ impl TagFormat for OggFormat {
                 internal_message: "Failed to write file".to_string(),
             })
         })?;
+        drop(w);
+        pending.staged();
         replace_tmp(&tmp_path, file_path).map_err(|_| {
             BackendError::WriteFailed(TagError {
                 path: file_path.to_str().unwrap_or("").to_string(),
@@ -350,6 +353,7 @@ impl TagFormat for OggFormat {
                 internal_message: "Failed to replace file".to_string(),
             })
         })?;
+        pending.done();
         Ok(())
     }
 }
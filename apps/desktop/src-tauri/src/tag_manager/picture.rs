@@ -0,0 +1,368 @@
+/// What the image header says about a picture, without decoding the image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PictureInfo {
+    pub mime: &'static str,
+    pub width: u32,
+    pub height: u32,
+    /// Bits per pixel, as the FLAC PICTURE block wants it
+    pub color_depth: u32,
+    /// Size of the palette for indexed pictures, 0 otherwise
+    pub colors_used: u32,
+}
+
+/// MIME type from the magic bytes, for JPEG, PNG, GIF, WebP and BMP
+pub fn sniff_mime(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("image/jpeg")
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        Some("image/webp")
+    } else if data.starts_with(b"BM") && data.len() > 26 {
+        Some("image/bmp")
+    } else {
+        None
+    }
+}
+
+/// The usual spelling of a MIME type some taggers write differently
+fn canonical_mime(mime: &str) -> String {
+    let mime = mime.trim().to_lowercase();
+    match mime.as_str() {
+        "image/jpg" | "image/pjpeg" | "jpg" | "jpeg" => "image/jpeg".to_string(),
+        "image/x-png" | "png" => "image/png".to_string(),
+        "image/x-ms-bmp" | "image/x-bmp" | "bmp" => "image/bmp".to_string(),
+        "gif" => "image/gif".to_string(),
+        "webp" => "image/webp".to_string(),
+        _ => mime,
+    }
+}
+
+/// MIME type to write for a picture: what the data actually is when it's recognised,
+/// otherwise the declared type in its usual spelling
+pub fn normalize_mime(mime: &str, data: &[u8]) -> String {
+    match sniff_mime(data) {
+        Some(sniffed) => sniffed.to_string(),
+        None => canonical_mime(mime),
+    }
+}
+
+/// Whether `mime` is just another spelling of `sniffed`
+pub fn is_same_mime(mime: &str, sniffed: &str) -> bool {
+    canonical_mime(mime) == sniffed
+}
+
+/// Format, dimensions and colour depth from the image header
+pub fn picture_info(data: &[u8]) -> Option<PictureInfo> {
+    let be16 = |i: usize| Some(u16::from_be_bytes([*data.get(i)?, *data.get(i + 1)?]) as u32);
+    let le16 = |i: usize| Some(u16::from_le_bytes([*data.get(i)?, *data.get(i + 1)?]) as u32);
+    let be32 = |i: usize| Some(u32::from_be_bytes(data.get(i..i + 4)?.try_into().ok()?));
+    let le32 = |i: usize| Some(u32::from_le_bytes(data.get(i..i + 4)?.try_into().ok()?));
+    let le24 = |i: usize| {
+        Some(
+            *data.get(i)? as u32
+                | (*data.get(i + 1)? as u32) << 8
+                | (*data.get(i + 2)? as u32) << 16,
+        )
+    };
+    let mime = sniff_mime(data)?;
+    let info = |width, height, color_depth, colors_used| {
+        Some(PictureInfo {
+            mime,
+            width,
+            height,
+            color_depth,
+            colors_used,
+        })
+    };
+
+    match mime {
+        "image/png" => {
+            let bit_depth = *data.get(24)? as u32;
+            let color_type = *data.get(25)?;
+            let channels = match color_type {
+                2 => 3,
+                4 => 2,
+                6 => 4,
+                _ => 1,
+            };
+            let mut colors_used = 0;
+            if color_type == 3 {
+                // palette size from the PLTE chunk
+                let mut i = 8;
+                while let Some(len) = be32(i) {
+                    match data.get(i + 4..i + 8) {
+                        Some(b"PLTE") => {
+                            colors_used = len / 3;
+                            break;
+                        }
+                        Some(b"IDAT") | None => break,
+                        _ => i += 12 + len as usize,
+                    }
+                }
+            }
+            info(be32(16)?, be32(20)?, bit_depth * channels, colors_used)
+        }
+        "image/gif" => {
+            let packed = *data.get(10)? as u32;
+            let bits = (packed & 0x07) + 1;
+            let colors_used = if packed & 0x80 != 0 { 1 << bits } else { 0 };
+            info(le16(6)?, le16(8)?, bits, colors_used)
+        }
+        "image/bmp" => {
+            let width = le32(18)? as i32;
+            let height = le32(22)? as i32;
+            let bits = le16(28)?;
+            let colors_used = match le32(46).unwrap_or(0) {
+                0 if bits <= 8 => 1 << bits,
+                n => n,
+            };
+            info(
+                width.unsigned_abs(),
+                height.unsigned_abs(),
+                bits,
+                if bits <= 8 { colors_used } else { 0 },
+            )
+        }
+        "image/webp" => match data.get(12..16)? {
+            b"VP8 " => info(le16(26)? & 0x3fff, le16(28)? & 0x3fff, 24, 0),
+            b"VP8L" => {
+                let bits = le32(21)?;
+                let alpha = (bits >> 28) & 1 == 1;
+                info(
+                    (bits & 0x3fff) + 1,
+                    ((bits >> 14) & 0x3fff) + 1,
+                    if alpha { 32 } else { 24 },
+                    0,
+                )
+            }
+            b"VP8X" => {
+                let alpha = *data.get(20)? & 0x10 != 0;
+                info(le24(24)? + 1, le24(27)? + 1, if alpha { 32 } else { 24 }, 0)
+            }
+            _ => None,
+        },
+        _ => {
+            // walk the JPEG markers up to the first start of frame
+            let mut i = 2;
+            while i + 4 <= data.len() {
+                if data[i] != 0xff {
+                    i += 1;
+                    continue;
+                }
+                let marker = data[i + 1];
+                if marker == 0xff {
+                    i += 1;
+                    continue;
+                }
+                if marker == 0xd8 || marker == 0x01 || (0xd0..=0xd7).contains(&marker) {
+                    i += 2;
+                    continue;
+                }
+                let len = be16(i + 2)? as usize;
+                let is_sof =
+                    (0xc0..=0xcf).contains(&marker) && !matches!(marker, 0xc4 | 0xc8 | 0xcc);
+                if is_sof {
+                    let precision = *data.get(i + 4)? as u32;
+                    let components = *data.get(i + 9)? as u32;
+                    return info(be16(i + 7)?, be16(i + 5)?, precision * components, 0);
+                }
+                i += 2 + len;
+            }
+            None
+        }
+    }
+}
+
+/// Width and height from the image header, without decoding the image.
+/// Handles JPEG, PNG, GIF, BMP and WebP
+pub fn picture_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    picture_info(data).map(|info| (info.width, info.height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PNG with an IHDR chunk, then `chunks`. CRCs are left zero, nothing here checks them
+    fn png(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        color_type: u8,
+        chunks: &[(&[u8; 4], &[u8])],
+    ) -> Vec<u8> {
+        let mut ihdr = Vec::new();
+        ihdr.extend(width.to_be_bytes());
+        ihdr.extend(height.to_be_bytes());
+        ihdr.extend([bit_depth, color_type, 0, 0, 0]);
+        let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+        for (kind, body) in
+            std::iter::once((b"IHDR", ihdr.as_slice())).chain(chunks.iter().copied())
+        {
+            out.extend((body.len() as u32).to_be_bytes());
+            out.extend(kind.as_slice());
+            out.extend(body);
+            out.extend([0u8; 4]);
+        }
+        out
+    }
+
+    /// A baseline JPEG header: SOI, APP0, `fill` bytes, then a start of frame
+    fn jpeg(width: u16, height: u16, components: u8, fill: usize) -> Vec<u8> {
+        let mut out = vec![0xff, 0xd8, 0xff, 0xe0, 0x00, 0x10];
+        out.extend(b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0");
+        out.extend(vec![0xff; fill]);
+        out.extend([0xff, 0xc0, 0x00, 8 + 3 * components as u8, 8]);
+        out.extend(height.to_be_bytes());
+        out.extend(width.to_be_bytes());
+        out.push(components);
+        for id in 1..=components {
+            out.extend([id, 0x11, 0]);
+        }
+        out.extend([0xff, 0xd9]);
+        out
+    }
+
+    #[test]
+    fn sniffs_mime_from_magic_bytes() {
+        assert_eq!(sniff_mime(&jpeg(1, 1, 3, 0)), Some("image/jpeg"));
+        assert_eq!(sniff_mime(&png(1, 1, 8, 2, &[])), Some("image/png"));
+        assert_eq!(sniff_mime(b"GIF89a\x01\x00\x01\x00\x00"), Some("image/gif"));
+        assert_eq!(sniff_mime(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(
+            sniff_mime(&[b"BM".as_slice(), &[0; 30]].concat()),
+            Some("image/bmp")
+        );
+        // too short to hold a BMP header
+        assert_eq!(sniff_mime(b"BM\0\0"), None);
+        assert_eq!(sniff_mime(b"RIFF\0\0\0\0WAVE"), None);
+        assert_eq!(sniff_mime(b""), None);
+    }
+
+    #[test]
+    fn normalizes_declared_mime() {
+        assert_eq!(normalize_mime("image/jpg", b"not an image"), "image/jpeg");
+        assert_eq!(normalize_mime(" PNG ", b""), "image/png");
+        // what the data is wins over what it claims to be
+        assert_eq!(
+            normalize_mime("image/jpeg", &png(1, 1, 8, 2, &[])),
+            "image/png"
+        );
+        assert!(is_same_mime("image/pjpeg", "image/jpeg"));
+        assert!(!is_same_mime("image/png", "image/jpeg"));
+    }
+
+    #[test]
+    fn png_truecolor() {
+        let info = picture_info(&png(640, 480, 8, 6, &[(b"IDAT", &[0; 4])])).unwrap();
+        assert_eq!(
+            (info.mime, info.width, info.height),
+            ("image/png", 640, 480)
+        );
+        assert_eq!((info.color_depth, info.colors_used), (32, 0));
+        let info = picture_info(&png(2, 2, 16, 2, &[])).unwrap();
+        assert_eq!((info.color_depth, info.colors_used), (48, 0));
+    }
+
+    #[test]
+    fn png_palette_size_from_plte() {
+        // the palette may come after other chunks
+        let data = png(
+            16,
+            16,
+            4,
+            3,
+            &[
+                (b"tEXt", b"Comment\0hi"),
+                (b"PLTE", &[0; 9]),
+                (b"IDAT", &[0; 4]),
+            ],
+        );
+        let info = picture_info(&data).unwrap();
+        assert_eq!((info.color_depth, info.colors_used), (4, 3));
+    }
+
+    #[test]
+    fn png_palette_missing_or_cut_off() {
+        let no_plte = png(16, 16, 8, 3, &[(b"IDAT", &[0; 4])]);
+        assert_eq!(picture_info(&no_plte).unwrap().colors_used, 0);
+        // chunk walk runs off the end without panicking
+        let mut cut = png(16, 16, 8, 3, &[(b"PLTE", &[0; 768])]);
+        cut.truncate(40);
+        assert_eq!(picture_info(&cut).unwrap().colors_used, 0);
+    }
+
+    #[test]
+    fn png_truncated_header() {
+        let data = png(640, 480, 8, 6, &[]);
+        assert_eq!(picture_info(&data[..20]), None);
+        assert_eq!(picture_info(&data[..25]), None);
+        assert!(picture_info(&data[..26]).is_some());
+    }
+
+    #[test]
+    fn jpeg_dimensions_and_depth() {
+        let info = picture_info(&jpeg(1200, 800, 3, 0)).unwrap();
+        assert_eq!(
+            (info.mime, info.width, info.height),
+            ("image/jpeg", 1200, 800)
+        );
+        assert_eq!((info.color_depth, info.colors_used), (24, 0));
+        assert_eq!(picture_info(&jpeg(10, 10, 1, 0)).unwrap().color_depth, 8);
+    }
+
+    #[test]
+    fn jpeg_fill_bytes_before_marker() {
+        let info = picture_info(&jpeg(300, 200, 3, 3)).unwrap();
+        assert_eq!((info.width, info.height), (300, 200));
+    }
+
+    #[test]
+    fn jpeg_truncated() {
+        let data = jpeg(300, 200, 3, 0);
+        let sof = data.len() - 2 - 9 - 10;
+        // cut inside the frame header
+        assert_eq!(picture_info(&data[..sof + 6]), None);
+        // cut before any frame header
+        assert_eq!(picture_info(&data[..sof]), None);
+        assert_eq!(picture_info(&[0xff, 0xd8, 0xff]), None);
+    }
+
+    #[test]
+    fn gif_global_palette() {
+        let info = picture_info(b"GIF89a\x0a\x00\x14\x00\x82\x00\x00").unwrap();
+        assert_eq!((info.width, info.height), (10, 20));
+        assert_eq!((info.color_depth, info.colors_used), (3, 8));
+        let info = picture_info(b"GIF87a\x0a\x00\x14\x00\x02\x00\x00").unwrap();
+        assert_eq!(info.colors_used, 0);
+        assert_eq!(picture_info(b"GIF89a\x0a\x00"), None);
+    }
+
+    #[test]
+    fn bmp_palette_defaults_to_full() {
+        let mut data = vec![0u8; 54];
+        data[..2].copy_from_slice(b"BM");
+        data[18..22].copy_from_slice(&32i32.to_le_bytes());
+        // bottom-up rows are stored with a negative height
+        data[22..26].copy_from_slice(&(-16i32).to_le_bytes());
+        data[28..30].copy_from_slice(&8u16.to_le_bytes());
+        let info = picture_info(&data).unwrap();
+        assert_eq!((info.width, info.height), (32, 16));
+        assert_eq!((info.color_depth, info.colors_used), (8, 256));
+        data[28..30].copy_from_slice(&24u16.to_le_bytes());
+        assert_eq!(picture_info(&data).unwrap().colors_used, 0);
+    }
+
+    #[test]
+    fn webp_variants() {
+        let mut vp8x = b"RIFF\0\0\0\0WEBPVP8X\x0a\0\0\0\x10\0\0\0".to_vec();
+        vp8x.extend([0x3f, 0x01, 0x00, 0xef, 0x00, 0x00]);
+        let info = picture_info(&vp8x).unwrap();
+        assert_eq!((info.width, info.height, info.color_depth), (320, 240, 32));
+        assert_eq!(picture_info(&vp8x[..28]), None);
+        assert_eq!(picture_info(b"RIFF\0\0\0\0WEBPVP9 \0\0\0\0"), None);
+    }
+}
@@ -0,0 +1,57 @@
+use super::{average_bitrate, AudioProperties};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+/// Parse a STREAMINFO block body (34 bytes)
+pub(crate) fn parse_streaminfo(b: &[u8]) -> Option<(u32, u16, u16, u64)> {
+    if b.len() < 18 {
+        return None;
+    }
+    let sample_rate = ((b[10] as u32) << 12) | ((b[11] as u32) << 4) | ((b[12] as u32) >> 4);
+    let channels = (((b[12] >> 1) & 0x07) + 1) as u16;
+    let bits_per_sample = ((((b[12] & 0x01) << 4) | (b[13] >> 4)) + 1) as u16;
+    let total_samples =
+        (((b[13] & 0x0f) as u64) << 32) | u32::from_be_bytes([b[14], b[15], b[16], b[17]]) as u64;
+    Some((sample_rate, channels, bits_per_sample, total_samples))
+}
+
+pub fn read(file: &mut File, start: u64, file_size: u64) -> Option<AudioProperties> {
+    file.seek(SeekFrom::Start(start + 4)).ok()?;
+
+    let mut info: Option<(u32, u16, u16, u64)> = None;
+    let mut audio_start = start + 4;
+    loop {
+        let mut header = [0u8; 4];
+        file.read_exact(&mut header).ok()?;
+        let is_last = header[0] & 0x80 != 0;
+        let block_type = header[0] & 0x7f;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as u64;
+
+        if block_type == 0 {
+            let mut body = vec![0u8; len as usize];
+            file.read_exact(&mut body).ok()?;
+            info = parse_streaminfo(&body);
+        } else {
+            file.seek(SeekFrom::Current(len as i64)).ok()?;
+        }
+        audio_start += 4 + len;
+        if is_last || audio_start >= file_size {
+            break;
+        }
+    }
+
+    let (sample_rate, channels, bits_per_sample, total_samples) = info?;
+    if sample_rate == 0 {
+        return None;
+    }
+    let duration_ms = total_samples * 1000 / sample_rate as u64;
+
+    Some(AudioProperties {
+        duration_ms,
+        bitrate: average_bitrate(file_size.saturating_sub(audio_start), duration_ms),
+        sample_rate,
+        channels,
+        bits_per_sample: Some(bits_per_sample),
+        codec: "FLAC".to_string(),
+    })
+}
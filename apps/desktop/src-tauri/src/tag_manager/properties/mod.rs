@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+mod flac;
+mod mp4;
+mod mpeg;
+mod ogg;
+
+/// Technical properties of the audio stream, read from the container headers
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioProperties {
+    pub duration_ms: u64,
+    /// Average bitrate in kbps
+    pub bitrate: u32,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits_per_sample: Option<u16>,
+    pub codec: String,
+}
+
+/// Average bitrate in kbps for `bytes` of audio lasting `duration_ms`
+pub(crate) fn average_bitrate(bytes: u64, duration_ms: u64) -> u32 {
+    if duration_ms == 0 {
+        return 0;
+    }
+    (bytes * 8 / duration_ms) as u32
+}
+
+/// Size of a leading ID3v2 tag, 0 if there is none
+pub(crate) fn id3v2_size(file: &mut File) -> u64 {
+    let mut header = [0u8; 10];
+    if file.seek(SeekFrom::Start(0)).is_err() || file.read_exact(&mut header).is_err() {
+        return 0;
+    }
+    if &header[0..3] != b"ID3" {
+        return 0;
+    }
+    let size = ((header[6] as u64 & 0x7f) << 21)
+        | ((header[7] as u64 & 0x7f) << 14)
+        | ((header[8] as u64 & 0x7f) << 7)
+        | (header[9] as u64 & 0x7f);
+    let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+    10 + size + footer
+}
+
+/// Read duration, bitrate, sample rate, channels and codec. Returns None for unknown or broken files
+pub fn read_properties(path: &Path) -> Option<AudioProperties> {
+    let mut file = File::open(path).ok()?;
+    let file_size = file.metadata().ok()?.len();
+
+    // some taggers put ID3v2 in front of FLAC streams too
+    let start = id3v2_size(&mut file);
+    let mut magic = [0u8; 8];
+    file.seek(SeekFrom::Start(start)).ok()?;
+    let read = file.read(&mut magic).ok()?;
+
+    let props = if read >= 4 && &magic[0..4] == b"fLaC" {
+        flac::read(&mut file, start, file_size)
+    } else if read >= 4 && &magic[0..4] == b"OggS" {
+        ogg::read(&mut file, start, file_size)
+    } else if read >= 8 && &magic[4..8] == b"ftyp" {
+        mp4::read(&mut file, file_size)
+    } else {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        if start > 0 || matches!(ext.as_str(), "mp3" | "mp2" | "mp1") {
+            mpeg::read(&mut file, start, file_size)
+        } else {
+            None
+        }
+    };
+
+    props.filter(|p| p.sample_rate > 0)
+}
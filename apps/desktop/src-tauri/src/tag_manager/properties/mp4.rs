@@ -0,0 +1,152 @@
+use super::{average_bitrate, AudioProperties};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+/// Child atoms of an in-memory container as (type, body)
+fn children(buf: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut out = Vec::new();
+    let mut pos = 0usize;
+    while pos + 8 <= buf.len() {
+        let size = u32::from_be_bytes([buf[pos], buf[pos + 1], buf[pos + 2], buf[pos + 3]]) as u64;
+        let kind = [buf[pos + 4], buf[pos + 5], buf[pos + 6], buf[pos + 7]];
+        let (header, size) = match size {
+            0 => (8u64, (buf.len() - pos) as u64),
+            1 if pos + 16 <= buf.len() => (
+                16u64,
+                u64::from_be_bytes(buf[pos + 8..pos + 16].try_into().unwrap()),
+            ),
+            _ => (8u64, size),
+        };
+        if size < header || pos as u64 + size > buf.len() as u64 {
+            break;
+        }
+        out.push((kind, &buf[pos + header as usize..pos + size as usize]));
+        pos += size as usize;
+    }
+    out
+}
+
+fn child<'a>(buf: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    children(buf)
+        .into_iter()
+        .find(|(k, _)| k == kind)
+        .map(|(_, b)| b)
+}
+
+/// Timescale and duration from an mvhd or mdhd body
+fn timescale_duration(body: &[u8]) -> Option<(u32, u64)> {
+    let be32 = |o: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(body.get(o..o + 4)?.try_into().ok()?))
+    };
+    if *body.first()? == 1 {
+        let timescale = be32(20)?;
+        let duration = u64::from_be_bytes(body.get(24..32)?.try_into().ok()?);
+        Some((timescale, duration))
+    } else {
+        Some((be32(12)?, be32(16)? as u64))
+    }
+}
+
+fn codec_name(format: &[u8; 4]) -> String {
+    match format {
+        b"mp4a" => "AAC".to_string(),
+        b"alac" => "ALAC".to_string(),
+        b"fLaC" => "FLAC".to_string(),
+        b"Opus" => "Opus".to_string(),
+        b"ac-3" => "AC-3".to_string(),
+        b"ec-3" => "E-AC-3".to_string(),
+        b".mp3" => "MP3".to_string(),
+        other => String::from_utf8_lossy(other).trim().to_string(),
+    }
+}
+
+pub fn read(file: &mut File, file_size: u64) -> Option<AudioProperties> {
+    let mut moov: Option<Vec<u8>> = None;
+    let mut mdat_size: u64 = 0;
+
+    let mut pos = 0u64;
+    while pos + 8 <= file_size {
+        file.seek(SeekFrom::Start(pos)).ok()?;
+        let mut header = [0u8; 16];
+        file.read_exact(&mut header[0..8]).ok()?;
+        let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let mut header_len = 8u64;
+        if size == 1 {
+            file.read_exact(&mut header[8..16]).ok()?;
+            size = u64::from_be_bytes(header[8..16].try_into().ok()?);
+            header_len = 16;
+        } else if size == 0 {
+            size = file_size - pos;
+        }
+        if size < header_len {
+            break;
+        }
+        match &header[4..8] {
+            b"moov" => {
+                let mut body = vec![0u8; (size - header_len) as usize];
+                file.read_exact(&mut body).ok()?;
+                moov = Some(body);
+            }
+            b"mdat" => mdat_size += size - header_len,
+            _ => {}
+        }
+        pos += size;
+    }
+
+    let moov = moov?;
+    let movie = child(&moov, b"mvhd").and_then(timescale_duration);
+
+    for (kind, trak) in children(&moov) {
+        if &kind != b"trak" {
+            continue;
+        }
+        let Some(mdia) = child(trak, b"mdia") else {
+            continue;
+        };
+        let is_sound = child(mdia, b"hdlr")
+            .and_then(|h| h.get(8..12))
+            .map(|h| h == b"soun")
+            .unwrap_or(false);
+        if !is_sound {
+            continue;
+        }
+        let stsd = child(mdia, b"minf")
+            .and_then(|m| child(m, b"stbl"))
+            .and_then(|s| child(s, b"stsd"))?;
+        // version/flags and entry count come before the first sample entry
+        let entry = stsd.get(8..)?;
+        if entry.len() < 36 {
+            return None;
+        }
+        let format: [u8; 4] = entry[4..8].try_into().ok()?;
+        let channels = u16::from_be_bytes([entry[24], entry[25]]);
+        let sample_size = u16::from_be_bytes([entry[26], entry[27]]);
+        let mut sample_rate = u16::from_be_bytes([entry[32], entry[33]]) as u32;
+
+        let (timescale, duration) = child(mdia, b"mdhd")
+            .and_then(timescale_duration)
+            .or(movie)?;
+        if sample_rate == 0 {
+            sample_rate = timescale;
+        }
+        let duration_ms = if timescale == 0 {
+            0
+        } else {
+            duration * 1000 / timescale as u64
+        };
+        let lossless = matches!(&format, b"alac" | b"fLaC");
+
+        return Some(AudioProperties {
+            duration_ms,
+            bitrate: average_bitrate(
+                if mdat_size > 0 { mdat_size } else { file_size },
+                duration_ms,
+            ),
+            sample_rate,
+            channels,
+            bits_per_sample: if lossless { Some(sample_size) } else { None },
+            codec: codec_name(&format),
+        });
+    }
+    None
+}
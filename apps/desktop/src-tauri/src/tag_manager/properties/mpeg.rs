@@ -0,0 +1,249 @@
+use super::{average_bitrate, AudioProperties};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+const BITRATES_V1: [[u32; 16]; 3] = [
+    [
+        0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448, 0,
+    ],
+    [
+        0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384, 0,
+    ],
+    [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 0,
+    ],
+];
+const BITRATES_V2: [[u32; 16]; 3] = [
+    [
+        0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256, 0,
+    ],
+    [
+        0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160, 0,
+    ],
+    [
+        0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160, 0,
+    ],
+];
+const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+/// How far to look for the first frame after the ID3 tag
+const SYNC_SEARCH_LEN: usize = 65536;
+
+#[derive(Debug, Clone, Copy)]
+struct FrameHeader {
+    mpeg1: bool,
+    layer: u8,
+    bitrate: u32,
+    sample_rate: u32,
+    channels: u16,
+    length: u64,
+    samples: u64,
+}
+
+fn parse_header(b: &[u8]) -> Option<FrameHeader> {
+    if b.len() < 4 || b[0] != 0xFF || b[1] & 0xE0 != 0xE0 {
+        return None;
+    }
+    let version = (b[1] >> 3) & 0x03;
+    let layer = match (b[1] >> 1) & 0x03 {
+        0b11 => 1u8,
+        0b10 => 2,
+        0b01 => 3,
+        _ => return None,
+    };
+    let bitrate_index = (b[2] >> 4) as usize;
+    let rate_index = ((b[2] >> 2) & 0x03) as usize;
+    if version == 0b01 || bitrate_index == 0 || bitrate_index == 15 || rate_index == 3 {
+        return None;
+    }
+    let mpeg1 = version == 0b11;
+    let bitrate = if mpeg1 {
+        BITRATES_V1[layer as usize - 1][bitrate_index]
+    } else {
+        BITRATES_V2[layer as usize - 1][bitrate_index]
+    };
+    let sample_rate = match version {
+        0b11 => SAMPLE_RATES[rate_index],
+        0b10 => SAMPLE_RATES[rate_index] / 2,
+        _ => SAMPLE_RATES[rate_index] / 4,
+    };
+    let padding = ((b[2] >> 1) & 0x01) as u64;
+    let channels = if (b[3] >> 6) == 0b11 { 1 } else { 2 };
+
+    let (length, samples) = match layer {
+        1 => (
+            (12 * bitrate as u64 * 1000 / sample_rate as u64 + padding) * 4,
+            384,
+        ),
+        2 => (
+            144 * bitrate as u64 * 1000 / sample_rate as u64 + padding,
+            1152,
+        ),
+        _ if mpeg1 => (
+            144 * bitrate as u64 * 1000 / sample_rate as u64 + padding,
+            1152,
+        ),
+        _ => (
+            72 * bitrate as u64 * 1000 / sample_rate as u64 + padding,
+            576,
+        ),
+    };
+
+    Some(FrameHeader {
+        mpeg1,
+        layer,
+        bitrate,
+        sample_rate,
+        channels,
+        length,
+        samples,
+    })
+}
+
+/// Frame count and stream size from a Xing/Info or VBRI header, plus LAME delay and padding
+fn vbr_info(frame: &[u8], header: &FrameHeader) -> Option<(u64, Option<u64>, u64)> {
+    let side_info = match (header.mpeg1, header.channels) {
+        (true, 1) => 17,
+        (true, _) => 32,
+        (false, 1) => 9,
+        (false, _) => 17,
+    };
+    let be32 = |o: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(frame.get(o..o + 4)?.try_into().ok()?))
+    };
+
+    let xing = 4 + side_info;
+    if let Some(tag) = frame.get(xing..xing + 4) {
+        if tag == b"Xing" || tag == b"Info" {
+            let flags = be32(xing + 4)?;
+            let mut pos = xing + 8;
+            let mut frames = None;
+            let mut bytes = None;
+            if flags & 0x01 != 0 {
+                frames = Some(be32(pos)? as u64);
+                pos += 4;
+            }
+            if flags & 0x02 != 0 {
+                bytes = Some(be32(pos)? as u64);
+                pos += 4;
+            }
+            if flags & 0x04 != 0 {
+                pos += 100;
+            }
+            if flags & 0x08 != 0 {
+                pos += 4;
+            }
+            let mut trim = 0u64;
+            if frame.get(pos..pos + 4) == Some(b"LAME") {
+                if let Some(d) = frame.get(pos + 21..pos + 24) {
+                    let delay = ((d[0] as u64) << 4) | ((d[1] as u64) >> 4);
+                    let padding = (((d[1] & 0x0f) as u64) << 8) | d[2] as u64;
+                    trim = delay + padding;
+                }
+            }
+            return frames.map(|f| (f, bytes, trim));
+        }
+    }
+
+    if frame.get(36..40) == Some(b"VBRI") {
+        let bytes = be32(46)? as u64;
+        let frames = be32(50)? as u64;
+        return Some((frames, Some(bytes), 0));
+    }
+    None
+}
+
+/// Walk every frame header when the stream has no VBR header
+fn scan_frames(reader: &mut BufReader<&mut File>, start: u64, end: u64) -> (u64, u64) {
+    let mut pos = start;
+    let mut samples = 0u64;
+    let mut bytes = 0u64;
+    let mut buf = [0u8; 4];
+    if reader.seek(SeekFrom::Start(start)).is_err() {
+        return (0, 0);
+    }
+    while pos + 4 <= end {
+        if reader.read_exact(&mut buf).is_err() {
+            break;
+        }
+        match parse_header(&buf) {
+            Some(h) if h.length > 4 => {
+                samples += h.samples;
+                bytes += h.length;
+                pos += h.length;
+                // seek_relative keeps the buffer, a plain seek would drop it for every frame
+                if reader.seek_relative(h.length as i64 - 4).is_err() {
+                    break;
+                }
+            }
+            _ => break,
+        }
+    }
+    (samples, bytes)
+}
+
+pub fn read(file: &mut File, start: u64, file_size: u64) -> Option<AudioProperties> {
+    // stop before ID3v1 / APE trailers
+    let mut end = file_size;
+    if file_size >= 128 {
+        let mut tail = [0u8; 3];
+        file.seek(SeekFrom::Start(file_size - 128)).ok()?;
+        if file.read_exact(&mut tail).is_ok() && &tail == b"TAG" {
+            end -= 128;
+        }
+    }
+
+    file.seek(SeekFrom::Start(start)).ok()?;
+    let mut buf = vec![0u8; SYNC_SEARCH_LEN];
+    let read = file.read(&mut buf).ok()?;
+    buf.truncate(read);
+
+    // require a second frame right after the first to rule out false syncs
+    let mut offset = None;
+    for i in 0..buf.len().saturating_sub(4) {
+        if let Some(h) = parse_header(&buf[i..]) {
+            let next = i + h.length as usize;
+            let confirmed = match buf.get(next..next + 4) {
+                Some(n) => parse_header(n).is_some(),
+                None => true,
+            };
+            if confirmed {
+                offset = Some(i);
+                break;
+            }
+        }
+    }
+    let offset = offset?;
+    let first = parse_header(&buf[offset..])?;
+    let audio_start = start + offset as u64;
+    let frame_end = (offset + first.length as usize).min(buf.len());
+
+    let (duration_ms, bitrate) = match vbr_info(&buf[offset..frame_end], &first) {
+        Some((frames, bytes, trim)) => {
+            let samples = (frames * first.samples).saturating_sub(trim);
+            let duration_ms = samples * 1000 / first.sample_rate as u64;
+            let bytes = bytes.unwrap_or(end.saturating_sub(audio_start));
+            (duration_ms, average_bitrate(bytes, duration_ms))
+        }
+        None => {
+            let mut reader = BufReader::new(&mut *file);
+            let (samples, bytes) = scan_frames(&mut reader, audio_start, end);
+            let duration_ms = samples * 1000 / first.sample_rate as u64;
+            let bitrate = if duration_ms > 0 {
+                average_bitrate(bytes, duration_ms)
+            } else {
+                first.bitrate
+            };
+            (duration_ms, bitrate)
+        }
+    };
+
+    Some(AudioProperties {
+        duration_ms,
+        bitrate,
+        sample_rate: first.sample_rate,
+        channels: first.channels,
+        bits_per_sample: None,
+        codec: format!("MP{}", first.layer),
+    })
+}
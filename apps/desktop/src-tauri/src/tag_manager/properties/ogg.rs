@@ -0,0 +1,132 @@
+use super::flac::parse_streaminfo;
+use super::{average_bitrate, AudioProperties};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+const TAIL_LEN: u64 = 65536;
+
+struct Stream {
+    serial: u32,
+    codec: &'static str,
+    sample_rate: u32,
+    channels: u16,
+    bits_per_sample: Option<u16>,
+    // Opus granules run at 48kHz and include the encoder pre-skip
+    granule_rate: u32,
+    pre_skip: u64,
+}
+
+/// Identify the codec from the first packet of the first page
+fn identify(packet: &[u8], serial: u32) -> Option<Stream> {
+    let le32 =
+        |o: usize| u32::from_le_bytes([packet[o], packet[o + 1], packet[o + 2], packet[o + 3]]);
+
+    if packet.len() >= 16 && &packet[0..7] == b"\x01vorbis" {
+        let rate = le32(12);
+        return Some(Stream {
+            serial,
+            codec: "Vorbis",
+            sample_rate: rate,
+            channels: packet[11] as u16,
+            bits_per_sample: None,
+            granule_rate: rate,
+            pre_skip: 0,
+        });
+    }
+    if packet.len() >= 16 && &packet[0..8] == b"OpusHead" {
+        return Some(Stream {
+            serial,
+            codec: "Opus",
+            sample_rate: le32(12),
+            channels: packet[9] as u16,
+            bits_per_sample: None,
+            granule_rate: 48000,
+            pre_skip: u16::from_le_bytes([packet[10], packet[11]]) as u64,
+        });
+    }
+    if packet.len() >= 52 && &packet[0..8] == b"Speex   " {
+        let rate = le32(36);
+        return Some(Stream {
+            serial,
+            codec: "Speex",
+            sample_rate: rate,
+            channels: le32(48) as u16,
+            bits_per_sample: None,
+            granule_rate: rate,
+            pre_skip: 0,
+        });
+    }
+    if packet.len() >= 51 && &packet[0..5] == b"\x7fFLAC" && &packet[9..13] == b"fLaC" {
+        let (rate, channels, bits, _) = parse_streaminfo(&packet[17..])?;
+        return Some(Stream {
+            serial,
+            codec: "FLAC",
+            sample_rate: rate,
+            channels,
+            bits_per_sample: Some(bits),
+            granule_rate: rate,
+            pre_skip: 0,
+        });
+    }
+    None
+}
+
+/// Granule position of the last page belonging to the stream
+fn last_granule(file: &mut File, file_size: u64, serial: u32) -> Option<u64> {
+    let tail_start = file_size.saturating_sub(TAIL_LEN);
+    file.seek(SeekFrom::Start(tail_start)).ok()?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail).ok()?;
+
+    let mut i = tail.len().checked_sub(27)?;
+    loop {
+        if &tail[i..i + 4] == b"OggS" {
+            let granule = u64::from_le_bytes(tail[i + 6..i + 14].try_into().ok()?);
+            let page_serial = u32::from_le_bytes(tail[i + 14..i + 18].try_into().ok()?);
+            if page_serial == serial && granule != u64::MAX {
+                return Some(granule);
+            }
+        }
+        if i == 0 {
+            return None;
+        }
+        i -= 1;
+    }
+}
+
+pub fn read(file: &mut File, start: u64, file_size: u64) -> Option<AudioProperties> {
+    file.seek(SeekFrom::Start(start)).ok()?;
+    let mut header = [0u8; 27];
+    file.read_exact(&mut header).ok()?;
+    let serial = u32::from_le_bytes([header[14], header[15], header[16], header[17]]);
+
+    let mut segments = vec![0u8; header[26] as usize];
+    file.read_exact(&mut segments).ok()?;
+    // the identification header always fits in the first page
+    let mut first_len = 0usize;
+    for s in segments.iter() {
+        first_len += *s as usize;
+        if *s < 255 {
+            break;
+        }
+    }
+    let mut packet = vec![0u8; first_len];
+    file.read_exact(&mut packet).ok()?;
+
+    let stream = identify(&packet, serial)?;
+    if stream.granule_rate == 0 {
+        return None;
+    }
+    let granule = last_granule(file, file_size, stream.serial).unwrap_or(0);
+    let samples = granule.saturating_sub(stream.pre_skip);
+    let duration_ms = samples * 1000 / stream.granule_rate as u64;
+
+    Some(AudioProperties {
+        duration_ms,
+        bitrate: average_bitrate(file_size.saturating_sub(start), duration_ms),
+        sample_rate: stream.sample_rate,
+        channels: stream.channels,
+        bits_per_sample: stream.bits_per_sample,
+        codec: stream.codec.to_string(),
+    })
+}
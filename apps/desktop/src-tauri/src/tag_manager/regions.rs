@@ -0,0 +1,359 @@
+use super::journal::{self, JournalPair};
+use super::ogg::utils::{read_page, write_page};
+use super::utils::{replace_tmp, temp_path_for};
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+
+/// The part of a file its tags live in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegionKind {
+    /// The ID3v2 block at the start, plus the ID3v1 tag at the end when there is one
+    Id3,
+    /// Every metadata block before the first audio frame
+    Flac,
+    /// The `moov` atom
+    Mp4,
+    /// The header pages, up to the first page with audio
+    Ogg,
+}
+
+impl RegionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RegionKind::Id3 => "id3",
+            RegionKind::Flac => "flac",
+            RegionKind::Mp4 => "mp4",
+            RegionKind::Ogg => "ogg",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "id3" => Some(RegionKind::Id3),
+            "flac" => Some(RegionKind::Flac),
+            "mp4" => Some(RegionKind::Mp4),
+            "ogg" => Some(RegionKind::Ogg),
+            _ => None,
+        }
+    }
+}
+
+/// Where a file keeps its tags
+#[derive(Debug, Clone)]
+pub struct Layout {
+    pub kind: RegionKind,
+    pub range: Range<u64>,
+    /// Size of the ID3v1 tag at the end of the file, 0 or 128
+    pub tail: u64,
+    /// Where the media data starts, MP4 chunk offsets point past it
+    pub data_offset: Option<u64>,
+}
+
+/// A metadata region as read from a file
+#[derive(Debug, Clone)]
+pub struct Region {
+    pub layout: Layout,
+    /// The region followed by the tail
+    pub bytes: Vec<u8>,
+}
+
+fn read_at(file: &mut File, pos: u64, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    file.seek(SeekFrom::Start(pos))?;
+    file.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Size of the ID3v2 block a header starts, footer included. 0 when there is none
+fn id3v2_len(header: &[u8]) -> u64 {
+    if header.len() < 10 || &header[0..3] != b"ID3" {
+        return 0;
+    }
+    let size = header[6..10]
+        .iter()
+        .fold(0u64, |acc, b| (acc << 7) | (*b as u64 & 0x7f));
+    let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+    10 + size + footer
+}
+
+fn flac_end(file: &mut File, start: u64, len: u64) -> io::Result<u64> {
+    let mut pos = start + 4;
+    while pos + 4 <= len {
+        let header = read_at(file, pos, 4)?;
+        let size = u32::from_be_bytes([0, header[1], header[2], header[3]]) as u64;
+        pos += 4 + size;
+        if header[0] & 0x80 != 0 {
+            break;
+        }
+    }
+    Ok(pos.min(len))
+}
+
+/// Length of the leading pages that carry no audio: granule 0, or -1 for a page no packet ends on
+fn ogg_header_len<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut len = 0;
+    while let Some(page) = read_page(r)? {
+        if page.granule_position != 0 && page.granule_position != u64::MAX {
+            break;
+        }
+        len += (27 + page.segment_table.len() + page.payload.len()) as u64;
+    }
+    Ok(len)
+}
+
+/// Top-level atoms as (type, range)
+fn top_level_atoms(file: &mut File, len: u64) -> io::Result<Vec<([u8; 4], Range<u64>)>> {
+    let mut atoms = Vec::new();
+    let mut pos = 0;
+    while pos + 8 <= len {
+        let header = read_at(file, pos, 8)?;
+        let mut size = u32::from_be_bytes(header[0..4].try_into().unwrap()) as u64;
+        if size == 1 {
+            size = u64::from_be_bytes(read_at(file, pos + 8, 8)?.try_into().unwrap());
+        } else if size == 0 {
+            size = len - pos;
+        }
+        if size < 8 {
+            break;
+        }
+        atoms.push((header[4..8].try_into().unwrap(), pos..(pos + size).min(len)));
+        pos += size;
+    }
+    Ok(atoms)
+}
+
+/// Find the metadata region of a file. None for formats without a known one
+pub fn layout(path: &Path) -> Result<Option<Layout>, String> {
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    let len = file.metadata().map_err(|e| e.to_string())?.len();
+    let mut header = [0u8; 10];
+    let read = file.read(&mut header).map_err(|e| e.to_string())?;
+    let header = &header[..read];
+    let layout = |kind, range, tail, data_offset| {
+        Some(Layout {
+            kind,
+            range,
+            tail,
+            data_offset,
+        })
+    };
+
+    if header.starts_with(b"OggS") {
+        file.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+        let end = ogg_header_len(&mut BufReader::new(&mut file)).map_err(|e| e.to_string())?;
+        return Ok(layout(RegionKind::Ogg, 0..end, 0, None));
+    }
+    if header.get(4..8) == Some(b"ftyp") {
+        let atoms = top_level_atoms(&mut file, len).map_err(|e| e.to_string())?;
+        let moov = atoms
+            .iter()
+            .find(|(t, _)| t == b"moov")
+            .ok_or("No moov atom")?;
+        let mdat = atoms
+            .iter()
+            .find(|(t, _)| t == b"mdat")
+            .map(|(_, r)| r.start);
+        return Ok(layout(RegionKind::Mp4, moov.1.clone(), 0, mdat));
+    }
+
+    // FLAC can sit behind an ID3v2 block
+    let id3_len = id3v2_len(header);
+    if id3_len + 4 <= len && read_at(&mut file, id3_len, 4).map_err(|e| e.to_string())? == b"fLaC" {
+        let end = flac_end(&mut file, id3_len, len).map_err(|e| e.to_string())?;
+        return Ok(layout(RegionKind::Flac, 0..end, 0, None));
+    }
+
+    let is_mpeg = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| matches!(e.to_lowercase().as_str(), "mp3" | "mp2" | "mp1"))
+        .unwrap_or(false);
+    if !is_mpeg {
+        return Ok(None);
+    }
+    let tail = if len >= id3_len + 128
+        && read_at(&mut file, len - 128, 3).map_err(|e| e.to_string())? == b"TAG"
+    {
+        128
+    } else {
+        0
+    };
+    Ok(layout(RegionKind::Id3, 0..id3_len.min(len), tail, None))
+}
+
+/// Read the metadata region of a file. None for formats without a known one
+pub fn read_region(path: &Path) -> Result<Option<Region>, String> {
+    let Some(layout) = layout(path)? else {
+        return Ok(None);
+    };
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    let len = file.metadata().map_err(|e| e.to_string())?.len();
+    let mut bytes = read_at(
+        &mut file,
+        layout.range.start,
+        (layout.range.end - layout.range.start) as usize,
+    )
+    .map_err(|e| e.to_string())?;
+    if layout.tail > 0 {
+        let tail = read_at(&mut file, len - layout.tail, layout.tail as usize)
+            .map_err(|e| e.to_string())?;
+        bytes.extend_from_slice(&tail);
+    }
+    Ok(Some(Region { layout, bytes }))
+}
+
+/// Child atoms of the atom spanning `range` of `buf`, as (type, range)
+fn child_atoms(buf: &[u8], range: Range<usize>) -> Vec<([u8; 4], Range<usize>)> {
+    let mut atoms = Vec::new();
+    let mut pos = range.start + 8;
+    while pos + 8 <= range.end {
+        let size = u32::from_be_bytes(buf[pos..pos + 4].try_into().unwrap()) as usize;
+        if size < 8 || pos + size > range.end {
+            break;
+        }
+        atoms.push((buf[pos + 4..pos + 8].try_into().unwrap(), pos..pos + size));
+        pos += size;
+    }
+    atoms
+}
+
+/// Move every chunk offset of a `moov` atom by `shift`, for when the media data moved
+fn shift_chunk_offsets(moov: &mut [u8], shift: i64) {
+    let find = |buf: &[u8], range: Range<usize>, kind: &[u8; 4]| {
+        child_atoms(buf, range)
+            .into_iter()
+            .filter(|(t, _)| t == kind)
+            .map(|(_, r)| r)
+            .collect::<Vec<_>>()
+    };
+    let mut tables = Vec::new();
+    for trak in find(moov, 0..moov.len(), b"trak") {
+        for mdia in find(moov, trak, b"mdia") {
+            for minf in find(moov, mdia, b"minf") {
+                for stbl in find(moov, minf, b"stbl") {
+                    tables.extend(child_atoms(moov, stbl).into_iter().filter_map(
+                        |(t, r)| match &t {
+                            b"stco" => Some((4, r)),
+                            b"co64" => Some((8, r)),
+                            _ => None,
+                        },
+                    ));
+                }
+            }
+        }
+    }
+    for (width, range) in tables {
+        if range.len() < 16 {
+            continue;
+        }
+        let count =
+            u32::from_be_bytes(moov[range.start + 12..range.start + 16].try_into().unwrap());
+        for i in 0..count as usize {
+            let pos = range.start + 16 + i * width;
+            if pos + width > range.end {
+                break;
+            }
+            if width == 4 {
+                let old = u32::from_be_bytes(moov[pos..pos + 4].try_into().unwrap());
+                let new = (old as i64 + shift) as u32;
+                moov[pos..pos + 4].copy_from_slice(&new.to_be_bytes());
+            } else {
+                let old = u64::from_be_bytes(moov[pos..pos + 8].try_into().unwrap());
+                let new = (old as i128 + shift as i128) as u64;
+                moov[pos..pos + 8].copy_from_slice(&new.to_be_bytes());
+            }
+        }
+    }
+}
+
+/// Pages per stream in a run of Ogg pages
+fn ogg_page_counts(bytes: &[u8]) -> HashMap<u32, i64> {
+    let mut counts = HashMap::new();
+    let mut r = Cursor::new(bytes);
+    while let Ok(Some(page)) = read_page(&mut r) {
+        *counts.entry(page.bitstream_serial_number).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Put a region read earlier back in place of the file's current one. The audio is kept as is,
+/// only what's around it is rewritten
+pub fn restore_region(path: &Path, original: &Region) -> Result<(), String> {
+    let current = layout(path)?.ok_or("No tags to restore in this format")?;
+    if current.kind != original.layout.kind {
+        return Err("The file is no longer in the format it was backed up in".to_string());
+    }
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    let len = file.metadata().map_err(|e| e.to_string())?.len();
+    let split = original.bytes.len() - original.layout.tail as usize;
+    let mut head = original.bytes[..split].to_vec();
+    let tail = &original.bytes[split..];
+    let body = current.range.end..len - current.tail;
+
+    if current.kind == RegionKind::Mp4 {
+        // where the media data lands once the original moov is back
+        let data_offset = current.data_offset.map(|d| {
+            if d >= current.range.end {
+                d - (current.range.end - current.range.start) + head.len() as u64
+            } else {
+                d
+            }
+        });
+        if let (Some(new), Some(old)) = (data_offset, original.layout.data_offset) {
+            if new != old {
+                shift_chunk_offsets(&mut head, new as i64 - old as i64);
+            }
+        }
+    }
+
+    // an Ogg stream with a different number of header pages needs the pages after renumbered
+    let mut renumber: HashMap<u32, i64> = HashMap::new();
+    if current.kind == RegionKind::Ogg {
+        let current_head = read_at(
+            &mut file,
+            current.range.start,
+            (current.range.end - current.range.start) as usize,
+        )
+        .map_err(|e| e.to_string())?;
+        let before = ogg_page_counts(&current_head);
+        for (serial, count) in ogg_page_counts(&head) {
+            let shift = count - before.get(&serial).copied().unwrap_or(0);
+            if shift != 0 {
+                renumber.insert(serial, shift);
+            }
+        }
+    }
+
+    let tmp = temp_path_for(path);
+    let pending = journal::begin(vec![JournalPair::new(&tmp, path)]);
+    let write = |file: &mut File| -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(&tmp)?);
+        file.seek(SeekFrom::Start(0))?;
+        io::copy(&mut (&mut *file).take(current.range.start), &mut w)?;
+        w.write_all(&head)?;
+        file.seek(SeekFrom::Start(body.start))?;
+        let mut rest = BufReader::new((&mut *file).take(body.end - body.start));
+        if renumber.is_empty() {
+            io::copy(&mut rest, &mut w)?;
+        } else {
+            while let Some(mut page) = read_page(&mut rest)? {
+                if let Some(shift) = renumber.get(&page.bitstream_serial_number) {
+                    page.page_sequence_number = (page.page_sequence_number as i64 + shift) as u32;
+                }
+                write_page(&mut w, &page)?;
+            }
+        }
+        w.write_all(tail)?;
+        w.flush()
+    };
+    write(&mut file).map_err(|e| e.to_string())?;
+    drop(file);
+    pending.staged();
+    replace_tmp(&tmp, path).map_err(|_| "Could not replace file".to_string())?;
+    pending.done();
+    Ok(())
+}
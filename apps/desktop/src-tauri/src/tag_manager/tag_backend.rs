@@ -1,16 +1,19 @@
+use super::picture::normalize_mime;
+use super::properties::read_properties;
+use super::regions::{self, RegionKind};
 use super::traits::{Formats, TagFormat};
 use super::utils;
 use super::utils::{Changes, File, FrameKey, SerializableTagValue, TagValue};
 use super::TagManager;
+use crate::backup;
 use base64::Engine;
 use serde::Serialize;
 use std::collections::HashMap;
+use std::fs;
 use std::path::PathBuf;
 use uuid::Uuid;
 pub trait TagBackend {
     fn read(&self, path: &PathBuf) -> Result<File, BackendError>;
-
-    fn write_changes(&self, changes: &Changes) -> Vec<BackendError>;
 }
 
 #[derive(Debug, Clone)]
@@ -33,6 +36,124 @@ impl DefaultBackend {
         self.manager.get_release_class(fmt)
     }
 
+    /// Write `tags` into `out`, a fresh copy of `path`, leaving `path` itself untouched
+    pub fn write_to_copy(
+        &self,
+        path: &PathBuf,
+        out: &PathBuf,
+        tags: &HashMap<FrameKey, Vec<SerializableTagValue>>,
+    ) -> Result<(), BackendError> {
+        let path_str = path.to_string_lossy().to_string();
+        let failed = |public: &str, internal: String| {
+            BackendError::WriteFailed(TagError {
+                path: path_str.clone(),
+                public_message: public.to_string(),
+                internal_message: internal,
+            })
+        };
+        let release = self
+            .resolve_release(&self.resolve_format(path))
+            .ok_or_else(|| {
+                failed(
+                    "Unsupported format",
+                    "Could not resolve tag format for writing".to_string(),
+                )
+            })?;
+        if fs::metadata(path)
+            .map(|m| m.permissions().readonly())
+            .unwrap_or(false)
+        {
+            return Err(failed("File is read-only", "Read-only file".to_string()));
+        }
+        backup::ensure(path).map_err(|e| failed("Could not back up original tags", e))?;
+        fs::copy(path, out).map_err(|e| failed("Could not copy file", e.to_string()))?;
+        release.write_tags(out, tag_values(tags))
+    }
+
+    /// Write the tags of `path` into `out` as an ID3v2 tag of version `target`, in place of the
+    /// ID3v2 tag `path` has. Frames the app doesn't know are not carried over, an ID3v1 tag at
+    /// the end stays as it is
+    pub fn convert_to_copy(
+        &self,
+        path: &PathBuf,
+        out: &PathBuf,
+        target: &Formats,
+    ) -> Result<(), BackendError> {
+        let path_str = path.to_string_lossy().to_string();
+        let failed = |public: &str, internal: String| {
+            BackendError::WriteFailed(TagError {
+                path: path_str.clone(),
+                public_message: public.to_string(),
+                internal_message: internal,
+            })
+        };
+        let version = match target {
+            Formats::Id3v23 => 3,
+            Formats::Id3v24 => 4,
+            _ => {
+                return Err(failed(
+                    "Can only convert to ID3v2.3 or ID3v2.4",
+                    format!("Conversion to {} requested", target),
+                ))
+            }
+        };
+        let layout = regions::layout(path)
+            .map_err(|e| failed("Could not read file", e))?
+            .filter(|l| l.kind == RegionKind::Id3)
+            .ok_or_else(|| {
+                failed(
+                    "Only MP3 files can be converted",
+                    "No ID3 region".to_string(),
+                )
+            })?;
+        let file = self.read(path)?;
+        let release = self.resolve_release(target).ok_or_else(|| {
+            failed(
+                "Unsupported format",
+                "Could not resolve tag format for writing".to_string(),
+            )
+        })?;
+        if fs::metadata(path)
+            .map(|m| m.permissions().readonly())
+            .unwrap_or(false)
+        {
+            return Err(failed("File is read-only", "Read-only file".to_string()));
+        }
+        backup::ensure(path).map_err(|e| failed("Could not back up original tags", e))?;
+        let data = fs::read(path).map_err(|e| failed("Could not read file", e.to_string()))?;
+        // an empty tag of the target version, for the writer to fill in
+        let mut copy = vec![b'I', b'D', b'3', version, 0, 0, 0, 0, 0, 0];
+        copy.extend_from_slice(&data[layout.range.end as usize..]);
+        fs::write(out, copy).map_err(|e| failed("Could not copy file", e.to_string()))?;
+        release.write_tags(out, file.tags)
+    }
+
+    /// The frames writing `changes` would change in each file, without writing anything
+    pub fn preview_changes(&self, changes: &Changes) -> Vec<TagPreview> {
+        let updated = tag_values(&changes.tags);
+        changes
+            .paths
+            .iter()
+            .map(|path_str| {
+                let path = PathBuf::from(path_str);
+                let preview = |diffs, error: Option<&str>| TagPreview {
+                    path: path_str.clone(),
+                    diffs,
+                    error: error.map(|e| e.to_string()),
+                };
+                let Some(release) = self.resolve_release(&self.resolve_format(&path)) else {
+                    return preview(Vec::new(), Some("Unsupported format"));
+                };
+                let existing = release.get_tags(&path).unwrap_or_default();
+                let diffs = tag_diffs(&existing, &updated);
+                let read_only = fs::metadata(&path)
+                    .map(|m| m.permissions().readonly())
+                    .unwrap_or(false);
+                preview(diffs, read_only.then_some("File is read-only"))
+            })
+            .collect()
+    }
+
     pub fn detect_all_formats(&self, path: &PathBuf, primary: &Formats) -> Vec<Formats> {
         use std::fs::File;
         use std::io::{Read, Seek, SeekFrom};
@@ -175,7 +296,6 @@ impl TagBackend for DefaultBackend {
     /// * `Err(BackendError)` - If there was an error reading the tags, returns a `BackendError` with details about the failure.
     fn read(&self, path: &PathBuf) -> Result<File, BackendError> {
         let fmt = self.resolve_format(path);
-        println!("Detected format: {:?}", fmt);
         let release = self.resolve_release(&fmt).ok_or_else(|| {
             BackendError::ReadFailed(TagError {
                 path: path.to_string_lossy().to_string(),
@@ -193,107 +313,79 @@ impl TagBackend for DefaultBackend {
             tag_format: fmt,
             tag_formats,
             freeforms,
+            properties: read_properties(path),
         })
     }
+}
 
-    /// Writes the specified tag changes to the corresponding files and returns a vector of `BackendError` instances for any failures that occur during the write process.
-    ///
-    /// # Arguments
-    ///
-    /// * `changes` - A reference to a `Changes` struct containing the tag changes to be written.
-    ///
-    /// # Returns
-    ///
-    /// * `Vec<BackendError>` - A vector of `BackendError` instances representing any errors that occurred during the write process. If the vector is empty, it indicates that all changes were successfully written.
-    fn write_changes(&self, changes: &Changes) -> Vec<BackendError> {
-        let mut results: Vec<BackendError> = Vec::new();
-        for path_str in &changes.paths {
-            let path = PathBuf::from(path_str);
-            let fmt = self.resolve_format(&path);
-            let Some(release) = self.resolve_release(&fmt) else {
-                results.push(BackendError::WriteFailed(TagError {
-                    path: path_str.clone(),
-                    public_message: "Unsupported format".to_string(),
-                    internal_message: "Could not resolve tag format for writing".to_string(),
-                }));
-                continue;
-            };
-            let mut updated: HashMap<FrameKey, Vec<TagValue>> = HashMap::new();
-            for (k, vals) in &changes.tags {
-                let mut out_vals: Vec<TagValue> = Vec::new();
-                for v in vals {
-                    match v {
-                        utils::SerializableTagValue::Text(s) => {
-                            out_vals.push(TagValue::Text(s.clone()))
-                        }
-                        utils::SerializableTagValue::Picture {
-                            mime,
-                            data_base64,
-                            picture_type,
-                            description,
-                        } => {
-                            if let Ok(data) =
-                                base64::engine::general_purpose::STANDARD.decode(data_base64)
-                            {
-                                out_vals.push(TagValue::Picture {
-                                    mime: mime.clone(),
-                                    data,
-                                    picture_type: *picture_type,
-                                    description: description.clone(),
-                                });
-                            }
-                        }
-                        utils::SerializableTagValue::UserText(item) => {
-                            out_vals.push(TagValue::UserText(item.clone()))
-                        }
-                        utils::SerializableTagValue::UserUrl(item) => {
-                            out_vals.push(TagValue::UserUrl(item.clone()))
-                        }
-                        utils::SerializableTagValue::Comment {
-                            encoding,
-                            language,
-                            description,
-                            text,
-                        } => {
-                            out_vals.push(TagValue::Comment {
-                                encoding: encoding.clone(),
-                                language: language.clone(),
-                                description: description.clone(),
-                                text: text.clone(),
-                            });
-                        }
+/// Frames whose values `updated` changes, with the values before and after
+fn tag_diffs(
+    existing: &HashMap<FrameKey, Vec<TagValue>>,
+    updated: &HashMap<FrameKey, Vec<TagValue>>,
+) -> Vec<TagDiff> {
+    let mut diffs: Vec<TagDiff> = Vec::new();
+    for (k, new_vals) in updated {
+        let old_vals = existing.get(k).cloned();
+        if old_vals.as_ref() != Some(new_vals) {
+            diffs.push(TagDiff::from_change(*k, old_vals, Some(new_vals.clone())));
+        }
+    }
+    diffs.sort_by(|a, b| a.key.cmp(&b.key));
+    diffs
+}
+
+/// Tag values to write, with picture MIME types normalised
+fn tag_values(
+    tags: &HashMap<FrameKey, Vec<SerializableTagValue>>,
+) -> HashMap<FrameKey, Vec<TagValue>> {
+    let mut updated: HashMap<FrameKey, Vec<TagValue>> = HashMap::new();
+    for (k, vals) in tags {
+        let mut out_vals: Vec<TagValue> = Vec::new();
+        for v in vals {
+            match v {
+                utils::SerializableTagValue::Text(s) => out_vals.push(TagValue::Text(s.clone())),
+                utils::SerializableTagValue::Picture {
+                    mime,
+                    data_base64,
+                    picture_type,
+                    description,
+                } => {
+                    if let Ok(data) = base64::engine::general_purpose::STANDARD.decode(data_base64)
+                    {
+                        out_vals.push(TagValue::Picture {
+                            mime: normalize_mime(mime, &data),
+                            data,
+                            picture_type: *picture_type,
+                            description: description.clone(),
+                        });
                     }
                 }
-                updated.insert(*k, out_vals);
-            }
-            let existing_tags = match release.get_tags(&path) {
-                Ok(m) => m,
-                Err(_) => HashMap::new(),
-            };
-            let mut diffs: Vec<TagDiff> = Vec::new();
-            for (k, new_vals) in &updated {
-                let old_vals = existing_tags.get(k).cloned();
-                if old_vals.as_ref() != Some(new_vals) {
-                    diffs.push(TagDiff::from_change(
-                        *k,
-                        old_vals.clone(),
-                        Some(new_vals.clone()),
-                    ));
+                // list views only, never sent back
+                utils::SerializableTagValue::PictureRef(_) => {}
+                utils::SerializableTagValue::UserText(item) => {
+                    out_vals.push(TagValue::UserText(item.clone()))
+                }
+                utils::SerializableTagValue::UserUrl(item) => {
+                    out_vals.push(TagValue::UserUrl(item.clone()))
+                }
+                utils::SerializableTagValue::Comment {
+                    encoding,
+                    language,
+                    description,
+                    text,
+                } => {
+                    out_vals.push(TagValue::Comment {
+                        encoding: encoding.clone(),
+                        language: language.clone(),
+                        description: description.clone(),
+                        text: text.clone(),
+                    });
                 }
-            }
-
-            let write_res = release.write_tags(&path, updated);
-            if write_res.is_err() {
-                results.push(BackendError::WriteFailed(TagError {
-                    path: path_str.clone(),
-                    public_message: "Failed to write tags".to_string(),
-                    internal_message: "An error occurred while writing tags".to_string(),
-                }));
-                continue;
             }
         }
-        results
+        updated.insert(*k, out_vals);
     }
+    updated
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -316,6 +408,15 @@ pub struct TagDiff {
     pub after: Option<Vec<SerializableTagValue>>,
 }
 
+/// What a write would do to one file
+#[derive(Debug, Clone, Serialize)]
+pub struct TagPreview {
+    pub path: String,
+    pub diffs: Vec<TagDiff>,
+    /// Why the write would fail
+    pub error: Option<String>,
+}
+
 impl TagDiff {
     /// Creates a `TagDiff` instance from the given `FrameKey`, optional `before` values, and optional `after` values.
     fn from_change(
@@ -1,7 +1,8 @@
+use crate::artwork::picture_hash;
 use crate::config::user::ColumnKind;
+use crate::tag_manager::properties::AudioProperties;
 use crate::tag_manager::traits::Formats;
 
-use base64::{engine::general_purpose, Engine as _};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
@@ -229,6 +230,7 @@ pub struct SerializableFile {
     pub tag_formats: Vec<String>,
     pub tags: HashMap<String, Vec<SerializableTagValue>>,
     pub freeforms: Vec<SerializableFreeform>,
+    pub properties: Option<AudioProperties>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -258,6 +260,9 @@ pub enum SerializableTagValue {
         picture_type: Option<u8>,
         description: Option<String>,
     },
+    /// A picture in a list view, without its data. Only ever sent to the frontend
+    #[serde(skip_deserializing)]
+    PictureRef(PictureRef),
     UserText(UserTextEntry),
     UserUrl(UserUrlEntry),
     Comment {
@@ -268,6 +273,18 @@ pub enum SerializableTagValue {
     },
 }
 
+/// Stands in for an embedded picture in `SerializableFile`. The thumbnail loads from
+/// `thumbnail://localhost/<hash>/<size>?path=<path>` and the data itself from `get_pictures`
+#[derive(Debug, Clone, Serialize)]
+pub struct PictureRef {
+    pub mime: String,
+    pub hash: String,
+    /// Length of the picture data in bytes
+    pub size: usize,
+    pub picture_type: Option<u8>,
+    pub description: Option<String>,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum CleanupRule {
@@ -299,6 +316,7 @@ pub struct File {
     pub tag_format: Formats,
     pub tag_formats: Vec<Formats>,
     pub freeforms: Vec<FreeformTag>,
+    pub properties: Option<AudioProperties>,
 }
 impl fmt::Display for TagValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -660,41 +678,45 @@ impl From<SerializableTagValuesWrapper> for Vec<TagValue> {
         let collected = values
             .0
             .iter()
-            .map(|value| match value {
-                SerializableTagValue::Text(s) => TagValue::Text(s.clone()),
-                SerializableTagValue::Picture {
-                    mime,
-                    data_base64,
-                    picture_type,
-                    description,
-                } => {
-                    use base64::{engine::general_purpose::STANDARD, Engine as _};
-                    let mime = mime.clone();
-
-                    let data = STANDARD.decode(data_base64);
-                    let data = data.unwrap_or_default();
-                    let picture_type = picture_type.clone();
-                    let description = description.clone();
-                    TagValue::Picture {
+            .filter_map(|value| {
+                Some(match value {
+                    SerializableTagValue::Text(s) => TagValue::Text(s.clone()),
+                    // never deserialized, so never part of a write
+                    SerializableTagValue::PictureRef(_) => return None,
+                    SerializableTagValue::Picture {
                         mime,
-                        data,
+                        data_base64,
                         picture_type,
                         description,
+                    } => {
+                        use base64::{engine::general_purpose::STANDARD, Engine as _};
+                        let mime = mime.clone();
+
+                        let data = STANDARD.decode(data_base64);
+                        let data = data.unwrap_or_default();
+                        let picture_type = picture_type.clone();
+                        let description = description.clone();
+                        TagValue::Picture {
+                            mime,
+                            data,
+                            picture_type,
+                            description,
+                        }
                     }
-                }
-                SerializableTagValue::UserText(entry) => TagValue::UserText(entry.clone()),
-                SerializableTagValue::UserUrl(entry) => TagValue::UserUrl(entry.clone()),
-                SerializableTagValue::Comment {
-                    encoding,
-                    language,
-                    description,
-                    text,
-                } => TagValue::Comment {
-                    encoding: encoding.to_owned(),
-                    language: language.to_owned(),
-                    description: description.to_owned(),
-                    text: text.to_owned(),
-                },
+                    SerializableTagValue::UserText(entry) => TagValue::UserText(entry.clone()),
+                    SerializableTagValue::UserUrl(entry) => TagValue::UserUrl(entry.clone()),
+                    SerializableTagValue::Comment {
+                        encoding,
+                        language,
+                        description,
+                        text,
+                    } => TagValue::Comment {
+                        encoding: encoding.to_owned(),
+                        language: language.to_owned(),
+                        description: description.to_owned(),
+                        text: text.to_owned(),
+                    },
+                })
             })
             .collect();
         collected
@@ -717,12 +739,13 @@ impl From<File> for SerializableFile {
                         data,
                         picture_type,
                         description,
-                    } => out_vals.push(SerializableTagValue::Picture {
+                    } => out_vals.push(SerializableTagValue::PictureRef(PictureRef {
                         mime,
-                        data_base64: general_purpose::STANDARD.encode(data),
+                        hash: picture_hash(&data),
+                        size: data.len(),
                         picture_type,
                         description,
-                    }),
+                    })),
                     TagValue::UserText(item) => out_vals.push(SerializableTagValue::UserText(item)),
                     TagValue::UserUrl(item) => out_vals.push(SerializableTagValue::UserUrl(item)),
                     TagValue::Comment {
@@ -765,6 +788,7 @@ impl From<File> for SerializableFile {
                     value: ff.value,
                 })
                 .collect(),
+            properties: file.properties,
         }
     }
 }
@@ -785,10 +809,7 @@ pub fn temp_path_for(target: &Path) -> PathBuf {
 pub fn replace_tmp(tmp: &Path, target: &Path) -> Result<(), ()> {
     #[cfg(not(windows))]
     {
-        let ez = fs::remove_file(target);
-        if ez.is_err() {
-            return Err(());
-        }
+        // rename swaps the file in atomically, the target is never missing
         let rename_result = fs::rename(tmp, target);
         if rename_result.is_err() {
             return Err(());
@@ -1,4 +1,5 @@
 // Generic utils for handling vorbis for multiple audio formats
+use crate::tag_manager::picture::picture_info;
 use crate::tag_manager::utils::{FrameKey, TagValue};
 use base64::{engine::general_purpose as b64_gp, Engine as _};
 use once_cell::sync::Lazy;
@@ -26,6 +27,15 @@ pub fn vorbis_code(key: FrameKey) -> &'static str {
         FrameKey::BeatsPerMinute => "BPM",
         FrameKey::Language => "LANGUAGE",
         FrameKey::UserDefinedURL => "URL",
+        FrameKey::ReplayGainTrackGain => "REPLAYGAIN_TRACK_GAIN",
+        FrameKey::ReplayGainTrackPeak => "REPLAYGAIN_TRACK_PEAK",
+        FrameKey::ReplayGainTrackRange => "REPLAYGAIN_TRACK_RANGE",
+        FrameKey::ReplayGainAlbumGain => "REPLAYGAIN_ALBUM_GAIN",
+        FrameKey::ReplayGainAlbumPeak => "REPLAYGAIN_ALBUM_PEAK",
+        FrameKey::ReplayGainAlbumRange => "REPLAYGAIN_ALBUM_RANGE",
+        FrameKey::ReplayGainReferenceLoudness => "REPLAYGAIN_REFERENCE_LOUDNESS",
+        FrameKey::AcoustidId => "ACOUSTID_ID",
+        FrameKey::AcoustidFingerprint => "ACOUSTID_FINGERPRINT",
 
         _ => "COMMENT",
     }
@@ -81,6 +91,15 @@ pub fn raw_to_tags(raw: &HashMap<String, Vec<TagValue>>) -> HashMap<FrameKey, Ve
                     "LABEL" => Some(FrameKey::Label),
                     "ISRC" => Some(FrameKey::Isrc),
                     "METADATA_BLOCK_PICTURE" => Some(FrameKey::AttachedPicture),
+                    "REPLAYGAIN_TRACK_GAIN" => Some(FrameKey::ReplayGainTrackGain),
+                    "REPLAYGAIN_TRACK_PEAK" => Some(FrameKey::ReplayGainTrackPeak),
+                    "REPLAYGAIN_TRACK_RANGE" => Some(FrameKey::ReplayGainTrackRange),
+                    "REPLAYGAIN_ALBUM_GAIN" => Some(FrameKey::ReplayGainAlbumGain),
+                    "REPLAYGAIN_ALBUM_PEAK" => Some(FrameKey::ReplayGainAlbumPeak),
+                    "REPLAYGAIN_ALBUM_RANGE" => Some(FrameKey::ReplayGainAlbumRange),
+                    "REPLAYGAIN_REFERENCE_LOUDNESS" => Some(FrameKey::ReplayGainReferenceLoudness),
+                    "ACOUSTID_ID" => Some(FrameKey::AcoustidId),
+                    "ACOUSTID_FINGERPRINT" => Some(FrameKey::AcoustidFingerprint),
 
                     _ => None,
                 });
@@ -341,10 +360,15 @@ pub fn build_picture_comment(tag: &TagValue) -> Option<Vec<u8>> {
         out.extend(&(desc_bytes.len() as u32).to_be_bytes());
         out.extend(desc_bytes);
 
-        out.extend(&0u32.to_be_bytes());
-        out.extend(&0u32.to_be_bytes());
-        out.extend(&0u32.to_be_bytes());
-        out.extend(&0u32.to_be_bytes());
+        let info = picture_info(data);
+        for field in [
+            info.map(|i| i.width),
+            info.map(|i| i.height),
+            info.map(|i| i.color_depth),
+            info.map(|i| i.colors_used),
+        ] {
+            out.extend(&field.unwrap_or(0).to_be_bytes());
+        }
 
         out.extend(&(data.len() as u32).to_be_bytes());
         out.extend(data);
@@ -378,10 +402,15 @@ pub fn build_picture_tag(tags: &HashMap<FrameKey, Vec<TagValue>>) -> Vec<Vec<u8>
                 out.extend(&(desc_bytes.len() as u32).to_be_bytes());
                 out.extend(desc_bytes);
 
-                out.extend(&0u32.to_be_bytes());
-                out.extend(&0u32.to_be_bytes());
-                out.extend(&0u32.to_be_bytes());
-                out.extend(&0u32.to_be_bytes());
+                let info = picture_info(data);
+                for field in [
+                    info.map(|i| i.width),
+                    info.map(|i| i.height),
+                    info.map(|i| i.color_depth),
+                    info.map(|i| i.colors_used),
+                ] {
+                    out.extend(&field.unwrap_or(0).to_be_bytes());
+                }
 
                 out.extend(&(data.len() as u32).to_be_bytes());
                 out.extend(data);
@@ -1,20 +1,25 @@
+use crate::artwork::picture_hash;
+use crate::backup;
 use crate::commands::import_paths::import_paths;
 use crate::config::user::ViewMode;
 use crate::database::Database;
+use crate::history::{Action, HistoryActionType};
+use crate::library::indexer::{read_and_store, start_import};
 use std::path::MAIN_SEPARATOR_STR;
 // use crate::tag_manager::utils::SerializableFile;
-use crate::tag_manager::tag_backend::{BackendError, DefaultBackend, TagBackend};
+use crate::tag_manager::properties::{read_properties, AudioProperties};
+use crate::tag_manager::tag_backend::{BackendError, DefaultBackend};
 use crate::tag_manager::utils::{
     File, FrameKey, SerializableFile, TagValue, UserTextEntry, UserUrlEntry,
 };
 
 use crate::{AppState, FileNode};
 use serde::Serialize;
-use sqlx::SqlitePool;
-use std::collections::HashMap;
+use sqlx::{Row, SqliteConnection, SqlitePool};
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
 use std::fs::{self, metadata};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tauri::{async_runtime, Emitter, Manager, State};
@@ -104,8 +109,9 @@ pub fn handle_import_files(
 ) {
     let mut errs: Vec<BackendError> = vec![];
     if state.view_mode == ViewMode::Simple {
-        {
+        let added: Vec<String> = {
             let mut ws = state.workspace.lock().unwrap();
+            let before: HashSet<PathBuf> = ws.files.iter().map(|f| f.path.clone()).collect();
             for path in paths {
                 let r = ws.import(PathBuf::from(path));
                 if let Err(e) = r {
@@ -113,6 +119,19 @@ pub fn handle_import_files(
                     errs.push(e);
                 }
             }
+            ws.files
+                .iter()
+                .filter(|f| !before.contains(&f.path))
+                .map(|f| f.path.to_string_lossy().to_string())
+                .collect()
+        };
+        if !added.is_empty() {
+            state.history.lock().unwrap().add(Action {
+                action_type: HistoryActionType::Workspace {
+                    added,
+                    removed: Vec::new(),
+                },
+            });
         }
 
         let serializable_files: Vec<SerializableFile> = {
@@ -179,6 +198,10 @@ pub fn handle_import_files(
 
             let (new_files, _existing_files) =
                 check_files_against_db(&db.pool, scanned_files).await;
+            let to_index: Vec<String> = new_files
+                .iter()
+                .map(|p| p.to_string_lossy().to_string())
+                .collect();
             if let Err(e) = insert_pending_files(&db.pool, new_files).await {
                 println!("insert_pending_files failed: {e}");
             }
@@ -205,6 +228,10 @@ pub fn handle_import_files(
             }
 
             let _ = app_handle.emit("workspace-roots", result);
+
+            if !to_index.is_empty() {
+                start_import(&app_handle, to_index);
+            }
         });
 
         if let Ok(mut watcher) = state.file_watcher.lock() {
@@ -248,7 +275,7 @@ fn read_folder(path: PathBuf) -> Vec<PathBuf> {
 fn unix_to_systemtime(timestamp: i64) -> SystemTime {
     UNIX_EPOCH + Duration::from_secs(timestamp as u64)
 }
-fn systemtime_to_unix(time: SystemTime) -> i64 {
+pub fn systemtime_to_unix(time: SystemTime) -> i64 {
     time.duration_since(UNIX_EPOCH)
         .unwrap_or(Duration::from_secs(0))
         .as_secs() as i64
@@ -262,14 +289,32 @@ pub fn delete_file_path(app_handle: tauri::AppHandle, file_path: PathBuf) {
 
     let _ = async_runtime::block_on(async {
         let _ = sqlx::query("DELETE FROM files WHERE path = ?1")
-            .bind(file_path_str)
+            .bind(&file_path_str)
             .execute(&db.pool)
             .await;
     });
 }
 
-/// Update file path in db when file is renamed or moved
+/// Update file path in db when file is renamed or moved outside the app. Its backup and its
+/// actions in the undo history follow it
 pub fn update_file_path(app_handle: tauri::AppHandle, old_path: PathBuf, new_path: PathBuf) {
+    if !move_indexed_file(&app_handle, &old_path, &new_path) {
+        return;
+    }
+    let old_path = old_path.to_string_lossy().to_string();
+    let new_path = new_path.to_string_lossy().to_string();
+    backup::follow_renames(&[(old_path.clone(), new_path.clone())]);
+    let state = app_handle.state::<AppState>();
+    state
+        .history
+        .lock()
+        .unwrap()
+        .follow_rename(&old_path, &new_path);
+}
+
+/// Move the rows of a file in the index to its new path. False when the new path was indexed
+/// already, the old rows are dropped then
+pub fn move_indexed_file(app_handle: &tauri::AppHandle, old_path: &Path, new_path: &Path) -> bool {
     let state = app_handle.state::<AppState>();
 
     let db = state.db.clone();
@@ -277,7 +322,7 @@ pub fn update_file_path(app_handle: tauri::AppHandle, old_path: PathBuf, new_pat
     let new_path_str = new_path.to_string_lossy().to_string();
     let old_path_str = old_path.to_string_lossy().to_string();
 
-    let _ = async_runtime::block_on(async {
+    async_runtime::block_on(async {
         let exists = sqlx::query_scalar::<_, i64>("SELECT 1 FROM files WHERE path = ?1")
             .bind(&new_path_str)
             .fetch_optional(&db.pool)
@@ -291,6 +336,7 @@ pub fn update_file_path(app_handle: tauri::AppHandle, old_path: PathBuf, new_pat
                 .bind(&old_path_str)
                 .execute(&db.pool)
                 .await;
+            false
         } else {
             let new_file_name = new_path
                 .file_name()
@@ -298,18 +344,83 @@ pub fn update_file_path(app_handle: tauri::AppHandle, old_path: PathBuf, new_pat
                 .to_string_lossy()
                 .to_string();
 
-            let _ = sqlx::query("UPDATE files SET path = ?2, file_name = ?3 WHERE path = ?1")
-                .bind(&old_path_str)
-                .bind(&new_path_str)
-                .bind(new_file_name)
-                .execute(&db.pool)
-                .await;
+            match move_file_rows(&db.pool, &old_path_str, &new_path_str, &new_file_name).await {
+                Ok(()) => true,
+                Err(e) => {
+                    eprintln!("failed to move {} in the index: {e}", old_path_str);
+                    false
+                }
+            }
         }
-    });
+    })
+}
+
+/// Rename a file row together with the rows that belong to it. Foreign keys are checked at
+/// commit, once parent and children agree again
+async fn move_file_rows(
+    pool: &SqlitePool,
+    old_path: &str,
+    new_path: &str,
+    new_file_name: &str,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("PRAGMA defer_foreign_keys = ON")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE files SET path = ?2, file_name = ?3 WHERE path = ?1")
+        .bind(old_path)
+        .bind(new_path)
+        .bind(new_file_name)
+        .execute(&mut *tx)
+        .await?;
+    for table in [
+        "tag_text",
+        "tag_pictures",
+        "tag_user_text",
+        "tag_user_url",
+        "tag_comment",
+        "freeform_tags",
+        "saved_query_results",
+        "fingerprints",
+        "audio_hashes",
+    ] {
+        sqlx::query(&format!(
+            "UPDATE {} SET file_path = ?2 WHERE file_path = ?1",
+            table
+        ))
+        .bind(old_path)
+        .bind(new_path)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+/// Re-read tags of files changed by a background job and notify the frontend
+pub fn refresh_files(app_handle: tauri::AppHandle, paths: Vec<String>) {
+    let state = app_handle.state::<AppState>();
+
+    if state.view_mode == ViewMode::Simple {
+        let serializable_files: Vec<SerializableFile> = {
+            let mut ws = state.workspace.lock().unwrap();
+            for p in &paths {
+                ws.refresh_tags(&PathBuf::from(p));
+            }
+            ws.files
+                .clone()
+                .into_iter()
+                .map(SerializableFile::from)
+                .collect()
+        };
+        let _ = app_handle.emit("workspace-updated", serializable_files);
+    } else {
+        let db = state.db.clone();
+        let _ = async_runtime::block_on(async { get_tags(&db, paths, true).await });
+    }
 }
 
 /// Insert new files into the database with pending status. If file already exists, update its metadata.
-async fn insert_pending_files(
+pub async fn insert_pending_files(
     pool: &SqlitePool,
     new_files: Vec<PathBuf>,
 ) -> Result<(), sqlx::Error> {
@@ -326,8 +437,8 @@ async fn insert_pending_files(
             .and_then(|m| m.modified().ok());
 
         let res = sqlx::query(
-            "INSERT INTO files (path, file_name, file_size, last_modified, status) \
-             VALUES (?1, ?2, ?3, ?4, 'pending') \
+            "INSERT INTO files (path, file_name, file_size, last_modified, status, added_at) \
+             VALUES (?1, ?2, ?3, ?4, 'pending', strftime('%s', 'now')) \
              ON CONFLICT(path)\
               DO UPDATE SET file_name=?2, file_size=?3",
         )
@@ -502,138 +613,137 @@ async fn remove_child_roots(pool: &SqlitePool, children: &[String]) -> Result<()
     tx.commit().await?;
     Ok(())
 }
-/// useless for now
-async fn update_root_scan_time(pool: &SqlitePool, path: &str) -> Result<(), sqlx::Error> {
+/// Record that a root was just walked
+pub async fn update_root_scan_time(pool: &SqlitePool, path: &str) -> Result<(), sqlx::Error> {
     sqlx::query("UPDATE import_roots SET last_scanned = strftime('%s', 'now') WHERE path = ?1")
         .bind(path)
         .execute(pool)
         .await?;
     Ok(())
 }
+/// Index status and modification time of the given paths that are in the database
+async fn index_state(
+    pool: &SqlitePool,
+    file_paths: &[String],
+) -> std::result::Result<HashMap<String, (bool, Option<i64>)>, String> {
+    let mut state = HashMap::new();
+    for chunk in file_paths.chunks(500) {
+        let sql = format!(
+            "SELECT path, metadata_status = 'indexed' AS indexed, last_modified FROM files WHERE path IN ({})",
+            vec!["?"; chunk.len()].join(", ")
+        );
+        let mut query = sqlx::query(&sql);
+        for path in chunk {
+            query = query.bind(path);
+        }
+        for row in query.fetch_all(pool).await.map_err(|e| e.to_string())? {
+            state.insert(
+                row.get::<String, _>("path"),
+                (
+                    row.get::<Option<bool>, _>("indexed").unwrap_or(false),
+                    row.get::<Option<i64>, _>("last_modified"),
+                ),
+            );
+        }
+    }
+    Ok(state)
+}
+
 /// Get tags from database if exists.
-/// If does not exist read file metadata from disk
+/// If does not exist read file metadata from disk. Files that need reading are parsed in
+/// parallel and stored in batches
 pub async fn get_tags(
     db: &Database,
     file_paths: Vec<String>,
     force: bool,
 ) -> std::result::Result<Vec<File>, String> {
     let tag_backend = DefaultBackend::new();
-    let mut files: Vec<File> = vec![];
-    for file_path in file_paths {
-        let file_exists_in_db = sqlx::query_scalar::<_, i64>("SELECT 1 FROM files WHERE path = ?1")
-            .bind(&file_path)
-            .fetch_optional(&db.pool)
-            .await
-            .map_err(|e| e.to_string())?
-            .is_some();
+    let indexed = index_state(&db.pool, &file_paths).await?;
 
-        if !file_exists_in_db {
-            let real_path = PathBuf::from(&file_path);
-            if real_path.exists() == false {
-                continue;
+    let unknown: Vec<PathBuf> = file_paths
+        .iter()
+        .filter(|p| !indexed.contains_key(*p))
+        .map(PathBuf::from)
+        .filter(|p| p.exists())
+        .collect();
+    if !unknown.is_empty() {
+        if let Err(e) = insert_pending_files(&db.pool, unknown).await {
+            println!("Err inserting: {e}");
+        }
+    }
+
+    let stale: Vec<String> = file_paths
+        .iter()
+        .filter(|file_path| match indexed.get(*file_path) {
+            None => Path::new(file_path).exists(),
+            Some((is_indexed, db_modified)) => {
+                let disk_modified = metadata(file_path).ok().and_then(|m| m.modified().ok());
+                let needs_update = match (db_modified, disk_modified) {
+                    (Some(db_mod), Some(disk_mod)) => *db_mod < systemtime_to_unix(disk_mod),
+                    _ => true,
+                };
+                force || !is_indexed || needs_update
             }
+        })
+        .cloned()
+        .collect();
+    let mut read: HashMap<String, std::result::Result<File, String>> = if stale.is_empty() {
+        HashMap::new()
+    } else {
+        read_and_store(&db.pool, stale).await?.into_iter().collect()
+    };
 
-            if let Err(e) = insert_pending_files(&db.pool, vec![real_path]).await {
-                println!("Err inserting: {e}");
+    let mut files: Vec<File> = vec![];
+    for file_path in file_paths {
+        if let Some(result) = read.remove(&file_path) {
+            match result {
+                Ok(mut file) => {
+                    file.freeforms = Vec::new();
+                    files.push(file);
+                }
+                Err(e) => println!("Error updating metadata for file {}: {e}", file_path),
             }
+            continue;
         }
-
-        let mut is_indexed = sqlx::query_scalar::<_, i64>(
-            "SELECT 1 FROM files WHERE path = ?1 AND metadata_status = 'indexed'",
-        )
-        .bind(file_path.as_str())
-        .fetch_optional(&db.pool)
-        .await
-        .map_err(|e| e.to_string())?
-        .is_some();
-
-        if force == true {
-            is_indexed = false
+        if !indexed.contains_key(&file_path) {
+            continue;
         }
 
-        let db_modified: Option<SystemTime> =
-            sqlx::query_scalar::<_, i64>("SELECT last_modified FROM files WHERE path = ?1")
-                .bind(file_path.as_str())
-                .fetch_optional(&db.pool)
-                .await
-                .map_err(|e| e.to_string())?
-                .map(unix_to_systemtime);
-
-        let disk_modified = metadata(&file_path).ok().and_then(|m| m.modified().ok());
-        let needs_update = if let (Some(db_mod), Some(disk_mod)) = (db_modified, disk_modified) {
-            let db_mod_unix = db_mod
-                .duration_since(UNIX_EPOCH)
-                .unwrap_or(Duration::from_secs(0))
-                .as_secs();
-            let disk_mod_unix = disk_mod
-                .duration_since(UNIX_EPOCH)
-                .unwrap_or(Duration::from_secs(1))
-                .as_secs();
-            db_mod_unix < disk_mod_unix
-        } else {
-            true
+        let properties = match detected_properties_in_db(&db.pool, &file_path).await {
+            Some(stored) => stored,
+            // indexed before audio properties were stored
+            None => store_properties_in_db(&db.pool, &file_path).await,
         };
-
-        if !is_indexed || needs_update || !file_exists_in_db {
-            let tags = match update_metadata_in_db(&db.pool, &file_path, &tag_backend).await {
-                Ok(t) => t,
-                Err(e) => {
-                    println!("Error updating metadata for file {}: {e}", file_path);
-                    continue;
-                }
-            };
-            let tag_format = tag_backend.resolve_format(&PathBuf::from(&file_path));
-            let tag_formats =
-                tag_backend.detect_all_formats(&PathBuf::from(&file_path), &tag_format);
-
-            let file = File {
-                path: PathBuf::from(&file_path),
-                tags,
-                freeforms: Vec::new(),
-                tag_format,
-                tag_formats,
-                id: uuid::Uuid::new_v4(),
-            };
-            files.push(file);
-            println!("Updated tags for file: {}", file_path);
-        } else {
-            println!("File already indexed: {}", file_path);
-
-            let tags = match detected_tags_in_db(&db.pool, &file_path).await {
-                Ok(t) => t,
-                Err(e) => {
-                    println!("Error retrieving metadata for file {}: {e}", file_path);
-                    continue;
-                }
-            };
-            let tag_format = tag_backend.resolve_format(&PathBuf::from(&file_path));
-            let tag_formats =
-                tag_backend.detect_all_formats(&PathBuf::from(&file_path), &tag_format);
-
-            let file = File {
-                path: PathBuf::from(&file_path),
-                tags,
-                freeforms: Vec::new(),
-                tag_format,
-                tag_formats,
-                id: uuid::Uuid::new_v4(),
-            };
-            files.push(file);
-        }
+        let tags = match detected_tags_in_db(&db.pool, &file_path).await {
+            Ok(t) => t,
+            Err(e) => {
+                println!("Error retrieving metadata for file {}: {e}", file_path);
+                continue;
+            }
+        };
+        let tag_format = tag_backend.resolve_format(&PathBuf::from(&file_path));
+        let tag_formats = tag_backend.detect_all_formats(&PathBuf::from(&file_path), &tag_format);
+
+        let file = File {
+            path: PathBuf::from(&file_path),
+            tags,
+            freeforms: Vec::new(),
+            tag_format,
+            tag_formats,
+            id: uuid::Uuid::new_v4(),
+            properties,
+        };
+        files.push(file);
     }
     Ok(files)
 }
-/// Update metadata in database
-async fn update_metadata_in_db(
-    pool: &SqlitePool,
-    file_path: &str,
-    tag_backend: &DefaultBackend,
-) -> std::result::Result<HashMap<FrameKey, Vec<TagValue>>, String> {
-    let f = tag_backend
-        .read(&PathBuf::from(file_path))
-        .map_err(|e| format!("{e:?}"))?;
-
-    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+/// Write what was read from a file into the index, on the caller's connection or transaction
+pub async fn store_metadata(
+    tx: &mut SqliteConnection,
+    f: &File,
+) -> std::result::Result<(), String> {
+    let path = f.path.to_string_lossy().to_string();
+    let file_path = path.as_str();
 
     sqlx::query("DELETE FROM tag_text WHERE file_path = ?1")
         .bind(file_path)
@@ -661,8 +771,7 @@ async fn update_metadata_in_db(
         .await
         .map_err(|e| e.to_string())?;
 
-    let tags = f.tags;
-    for (frame_key, tag_values) in &tags {
+    for (frame_key, tag_values) in &f.tags {
         for tag_value in tag_values {
             match tag_value {
                 TagValue::Text(text) => {
@@ -684,7 +793,7 @@ async fn update_metadata_in_db(
                     description,
                 } => {
                     sqlx::query(
-                        "INSERT INTO tag_pictures (file_path, key, mime_type, data, picture_type, description) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                        "INSERT INTO tag_pictures (file_path, key, mime_type, data, picture_type, description, hash) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
                     )
                     .bind(file_path)
                     .bind(frame_key.to_string())
@@ -692,6 +801,7 @@ async fn update_metadata_in_db(
                     .bind(data)
                     .bind(picture_type.unwrap_or(3) as i64)
                     .bind(description.clone().unwrap_or_default())
+                    .bind(picture_hash(data))
                     .execute(&mut *tx)
                     .await
                     .map_err(|e| e.to_string())?;
@@ -743,6 +853,10 @@ async fn update_metadata_in_db(
         }
     }
 
+    write_properties(&mut *tx, file_path, f.properties.as_ref())
+        .await
+        .map_err(|e| e.to_string())?;
+
     sqlx::query("UPDATE files SET metadata_status = 'indexed' WHERE path = ?1")
         .bind(file_path)
         .execute(&mut *tx)
@@ -753,10 +867,87 @@ async fn update_metadata_in_db(
         .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
+    // kept for searching by tag format
+    sqlx::query("UPDATE files SET tag_format = ?2, tag_formats = ?3 WHERE path = ?1")
+        .bind(file_path)
+        .bind(f.tag_format.to_string())
+        .bind(
+            f.tag_formats
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
 
-    tx.commit().await.map_err(|e| e.to_string())?;
+    Ok(())
+}
 
-    Ok(tags)
+/// Codec stored for files whose audio properties couldn't be read, so they aren't read again
+/// every time the file is loaded
+const UNREADABLE_CODEC: &str = "";
+
+/// Store the audio properties of a file, or mark them unreadable when there are none
+async fn write_properties(
+    conn: &mut SqliteConnection,
+    file_path: &str,
+    props: Option<&AudioProperties>,
+) -> std::result::Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE files SET duration_ms = ?2, bitrate = ?3, sample_rate = ?4, channels = ?5, bits_per_sample = ?6, codec = ?7 WHERE path = ?1",
+    )
+    .bind(file_path)
+    .bind(props.map(|p| p.duration_ms as i64))
+    .bind(props.map(|p| p.bitrate as i64))
+    .bind(props.map(|p| p.sample_rate as i64))
+    .bind(props.map(|p| p.channels as i64))
+    .bind(props.and_then(|p| p.bits_per_sample.map(|b| b as i64)))
+    .bind(props.map(|p| p.codec.as_str()).unwrap_or(UNREADABLE_CODEC))
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Read audio properties from disk and store them for an already indexed file
+async fn store_properties_in_db(pool: &SqlitePool, file_path: &str) -> Option<AudioProperties> {
+    let props = read_properties(Path::new(file_path));
+    if let Ok(mut conn) = pool.acquire().await {
+        let _ = write_properties(&mut conn, file_path, props.as_ref()).await;
+    }
+    props
+}
+
+/// Get audio properties from Database. None when they were never stored, Some(None) when the
+/// file's properties couldn't be read
+async fn detected_properties_in_db(
+    pool: &SqlitePool,
+    file_path: &str,
+) -> Option<Option<AudioProperties>> {
+    let row: Option<(Option<i64>, Option<i64>, Option<i64>, Option<i64>, Option<i64>, Option<String>)> =
+        sqlx::query_as(
+            "SELECT duration_ms, bitrate, sample_rate, channels, bits_per_sample, codec FROM files WHERE path = ?1",
+        )
+        .bind(file_path)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten();
+
+    let (duration_ms, bitrate, sample_rate, channels, bits_per_sample, codec) = row?;
+    let codec = codec?;
+    let Some(duration_ms) = duration_ms else {
+        return (codec == UNREADABLE_CODEC).then_some(None);
+    };
+    Some(Some(AudioProperties {
+        duration_ms: duration_ms as u64,
+        bitrate: bitrate.unwrap_or(0) as u32,
+        sample_rate: sample_rate.unwrap_or(0) as u32,
+        channels: channels.unwrap_or(0) as u16,
+        bits_per_sample: bits_per_sample.map(|b| b as u16),
+        codec,
+    }))
 }
 
 /// Get Tags from Database
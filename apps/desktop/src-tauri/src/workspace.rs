@@ -1,10 +1,13 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use tauri::AppHandle;
 
+use crate::history::moves::same_file;
 use crate::tag_manager;
+use crate::tag_manager::batch::write_batch;
 use crate::tag_manager::tag_backend::{BackendError, DefaultBackend, TagBackend, TagError};
 use crate::tag_manager::utils::{CleanupRule, File, FrameKey, SerializableTagValue, TagValue};
 use base64::Engine;
+use serde::Serialize;
 use std::fs;
 use std::fs::metadata;
 use std::path::{Path, PathBuf};
@@ -79,7 +82,7 @@ impl Workspace {
                         })
                         .collect(),
                 };
-                let _res = self.backend.write_changes(&changes);
+                let _report = write_batch(&self.backend, std::slice::from_ref(&changes));
                 match self.backend.read(&file.path) {
                     Ok(refreshed) => {
                         file.tags = refreshed.tags;
@@ -214,307 +217,390 @@ impl Workspace {
         file_paths: Vec<String>,
         options: Vec<CleanupRule>,
     ) -> Vec<(String, String, Result<(), String>)> {
-        let mut results = Vec::new();
+        let plan = self.plan_clean_up_file_names(&file_paths, &options);
+        self.apply_renames(plan)
+    }
 
+    /// The renames `clean_up_file_names` would do, without touching any file
+    pub fn plan_clean_up_file_names(
+        &self,
+        file_paths: &[String],
+        options: &[CleanupRule],
+    ) -> Vec<PlannedRename> {
+        let mut claims = NameClaims::default();
+        let mut plan = Vec::new();
         for path_str in file_paths {
-            let path = PathBuf::from(&path_str);
+            let path = PathBuf::from(path_str);
+            let Some(file) = self.files.iter().find(|x| x.path == path) else {
+                continue;
+            };
+            let stem = file
+                .path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("untitled");
+            let mut new_name = clean_up_name(stem, options);
+            let ext = file.path.extension().and_then(|e| e.to_str()).unwrap_or("");
+            if !ext.is_empty() {
+                new_name = format!("{}.{}", new_name, ext);
+            }
+            let parent = file.path.parent().unwrap_or(Path::new("."));
+            let target = parent.join(&new_name);
+            if target == file.path {
+                continue;
+            }
+            let collision = claims
+                .taken(&file.path, &target)
+                .then(|| target.display().to_string());
+            if collision.is_none() {
+                claims.claim(&file.path, &target);
+            }
+            plan.push(PlannedRename {
+                old: path_str.clone(),
+                new: target.display().to_string(),
+                collision,
+                error: None,
+            });
+        }
+        plan
+    }
+
+    /// Rename files based on a pattern. The pattern can include placeholders like
+    pub fn rename_by_pattern(
+        &mut self,
+        file_paths: Vec<String>,
+        pattern: &str,
+    ) -> Vec<(String, String, Result<(), String>)> {
+        let plan = self.plan_rename_by_pattern(&file_paths, pattern);
+        self.apply_renames(plan)
+    }
 
-            if let Some(file) = self.files.iter_mut().find(|x| x.path == path) {
-                let mut new_name = file
+    /// The renames `rename_by_pattern` would do, without touching any file. A name already
+    /// taken gets a number, the collision is still reported
+    pub fn plan_rename_by_pattern(
+        &self,
+        file_paths: &[String],
+        pattern: &str,
+    ) -> Vec<PlannedRename> {
+        let mut claims = NameClaims::default();
+        let mut plan = Vec::new();
+        for path_str in file_paths {
+            let old_path = PathBuf::from(path_str);
+            let Some(file) = self.files.iter().find(|x| x.path == old_path) else {
+                plan.push(PlannedRename {
+                    old: path_str.clone(),
+                    new: path_str.clone(),
+                    collision: None,
+                    error: Some("File not found in workspace".to_string()),
+                });
+                continue;
+            };
+            let mut base_name = apply_pattern(file, pattern);
+            if base_name.is_empty() {
+                base_name = file
                     .path
                     .file_stem()
                     .and_then(|s| s.to_str())
                     .unwrap_or("untitled")
                     .to_string();
-                for rule in &options {
-                    match rule {
-                        CleanupRule::ReplaceUnderscores => {
-                            new_name = new_name.replace('_', " ");
-                        }
-                        CleanupRule::NormalizeDashes => {
-                            new_name = new_name
-                                .chars()
-                                .map(|c| if c == '–' || c == '—' { '-' } else { c })
-                                .collect();
-                        }
-                        CleanupRule::NormalizeFeat => {
-                            let patterns = vec![
-                                " feat. ",
-                                " ft. ",
-                                " featuring ",
-                                "Feat. ",
-                                "Ft. ",
-                                "Featuring ",
-                                " FEAT. ",
-                                " FT. ",
-                                " FEATURING ",
-                            ];
-                            for p in patterns {
-                                if new_name.contains(p) {
-                                    new_name = new_name.replace(p, " feat. ");
-                                }
-                            }
-                        }
-                        CleanupRule::RemoveSuffixes => {
-                            let suffixes = vec![
-                                "(Official Video)",
-                                "[Official Video]",
-                                "(Official Music Video)",
-                                "[Official Music Video]",
-                                "(Lyric Video)",
-                                "[Lyric Video]",
-                                "(Audio)",
-                                "[Audio]",
-                            ];
-                            for suffix in suffixes {
-                                if new_name.ends_with(suffix) {
-                                    new_name = new_name.trim_end_matches(suffix).trim().to_string();
-                                }
-                            }
-                        }
-
-                        CleanupRule::RemoveBrackets => {
-                            let patterns = vec![('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')];
-                            for (open, close) in patterns {
-                                loop {
-                                    if let Some(start) = new_name.find(open) {
-                                        if let Some(end) = new_name[start..].find(close) {
-                                            let end = start + end;
-                                            new_name.replace_range(start..=end, "");
-                                            new_name = new_name.trim().to_string();
-                                        } else {
-                                            break;
-                                        }
-                                    } else {
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-                        CleanupRule::FixCapitalization => {
-                            fn capitalize_word(word: &str) -> String {
-                                let mut chars = word.chars();
-                                match chars.next() {
-                                    Some(first) => {
-                                        first.to_uppercase().collect::<String>() + chars.as_str()
-                                    }
-                                    None => String::new(),
-                                }
-                            }
-                            const LOWERS: &[&str] = &[
-                                "the", "a", "an", "and", "or", "but", "for", "nor", "as", "at",
-                                "by", "in", "on", "of", "per", "to", "vs",
-                            ];
-
-                            let words: Vec<&str> = new_name.split_whitespace().collect();
-
-                            let mut result: Vec<String> = Vec::with_capacity(words.len());
-
-                            for (i, word) in words.iter().enumerate() {
-                                let w = word.to_lowercase();
-
-                                if i == 0 {
-                                    result.push(capitalize_word(&w));
-                                } else if LOWERS.contains(&w.as_str()) {
-                                    result.push(w);
-                                } else {
-                                    result.push(capitalize_word(&w));
-                                }
-                            }
-
-                            new_name = result.join(" ");
-                        }
-
-                        CleanupRule::CollapseSpaces => {
-                            let mut collapsed = String::new();
-                            let mut prev_space = false;
-                            for c in new_name.chars() {
-                                if c.is_whitespace() {
-                                    if !prev_space {
-                                        collapsed.push(' ');
-                                        prev_space = true;
-                                    }
-                                } else {
-                                    collapsed.push(c);
-                                    prev_space = false;
-                                }
-                            }
-                            new_name = collapsed;
-                        }
-                        CleanupRule::TrimWhitespace => {
-                            new_name = new_name.trim().to_string();
-                        }
-                    }
-                }
-
-                let ext = file.path.extension().and_then(|e| e.to_str()).unwrap_or("");
-                if !ext.is_empty() {
-                    new_name = format!("{}.{}", new_name, ext);
+            }
+            let ext = file.path.extension().and_then(|e| e.to_str()).unwrap_or("");
+            if !ext.is_empty() {
+                let lower = base_name.to_lowercase();
+                let want = format!(".{}", ext.to_lowercase());
+                if !lower.ends_with(&want) {
+                    base_name = format!("{}.{}", base_name, ext);
                 }
-                let parent = file.path.parent().unwrap_or(Path::new("."));
-                let target = parent.join(&new_name);
-                if target != file.path {
-                    match fs::rename(&file.path, &target) {
-                        Ok(_) => {
-                            file.path = target.clone();
-                            results.push((path_str.clone(), target.display().to_string(), Ok(())));
-                        }
-                        Err(e) => {
-                            results.push((
-                                path_str.clone(),
-                                target.display().to_string(),
-                                Err(e.to_string()),
-                            ));
+            }
+            let parent = file.path.parent().unwrap_or(Path::new("."));
+            let mut target = parent.join(&base_name);
+            let mut collision = None;
+            if target != file.path && claims.taken(&file.path, &target) {
+                collision = Some(target.display().to_string());
+                let stem = target
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or("file")
+                    .to_string();
+                let ext2 = target
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .map(|e| e.to_string());
+                let mut idx = 1;
+                loop {
+                    let candidate = match &ext2 {
+                        Some(e) if !e.is_empty() => {
+                            parent.join(format!("{} ({}).{}", stem, idx, e))
                         }
+                        _ => parent.join(format!("{} ({})", stem, idx)),
+                    };
+                    if !claims.taken(&file.path, &candidate) {
+                        target = candidate;
+                        break;
+                    }
+                    idx += 1;
+                    if idx > 9999 {
+                        break;
                     }
                 }
             }
+            claims.claim(&file.path, &target);
+            plan.push(PlannedRename {
+                old: path_str.clone(),
+                new: target.display().to_string(),
+                collision,
+                error: None,
+            });
         }
-        results
+        plan
     }
 
-    /// Rename files based on a pattern. The pattern can include placeholders like
-    pub fn rename_by_pattern(
+    /// Carry out planned renames in order. Unresolved collisions are refused rather than
+    /// replacing the file in the way
+    fn apply_renames(
         &mut self,
-        file_paths: Vec<String>,
-        pattern: &str,
+        plan: Vec<PlannedRename>,
     ) -> Vec<(String, String, Result<(), String>)> {
-        fn sanitize_filename(name: &str) -> String {
-            let mut s = name.trim().to_string();
-            let invalid = ['\\', '/', ':', '*', '?', '"', '<', '>', '|'];
-            s = s
-                .chars()
-                .map(|c| if invalid.contains(&c) { '_' } else { c })
-                .collect();
-            let mut prev_space = false;
-            let mut out = String::new();
-            for ch in s.chars() {
-                if ch.is_whitespace() {
-                    if !prev_space {
-                        out.push(' ');
-                    }
-                    prev_space = true;
-                } else {
-                    prev_space = false;
-                    out.push(ch);
+        let mut results = Vec::new();
+        for planned in plan {
+            let (old, new) = (PathBuf::from(&planned.old), PathBuf::from(&planned.new));
+            let res = if let Some(e) = planned.error {
+                Err(e)
+            } else if old == new {
+                Ok(())
+            } else if new.exists() && !same_file(&old, &new) {
+                Err(format!("{} already exists", planned.new))
+            } else {
+                match self.files.iter_mut().find(|x| x.path == old) {
+                    Some(file) => fs::rename(&file.path, &new)
+                        .map(|_| file.path = new.clone())
+                        .map_err(|e| e.to_string()),
+                    None => Err("File not found in workspace".to_string()),
                 }
-            }
-            out.trim().trim_matches('.').to_string()
+            };
+            results.push((planned.old, planned.new, res));
         }
+        results
+    }
+}
 
-        fn apply_pattern(file: &File, pattern: &str) -> String {
-            let result = pattern.to_string();
-            let mut map: HashMap<String, String> = HashMap::new();
-            for (k, vec_v) in &file.tags {
-                let key = k.to_string();
-                let concatenated = vec_v
-                    .iter()
-                    .filter_map(|v| match v {
-                        TagValue::Text(s) => Some(s.clone()),
-                        _ => None,
-                    })
-                    .collect::<Vec<_>>()
-                    .join("/");
-                if !concatenated.is_empty() {
-                    map.insert(key, concatenated);
-                }
-            }
-            let ext = file.path.extension().and_then(|e| e.to_str()).unwrap_or("");
-            map.insert("ext".to_string(), ext.to_string());
+/// A rename as planned, before anything is moved
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlannedRename {
+    pub old: String,
+    pub new: String,
+    /// The path the rename ran into, either on disk or claimed by an earlier file of the batch
+    pub collision: Option<String>,
+    pub error: Option<String>,
+}
 
-            let mut out = String::new();
-            let mut chars = result.chars().peekable();
-            while let Some(c) = chars.next() {
-                if c == '{' {
-                    let mut name = String::new();
-                    while let Some(&nc) = chars.peek() {
-                        chars.next();
-                        if nc == '}' {
-                            break;
-                        }
-                        name.push(nc);
+/// Names as they'd be after the renames planned so far
+#[derive(Default)]
+struct NameClaims {
+    claimed: HashSet<PathBuf>,
+    vacated: HashSet<PathBuf>,
+}
+
+impl NameClaims {
+    /// Whether `from` can't be renamed to `path`. A name that differs only in case is still free
+    fn taken(&self, from: &Path, path: &Path) -> bool {
+        self.claimed.contains(path)
+            || (path.exists() && !self.vacated.contains(path) && !same_file(from, path))
+    }
+
+    fn claim(&mut self, from: &Path, to: &Path) {
+        self.claimed.remove(from);
+        self.vacated.insert(from.to_path_buf());
+        self.vacated.remove(to);
+        self.claimed.insert(to.to_path_buf());
+    }
+}
+
+fn clean_up_name(stem: &str, rules: &[CleanupRule]) -> String {
+    let mut new_name = stem.to_string();
+    for rule in rules {
+        match rule {
+            CleanupRule::ReplaceUnderscores => {
+                new_name = new_name.replace('_', " ");
+            }
+            CleanupRule::NormalizeDashes => {
+                new_name = new_name
+                    .chars()
+                    .map(|c| if c == '–' || c == '—' { '-' } else { c })
+                    .collect();
+            }
+            CleanupRule::NormalizeFeat => {
+                let patterns = vec![
+                    " feat. ",
+                    " ft. ",
+                    " featuring ",
+                    "Feat. ",
+                    "Ft. ",
+                    "Featuring ",
+                    " FEAT. ",
+                    " FT. ",
+                    " FEATURING ",
+                ];
+                for p in patterns {
+                    if new_name.contains(p) {
+                        new_name = new_name.replace(p, " feat. ");
                     }
-                    let val = map.get(&name).cloned().unwrap_or_default();
-                    out.push_str(&val);
-                } else {
-                    out.push(c);
                 }
             }
-            sanitize_filename(&out)
-        }
-
-        let mut results = Vec::new();
-        for path_str in file_paths {
-            let old_path = PathBuf::from(&path_str);
-            if let Some(file) = self.files.iter_mut().find(|x| x.path == old_path) {
-                let mut base_name = apply_pattern(file, pattern);
-                if base_name.is_empty() {
-                    base_name = file
-                        .path
-                        .file_stem()
-                        .and_then(|s| s.to_str())
-                        .unwrap_or("untitled")
-                        .to_string();
-                }
-                let ext = file.path.extension().and_then(|e| e.to_str()).unwrap_or("");
-                if !ext.is_empty() {
-                    let lower = base_name.to_lowercase();
-                    let want = format!(".{}", ext.to_lowercase());
-                    if !lower.ends_with(&want) {
-                        base_name = format!("{}.{}", base_name, ext);
+            CleanupRule::RemoveSuffixes => {
+                let suffixes = vec![
+                    "(Official Video)",
+                    "[Official Video]",
+                    "(Official Music Video)",
+                    "[Official Music Video]",
+                    "(Lyric Video)",
+                    "[Lyric Video]",
+                    "(Audio)",
+                    "[Audio]",
+                ];
+                for suffix in suffixes {
+                    if new_name.ends_with(suffix) {
+                        new_name = new_name.trim_end_matches(suffix).trim().to_string();
                     }
                 }
-                let parent = file.path.parent().unwrap_or(Path::new("."));
-                let mut target = parent.join(&base_name);
-                if target == file.path {
-                    results.push((path_str.clone(), target.display().to_string(), Ok(())));
-                    continue;
-                }
-                if target.exists() {
-                    let stem = target
-                        .file_stem()
-                        .and_then(|s| s.to_str())
-                        .unwrap_or("file");
-                    let ext2 = target.extension().and_then(|e| e.to_str());
-                    let mut idx = 1;
+            }
+
+            CleanupRule::RemoveBrackets => {
+                let patterns = vec![('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')];
+                for (open, close) in patterns {
                     loop {
-                        let candidate = match ext2 {
-                            Some(e) if !e.is_empty() => {
-                                parent.join(format!("{} ({}).{}", stem, idx, e))
+                        if let Some(start) = new_name.find(open) {
+                            if let Some(end) = new_name[start..].find(close) {
+                                let end = start + end;
+                                new_name.replace_range(start..=end, "");
+                                new_name = new_name.trim().to_string();
+                            } else {
+                                break;
                             }
-                            _ => parent.join(format!("{} ({})", stem, idx)),
-                        };
-                        if !candidate.exists() {
-                            target = candidate;
-                            break;
-                        }
-                        idx += 1;
-                        if idx > 9999 {
+                        } else {
                             break;
                         }
                     }
                 }
-                match fs::rename(&file.path, &target) {
-                    Ok(_) => {
-                        file.path = target.clone();
-                        results.push((path_str.clone(), target.display().to_string(), Ok(())));
+            }
+            CleanupRule::FixCapitalization => {
+                fn capitalize_word(word: &str) -> String {
+                    let mut chars = word.chars();
+                    match chars.next() {
+                        Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
+                        None => String::new(),
                     }
-                    Err(e) => {
-                        results.push((
-                            path_str.clone(),
-                            target.display().to_string(),
-                            Err(e.to_string()),
-                        ));
+                }
+                const LOWERS: &[&str] = &[
+                    "the", "a", "an", "and", "or", "but", "for", "nor", "as", "at", "by", "in",
+                    "on", "of", "per", "to", "vs",
+                ];
+
+                let words: Vec<&str> = new_name.split_whitespace().collect();
+
+                let mut result: Vec<String> = Vec::with_capacity(words.len());
+
+                for (i, word) in words.iter().enumerate() {
+                    let w = word.to_lowercase();
+
+                    if i == 0 {
+                        result.push(capitalize_word(&w));
+                    } else if LOWERS.contains(&w.as_str()) {
+                        result.push(w);
+                    } else {
+                        result.push(capitalize_word(&w));
                     }
                 }
-            } else {
-                results.push((
-                    path_str.clone(),
-                    path_str.clone(),
-                    Err("File not found in workspace".to_string()),
-                ));
+
+                new_name = result.join(" ");
+            }
+
+            CleanupRule::CollapseSpaces => {
+                let mut collapsed = String::new();
+                let mut prev_space = false;
+                for c in new_name.chars() {
+                    if c.is_whitespace() {
+                        if !prev_space {
+                            collapsed.push(' ');
+                            prev_space = true;
+                        }
+                    } else {
+                        collapsed.push(c);
+                        prev_space = false;
+                    }
+                }
+                new_name = collapsed;
+            }
+            CleanupRule::TrimWhitespace => {
+                new_name = new_name.trim().to_string();
             }
         }
-        results
     }
+
+    new_name
+}
+
+fn sanitize_filename(name: &str) -> String {
+    let mut s = name.trim().to_string();
+    let invalid = ['\\', '/', ':', '*', '?', '"', '<', '>', '|'];
+    s = s
+        .chars()
+        .map(|c| if invalid.contains(&c) { '_' } else { c })
+        .collect();
+    let mut prev_space = false;
+    let mut out = String::new();
+    for ch in s.chars() {
+        if ch.is_whitespace() {
+            if !prev_space {
+                out.push(' ');
+            }
+            prev_space = true;
+        } else {
+            prev_space = false;
+            out.push(ch);
+        }
+    }
+    out.trim().trim_matches('.').to_string()
+}
+
+fn apply_pattern(file: &File, pattern: &str) -> String {
+    let result = pattern.to_string();
+    let mut map: HashMap<String, String> = HashMap::new();
+    for (k, vec_v) in &file.tags {
+        let key = k.to_string();
+        let concatenated = vec_v
+            .iter()
+            .filter_map(|v| match v {
+                TagValue::Text(s) => Some(s.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        if !concatenated.is_empty() {
+            map.insert(key, concatenated);
+        }
+    }
+    let ext = file.path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    map.insert("ext".to_string(), ext.to_string());
+
+    let mut out = String::new();
+    let mut chars = result.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '{' {
+            let mut name = String::new();
+            while let Some(&nc) = chars.peek() {
+                chars.next();
+                if nc == '}' {
+                    break;
+                }
+                name.push(nc);
+            }
+            let val = map.get(&name).cloned().unwrap_or_default();
+            out.push_str(&val);
+        } else {
+            out.push(c);
+        }
+    }
+    sanitize_filename(&out)
 }
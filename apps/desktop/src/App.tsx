@@ -47,8 +47,41 @@ import { path } from "@tauri-apps/api";
 
 import Bottombar from "./components/Bottombar";
 import { useBottombarHeight } from "./hooks/useBottombarHeight";
-import { shortcutAccelerator } from "./lib/utils";
+import { shortcutAccelerator, thumbnailUrl } from "./lib/utils";
 import { ContextMenuArea } from "./components/ContextMenu";
+
+const PROPERTY_COLUMNS: Record<
+  string,
+  { field: string; numeric: boolean; display: (v: any) => string }
+> = {
+  duration: {
+    field: "durationMs",
+    numeric: true,
+    display: (ms: number) => {
+      const total = Math.round(ms / 1000);
+      const h = Math.floor(total / 3600);
+      const m = Math.floor((total % 3600) / 60);
+      const sec = String(total % 60).padStart(2, "0");
+      return h > 0
+        ? `${h}:${String(m).padStart(2, "0")}:${sec}`
+        : `${m}:${sec}`;
+    },
+  },
+  bitrate: { field: "bitrate", numeric: true, display: (v) => `${v} kbps` },
+  sampleRate: {
+    field: "sampleRate",
+    numeric: true,
+    display: (v) => `${(v / 1000).toFixed(1)} kHz`,
+  },
+  channels: { field: "channels", numeric: true, display: (v) => String(v) },
+  bitsPerSample: {
+    field: "bitsPerSample",
+    numeric: true,
+    display: (v) => `${v}-bit`,
+  },
+  codec: { field: "codec", numeric: false, display: (v) => String(v) },
+};
+
 const params = new URLSearchParams(window.location.href);
 
 let viewMode = params.get("view") ?? "simple";
@@ -137,6 +170,7 @@ function App() {
         tag_formats: sf.tag_formats,
         fileName: sf.file_name || sf.path.split(path.sep()).pop() || sf.path,
         release: sf.tag_format,
+        properties: sf.properties,
 
         frames: tagsMap,
       } as File;
@@ -184,7 +218,7 @@ function App() {
           if (!frame || !Array.isArray(frame))
             return <div className="text-[11px] italic opacity-40">—</div>;
 
-          if (frame[0].type !== "Picture") {
+          if (frame[0].type !== "PictureRef") {
             return <div className="text-[11px] italic opacity-40">—</div>;
           }
 
@@ -195,13 +229,13 @@ function App() {
             (row.original as any).frames?.attachedPicture,
           )
             ? ((row.original as any).frames.attachedPicture as any[]).filter(
-                (v) => v && v.type === "Picture",
+                (v) => v && v.type === "PictureRef",
               ).length
             : 0;
           return (
             <div className=" flex justify-center items-center">
               <img
-                src={`data:${pic.value.mime};base64,${pic.value.data_base64}`}
+                src={thumbnailUrl(pic.value.hash, 64, row.original.path)}
                 alt="Img cover"
                 className="max-h-9.5 w-auto rounded-sm border border-border self-center justify-self-center"
               />
@@ -267,6 +301,37 @@ function App() {
         },
       }) as ColumnDef<File, any>;
     }
+    if (item.value in PROPERTY_COLUMNS) {
+      const format = PROPERTY_COLUMNS[item.value];
+      return (columnHelper as any).accessor(
+        (row: File) => {
+          const props = row.properties as Record<string, any> | undefined;
+          return props ? (props[format.field] ?? undefined) : undefined;
+        },
+        {
+          id: item.value,
+          size: item.size,
+          header: () => (
+            <span className="w-full truncate uppercase tracking-wide text-[11px] font-semibold text-foreground/70">
+              {item.label}
+            </span>
+          ),
+          sortingFn: format.numeric ? "basic" : "alphanumeric",
+          sortUndefined: "last",
+          enableSorting: true,
+          cell: ({ getValue }: any) => {
+            const value = getValue();
+            if (value === null || value === undefined || value === "")
+              return <div className="text-[11px] italic opacity-40">—</div>;
+            return (
+              <div className="text-[11px] truncate px-2">
+                {format.display(value)}
+              </div>
+            );
+          },
+        },
+      ) as ColumnDef<File, any>;
+    }
     return (columnHelper as any).accessor(
       (row: File) => {
         const tag = row.frames[item.value];
@@ -1,6 +1,11 @@
 import { useChanges } from "../hooks/useChanges";
 import { Input } from "./Input";
-import { AllTags, SerializableTagFrameValue, TagPicture } from "@/ui/types";
+import {
+  AllTags,
+  Frames,
+  SerializableTagFrameValue,
+  TagPicture,
+} from "@/ui/types";
 import { ReactNode, useEffect, useState } from "react";
 import { Button } from "./Button";
 
@@ -29,6 +34,13 @@ export default function EditMenu({ bottombar }: Props): ReactNode {
 
   const disabled = false;
 
+  // list views only carry references, the editor needs the pictures themselves
+  function loadPictures(path: string) {
+    invoke<TagPicture[]>("get_pictures", { path })
+      .then((pics) => setPictures(pics))
+      .catch(() => setPictures([]));
+  }
+
   useEffect(() => {
     setDefaultValues({});
 
@@ -65,32 +77,7 @@ export default function EditMenu({ bottombar }: Props): ReactNode {
 
       setDefaultValues(map);
 
-      const selectedPath = [...selected][0];
-      const fileObj = files.find((f) => f.path === selectedPath);
-      const framePics: TagPicture[] = Array.isArray(
-        (fileObj as any)?.frames?.attachedPicture,
-      )
-        ? ((fileObj as any).frames.attachedPicture as any[])
-            .filter((v) => v && v.type === "Picture" && v.value)
-            .map((v) => ({ type: "Picture", value: v.value }))
-        : [];
-
-      if (framePics.length > 0) {
-        setPictures(framePics);
-      } else if (selectedFiles[0].attachedPicture) {
-        if (!selectedFiles[0]) return;
-        if (!selectedFiles[0].attachedPicture) return;
-
-        const img = selectedFiles[0].attachedPicture[0] as
-          | TagPicture
-          | undefined;
-        if (!img) return;
-        if (!img.value) return;
-
-        setPictures([img]);
-      } else {
-        setPictures([]);
-      }
+      loadPictures([...selected][0]);
     } else {
       const defaultValue: Record<
         string,
@@ -123,32 +110,15 @@ export default function EditMenu({ bottombar }: Props): ReactNode {
       });
 
       setDefaultValues(defaultValue as any);
+      const pictureHashes = (f: Frames) =>
+        (f.attachedPicture ?? [])
+          .map((v) => (v.type === "PictureRef" ? v.value.hash : ""))
+          .join();
       const picTheSame = selectedFiles.every(
-        (f) => f.attachedPicture === selectedFiles[0].attachedPicture,
+        (f) => pictureHashes(f) === pictureHashes(selectedFiles[0]),
       );
       if (picTheSame) {
-        const selectedPath = [...selected][0];
-        const fileObj = files.find((f) => f.path === selectedPath);
-        const framePics: TagPicture[] = Array.isArray(
-          (fileObj as any)?.frames?.attachedPicture,
-        )
-          ? ((fileObj as any).frames.attachedPicture as any[])
-              .filter((v) => v && v.type === "Picture" && v.value)
-              .map((v) => ({ type: "Picture", value: v.value }))
-          : [];
-        if (framePics.length > 0) {
-          setPictures(framePics);
-        } else {
-          if (!selectedFiles[0].attachedPicture) return;
-          const img = selectedFiles[0].attachedPicture[0] as
-            | TagPicture
-            | undefined;
-          if (img && img.value) {
-            setPictures([img]);
-          } else {
-            setPictures([]);
-          }
-        }
+        loadPictures([...selected][0]);
       }
     }
   }, [selected, files, sidebar_items]);
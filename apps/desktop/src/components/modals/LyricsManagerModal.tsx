@@ -9,7 +9,7 @@ import { AnimatePresence } from "motion/react";
 import { Modal } from "./Modal";
 import { useChanges } from "@/ui/hooks/useChanges";
 import { Select, SelectOption } from "@/ui/components/Select";
-import { cn, getFirstValue } from "@/ui/lib/utils";
+import { cn, getFirstValue, thumbnailUrl } from "@/ui/lib/utils";
 import { MinusIcon, PauseIcon, PlayIcon, PlusIcon, XIcon } from "lucide-react";
 import { motion } from "motion/react";
 export interface LyricsManagerModalProps {
@@ -507,10 +507,10 @@ export default function LyricsManagerModal({
                 if (file) {
                   const image = getFirstValue(
                     file.frames.attachedPicture,
-                    "Picture",
+                    "PictureRef",
                   );
                   if (image) {
-                    return `data:${image.value.mime};base64,${image.value.data_base64}`;
+                    return thumbnailUrl(image.value.hash, 64, file.path);
                   }
                 }
 
@@ -1,4 +1,5 @@
 import { clsx, type ClassValue } from "clsx";
+import { convertFileSrc } from "@tauri-apps/api/core";
 
 import { twMerge } from "tailwind-merge";
 import { SerializableTagFrameValue } from "../types";
@@ -48,6 +49,13 @@ export function getFirstValue<T extends SerializableTagFrameValue["type"]>(
   return undefined;
 }
 
+/** Url of the thumbnail of a picture embedded in the file at `path` */
+export function thumbnailUrl(hash: string, size: number, path: string) {
+  // "thumbnail://localhost/" or "http://thumbnail.localhost/" depending on the platform
+  const base = convertFileSrc("", "thumbnail");
+  return `${base}${hash}/${size}?path=${encodeURIComponent(path)}`;
+}
+
 export function isValidFileName(
   name: string,
   ignoreMaxChar: boolean,
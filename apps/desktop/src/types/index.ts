@@ -239,6 +239,18 @@ export interface TagPicture {
   };
 }
 
+/** A picture in list views, the data is loaded with `get_pictures` */
+export interface TagPictureRef {
+  type: "PictureRef";
+  value: {
+    mime: string;
+    hash: string;
+    size: number;
+    picture_type?: number;
+    description?: string;
+  };
+}
+
 export interface UserTextEntry {
   description: string;
   value: string;
@@ -251,6 +263,7 @@ export interface UserUrlEntry {
 export type SerializableTagFrameValue =
   | TagText
   | TagPicture
+  | TagPictureRef
   | { type: "UserText"; value: UserTextEntry }
   | { type: "UserUrl"; value: UserUrlEntry };
 
@@ -264,12 +277,21 @@ export interface FrameChangesPayload {
   frames: SerializableTagFrame[];
 }
 
+export interface AudioProperties {
+  durationMs: number;
+  bitrate: number;
+  sampleRate: number;
+  channels: number;
+  bitsPerSample?: number | null;
+  codec: string;
+}
 export interface RawFile {
   path: string;
   file_name: string;
   tag_format: string;
   tag_formats?: string[];
   tags: Frames;
+  properties?: AudioProperties | null;
 }
 export interface File {
   path: string;
@@ -277,6 +299,7 @@ export interface File {
   tag_format: string;
   tag_formats?: string[];
   frames: Frames;
+  properties?: AudioProperties | null;
 }
 export type Frames = {
   [key: string]: SerializableTagFrameValue[];